@@ -0,0 +1,50 @@
+extern crate criterion;
+extern crate np1th_irc;
+
+use std::{convert::TryFrom, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use np1th_irc::{
+    command::client::Command,
+    message::{Message, MessageRef},
+};
+
+const LINES: &[&str] = &[
+    ":nick!user@host.example.com PRIVMSG #channel :hello there, how is everyone doing today?\r\n",
+    "@time=2019-01-01T00:00:00.000Z;msgid=abc :nick!user@host PRIVMSG #channel :tagged\r\n",
+    ":irc.example.com 001 nick :Welcome to the Internet Relay Network nick!user@host\r\n",
+    ":nick!user@host JOIN #channel\r\n",
+    "PING :irc.example.com\r\n",
+];
+
+fn parse(c: &mut Criterion) {
+    let bytes = LINES.iter().map(|line| line.len() as u64).sum();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes));
+
+    group.bench_function("Message", |b| {
+        b.iter(|| {
+            for line in LINES {
+                let _ = black_box(Message::<Command>::try_from(black_box(*line)));
+            }
+        })
+    });
+
+    group.bench_function("MessageRef", |b| {
+        b.iter(|| {
+            for line in LINES {
+                let message = MessageRef::parse(black_box(*line)).unwrap();
+
+                black_box(message.command());
+                black_box(message.params().count());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
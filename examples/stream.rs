@@ -2,7 +2,11 @@ extern crate np1th_irc;
 
 use std::thread::yield_now;
 
-use np1th_irc::{command::client::Command::*, stream::{ClientStream, Port}};
+use np1th_irc::{
+    command::client::Command::*,
+    stream::{ClientStream, Port},
+    target::MessageTarget,
+};
 
 fn main() -> Result<(), Box<std::error::Error>> {
     println!("Trying to connect..");
@@ -39,7 +43,7 @@ fn main() -> Result<(), Box<std::error::Error>> {
 
                     stream.send(PrivMsg {
                         text: format!("Hello {}!", target),
-                        targets: vec![target.to_string()],
+                        targets: vec![MessageTarget::Nick(target.to_string())],
                     })?;
                 }
 
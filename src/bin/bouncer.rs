@@ -0,0 +1,103 @@
+extern crate np1th_irc;
+
+use std::{env, process};
+
+use np1th_irc::{
+    bouncer::Bouncer, connection::client::Client, origin::Origin, stream::Port, user::User,
+};
+
+const USAGE: &str = "usage: bouncer --host HOST [--port PORT | --tls-port PORT] --nick NICK \
+                     [--user USER] [--real-name NAME] [--server-password PASSWORD] \
+                     [--listen ADDRESS].. [--password PASSWORD] [--buffer COUNT]";
+
+fn value(args: &mut env::Args, option: &str) -> String {
+    args.next().unwrap_or_else(|| {
+        eprintln!("{} needs a value\n{}", option, USAGE);
+        process::exit(2)
+    })
+}
+
+fn number<T: std::str::FromStr>(args: &mut env::Args, option: &str) -> T {
+    value(args, option).parse().unwrap_or_else(|_| {
+        eprintln!("{} needs a number\n{}", option, USAGE);
+        process::exit(2)
+    })
+}
+
+fn main() -> Result<(), Box<std::error::Error>> {
+    let mut builder = Bouncer::builder();
+    let mut args = env::args();
+    let mut host = None;
+    let mut port = Port::Insecure(6667);
+    let mut nick = None;
+    let mut user = None;
+    let mut real_name = None;
+    let mut server_password: Option<String> = None;
+    let mut listening = false;
+
+    args.next();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--host" => host = Some(value(&mut args, &arg)),
+            "--port" => port = Port::Insecure(number(&mut args, &arg)),
+            "--tls-port" => port = Port::Secure(number(&mut args, &arg)),
+            "--nick" => nick = Some(value(&mut args, &arg)),
+            "--user" => user = Some(value(&mut args, &arg)),
+            "--real-name" => real_name = Some(value(&mut args, &arg)),
+            "--server-password" => server_password = Some(value(&mut args, &arg)),
+            "--listen" => {
+                builder = builder.listen(&value(&mut args, &arg));
+                listening = true;
+            }
+            "--password" => builder = builder.password(&value(&mut args, &arg)),
+            "--buffer" => builder = builder.buffer(number(&mut args, &arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2)
+            }
+        }
+    }
+
+    let (host, nick) = match (host, nick) {
+        (Some(host), Some(nick)) => (host, nick),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2)
+        }
+    };
+
+    if !listening {
+        builder = builder.listen("127.0.0.1:6667");
+    }
+
+    let user = user.unwrap_or_else(|| nick.clone());
+    let real_name = real_name.unwrap_or_else(|| nick.clone());
+
+    let mut bouncer = builder
+        .upstream(move || {
+            let myself = User::new(
+                Origin::User {
+                    nick: nick.clone(),
+                    user: Some(user.clone()),
+                    host: None,
+                },
+                &real_name,
+            );
+
+            let mut client = Client::builder().host(&host).port(port).user(myself);
+
+            if let Some(ref password) = server_password {
+                client = client.password(password);
+            }
+
+            client
+        })
+        .build()?;
+
+    for address in bouncer.addresses() {
+        eprintln!("listening on {}", address);
+    }
+
+    bouncer.run()
+}
@@ -0,0 +1,131 @@
+extern crate np1th_irc;
+
+use std::{
+    env, fs, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use native_tls::Identity;
+
+use np1th_irc::ircd::{config, oper, Ircd, POLL_INTERVAL};
+
+const USAGE: &str = "usage: ircd [--config FILE.toml] [--name NAME] [--listen ADDRESS].. \
+                     [--tls ADDRESS --identity FILE.p12 [--identity-password PASSWORD]] \
+                     [--motd FILE] [--password PASSWORD] [--ban-file FILE] \
+                     [--account-file FILE] | --mkpasswd PASSWORD";
+
+fn value<I>(args: &mut I, option: &str) -> String
+where
+    I: Iterator<Item = String>,
+{
+    args.next().unwrap_or_else(|| {
+        eprintln!("{} needs a value\n{}", option, USAGE);
+        process::exit(2)
+    })
+}
+
+fn main() -> Result<(), Box<std::error::Error>> {
+    let all = env::args().skip(1).collect::<Vec<String>>();
+    let mut tls = Vec::new();
+    let mut identity = None;
+    let mut identity_password = String::new();
+    let mut listening = false;
+
+    let configs = all
+        .iter()
+        .enumerate()
+        .filter(|(_, arg)| *arg == "--config")
+        .map(|(pos, _)| pos)
+        .collect::<Vec<usize>>();
+
+    // the file is loaded first wherever it's given, the other options override it
+    let mut builder = match configs.as_slice() {
+        [] => Ircd::builder(),
+        [pos] => {
+            listening = true;
+
+            config::load(value(&mut all[pos + 1..].iter().cloned(), "--config").as_ref())?
+        }
+        _ => {
+            eprintln!("--config may only be given once\n{}", USAGE);
+            process::exit(2)
+        }
+    };
+
+    let mut args = all.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--config" => {
+                value(&mut args, &arg);
+            }
+            "--name" => builder = builder.name(&value(&mut args, &arg)),
+            "--listen" => {
+                builder = builder.listen(&value(&mut args, &arg));
+                listening = true;
+            }
+            "--tls" => tls.push(value(&mut args, &arg)),
+            "--identity" => identity = Some(value(&mut args, &arg)),
+            "--identity-password" => identity_password = value(&mut args, &arg),
+            "--motd" => builder = builder.motd(&fs::read_to_string(value(&mut args, &arg))?),
+            "--password" => builder = builder.password(&value(&mut args, &arg)),
+            "--ban-file" => builder = builder.ban_file(value(&mut args, &arg)),
+            "--account-file" => builder = builder.account_file(value(&mut args, &arg)),
+            "--mkpasswd" => {
+                println!("{}", oper::hash_password(&value(&mut args, &arg)));
+
+                return Ok(());
+            }
+            _ => {
+                eprintln!("{}", USAGE);
+                process::exit(2)
+            }
+        }
+    }
+
+    if !tls.is_empty() {
+        let identity = match identity {
+            Some(identity) => fs::read(identity)?,
+            None => {
+                eprintln!("--tls needs --identity\n{}", USAGE);
+                process::exit(2)
+            }
+        };
+
+        for address in tls {
+            builder = builder.listen_tls(
+                &address,
+                Identity::from_pkcs12(&identity, &identity_password)?,
+            );
+        }
+    } else if !listening {
+        builder = builder.listen("0.0.0.0:6667");
+    }
+
+    let mut ircd = builder.build()?;
+
+    for address in ircd.addresses() {
+        eprintln!("listening on {}", address);
+    }
+
+    // SIGHUP rehashes
+    let hangup = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone())?;
+
+    loop {
+        ircd.poll()?;
+
+        if hangup.swap(false, Ordering::Relaxed) {
+            match ircd.rehash() {
+                Ok(()) => eprintln!("rehashed"),
+                Err(e) => eprintln!("rehash failed: {}", e),
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
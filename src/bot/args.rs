@@ -0,0 +1,222 @@
+use std::error::Error;
+
+use crate::validate;
+
+pub mod error {
+    impl_error!(MissingArgumentError { name: String });
+    impl_error!(InvalidArgumentError {
+        name: String,
+        value: String
+    });
+    impl_error!(UnclosedQuoteError {});
+    impl_error!(TooManyArgumentsError {});
+}
+
+const QUOTE: char = '"';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// A single word, `"quoted words"` count as one
+    Word,
+    Integer,
+    Number,
+    Nick,
+    Channel,
+    /// Everything left, as written
+    Rest,
+}
+
+/// An argument a command takes, optional ones have to come last
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    name: String,
+    kind: Kind,
+    optional: bool,
+}
+
+impl Arg {
+    pub fn new(name: &str, kind: Kind) -> Self {
+        Arg {
+            name: name.to_string(),
+            kind,
+            optional: false,
+        }
+    }
+
+    pub fn word(name: &str) -> Self {
+        Arg::new(name, Kind::Word)
+    }
+
+    pub fn integer(name: &str) -> Self {
+        Arg::new(name, Kind::Integer)
+    }
+
+    pub fn number(name: &str) -> Self {
+        Arg::new(name, Kind::Number)
+    }
+
+    pub fn nick(name: &str) -> Self {
+        Arg::new(name, Kind::Nick)
+    }
+
+    pub fn channel(name: &str) -> Self {
+        Arg::new(name, Kind::Channel)
+    }
+
+    pub fn rest(name: &str) -> Self {
+        Arg::new(name, Kind::Rest)
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+
+        self
+    }
+}
+
+impl Arg {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// `<name>`, `[name]` if it's optional, with `...` for the rest
+    pub fn usage(&self) -> String {
+        let name = match self.kind {
+            Kind::Rest => format!("{}...", self.name),
+            _ => self.name.clone(),
+        };
+
+        if self.optional {
+            format!("[{}]", name)
+        } else {
+            format!("<{}>", name)
+        }
+    }
+
+    fn parse(&self, data: &str) -> Result<Value, Box<Error>> {
+        let invalid = || error::InvalidArgumentError::new(self.name.clone(), data.to_string());
+
+        match self.kind {
+            Kind::Integer => data.parse().map(Value::Integer).map_err(|_| invalid()),
+            Kind::Number => data.parse().map(Value::Number).map_err(|_| invalid()),
+            Kind::Nick => validate::nick_name(data)
+                .map(|_| Value::Text(data.to_string()))
+                .map_err(|_| invalid()),
+            Kind::Channel => validate::channel_name(data)
+                .map(|_| Value::Text(data.to_string()))
+                .map_err(|_| invalid()),
+            Kind::Word | Kind::Rest => Ok(Value::Text(data.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Text(String),
+    Integer(i64),
+    Number(f64),
+}
+
+/// Arguments a command got, by name
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Args {
+    values: Vec<(String, Value)>,
+}
+
+impl Args {
+    /// Parses `data` as the arguments `specs` describe. Missing optional arguments are left out.
+    pub fn parse(specs: &[Arg], data: &str) -> Result<Self, Box<Error>> {
+        let mut values = Vec::new();
+        let mut rest = data.trim();
+
+        for spec in specs {
+            if rest.is_empty() {
+                if spec.optional {
+                    continue;
+                }
+
+                return Err(error::MissingArgumentError::new(spec.name.clone()));
+            }
+
+            let word = match spec.kind {
+                Kind::Rest => std::mem::take(&mut rest),
+                _ => {
+                    let (word, remaining) = next_word(rest)?;
+                    rest = remaining;
+
+                    word
+                }
+            };
+
+            values.push((spec.name.clone(), spec.parse(word)?));
+        }
+
+        if !rest.is_empty() {
+            return Err(error::TooManyArgumentsError::new());
+        }
+
+        Ok(Args { values })
+    }
+}
+
+impl Args {
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn text(&self, name: &str) -> Option<&str> {
+        match self.get(name) {
+            Some(Value::Text(ref text)) => Some(text.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        match self.get(name) {
+            Some(Value::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, name: &str) -> Option<f64> {
+        match self.get(name) {
+            Some(Value::Number(value)) => Some(*value),
+            Some(Value::Integer(value)) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+/// The first word, without the quotes if it's quoted, and what's left after it
+fn next_word(data: &str) -> Result<(&str, &str), Box<Error>> {
+    if let Some(quoted) = data.strip_prefix(QUOTE) {
+        return match quoted.find(QUOTE) {
+            Some(end) => Ok((&quoted[..end], quoted[end + 1..].trim_start())),
+            None => Err(error::UnclosedQuoteError::new()),
+        };
+    }
+
+    Ok(match data.find(char::is_whitespace) {
+        Some(end) => (&data[..end], data[end..].trim_start()),
+        None => (data, ""),
+    })
+}
@@ -0,0 +1,521 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command,
+    connection::client::Client,
+    ctcp,
+    message::Message,
+    origin::{Mask, Origin},
+    target::MessageTarget,
+};
+
+pub mod args;
+
+pub use self::args::{Arg, Args, Kind, Value};
+
+/// Name of the built-in command listing the others
+pub const HELP: &str = "help";
+
+/// Time before somebody who may not use a command gets told so again
+pub const DENIAL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Minimum time between two usages or help answers in the same channel, or to the same sender
+/// in private
+pub const HELP_COOLDOWN: Duration = Duration::from_secs(5);
+
+/// Time between two denial notices to anybody, so many senders can't make us flood either
+const DENIAL_SPACING: Duration = Duration::from_secs(2);
+
+/// Who may use a command besides everybody
+#[derive(Debug, Clone, PartialEq)]
+pub enum Permission {
+    /// Hostmask like `*!*@trusted.example`
+    Mask(String),
+    /// Account the sender is logged into, known from `account-tag` or the tracked users
+    Account(String),
+    /// Operator in the channel the command is used in
+    Operator,
+    /// Voiced or operator in the channel the command is used in
+    Voice,
+}
+
+/// Runs a command, `Context::reply` answers where it was used
+pub type Handler = Box<FnMut(&Context) -> Result<(), Box<Error>>>;
+
+/// A command the bot knows, with its arguments, help text and restrictions
+pub struct Definition {
+    name: String,
+    aliases: Vec<String>,
+    args: Vec<Arg>,
+    help: Option<String>,
+    cooldown: Duration,
+    permissions: Vec<Permission>,
+    handler: Handler,
+}
+
+impl Definition {
+    pub fn new<F>(name: &str, handler: F) -> Self
+    where
+        F: FnMut(&Context) -> Result<(), Box<Error>> + 'static,
+    {
+        Definition {
+            name: name.to_string(),
+            aliases: Vec::new(),
+            args: Vec::new(),
+            help: None,
+            cooldown: Duration::from_secs(0),
+            permissions: Vec::new(),
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn alias(mut self, alias: &str) -> Self {
+        self.aliases.push(alias.to_string());
+
+        self
+    }
+
+    /// Arguments come in the order they're added
+    pub fn arg(mut self, arg: Arg) -> Self {
+        self.args.push(arg);
+
+        self
+    }
+
+    pub fn help(mut self, text: &str) -> Self {
+        self.help = Some(text.to_string());
+
+        self
+    }
+
+    /// Minimum time between two uses of the command in the same channel, or by the same sender
+    /// in private
+    pub fn cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+
+        self
+    }
+
+    /// Restricts the command, any of the permissions added lets the sender use it
+    pub fn permission(mut self, permission: Permission) -> Self {
+        self.permissions.push(permission);
+
+        self
+    }
+}
+
+impl Definition {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn args(&self) -> &Vec<Arg> {
+        &self.args
+    }
+
+    /// Like `!roll <sides> [count]`
+    pub fn usage(&self, prefix: &str) -> String {
+        let mut usage = format!("{}{}", prefix, self.name);
+
+        for arg in self.args.iter() {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+
+        usage
+    }
+
+    fn is_called(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self
+                .aliases
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A command being run: who used it where, and with which arguments
+pub struct Context<'a> {
+    client: &'a Client,
+    message: &'a Message<Command>,
+    channel: Option<String>,
+    args: Args,
+}
+
+impl<'a> Context<'a> {
+    pub fn client(&self) -> &Client {
+        self.client
+    }
+
+    pub fn message(&self) -> &Message<Command> {
+        self.message
+    }
+
+    pub fn sender(&self) -> &Origin {
+        self.message.origin()
+    }
+
+    pub fn nick(&self) -> &str {
+        self.message.origin().nick().unwrap_or_default()
+    }
+
+    /// `None` for private messages
+    pub fn channel(&self) -> Option<&str> {
+        self.channel.as_deref()
+    }
+
+    pub fn args(&self) -> &Args {
+        &self.args
+    }
+
+    /// Answers in the channel, or privately to the sender
+    pub fn reply(&self, text: &str) -> Result<(), Box<Error>> {
+        self.client
+            .privmsg(self.channel().unwrap_or(self.nick()), text)?;
+
+        Ok(())
+    }
+
+    /// Answers the sender with a `NOTICE`, even if the command was used in a channel
+    pub fn notice(&self, text: &str) -> Result<(), Box<Error>> {
+        self.client.notice(self.nick(), text)?;
+
+        Ok(())
+    }
+}
+
+/// Finds commands like `!roll 6` or `bot: roll 6` in messages and runs them
+///
+/// Private messages don't need a prefix. Commands somebody may not use get a notice, at most once
+/// per `DENIAL_INTERVAL`. Commands still cooling down are ignored, and so are bad arguments or
+/// `help` within `HELP_COOLDOWN` of the last usage or help shown there.
+pub struct Bot {
+    prefixes: Vec<String>,
+    addressing: bool,
+    help: bool,
+    commands: Vec<Definition>,
+    /// By command name and the channel, or the sender in private
+    last_used: HashMap<(String, String), Instant>,
+    /// Senders told they may not use a command
+    denied: HashMap<String, Instant>,
+    last_denial: Option<Instant>,
+    /// By the channel, or the sender in private
+    helped: HashMap<String, Instant>,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Bot {
+            prefixes: vec!["!".to_string()],
+            addressing: true,
+            help: true,
+            commands: Vec::new(),
+            last_used: HashMap::new(),
+            denied: HashMap::new(),
+            last_denial: None,
+            helped: HashMap::new(),
+        }
+    }
+}
+
+impl Bot {
+    pub fn new() -> Self {
+        Bot::default()
+    }
+
+    /// Prefixes commands start with, `!` by default
+    pub fn prefixes(mut self, prefixes: Vec<&str>) -> Self {
+        self.prefixes = prefixes.iter().map(|prefix| prefix.to_string()).collect();
+
+        self
+    }
+
+    /// Whether `nick: command` and `nick, command` work too, on by default
+    pub fn addressing(mut self, enabled: bool) -> Self {
+        self.addressing = enabled;
+
+        self
+    }
+
+    /// Whether there's a `help` command listing the others, on by default
+    pub fn help(mut self, enabled: bool) -> Self {
+        self.help = enabled;
+
+        self
+    }
+
+    pub fn command(mut self, definition: Definition) -> Self {
+        self.commands.push(definition);
+
+        self
+    }
+}
+
+impl Bot {
+    pub fn commands(&self) -> &Vec<Definition> {
+        &self.commands
+    }
+
+    /// Runs the command in `message`, if it has one the sender may use. Returns the name of the
+    /// command run.
+    pub fn handle(
+        &mut self,
+        client: &Client,
+        message: &Message<Command>,
+    ) -> Result<Option<String>, Box<Error>> {
+        let (target, text) = match message.command() {
+            Command::PrivMsg { targets, text } if targets.len() == 1 => (&targets[0], text),
+            _ => return Ok(None),
+        };
+
+        let nick = match message.origin().nick() {
+            Some(nick) if !client.server().casemapping().eq(nick, client.nick()) => nick,
+            _ => return Ok(None),
+        };
+
+        if ctcp::Ctcp::parse(text).is_some() {
+            return Ok(None);
+        }
+
+        let channel = match target {
+            MessageTarget::Channel(ref channel)
+            | MessageTarget::ChannelStatus { ref channel, .. } => Some(channel.to_string()),
+            _ => None,
+        };
+
+        let invocation = match self.invocation(client, text, channel.is_none()) {
+            Some(invocation) => invocation,
+            None => return Ok(None),
+        };
+
+        let (name, rest) = match invocation.find(char::is_whitespace) {
+            Some(pos) => (&invocation[..pos], &invocation[pos..]),
+            None => (invocation, ""),
+        };
+
+        let casemapping = client.server().casemapping();
+        let place = casemapping.to_lower(channel.as_deref().unwrap_or(nick));
+
+        let position = match self.commands.iter().position(|c| c.is_called(name)) {
+            Some(position) => position,
+            None if self.help && name.eq_ignore_ascii_case(HELP) => {
+                if !self.may_help(&place) {
+                    return Ok(None);
+                }
+
+                let context = Context {
+                    client,
+                    message,
+                    channel,
+                    args: Args::default(),
+                };
+
+                self.show_help(&context, rest.trim())?;
+
+                return Ok(Some(HELP.to_string()));
+            }
+            None => return Ok(None),
+        };
+
+        let definition = &self.commands[position];
+
+        if !self.allowed(client, message, channel.as_ref(), definition) {
+            let notice = format!("You may not use {}{}", self.prefix(), definition.name);
+
+            if self.deny(&casemapping.to_lower(nick)) {
+                client.notice(nick, &notice)?;
+            }
+
+            return Ok(None);
+        }
+
+        let key = (definition.name.to_ascii_lowercase(), place.clone());
+
+        match self.last_used.get(&key) {
+            Some(last) if last.elapsed() < definition.cooldown => return Ok(None),
+            _ => (),
+        }
+
+        let parsed = Args::parse(&definition.args, rest);
+        let usage = format!("Usage: {}", definition.usage(&self.prefix()));
+        let commands = &self.commands;
+
+        // a use with bad arguments still counts, so the usage doesn't come faster than the command
+        self.last_used.retain(|(name, _), last| {
+            commands
+                .iter()
+                .any(|c| c.name.eq_ignore_ascii_case(name) && last.elapsed() < c.cooldown)
+        });
+        self.last_used.insert(key, Instant::now());
+
+        let args = match parsed {
+            Ok(args) => args,
+            Err(_) => {
+                if self.may_help(&place) {
+                    client.privmsg(channel.as_ref().map_or(nick, |c| c.as_str()), &usage)?;
+                }
+
+                return Ok(None);
+            }
+        };
+
+        let context = Context {
+            client,
+            message,
+            channel,
+            args,
+        };
+
+        let definition = &mut self.commands[position];
+        (definition.handler)(&context)?;
+
+        Ok(Some(definition.name.clone()))
+    }
+
+    /// The text after the prefix or our nick, `None` if it's not meant for us
+    fn invocation<'a>(&self, client: &Client, text: &'a str, private: bool) -> Option<&'a str> {
+        if let Some(prefix) = self.prefixes.iter().find(|p| text.starts_with(p.as_str())) {
+            return Some(text[prefix.len()..].trim_start()).filter(|rest| !rest.is_empty());
+        }
+
+        if self.addressing {
+            let nick = client.nick();
+            let addressed = text
+                .get(..nick.len())
+                .is_some_and(|start| client.server().casemapping().eq(start, nick));
+
+            if addressed {
+                let rest = &text[nick.len()..];
+
+                if rest.starts_with(':') || rest.starts_with(',') {
+                    return Some(rest[1..].trim_start()).filter(|rest| !rest.is_empty());
+                }
+            }
+        }
+
+        if private {
+            Some(text.trim()).filter(|text| !text.is_empty())
+        } else {
+            None
+        }
+    }
+
+    fn allowed(
+        &self,
+        client: &Client,
+        message: &Message<Command>,
+        channel: Option<&String>,
+        definition: &Definition,
+    ) -> bool {
+        if definition.permissions.is_empty() {
+            return true;
+        }
+
+        let server = client.server();
+        let origin = message.origin();
+        let user = origin.nick().and_then(|nick| server.user(nick));
+
+        let account = match message.tag("account") {
+            Some(Some(account)) => Some(account.to_string()),
+            _ => user
+                .as_ref()
+                .and_then(|user| user.borrow().account().map(|a| a.to_string())),
+        };
+
+        // operator or voice in the channel
+        let status = match (channel.and_then(|c| server.channel(c)), user.as_ref()) {
+            (Some(channel), Some(user)) => channel
+                .borrow()
+                .member(user)
+                .map(|member| (member.is_operator(), member.is_voiced())),
+            _ => None,
+        };
+
+        definition
+            .permissions
+            .iter()
+            .any(|permission| match permission {
+                Permission::Mask(ref mask) => Mask::new(mask)
+                    .casemapping(server.casemapping())
+                    .matches(origin),
+                Permission::Account(ref name) => account
+                    .as_ref()
+                    .is_some_and(|account| server.casemapping().eq(account, name)),
+                Permission::Operator => status.is_some_and(|(operator, _)| operator),
+                Permission::Voice => status.is_some_and(|(operator, voice)| operator || voice),
+            })
+    }
+
+    /// Whether the sender may be told about a denied command now, counting it if so
+    fn deny(&mut self, sender: &str) -> bool {
+        let spaced = self
+            .last_denial
+            .is_none_or(|last| last.elapsed() >= DENIAL_SPACING);
+
+        self.denied
+            .retain(|_, last| last.elapsed() < DENIAL_INTERVAL);
+
+        if !spaced || self.denied.contains_key(sender) {
+            return false;
+        }
+
+        self.denied.insert(sender.to_string(), Instant::now());
+        self.last_denial = Some(Instant::now());
+
+        true
+    }
+
+    /// Whether a usage or the help may be shown in the channel or to the sender now, counting it
+    /// if so
+    fn may_help(&mut self, place: &str) -> bool {
+        self.helped.retain(|_, last| last.elapsed() < HELP_COOLDOWN);
+
+        if self.helped.contains_key(place) {
+            return false;
+        }
+
+        self.helped.insert(place.to_string(), Instant::now());
+
+        true
+    }
+
+    /// The first prefix, shown in usages
+    fn prefix(&self) -> String {
+        self.prefixes.first().cloned().unwrap_or_default()
+    }
+
+    /// Lists the commands, or shows the usage and help text of one
+    fn show_help(&self, context: &Context, name: &str) -> Result<(), Box<Error>> {
+        let prefix = self.prefix();
+
+        if name.is_empty() {
+            let mut names = self
+                .commands
+                .iter()
+                .map(|definition| format!("{}{}", prefix, definition.name))
+                .collect::<Vec<String>>();
+
+            names.push(format!("{}{}", prefix, HELP));
+
+            return context.reply(&format!("Commands: {}", names.join(", ")));
+        }
+
+        let name = name.trim_start_matches(prefix.as_str());
+
+        match self
+            .commands
+            .iter()
+            .find(|definition| definition.is_called(name))
+        {
+            Some(definition) => context.reply(&match definition.help {
+                Some(ref help) => format!("{} - {}", definition.usage(&prefix), help),
+                None => definition.usage(&prefix),
+            }),
+            None => context.reply(&format!("Unknown command {}{}", prefix, name)),
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use super::{Bouncer, VERSION};
+use crate::{
+    command::client::Command, ircd::reply, message::Message, mode::Mode, stream::ClientStream, time,
+};
+
+/// Capabilities offered to downstream clients
+const CAPABILITIES: &[&str] = &["server-time"];
+
+/// Room for the names of a `RPL_NAMREPLY` line
+const NAMES_LENGTH: usize = 400;
+
+/// A client connected to the bouncer, it gets the upstream messages once attached
+pub(super) struct Downstream {
+    pub(super) stream: ClientStream,
+    nick: Option<String>,
+    user: bool,
+    password: Option<String>,
+    /// Capabilities enabled with `CAP REQ`
+    caps: Vec<String>,
+    /// Whether registration waits for `CAP END`
+    negotiating: bool,
+    registered: bool,
+    pub(super) attached: bool,
+    pub(super) quit: bool,
+}
+
+impl Downstream {
+    pub(super) fn new(stream: ClientStream) -> Self {
+        Downstream {
+            stream,
+            nick: None,
+            user: false,
+            password: None,
+            caps: Vec::new(),
+            negotiating: false,
+            registered: false,
+            attached: false,
+            quit: false,
+        }
+    }
+
+    fn has_cap(&self, cap: &str) -> bool {
+        self.caps.iter().any(|other| other == cap)
+    }
+
+    /// Sends the message without tags, clients with `server-time` get the `time` it came with
+    /// or the current one. Replies the client parsed into their own variants go out as numerics
+    /// again.
+    pub(super) fn send(&self, message: &Message<Command>, nick: &str) {
+        let command = numeric(message.command(), nick).unwrap_or_else(|| message.command().clone());
+        let mut sent = Message::new(message.origin().clone(), command);
+
+        if self.has_cap("server-time") {
+            let time = match message.tag("time") {
+                Some(Some(time)) => time.to_string(),
+                _ => time::format_time(time::now_millis()),
+            };
+
+            sent = sent.with_tag("time", Some(&time));
+        }
+
+        // a dead connection shows up on its next read
+        let _ = self.stream.send(sent);
+    }
+}
+
+impl Bouncer {
+    pub(super) fn read_downstreams(&mut self) {
+        for index in 0..self.downstreams.len() {
+            while !self.downstreams[index].quit {
+                match self.downstreams[index].stream.read() {
+                    Ok(Some(message)) => self.handle(index, message),
+                    Ok(None) => break,
+                    Err(_) => self.downstreams[index].quit = true,
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, index: usize, message: Message<Command>) {
+        let command = message.command().clone();
+
+        match command {
+            Command::Quit { .. } => self.downstreams[index].quit = true,
+            Command::Ping { server1, .. } => {
+                let pong = Message::new(
+                    self.origin(),
+                    Command::Pong {
+                        server1: self.server_name(),
+                        server2: Some(server1),
+                    },
+                );
+
+                self.downstreams[index].send(&pong, self.current_nick());
+            }
+            Command::Pong { .. } => (),
+            Command::CapLs { .. } | Command::CapList | Command::CapReq { .. } | Command::CapEnd => {
+                self.cap(index, command)
+            }
+            _ if !self.downstreams[index].registered => self.register(index, command),
+            Command::Pass { .. } | Command::User { .. } => self.numeric(
+                index,
+                reply::ERR_ALREADYREGISTRED,
+                &["You may not reregister"],
+            ),
+            _ => self.forward(index, command),
+        }
+    }
+
+    /// Passes a command on upstream, messages also go to the other attached clients
+    fn forward(&mut self, index: usize, command: Command) {
+        let upstream = match self.upstream.client() {
+            Some(upstream) => upstream,
+            None => return self.notice(index, "Not connected to the server"),
+        };
+
+        let echo = match command {
+            Command::PrivMsg { .. } | Command::Notice { .. } => Some(Message::new(
+                upstream.myself().origin().clone(),
+                command.clone(),
+            )),
+            _ => None,
+        };
+
+        if let Err(e) = upstream.send(command) {
+            return self.notice(index, &format!("Sending to the server failed: {}", e));
+        }
+
+        if let Some(echo) = echo {
+            self.send_all(&echo, Some(index));
+        }
+    }
+
+    fn register(&mut self, index: usize, command: Command) {
+        let downstream = &mut self.downstreams[index];
+
+        match command {
+            Command::Pass { password } => downstream.password = Some(password),
+            Command::Nick { name } => downstream.nick = Some(name),
+            Command::User { .. } => downstream.user = true,
+            _ => {
+                return self.numeric(
+                    index,
+                    reply::ERR_NOTREGISTERED,
+                    &["You have not registered"],
+                )
+            }
+        }
+
+        self.try_register(index);
+    }
+
+    fn try_register(&mut self, index: usize) {
+        let downstream = &self.downstreams[index];
+
+        if downstream.nick.is_none() || !downstream.user || downstream.negotiating {
+            return;
+        }
+
+        if self.password.is_some() && downstream.password != self.password {
+            self.numeric(index, reply::ERR_PASSWDMISMATCH, &["Password incorrect"]);
+
+            let _ = self.downstreams[index].stream.send(Command::ErrorMsg {
+                text: "Closing Link: Bad password".to_string(),
+            });
+
+            self.downstreams[index].quit = true;
+
+            return;
+        }
+
+        self.downstreams[index].registered = true;
+
+        if !self.upstream.is_connected() {
+            self.notice(index, "Waiting for the server connection");
+        }
+    }
+
+    /// Attaches the registered clients once there is an upstream connection
+    pub(super) fn attach_waiting(&mut self) {
+        if !self.upstream.is_connected() {
+            return;
+        }
+
+        for index in 0..self.downstreams.len() {
+            let downstream = &self.downstreams[index];
+
+            if downstream.registered && !downstream.attached && !downstream.quit {
+                self.attach(index);
+            }
+        }
+    }
+
+    /// Sends what a client would have seen registering and joining the channels, then plays
+    /// the buffered messages back
+    fn attach(&mut self, index: usize) {
+        for message in self.burst() {
+            self.downstreams[index].send(&message, self.current_nick());
+        }
+
+        while let Some(message) = self.buffer.pop_front() {
+            self.downstreams[index].send(&message, self.current_nick());
+        }
+
+        self.downstreams[index].attached = true;
+    }
+
+    fn burst(&self) -> Vec<Message<Command>> {
+        let upstream = match self.upstream.client() {
+            Some(upstream) => upstream,
+            None => return Vec::new(),
+        };
+
+        let origin = self.origin();
+        let server = upstream.server();
+        let myself = upstream.myself();
+        let nick = upstream.nick().to_string();
+        let name = self.server_name();
+
+        let numeric = |code: u16, params: Vec<String>| {
+            let mut all = vec![nick.clone()];
+            all.extend(params);
+
+            Message::new(origin.clone(), Command::Numeric { code, params: all })
+        };
+
+        let mut burst = vec![
+            numeric(
+                reply::RPL_WELCOME,
+                vec![format!(
+                    "Welcome to the Internet Relay Network {}",
+                    &myself.origin().to_string()[1..]
+                )],
+            ),
+            numeric(
+                reply::RPL_YOURHOST,
+                vec![format!(
+                    "Your host is {}, running version {}",
+                    name, VERSION
+                )],
+            ),
+            numeric(reply::RPL_MYINFO, vec![name.clone(), VERSION.to_string()]),
+            numeric(
+                reply::RPL_ISUPPORT,
+                vec![
+                    format!("CASEMAPPING={}", server.casemapping().name()),
+                    "PREFIX=(ov)@+".to_string(),
+                    "are supported by this server".to_string(),
+                ],
+            ),
+        ];
+
+        match server.motd() {
+            Some(motd) => {
+                burst.push(numeric(
+                    reply::RPL_MOTDSTART,
+                    vec![format!("- {} Message of the day - ", name)],
+                ));
+
+                for line in motd.lines() {
+                    burst.push(numeric(reply::RPL_MOTD, vec![format!("- {}", line)]));
+                }
+
+                burst.push(numeric(
+                    reply::RPL_ENDOFMOTD,
+                    vec!["End of MOTD command".to_string()],
+                ));
+            }
+            None => burst.push(numeric(
+                reply::ERR_NOMOTD,
+                vec!["MOTD File is missing".to_string()],
+            )),
+        }
+
+        if !myself.modes().is_empty() {
+            burst.push(numeric(
+                reply::RPL_UMODEIS,
+                vec![Mode::to_list_string(myself.modes())],
+            ));
+        }
+
+        for channel in server.channels() {
+            let channel = channel.borrow();
+            let name = channel.name().to_string();
+
+            burst.push(Message::new(
+                myself.origin().clone(),
+                Command::Join {
+                    channels: vec![name.clone()],
+                    keys: Vec::new(),
+                },
+            ));
+
+            if let Some(topic) = channel.topic() {
+                burst.push(numeric(
+                    reply::RPL_TOPIC,
+                    vec![name.clone(), topic.to_string()],
+                ));
+
+                match channel.topic_setter() {
+                    Some(setter) if !setter.is_empty() => burst.push(numeric(
+                        reply::RPL_TOPICWHOTIME,
+                        vec![
+                            name.clone(),
+                            setter.to_string(),
+                            channel.topic_time().to_string(),
+                        ],
+                    )),
+                    _ => (),
+                }
+            }
+
+            let names = channel
+                .members()
+                .iter()
+                .filter_map(|member| {
+                    let user = member.user().upgrade()?;
+                    let user = user.borrow();
+                    let nick = user.origin().nick()?;
+
+                    Some(match member.prefix() {
+                        Some(prefix) => format!("{}{}", prefix, nick),
+                        None => nick.to_string(),
+                    })
+                })
+                .collect::<Vec<String>>();
+
+            let mut line = String::new();
+
+            for member in names {
+                if !line.is_empty() && line.len() + member.len() >= NAMES_LENGTH {
+                    burst.push(numeric(
+                        reply::RPL_NAMREPLY,
+                        vec!["=".to_string(), name.clone(), line],
+                    ));
+
+                    line = String::new();
+                }
+
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+
+                line.push_str(&member);
+            }
+
+            if !line.is_empty() {
+                burst.push(numeric(
+                    reply::RPL_NAMREPLY,
+                    vec!["=".to_string(), name.clone(), line],
+                ));
+            }
+
+            burst.push(numeric(
+                reply::RPL_ENDOFNAMES,
+                vec![name, "End of /NAMES list.".to_string()],
+            ));
+        }
+
+        burst
+    }
+
+    /// `CAP` negotiation, registration waits for `CAP END` once it started
+    fn cap(&mut self, index: usize, command: Command) {
+        let registered = self.downstreams[index].registered;
+        let downstream = &mut self.downstreams[index];
+
+        let reply = match command {
+            Command::CapLs { .. } => {
+                downstream.negotiating = !registered;
+
+                Command::CapLsReply {
+                    caps: CAPABILITIES.iter().map(|cap| cap.to_string()).collect(),
+                    more: false,
+                }
+            }
+            Command::CapList => Command::CapListReply {
+                caps: downstream.caps.clone(),
+                more: false,
+            },
+            Command::CapReq { caps } => {
+                downstream.negotiating = !registered;
+
+                // all or nothing
+                if !caps
+                    .iter()
+                    .all(|cap| CAPABILITIES.contains(&cap.trim_start_matches('-')))
+                {
+                    Command::CapNak { caps }
+                } else {
+                    for cap in caps.iter() {
+                        if cap.starts_with('-') {
+                            downstream.caps.retain(|other| other != &cap[1..]);
+                        } else if !downstream.has_cap(cap) {
+                            downstream.caps.push(cap.to_string());
+                        }
+                    }
+
+                    Command::CapAck { caps }
+                }
+            }
+            _ => {
+                downstream.negotiating = false;
+
+                return self.try_register(index);
+            }
+        };
+
+        let reply = Message::new(self.origin(), reply);
+
+        self.downstreams[index].send(&reply, self.current_nick());
+    }
+
+    /// Sends a numeric reply with the nick (or `*`) in front of the parameters
+    fn numeric(&self, index: usize, code: u16, params: &[&str]) {
+        let target = if self.downstreams[index].registered {
+            self.current_nick().to_string()
+        } else {
+            self.downstreams[index]
+                .nick
+                .clone()
+                .unwrap_or_else(|| "*".to_string())
+        };
+
+        let mut all = vec![target];
+        all.extend(params.iter().map(|param| param.to_string()));
+
+        let message = Message::new(self.origin(), Command::Numeric { code, params: all });
+
+        self.downstreams[index].send(&message, self.current_nick());
+    }
+}
+
+/// Replies the client parses into their own variants, as the numerics they came as
+fn numeric(command: &Command, nick: &str) -> Option<Command> {
+    let (code, params) = match command {
+        Command::Welcome { nick, text } => {
+            return Some(Command::Numeric {
+                code: reply::RPL_WELCOME,
+                params: vec![nick.to_string(), text.to_string()],
+            });
+        }
+        Command::UModeIs { modes } => (reply::RPL_UMODEIS, vec![Mode::to_list_string(modes)]),
+        Command::IsOnReply { nicks } => (reply::RPL_ISON, vec![nicks.join(" ")]),
+        Command::WhoReply {
+            channel,
+            user,
+            host,
+            server,
+            nick,
+            flags,
+            hops,
+            real_name,
+        } => (
+            reply::RPL_WHOREPLY,
+            vec![
+                channel.to_string(),
+                user.to_string(),
+                host.to_string(),
+                server.to_string(),
+                nick.to_string(),
+                flags.to_string(),
+                format!("{} {}", hops, real_name),
+            ],
+        ),
+        Command::VisibleHost { host } => (
+            reply::RPL_VISIBLEHOST,
+            vec![host.to_string(), "is now your displayed host".to_string()],
+        ),
+        Command::MotdStart => (
+            reply::RPL_MOTDSTART,
+            vec!["- Message of the day - ".to_string()],
+        ),
+        Command::MotdBody { text } => (reply::RPL_MOTD, vec![format!("- {}", text)]),
+        Command::MotdEnd => (
+            reply::RPL_ENDOFMOTD,
+            vec!["End of MOTD command".to_string()],
+        ),
+        Command::NoMotd => (reply::ERR_NOMOTD, vec!["MOTD File is missing".to_string()]),
+        Command::ErroneusNickname { nick } => (
+            reply::ERR_ERRONEUSNICKNAME,
+            vec![nick.to_string(), "Erroneous nickname".to_string()],
+        ),
+        Command::NicknameInUse { nick } => (
+            reply::ERR_NICKNAMEINUSE,
+            vec![nick.to_string(), "Nickname is already in use".to_string()],
+        ),
+        Command::NickCollision { nick } => (
+            reply::ERR_NICKCOLLISION,
+            vec![nick.to_string(), "Nickname collision KILL".to_string()],
+        ),
+        Command::UnavailResource { name } => (
+            reply::ERR_UNAVAILRESOURCE,
+            vec![
+                name.to_string(),
+                "Nick/channel is temporarily unavailable".to_string(),
+            ],
+        ),
+        Command::PasswdMismatch => (
+            reply::ERR_PASSWDMISMATCH,
+            vec!["Password incorrect".to_string()],
+        ),
+        Command::YoureBannedCreep { text } => (reply::ERR_YOUREBANNEDCREEP, vec![text.to_string()]),
+        Command::MonOnline { targets } => (reply::RPL_MONONLINE, vec![targets.join(",")]),
+        Command::MonOffline { targets } => (reply::RPL_MONOFFLINE, vec![targets.join(",")]),
+        _ => return None,
+    };
+
+    let mut all = vec![nick.to_string()];
+    all.extend(params);
+
+    Some(Command::Numeric { code, params: all })
+}
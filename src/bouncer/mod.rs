@@ -0,0 +1,367 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    command::client::Command,
+    connection::client::{Builder as ClientBuilder, Client},
+    manager::{Connect, Event, Session, POLL_INTERVAL},
+    message::Message,
+    origin::Origin,
+    stream::{Accept, ClientStream},
+    target::MessageTarget,
+    time,
+};
+
+use self::downstream::Downstream;
+
+mod downstream;
+
+pub mod error {
+    impl_error!(NoListenerError {});
+    impl_error!(NoUpstreamError {});
+}
+
+/// Server name downstream clients see while there is no upstream connection
+pub const DEFAULT_NAME: &str = "bouncer.localhost";
+
+/// Name of the session of the upstream connection
+const UPSTREAM: &str = "upstream";
+
+pub const VERSION: &str = concat!("np1th-irc-bouncer-", env!("CARGO_PKG_VERSION"));
+
+/// Messages kept for playback while no client is attached
+pub const DEFAULT_BUFFER: usize = 500;
+
+#[derive(Default)]
+pub struct Builder {
+    addresses: Vec<String>,
+    password: Option<String>,
+    buffer: Option<usize>,
+    reconnect_delay: Option<Duration>,
+    connect: Option<Connect>,
+}
+
+impl Builder {
+    /// Address downstream clients connect to like `127.0.0.1:6667`, may be called several times
+    pub fn listen(mut self, address: &str) -> Self {
+        self.addresses.push(address.to_string());
+
+        self
+    }
+
+    /// Password downstream clients have to send with `PASS`
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+
+        self
+    }
+
+    /// How many messages are kept for playback, defaults to `DEFAULT_BUFFER`
+    pub fn buffer(mut self, limit: usize) -> Self {
+        self.buffer = Some(limit);
+
+        self
+    }
+
+    /// Defaults to `manager::RECONNECT_DELAY`
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = Some(delay);
+
+        self
+    }
+
+    /// Sets up the upstream `Client`, like `|| Client::builder().host(..).user(..)`
+    pub fn upstream<F>(mut self, connect: F) -> Self
+    where
+        F: FnMut() -> ClientBuilder + 'static,
+    {
+        self.connect = Some(Box::new(connect));
+
+        self
+    }
+
+    /// Binds the listeners, connecting upstream starts with the first poll
+    pub fn build(self) -> Result<Bouncer, Box<Error>> {
+        let connect = match self.connect {
+            Some(connect) => connect,
+            None => return Err(error::NoUpstreamError::new()),
+        };
+
+        let mut listeners = Vec::new();
+
+        for address in self.addresses {
+            let listener = TcpListener::bind(&address)?;
+            listener.set_nonblocking(true)?;
+
+            listeners.push(listener);
+        }
+
+        if listeners.is_empty() {
+            return Err(error::NoListenerError::new());
+        }
+
+        let mut upstream = Session::new(UPSTREAM, connect);
+
+        if let Some(delay) = self.reconnect_delay {
+            upstream = upstream.reconnect_delay(delay);
+        }
+
+        Ok(Bouncer {
+            upstream,
+            nick: String::new(),
+            listeners,
+            downstreams: Vec::new(),
+            password: self.password,
+            buffer: VecDeque::new(),
+            buffer_limit: self.buffer.unwrap_or(DEFAULT_BUFFER),
+        })
+    }
+}
+
+/// Keeps one connection to a network and lets any number of clients share it. Messages arriving
+/// while no client is attached are played back to the next one.
+pub struct Bouncer {
+    /// Connects again after losing the connection, rejoining its channels
+    upstream: Session,
+    /// Nick of the last upstream connection, attached clients go by it until the next one
+    nick: String,
+    listeners: Vec<TcpListener>,
+    downstreams: Vec<Downstream>,
+    password: Option<String>,
+    buffer: VecDeque<Message<Command>>,
+    buffer_limit: usize,
+}
+
+impl Bouncer {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Addresses actually bound, useful with port `0`
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect()
+    }
+
+    /// `None` while connecting
+    pub fn upstream(&self) -> Option<&Client> {
+        self.upstream.client()
+    }
+
+    /// Number of downstream clients which get the upstream messages
+    pub fn attached(&self) -> usize {
+        self.downstreams
+            .iter()
+            .filter(|downstream| downstream.attached)
+            .count()
+    }
+
+    /// Messages waiting for the next client to attach
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<Error>> {
+        loop {
+            self.poll()?;
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Accepts new clients, connects upstream if needed and passes the messages read on
+    pub fn poll(&mut self) -> Result<(), Box<Error>> {
+        self.accept();
+        self.read_upstream();
+        self.read_downstreams();
+        self.attach_waiting();
+        self.reap();
+
+        Ok(())
+    }
+}
+
+impl Bouncer {
+    fn accept(&mut self) {
+        for listener in self.listeners.iter() {
+            loop {
+                let tcp_stream = match listener.accept() {
+                    Ok((tcp_stream, _)) => tcp_stream,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => break,
+                };
+
+                // without TLS, accepting is done right away
+                if let Ok(Accept::Done(stream)) = ClientStream::accept(tcp_stream, None) {
+                    self.downstreams.push(Downstream::new(stream));
+                }
+            }
+        }
+    }
+
+    fn connected(&mut self) {
+        let nick = match self.upstream.client() {
+            Some(upstream) => upstream.nick().to_string(),
+            None => return,
+        };
+
+        // attached clients still go by the old nick
+        if !self.nick.is_empty() && nick != self.nick {
+            let message = Message::new(
+                Origin::User {
+                    nick: self.nick.clone(),
+                    user: None,
+                    host: None,
+                },
+                Command::Nick { name: nick.clone() },
+            );
+
+            self.send_all(&message, None);
+        }
+
+        self.nick = nick;
+
+        self.notice_all("Connected to the server");
+    }
+
+    fn read_upstream(&mut self) {
+        let mut events = Vec::new();
+
+        self.upstream.poll(&mut events);
+
+        for event in events {
+            match event {
+                Event::Connected { .. } => self.connected(),
+                Event::ConnectFailed { reason, .. } => {
+                    self.notice_all(&format!("Connecting to the server failed: {}", reason))
+                }
+                Event::Disconnected { reason, .. } => {
+                    self.notice_all(&format!("Disconnected from the server: {}", reason))
+                }
+                Event::Message { message, .. } => self.relay(*message),
+            }
+        }
+    }
+
+    /// Passes an upstream message on to the attached clients, or keeps it for playback if it's
+    /// a message for us and none is attached
+    fn relay(&mut self, message: Message<Command>) {
+        // the connection may be gone by now, the next one goes by this nick again
+        if let Command::Nick { name } = message.command() {
+            if message.origin().nick() == Some(self.nick.as_str()) {
+                self.nick = name.to_string();
+            }
+        }
+
+        match message.command() {
+            // the client takes care of these
+            Command::Ping { .. }
+            | Command::CapLsReply { .. }
+            | Command::CapListReply { .. }
+            | Command::CapAck { .. }
+            | Command::CapNak { .. }
+            | Command::CapNew { .. }
+            | Command::CapDel { .. } => return,
+            _ => (),
+        }
+
+        if self.attached() > 0 {
+            return self.send_all(&message, None);
+        }
+
+        let keep = match message.command() {
+            Command::PrivMsg { .. } | Command::Notice { .. } => message.origin().is_user(),
+            _ => false,
+        };
+
+        if keep && self.buffer_limit > 0 {
+            let message = match message.tag("time") {
+                Some(Some(_)) => message,
+                _ => message.with_tag("time", Some(&time::format_time(time::now_millis()))),
+            };
+
+            while self.buffer.len() >= self.buffer_limit {
+                self.buffer.pop_front();
+            }
+
+            self.buffer.push_back(message);
+        }
+    }
+
+    /// Sends the message to the attached clients except the one at `except`
+    fn send_all(&self, message: &Message<Command>, except: Option<usize>) {
+        let nick = self.current_nick();
+
+        for (index, downstream) in self.downstreams.iter().enumerate() {
+            if downstream.attached && Some(index) != except {
+                downstream.send(message, nick);
+            }
+        }
+    }
+
+    fn notice_all(&self, text: &str) {
+        for index in 0..self.downstreams.len() {
+            if self.downstreams[index].attached {
+                self.notice(index, text);
+            }
+        }
+    }
+
+    fn notice(&self, index: usize, text: &str) {
+        let message = Message::new(
+            self.origin(),
+            Command::Notice {
+                target: MessageTarget::Nick(self.current_nick().to_string()),
+                text: text.to_string(),
+            },
+        );
+
+        self.downstreams[index].send(&message, self.current_nick());
+    }
+
+    /// The upstream server, as far as downstream clients are concerned
+    fn origin(&self) -> Origin {
+        match self.upstream.client() {
+            Some(upstream) if upstream.server().origin().is_server() => {
+                upstream.server().origin().clone()
+            }
+            _ => Origin::Server {
+                name: DEFAULT_NAME.to_string(),
+            },
+        }
+    }
+
+    fn server_name(&self) -> String {
+        match self.origin() {
+            Origin::Server { name } => name,
+            _ => DEFAULT_NAME.to_string(),
+        }
+    }
+
+    fn current_nick(&self) -> &str {
+        match self.upstream.client() {
+            Some(upstream) => upstream.nick(),
+            None => &self.nick,
+        }
+    }
+
+    fn reap(&mut self) {
+        let mut index = 0;
+
+        while index < self.downstreams.len() {
+            if self.downstreams[index].quit {
+                self.downstreams.remove(index).stream.close();
+            } else {
+                index += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,857 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    casemapping::CaseMapping,
+    command::client::Command,
+    connection::client::Builder,
+    ctcp::Ctcp,
+    manager::{Event, Manager, Session, POLL_INTERVAL},
+    message::Message,
+    origin::Mask,
+    target::MessageTarget,
+};
+
+use self::outbox::{Kind, Line, Outbox};
+
+mod outbox;
+
+/// How the relay shows remote users, `{nick}` and `{network}` are replaced
+pub const DEFAULT_FORMAT: &str = "<{nick}@{network}>";
+
+/// Nick of the puppet of a remote user, same placeholders as the format
+pub const DEFAULT_PUPPET_NICK: &str = "{nick}|{network}";
+
+/// At most this many lines per connection within `RATE_WINDOW`
+pub const RATE_LIMIT: usize = 4;
+pub const RATE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Lines waiting per connection, the oldest are dropped beyond
+pub const QUEUE_LIMIT: usize = 100;
+
+const RPL_NAMREPLY: u16 = 353;
+
+/// Sets up the client of the puppet of a remote user, gets the nick to use
+pub type Connect = Box<FnMut(&str) -> Builder>;
+
+/// A channel on one of the networks of the manager
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    network: String,
+    channel: String,
+}
+
+impl Endpoint {
+    pub fn new(network: &str, channel: &str) -> Self {
+        Endpoint {
+            network: network.to_string(),
+            channel: channel.to_string(),
+        }
+    }
+
+    pub fn network(&self) -> &str {
+        self.network.as_str()
+    }
+
+    pub fn channel(&self) -> &str {
+        self.channel.as_str()
+    }
+}
+
+/// The connection a remote user speaks through on one network
+struct Puppet {
+    network: String,
+    channels: Vec<String>,
+    /// Channels it was kicked from or left on its own, the relay speaks for it there
+    refused: Vec<String>,
+}
+
+/// Mirrors messages, notices, actions, joins, parts, kicks, nick changes and quits between linked
+/// channels of the networks of a `Manager`
+///
+/// The relay connections of the manager say what remote users did, like `<alice@libera> hi`.
+/// Networks with puppets get a connection per remote user instead, which joins, speaks and
+/// leaves as the user does, a kick makes it leave too. Where a puppet gets kicked itself, the
+/// relay speaks for the user again. Nothing said by the relay, its puppets or ignored users is
+/// mirrored, so two channels linked both ways don't echo each other.
+pub struct Bridge {
+    links: Vec<(Endpoint, Endpoint)>,
+    format: String,
+    puppet_nick: String,
+    connectors: HashMap<String, Rc<RefCell<Connect>>>,
+    /// By the name of their session in the manager
+    puppets: HashMap<String, Puppet>,
+    ignored: Vec<String>,
+    /// Nicks on the linked channels, to know where users quit
+    members: HashMap<(String, String), Vec<String>>,
+    rate_limit: usize,
+    rate_window: Duration,
+    /// By the name of the session sending them
+    outboxes: HashMap<String, Outbox>,
+}
+
+impl Default for Bridge {
+    fn default() -> Self {
+        Bridge {
+            links: Vec::new(),
+            format: DEFAULT_FORMAT.to_string(),
+            puppet_nick: DEFAULT_PUPPET_NICK.to_string(),
+            connectors: HashMap::new(),
+            puppets: HashMap::new(),
+            ignored: Vec::new(),
+            members: HashMap::new(),
+            rate_limit: RATE_LIMIT,
+            rate_window: RATE_WINDOW,
+            outboxes: HashMap::new(),
+        }
+    }
+}
+
+impl Bridge {
+    pub fn new() -> Self {
+        Bridge::default()
+    }
+
+    /// Mirrors both channels into each other, may be called several times. Links don't chain,
+    /// each channel only gets what its own partners said.
+    pub fn link(mut self, one: Endpoint, other: Endpoint) -> Self {
+        self.links.push((one, other));
+
+        self
+    }
+
+    /// Defaults to `DEFAULT_FORMAT`
+    pub fn format(mut self, format: &str) -> Self {
+        self.format = format.to_string();
+
+        self
+    }
+
+    /// Gives remote users their own connection on `network`, like
+    /// `|nick| Client::builder().host(..).user(User::new(.., nick ..))`
+    pub fn puppets<F>(mut self, network: &str, connect: F) -> Self
+    where
+        F: FnMut(&str) -> Builder + 'static,
+    {
+        self.connectors.insert(
+            network.to_string(),
+            Rc::new(RefCell::new(Box::new(connect))),
+        );
+
+        self
+    }
+
+    /// Defaults to `DEFAULT_PUPPET_NICK`
+    pub fn puppet_nick(mut self, format: &str) -> Self {
+        self.puppet_nick = format.to_string();
+
+        self
+    }
+
+    /// Never mirrors users matching the mask, like other relays
+    pub fn ignore(mut self, mask: &str) -> Self {
+        self.ignored.push(mask.to_string());
+
+        self
+    }
+
+    /// At most `limit` lines per connection within `window`, the rest waits
+    pub fn rate_limit(mut self, limit: usize, window: Duration) -> Self {
+        self.rate_limit = limit;
+        self.rate_window = window;
+
+        self
+    }
+}
+
+impl Bridge {
+    pub fn links(&self) -> &Vec<(Endpoint, Endpoint)> {
+        &self.links
+    }
+
+    /// Lines waiting for the rate limit or for a puppet to connect
+    pub fn queued(&self) -> usize {
+        self.outboxes
+            .values()
+            .map(|outbox| outbox.waiting.len())
+            .sum()
+    }
+
+    /// Polls the manager and mirrors what happens until something fails
+    pub fn run(&mut self, manager: &mut Manager) -> Result<(), Box<Error>> {
+        loop {
+            match manager.poll() {
+                Some(event) => self.handle(manager, &event)?,
+                None => thread::sleep(POLL_INTERVAL),
+            }
+
+            self.flush(manager)?;
+        }
+    }
+
+    /// Queues what the event means for the linked channels, `flush` sends it
+    pub fn handle(&mut self, manager: &mut Manager, event: &Event) -> Result<(), Box<Error>> {
+        match event {
+            // the puppets only speak, unless they're kicked
+            Event::Message { network, message } if self.puppets.contains_key(network) => {
+                self.refused(manager, network, message);
+
+                Ok(())
+            }
+            Event::Message { network, message } => self.mirror(manager, network, message),
+            Event::Disconnected { network, .. } | Event::ConnectFailed { network, .. }
+                if self.puppets.contains_key(network) =>
+            {
+                self.drop_puppet(manager, network);
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends the waiting lines the rate limit allows
+    pub fn flush(&mut self, manager: &mut Manager) -> Result<(), Box<Error>> {
+        let mut quit = Vec::new();
+
+        for (session, outbox) in self.outboxes.iter_mut() {
+            let client = match manager.client(session) {
+                Some(client) => client,
+                None => continue,
+            };
+
+            let puppet = self.puppets.contains_key(session);
+
+            if outbox.flush(client, puppet, self.rate_limit, self.rate_window)? {
+                quit.push(session.clone());
+            }
+        }
+
+        for session in quit {
+            self.puppets.remove(&session);
+            self.outboxes.remove(&session);
+            manager.remove(&session);
+        }
+
+        Ok(())
+    }
+}
+
+impl Bridge {
+    fn mirror(
+        &mut self,
+        manager: &mut Manager,
+        network: &str,
+        message: &Message<Command>,
+    ) -> Result<(), Box<Error>> {
+        let casemapping = match manager.client(network) {
+            Some(client) => client.server().casemapping(),
+            None => return Ok(()),
+        };
+
+        let nick = match message.origin().nick() {
+            Some(nick) => nick.to_string(),
+            None => return self.names(network, casemapping, message),
+        };
+
+        if self.is_ours(manager, network, casemapping, message) {
+            return Ok(());
+        }
+
+        match message.command() {
+            Command::PrivMsg { targets, text } => {
+                let kind = match Ctcp::parse(text) {
+                    Some(Ctcp::Action(text)) => Kind::Action(text),
+                    Some(_) => return Ok(()),
+                    None => Kind::Message(text.to_string()),
+                };
+
+                for channel in targets.iter().filter_map(channel_name) {
+                    self.relay(manager, network, channel, &nick, kind.clone())?;
+                }
+            }
+
+            Command::Notice { target, text } if Ctcp::parse(text).is_none() => {
+                if let Some(channel) = channel_name(target) {
+                    self.relay(manager, network, channel, &nick, Kind::Notice(text.clone()))?;
+                }
+            }
+
+            Command::Join { channels, .. } => {
+                for channel in channels {
+                    self.joined(network, channel, &nick, casemapping);
+                    self.relay(manager, network, channel, &nick, Kind::Join)?;
+                }
+            }
+
+            Command::ExtendedJoin { channel, .. } => {
+                self.joined(network, channel, &nick, casemapping);
+                self.relay(manager, network, channel, &nick, Kind::Join)?;
+            }
+
+            Command::Part { channels, reason } => {
+                for channel in channels {
+                    self.left(network, channel, &nick, casemapping);
+                    self.relay(manager, network, channel, &nick, Kind::Part(reason.clone()))?;
+                }
+            }
+
+            Command::Kick {
+                channels,
+                users,
+                reason,
+            } => {
+                let reason = match reason {
+                    Some(reason) if !reason.is_empty() => format!("Kicked by {}: {}", nick, reason),
+                    _ => format!("Kicked by {}", nick),
+                };
+
+                for (pos, kicked) in users.iter().enumerate() {
+                    if let Some(channel) = channels.get(pos).or_else(|| channels.first()) {
+                        self.left(network, channel, kicked, casemapping);
+
+                        // the puppets of other networks leave by themselves
+                        if !self.is_us(manager, network, casemapping, kicked) {
+                            let kind = Kind::Part(Some(reason.clone()));
+
+                            self.relay(manager, network, channel, kicked, kind)?;
+                        }
+                    }
+                }
+            }
+
+            Command::Nick { name } => {
+                let mut channels = Vec::new();
+
+                for ((other, channel), nicks) in self.members.iter_mut() {
+                    if other == network && nicks.iter().any(|m| casemapping.eq(m, &nick)) {
+                        for member in nicks.iter_mut() {
+                            if casemapping.eq(member, &nick) {
+                                *member = name.to_string();
+                            }
+                        }
+
+                        channels.push(channel.clone());
+                    }
+                }
+
+                self.renamed(manager, network, &channels, &nick, name)?;
+            }
+
+            Command::Quit { reason } => {
+                let mut channels = Vec::new();
+
+                for ((other, channel), nicks) in self.members.iter_mut() {
+                    if other == network && nicks.iter().any(|m| casemapping.eq(m, &nick)) {
+                        nicks.retain(|member| !casemapping.eq(member, &nick));
+                        channels.push(channel.clone());
+                    }
+                }
+
+                self.quit(manager, network, &channels, &nick, reason)?;
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Our own connections, their puppets and ignored users
+    fn is_ours(
+        &self,
+        manager: &Manager,
+        network: &str,
+        casemapping: CaseMapping,
+        message: &Message<Command>,
+    ) -> bool {
+        let nick = message.origin().nick().unwrap_or_default();
+
+        self.is_us(manager, network, casemapping, nick)
+            || self.ignored.iter().any(|mask| {
+                Mask::new(mask)
+                    .casemapping(casemapping)
+                    .matches(message.origin())
+            })
+    }
+
+    /// Whether `nick` is the relay or one of its puppets on `network`
+    fn is_us(
+        &self,
+        manager: &Manager,
+        network: &str,
+        casemapping: CaseMapping,
+        nick: &str,
+    ) -> bool {
+        let connected_as = |session: &str| {
+            manager
+                .client(session)
+                .is_some_and(|client| casemapping.eq(client.nick(), nick))
+        };
+
+        connected_as(network)
+            || self
+                .puppets
+                .iter()
+                .any(|(session, puppet)| puppet.network == network && connected_as(session))
+    }
+
+    /// Remembers the members of linked channels listed in `NAMES` replies
+    fn names(
+        &mut self,
+        network: &str,
+        casemapping: CaseMapping,
+        message: &Message<Command>,
+    ) -> Result<(), Box<Error>> {
+        if let Command::Numeric { code, params } = message.command() {
+            if let (RPL_NAMREPLY, [_, _, channel, names]) = (*code, params.as_slice()) {
+                for name in names.split_whitespace() {
+                    let nick = name.trim_start_matches(|c| "~&@%+".contains(c));
+                    let nick = nick.split('!').next().unwrap_or(nick);
+
+                    self.joined(network, channel, nick, casemapping);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn joined(&mut self, network: &str, channel: &str, nick: &str, casemapping: CaseMapping) {
+        if !self.is_linked(network, channel, casemapping) {
+            return;
+        }
+
+        let nicks = self
+            .members
+            .entry((network.to_string(), casemapping.to_lower(channel)))
+            .or_default();
+
+        if !nicks.iter().any(|member| casemapping.eq(member, nick)) {
+            nicks.push(nick.to_string());
+        }
+    }
+
+    fn left(&mut self, network: &str, channel: &str, nick: &str, casemapping: CaseMapping) {
+        let key = (network.to_string(), casemapping.to_lower(channel));
+
+        if let Some(nicks) = self.members.get_mut(&key) {
+            nicks.retain(|member| !casemapping.eq(member, nick));
+        }
+    }
+
+    fn is_linked(&self, network: &str, channel: &str, casemapping: CaseMapping) -> bool {
+        self.links.iter().any(|(one, other)| {
+            [one, other]
+                .iter()
+                .any(|end| end.network == network && casemapping.eq(&end.channel, channel))
+        })
+    }
+
+    /// The channels linked to `channel` on `network`
+    fn partners(&self, network: &str, channel: &str, casemapping: CaseMapping) -> Vec<Endpoint> {
+        let matches =
+            |end: &Endpoint| end.network == network && casemapping.eq(&end.channel, channel);
+
+        self.links
+            .iter()
+            .filter_map(|(one, other)| {
+                if matches(one) {
+                    Some(other.clone())
+                } else if matches(other) {
+                    Some(one.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn label(&self, format: &str, nick: &str, network: &str) -> String {
+        format.replace("{nick}", nick).replace("{network}", network)
+    }
+
+    /// Queues the line for every partner of the channel
+    fn relay(
+        &mut self,
+        manager: &mut Manager,
+        network: &str,
+        channel: &str,
+        nick: &str,
+        kind: Kind,
+    ) -> Result<(), Box<Error>> {
+        let casemapping = match manager.client(network) {
+            Some(client) => client.server().casemapping(),
+            None => return Ok(()),
+        };
+
+        for to in self.partners(network, channel, casemapping) {
+            let line = Line {
+                channel: to.channel.clone(),
+                label: self.label(&self.format, nick, network),
+                kind: kind.clone(),
+            };
+
+            if !self.connectors.contains_key(&to.network) {
+                self.push(&to.network, line);
+                continue;
+            }
+
+            let session = puppet_session(&to.network, &casemapping.to_lower(nick), network);
+
+            if self.refuses(&session, &to.channel) {
+                self.push(&to.network, line);
+                continue;
+            }
+
+            let (exists, joined) = match self.puppets.get(&session) {
+                Some(puppet) => (true, puppet.channels.contains(&to.channel)),
+                None => (false, false),
+            };
+
+            match line.kind {
+                // nothing to leave
+                Kind::Part(_) if !joined => continue,
+
+                // puppets on no channel anymore quit
+                Kind::Part(_) => {
+                    let empty = match self.puppets.get_mut(&session) {
+                        Some(puppet) => {
+                            puppet.channels.retain(|other| *other != to.channel);
+                            puppet.channels.is_empty()
+                        }
+                        None => false,
+                    };
+
+                    self.push(&session, line.clone());
+
+                    if empty {
+                        self.push(
+                            &session,
+                            Line {
+                                kind: Kind::Quit(None),
+                                ..line
+                            },
+                        );
+                    }
+
+                    continue;
+                }
+
+                // new puppets join when they connect
+                _ if !exists => {
+                    self.spawn(manager, &session, &to, nick, network)?;
+
+                    if line.kind == Kind::Join {
+                        continue;
+                    }
+                }
+
+                Kind::Join if joined => continue,
+
+                _ if !joined => {
+                    if let Some(puppet) = self.puppets.get_mut(&session) {
+                        puppet.channels.push(to.channel.clone());
+                    }
+
+                    if line.kind != Kind::Join {
+                        self.push(
+                            &session,
+                            Line {
+                                kind: Kind::Join,
+                                ..line.clone()
+                            },
+                        );
+                    }
+                }
+
+                _ => (),
+            }
+
+            self.push(&session, line);
+        }
+
+        Ok(())
+    }
+
+    /// Puppets quit once, the relay says so in every partner channel
+    fn quit(
+        &mut self,
+        manager: &mut Manager,
+        network: &str,
+        channels: &[String],
+        nick: &str,
+        reason: &Option<String>,
+    ) -> Result<(), Box<Error>> {
+        let mut quitting = HashSet::new();
+
+        for channel in channels {
+            let casemapping = match manager.client(network) {
+                Some(client) => client.server().casemapping(),
+                None => return Ok(()),
+            };
+
+            for to in self.partners(network, channel, casemapping) {
+                let line = Line {
+                    channel: to.channel.clone(),
+                    label: self.label(&self.format, nick, network),
+                    kind: Kind::Quit(reason.clone()),
+                };
+
+                if !self.connectors.contains_key(&to.network) {
+                    self.push(&to.network, line);
+                    continue;
+                }
+
+                let session = puppet_session(&to.network, &casemapping.to_lower(nick), network);
+
+                if self.refuses(&session, &to.channel) {
+                    self.push(&to.network, line.clone());
+                }
+
+                if self.puppets.contains_key(&session) && quitting.insert(session.clone()) {
+                    self.push(&session, line);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Puppets take the new nick once, the relay says so in every partner channel
+    fn renamed(
+        &mut self,
+        manager: &mut Manager,
+        network: &str,
+        channels: &[String],
+        nick: &str,
+        name: &str,
+    ) -> Result<(), Box<Error>> {
+        let casemapping = match manager.client(network) {
+            Some(client) => client.server().casemapping(),
+            None => return Ok(()),
+        };
+
+        let mut renamed = HashSet::new();
+
+        for channel in channels {
+            for to in self.partners(network, channel, casemapping) {
+                let session = puppet_session(&to.network, &casemapping.to_lower(nick), network);
+
+                let relayed = !self.connectors.contains_key(&to.network);
+
+                // a kicked puppet still takes the nick, the relay tells the channel
+                if relayed || self.refuses(&session, &to.channel) {
+                    let line = Line {
+                        channel: to.channel.clone(),
+                        label: self.label(&self.format, nick, network),
+                        kind: Kind::Nick(self.label(&self.format, name, network)),
+                    };
+
+                    self.push(&to.network, line);
+
+                    if relayed {
+                        continue;
+                    }
+                }
+                let renamed_session =
+                    puppet_session(&to.network, &casemapping.to_lower(name), network);
+
+                if !self.puppets.contains_key(&session) || !renamed.insert(session.clone()) {
+                    continue;
+                }
+
+                let nick_kind = Kind::Nick(self.label(&self.puppet_nick, name, network));
+
+                // only the case changed
+                let (target, kind) = if session == renamed_session {
+                    (session, nick_kind)
+                } else {
+                    match manager.rename(&session, &renamed_session) {
+                        Ok(()) => {
+                            self.rekey(&session, &renamed_session);
+
+                            (renamed_session, nick_kind)
+                        }
+
+                        // the new nick has a puppet already, this one makes way
+                        Err(_) => (session, Kind::Quit(None)),
+                    }
+                };
+
+                let line = Line {
+                    channel: to.channel.clone(),
+                    label: self.label(&self.format, nick, network),
+                    kind,
+                };
+
+                self.push(&target, line);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Moves a puppet and its waiting lines to the name of its renamed session
+    fn rekey(&mut self, session: &str, renamed: &str) {
+        if let Some(puppet) = self.puppets.remove(session) {
+            self.puppets.insert(renamed.to_string(), puppet);
+        }
+
+        if let Some(outbox) = self.outboxes.remove(session) {
+            self.outboxes.insert(renamed.to_string(), outbox);
+        }
+    }
+
+    /// Adds the session of a new puppet to the manager, it joins `to` once connected
+    fn spawn(
+        &mut self,
+        manager: &mut Manager,
+        session: &str,
+        to: &Endpoint,
+        nick: &str,
+        network: &str,
+    ) -> Result<(), Box<Error>> {
+        let connect = self.connectors[&to.network].clone();
+        let puppet_nick = self.label(&self.puppet_nick, nick, network);
+
+        manager.add(
+            Session::new(session, move || (connect.borrow_mut())(&puppet_nick))
+                .channel(&to.channel)
+                .reconnect(false),
+        )?;
+
+        self.puppets.insert(
+            session.to_string(),
+            Puppet {
+                network: to.network.clone(),
+                channels: vec![to.channel.clone()],
+                refused: Vec::new(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Takes the channels the puppet was kicked from or left on its own off it, the relay says
+    /// what it still had to say there and speaks for it from now on
+    fn refused(&mut self, manager: &Manager, session: &str, message: &Message<Command>) {
+        let client = match manager.client(session) {
+            Some(client) => client,
+            None => return,
+        };
+
+        let casemapping = client.server().casemapping();
+        let own = |nick: &str| casemapping.eq(nick, client.nick());
+
+        let channels = match message.command() {
+            Command::Kick {
+                channels, users, ..
+            } => users
+                .iter()
+                .enumerate()
+                .filter(|(_, user)| own(user))
+                .filter_map(|(pos, _)| channels.get(pos).or_else(|| channels.first()).cloned())
+                .collect(),
+            Command::Part { channels, .. } if message.origin().nick().is_some_and(own) => {
+                channels.clone()
+            }
+            _ => return,
+        };
+
+        let puppet = match self.puppets.get_mut(session) {
+            Some(puppet) => puppet,
+            None => return,
+        };
+
+        let mut moved = Vec::new();
+
+        for channel in channels {
+            // parts the puppet was told to send took the channel off already
+            let channel = match puppet
+                .channels
+                .iter()
+                .position(|c| casemapping.eq(c, &channel))
+            {
+                Some(pos) => puppet.channels.remove(pos),
+                None => continue,
+            };
+
+            if let Some(outbox) = self.outboxes.get_mut(session) {
+                let (lines, kept) = outbox
+                    .waiting
+                    .drain(..)
+                    .partition(|line: &Line| line.channel == channel);
+
+                outbox.waiting = kept;
+                moved.extend(lines);
+            }
+
+            puppet.refused.push(channel);
+        }
+
+        let network = puppet.network.clone();
+
+        for line in moved {
+            match line.kind {
+                Kind::Message(_) | Kind::Notice(_) | Kind::Action(_) => self.push(&network, line),
+                _ => (),
+            }
+        }
+    }
+
+    fn refuses(&self, session: &str, channel: &str) -> bool {
+        self.puppets
+            .get(session)
+            .is_some_and(|puppet| puppet.refused.iter().any(|other| other == channel))
+    }
+
+    /// Forgets a puppet that couldn't connect or lost its connection, the relay says what it
+    /// still had to say
+    fn drop_puppet(&mut self, manager: &mut Manager, session: &str) {
+        let puppet = match self.puppets.remove(session) {
+            Some(puppet) => puppet,
+            None => return,
+        };
+
+        manager.remove(session);
+
+        if let Some(outbox) = self.outboxes.remove(session) {
+            for line in outbox.waiting {
+                match line.kind {
+                    Kind::Message(_) | Kind::Notice(_) | Kind::Action(_) => {
+                        self.push(&puppet.network, line)
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, session: &str, line: Line) {
+        self.outboxes
+            .entry(session.to_string())
+            .or_default()
+            .push(line, QUEUE_LIMIT);
+    }
+}
+
+/// Name of the session of the puppet of `nick` from `from` on `network`
+fn puppet_session(network: &str, nick: &str, from: &str) -> String {
+    format!("{}/{}@{}", network, nick, from)
+}
+
+fn channel_name(target: &MessageTarget) -> Option<&str> {
+    match target {
+        MessageTarget::Channel(ref channel) | MessageTarget::ChannelStatus { ref channel, .. } => {
+            Some(channel.as_str())
+        }
+        _ => None,
+    }
+}
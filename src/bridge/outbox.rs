@@ -0,0 +1,167 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command, connection::client::Client, ctcp::Ctcp, target::MessageTarget,
+};
+
+/// What happened to the user on the other side
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Kind {
+    Message(String),
+    Notice(String),
+    Action(String),
+    Join,
+    Part(Option<String>),
+    /// The nick a puppet takes, or the new label the relay shows
+    Nick(String),
+    Quit(Option<String>),
+}
+
+/// Something to mirror into a channel, either by the relay or by a puppet
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Line {
+    pub(super) channel: String,
+    /// The user as the relay shows them, like `<nick@network>`
+    pub(super) label: String,
+    pub(super) kind: Kind,
+}
+
+impl Line {
+    /// Puppets act as the user, the relay says what the user did
+    fn send(&self, client: &Client, puppet: bool) -> Result<(), Box<Error>> {
+        let target = MessageTarget::Channel(self.channel.clone());
+
+        match self.kind {
+            Kind::Message(ref text) if puppet => {
+                client.privmsg(&self.channel, text)?;
+            }
+            Kind::Message(ref text) => {
+                client.privmsg(&self.channel, &format!("{} {}", self.label, text))?;
+            }
+            Kind::Notice(ref text) if puppet => {
+                client.notice(&self.channel, text)?;
+            }
+            Kind::Notice(ref text) => {
+                client.notice(&self.channel, &format!("{} {}", self.label, text))?;
+            }
+            Kind::Action(ref text) if puppet => {
+                client.send(Ctcp::Action(text.to_string()).query(target))?;
+            }
+            Kind::Action(ref text) => {
+                client.send(Ctcp::Action(format!("{} {}", self.label, text)).query(target))?;
+            }
+            Kind::Join if puppet => {
+                client.send(Command::Join {
+                    channels: vec![self.channel.clone()],
+                    keys: Vec::new(),
+                })?;
+            }
+            Kind::Join => {
+                client.notice(&self.channel, &format!("{} has joined", self.label))?;
+            }
+            Kind::Part(ref reason) if puppet => {
+                client.send(Command::Part {
+                    channels: vec![self.channel.clone()],
+                    reason: reason.clone(),
+                })?;
+            }
+            Kind::Part(ref reason) => {
+                client.notice(
+                    &self.channel,
+                    &format!("{} has left{}", self.label, because(reason)),
+                )?;
+            }
+            Kind::Nick(ref name) if puppet => {
+                client.send(Command::Nick {
+                    name: name.to_string(),
+                })?;
+            }
+            Kind::Nick(ref label) => {
+                client.notice(
+                    &self.channel,
+                    &format!("{} is now known as {}", self.label, label),
+                )?;
+            }
+            Kind::Quit(ref reason) if puppet => {
+                client.send(Command::Quit {
+                    reason: reason.clone(),
+                })?;
+            }
+            Kind::Quit(ref reason) => {
+                client.notice(
+                    &self.channel,
+                    &format!("{} has quit{}", self.label, because(reason)),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn because(reason: &Option<String>) -> String {
+    match reason {
+        Some(ref reason) if !reason.is_empty() => format!(" ({})", reason),
+        _ => String::new(),
+    }
+}
+
+/// Lines waiting for one connection, sent as fast as the rate limit allows
+#[derive(Debug, Default)]
+pub(super) struct Outbox {
+    sent: VecDeque<Instant>,
+    pub(super) waiting: VecDeque<Line>,
+}
+
+impl Outbox {
+    /// Drops the oldest line if `limit` are waiting already
+    pub(super) fn push(&mut self, line: Line, limit: usize) {
+        while self.waiting.len() >= limit.max(1) {
+            self.waiting.pop_front();
+        }
+
+        self.waiting.push_back(line);
+    }
+
+    /// Sends at most `limit` lines within `window`. Returns whether a puppet sent its `QUIT`.
+    pub(super) fn flush(
+        &mut self,
+        client: &Client,
+        puppet: bool,
+        limit: usize,
+        window: Duration,
+    ) -> Result<bool, Box<Error>> {
+        let now = Instant::now();
+
+        while self
+            .sent
+            .front()
+            .map(|sent| now.duration_since(*sent) >= window)
+            .unwrap_or(false)
+        {
+            self.sent.pop_front();
+        }
+
+        while self.sent.len() < limit {
+            let line = match self.waiting.pop_front() {
+                Some(line) => line,
+                None => break,
+            };
+
+            line.send(client, puppet)?;
+            self.sent.push_back(now);
+
+            if puppet {
+                if let Kind::Quit(_) = line.kind {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+}
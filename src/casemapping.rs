@@ -0,0 +1,67 @@
+use std::error::Error;
+
+pub mod error {
+    impl_error!(UnknownCaseMappingError { name: String });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How nick and channel names compare case insensitively, as announced by `CASEMAPPING`
+pub enum CaseMapping {
+    Ascii,
+    /// `[]\~` are the upper case versions of `{}|^`
+    Rfc1459,
+    /// Like `Rfc1459`, without `~` and `^`
+    StrictRfc1459,
+}
+
+impl Default for CaseMapping {
+    fn default() -> Self {
+        CaseMapping::Rfc1459
+    }
+}
+
+impl CaseMapping {
+    pub fn parse(name: &str) -> Result<Self, Box<Error>> {
+        match name.to_ascii_lowercase().as_str() {
+            "ascii" => Ok(CaseMapping::Ascii),
+            "rfc1459" => Ok(CaseMapping::Rfc1459),
+            "strict-rfc1459" => Ok(CaseMapping::StrictRfc1459),
+            _ => Err(error::UnknownCaseMappingError::new(name.to_string())),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CaseMapping::Ascii => "ascii",
+            CaseMapping::Rfc1459 => "rfc1459",
+            CaseMapping::StrictRfc1459 => "strict-rfc1459",
+        }
+    }
+}
+
+impl CaseMapping {
+    pub fn to_lower_char(&self, c: char) -> char {
+        match (self, c) {
+            (CaseMapping::Rfc1459, '~') => '^',
+            (CaseMapping::Rfc1459, _) | (CaseMapping::StrictRfc1459, _) => match c {
+                '[' => '{',
+                ']' => '}',
+                '\\' => '|',
+                _ => c.to_ascii_lowercase(),
+            },
+            (CaseMapping::Ascii, _) => c.to_ascii_lowercase(),
+        }
+    }
+
+    pub fn to_lower(&self, data: &str) -> String {
+        data.chars().map(|c| self.to_lower_char(c)).collect()
+    }
+
+    pub fn eq(&self, a: &str, b: &str) -> bool {
+        a.len() == b.len()
+            && a
+                .chars()
+                .zip(b.chars())
+                .all(|(a, b)| self.to_lower_char(a) == self.to_lower_char(b))
+    }
+}
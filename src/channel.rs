@@ -1,17 +1,83 @@
+use std::rc::Rc;
+
 use crate::{
-    mode::{channel, Mode},
-    user::WeakUser,
+    casemapping::CaseMapping,
+    mode::{channel, Mode, Parseable},
+    origin::{
+        mask::{ExtBan, Mask},
+        Origin,
+    },
+    time,
+    user::{RcUser, WeakUser},
 };
 
+#[derive(Debug, Clone)]
+/// A user in a channel together with the status the user has there
+pub struct Member {
+    user: WeakUser,
+    operator: bool,
+    voice: bool,
+}
+
+impl Member {
+    pub fn user(&self) -> WeakUser {
+        self.user.clone()
+    }
+
+    pub fn is_operator(&self) -> bool {
+        self.operator
+    }
+
+    pub fn is_voiced(&self) -> bool {
+        self.voice
+    }
+
+    pub fn set_operator(&mut self, operator: bool) {
+        self.operator = operator;
+    }
+
+    pub fn set_voice(&mut self, voice: bool) {
+        self.voice = voice;
+    }
+
+    /// The highest status prefix as shown in `NAMES`
+    pub fn prefix(&self) -> Option<char> {
+        if self.operator {
+            Some('@')
+        } else if self.voice {
+            Some('+')
+        } else {
+            None
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Channel {
     name: String,
     topic: Option<String>,
+    topic_setter: Option<String>,
+    topic_time: u64,
     modes: Vec<Mode<channel::Mode>>,
-    users: Vec<WeakUser>,
+    members: Vec<Member>,
+    invited: Vec<WeakUser>,
     //password: Option<String>,
 }
 
+impl Channel {
+    pub fn new(name: &str) -> Self {
+        Channel {
+            name: name.to_string(),
+            topic: None,
+            topic_setter: None,
+            topic_time: 0,
+            modes: vec![],
+            members: vec![],
+            invited: vec![],
+        }
+    }
+}
+
 impl Channel {
     pub fn name(&self) -> &str {
         self.name.as_str()
@@ -21,16 +87,193 @@ impl Channel {
         self.topic.as_ref().map(|s| s.as_str())
     }
 
+    pub fn topic_setter(&self) -> Option<&str> {
+        self.topic_setter.as_ref().map(|s| s.as_str())
+    }
+
+    /// Unix time the topic was set at
+    pub fn topic_time(&self) -> u64 {
+        self.topic_time
+    }
+
+    /// An empty topic removes it
+    pub fn set_topic(&mut self, topic: &str, setter: &str) {
+        self.topic = Some(topic.to_string()).filter(|t| !t.is_empty());
+        self.topic_setter = Some(setter.to_string());
+        self.topic_time = time::now();
+    }
+
+    /// Who set the topic and when, as servers tell it with RPL_TOPICWHOTIME
+    pub fn set_topic_details(&mut self, setter: &str, time: u64) {
+        self.topic_setter = Some(setter.to_string());
+        self.topic_time = time;
+    }
+
     pub fn modes(&self) -> &Vec<Mode<channel::Mode>> {
         &self.modes
     }
 
-    //pub fn password(&self) ->
+    /// Compares the mode letter only, so `Key { key: None }` tells whether there's any key
+    pub fn has_mode(&self, mode: &channel::Mode) -> bool {
+        self.modes
+            .iter()
+            .any(|m| m.mode().symbol() == mode.symbol())
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.modes.iter().find_map(|m| match m.mode() {
+            channel::Mode::Key { key: Some(ref key) } => Some(key.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.modes.iter().find_map(|m| match m.mode() {
+            channel::Mode::Limit { value } => *value,
+            _ => None,
+        })
+    }
+
+    /// Masks of a list mode like `b`
+    pub fn masks(&self, symbol: char) -> Vec<String> {
+        self.modes
+            .iter()
+            .filter(|m| m.mode().symbol() == symbol)
+            .filter_map(|m| m.mode().param())
+            .collect()
+    }
+
+    /// Whether a mask of the list mode like `b` matches the user. Quiets (`~q:mask`) only count
+    /// if `quiets` is set, other extended bans never match here.
+    fn listed(&self, symbol: char, origin: &Origin, mapping: CaseMapping, quiets: bool) -> bool {
+        self.masks(symbol).iter().any(|mask| match ExtBan::parse(mask) {
+            Some(ref ban) if ban.is_quiet() => {
+                quiets && ban.matches(origin, None, mapping) == Some(true)
+            }
+            Some(_) => false,
+            None => Mask::new(mask).casemapping(mapping).matches(origin),
+        })
+    }
+
+    /// Whether a ban (`+b`) keeps the user out, exceptions (`+e`) taken into account
+    pub fn is_banned(&self, origin: &Origin, mapping: CaseMapping) -> bool {
+        self.listed('b', origin, mapping, false) && !self.listed('e', origin, mapping, false)
+    }
+
+    /// Whether bans or quiets keep the user from speaking
+    pub fn is_quieted(&self, origin: &Origin, mapping: CaseMapping) -> bool {
+        self.listed('b', origin, mapping, true) && !self.listed('e', origin, mapping, true)
+    }
+
+    /// Whether an invite exception (`+I`) lets the user in without an invite
+    pub fn is_invite_excepted(&self, origin: &Origin, mapping: CaseMapping) -> bool {
+        self.listed('I', origin, mapping, false)
+    }
+
+    /// Adds granted and removes revoked modes, status modes belong to the members. Returns
+    /// whether anything changed.
+    pub fn apply_mode(&mut self, mode: &Mode<channel::Mode>) -> bool {
+        if mode.mode().is_status() {
+            return false;
+        }
+
+        let present = self.modes.iter().any(|m| m.mode() == mode.mode());
+
+        let changed = if mode.mode().is_list() {
+            self.modes.retain(|m| m.mode() != mode.mode());
+
+            present != mode.granted()
+        } else {
+            let changed = if mode.granted() {
+                !present
+            } else {
+                self.has_mode(mode.mode())
+            };
+
+            self.modes
+                .retain(|m| m.mode().symbol() != mode.mode().symbol());
+
+            changed
+        };
+
+        if mode.granted() {
+            self.modes.push(mode.clone());
+        }
+
+        changed
+    }
 }
 
 impl Channel {
     pub fn users(&self) -> Vec<WeakUser> {
-        self.users.clone()
+        self.members.iter().map(|member| member.user()).collect()
+    }
+
+    pub fn members(&self) -> &Vec<Member> {
+        &self.members
+    }
+
+    pub fn member(&self, user: &RcUser) -> Option<&Member> {
+        self.members.iter().find(|member| {
+            member
+                .user
+                .upgrade()
+                .map_or(false, |other| Rc::ptr_eq(&other, user))
+        })
+    }
+
+    pub fn member_mut(&mut self, user: &RcUser) -> Option<&mut Member> {
+        self.members.iter_mut().find(|member| {
+            member
+                .user
+                .upgrade()
+                .map_or(false, |other| Rc::ptr_eq(&other, user))
+        })
+    }
+
+    pub fn is_member(&self, user: &RcUser) -> bool {
+        self.member(user).is_some()
+    }
+
+    pub fn add_user(&mut self, user: &RcUser, operator: bool) {
+        if !self.is_member(user) {
+            self.members.push(Member {
+                user: Rc::downgrade(user),
+                operator,
+                voice: false,
+            });
+        }
+    }
+
+    /// Also drops members whose user is gone
+    pub fn remove_user(&mut self, user: &RcUser) {
+        self.members.retain(|member| match member.user.upgrade() {
+            Some(ref other) => !Rc::ptr_eq(other, user),
+            None => false,
+        });
+    }
+}
+
+impl Channel {
+    /// Lets the user join an invite only channel once
+    pub fn invite(&mut self, user: &RcUser) {
+        if !self.is_invited(user) {
+            self.invited.push(Rc::downgrade(user));
+        }
+    }
+
+    pub fn is_invited(&self, user: &RcUser) -> bool {
+        self.invited
+            .iter()
+            .any(|weak| weak.upgrade().map_or(false, |other| Rc::ptr_eq(&other, user)))
+    }
+
+    /// Also drops invites of users which are gone
+    pub fn remove_invite(&mut self, user: &RcUser) {
+        self.invited.retain(|weak| match weak.upgrade() {
+            Some(ref other) => !Rc::ptr_eq(other, user),
+            None => false,
+        });
     }
 }
 
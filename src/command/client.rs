@@ -2,9 +2,12 @@ use std::error::Error;
 
 use crate::{
     command::{RawCommand, TRAILING_DELIMITER},
+    history::{self, Range},
     message::{Message, ToMessage},
     mode::{channel, user, Mode},
-    parsing, validate, SEPARATOR,
+    parsing,
+    target::MessageTarget,
+    time, validate, SEPARATOR,
 };
 
 pub mod error {
@@ -91,11 +94,11 @@ pub enum Command {
 
     // Messages
     PrivMsg {
-        targets: Vec<String>,
+        targets: Vec<MessageTarget>,
         text: String,
     },
     Notice {
-        target: String,
+        target: MessageTarget,
         text: String,
     },
 
@@ -178,13 +181,171 @@ pub enum Command {
     ErrorMsg {
         text: String,
     },
+
+    // Optional
+    Rehash,
+    IsOn {
+        nicks: Vec<String>,
+    },
+
+    // Server bans (ircd-hybrid), durations are in minutes
+    KLine {
+        duration: Option<u64>,
+        mask: String,
+        reason: String,
+    },
+    UnKLine {
+        mask: String,
+    },
+    DLine {
+        duration: Option<u64>,
+        mask: String,
+        reason: String,
+    },
+    UnDLine {
+        mask: String,
+    },
+
+    // IRCv3
+    MonitorAdd {
+        targets: Vec<String>,
+    },
+    MonitorRemove {
+        targets: Vec<String>,
+    },
+    ChgHost {
+        user: String,
+        host: String,
+    },
+    CapLs {
+        version: Option<String>,
+    },
+    CapList,
+    CapReq {
+        caps: Vec<String>,
+    },
+    CapEnd,
+    CapLsReply {
+        caps: Vec<String>,
+        more: bool,
+    },
+    CapListReply {
+        caps: Vec<String>,
+        more: bool,
+    },
+    CapAck {
+        caps: Vec<String>,
+    },
+    CapNak {
+        caps: Vec<String>,
+    },
+    CapNew {
+        caps: Vec<String>,
+    },
+    CapDel {
+        caps: Vec<String>,
+    },
+    Batch {
+        reference: String,
+        kind: Option<String>,
+        params: Vec<String>,
+    },
+    /// SASL mechanism or base64 encoded data, `+` for none
+    Authenticate {
+        data: String,
+    },
+    /// Account the user logged into, `None` after logging out
+    Account {
+        account: Option<String>,
+    },
+    /// `JOIN` with the account and real name, sent to clients with `extended-join`
+    ExtendedJoin {
+        channel: String,
+        account: Option<String>,
+        real_name: String,
+    },
+    ChatHistory {
+        target: String,
+        range: Range,
+    },
+    /// Targets with messages between the timestamps, in milliseconds since the epoch
+    ChatHistoryTargets {
+        start: u64,
+        end: u64,
+        limit: usize,
+    },
+    /// Target with its latest message, one per line of the `TARGETS` reply
+    ChatHistoryTarget {
+        target: String,
+        time: u64,
+    },
+    /// Standard reply, the context is whatever the code needs
+    Fail {
+        command: String,
+        code: String,
+        context: Vec<String>,
+        description: String,
+    },
+
     // Server
     // - Replies
+    /* 001 */ Welcome {
+        nick: String,
+        text: String,
+    },
+    /* 221 */ UModeIs {
+        modes: Vec<Mode<user::Mode>>,
+    },
+    /* 303 */ IsOnReply {
+        nicks: Vec<String>,
+    },
+    /* 352 */ WhoReply {
+        channel: String,
+        user: String,
+        host: String,
+        server: String,
+        nick: String,
+        flags: String,
+        hops: u32,
+        real_name: String,
+    },
+    /* 396 */ VisibleHost {
+        host: String,
+    },
     /* 375 */ MotdStart,
     /* 372 */ MotdBody {
         text: String,
     },
-    /* 376 */ MotdEnd, // - Errors
+    /* 376 */ MotdEnd,
+    // - Errors
+    /* 422 */ NoMotd,
+    /* 432 */ ErroneusNickname {
+        nick: String,
+    },
+    /* 433 */ NicknameInUse {
+        nick: String,
+    },
+    /* 436 */ NickCollision {
+        nick: String,
+    },
+    /* 437 */ UnavailResource {
+        name: String,
+    },
+    /* 464 */ PasswdMismatch,
+    /* 465 */ YoureBannedCreep {
+        text: String,
+    },
+    /* 730 */ MonOnline {
+        targets: Vec<String>,
+    },
+    /* 731 */ MonOffline {
+        targets: Vec<String>,
+    },
+    /// Any other numeric reply, the first parameter is usually the target
+    Numeric {
+        code: u16,
+        params: Vec<String>,
+    },
 }
 
 impl crate::command::Command for Command {
@@ -223,15 +384,76 @@ impl crate::command::Command for Command {
                 }
             }
 
-            "PASS" => return not_implemented_err,
-            "OPER" => return not_implemented_err,
+            "PASS" => {
+                if r.parameters.len() >= 1 {
+                    return Ok(Command::Pass {
+                        password: parsing::skip_maybe_trailing(r.parameters[0]).to_string(),
+                    });
+                }
+            }
+            "OPER" => {
+                if r.parameters.len() == 2 {
+                    return Ok(Command::Oper {
+                        name: r.parameters[0].to_string(),
+                        password: parsing::skip_maybe_trailing(r.parameters[1]).to_string(),
+                    });
+                }
+            }
             "SERVICE" => return not_implemented_err,
-            "QUIT" => return not_implemented_err,
+            "QUIT" => {
+                let reason = parsing::skip_maybe_trailing(&r.parameters.join(SEPARATOR)).to_string();
+
+                return Ok(Command::Quit {
+                    reason: Some(reason).filter(|r| !r.is_empty()),
+                });
+            }
             "SQUIT" => return not_implemented_err,
+            "MODE" => {
+                if r.parameters.len() >= 1 && validate::channel_name(r.parameters[0]).is_ok() {
+                    let modes = match r.parameters.get(1) {
+                        Some(modes) => channel::Mode::parse_list(
+                            parsing::skip_maybe_trailing(modes),
+                            &r.parameters[2..]
+                                .iter()
+                                .map(|p| parsing::skip_maybe_trailing(p))
+                                .collect::<Vec<&str>>(),
+                        )?,
+                        None => Vec::new(),
+                    };
+
+                    return Ok(Command::CMode {
+                        channel: r.parameters[0].to_string(),
+                        modes,
+                    });
+                }
+
+                if r.parameters.len() >= 1 {
+                    let modes = match r.parameters.get(1) {
+                        Some(modes) => Mode::parse_list(parsing::skip_maybe_trailing(modes)),
+                        None => Ok(Vec::new()),
+                    };
+
+                    if let (Ok(name), Ok(modes)) = (parsing::nick_name(r.parameters[0]), modes) {
+                        return Ok(Command::UMode {
+                            name: name.to_string(),
+                            modes,
+                        });
+                    }
+                }
+            }
 
             // Channel
             "JOIN" => {
-                if r.parameters.len() == 1 && &r.parameters[0] == &"0" {
+                if r.parameters.len() >= 3 && validate::channel_name(r.parameters[0]).is_ok() {
+                    return Ok(Command::ExtendedJoin {
+                        channel: r.parameters[0].to_string(),
+                        account: Some(r.parameters[1])
+                            .filter(|account| *account != "*")
+                            .map(|account| account.to_string()),
+                        real_name: parsing::skip_maybe_trailing(&r.parameters[2..].join(SEPARATOR))
+                            .to_string(),
+                    });
+                } else if r.parameters.len() == 1 && &r.parameters[0] == &"0" {
                     return Ok(Command::Join0 {}.into());
                 } else {
                     if r.parameters.len() >= 1 && r.parameters.len() <= 2 {
@@ -303,11 +525,88 @@ impl crate::command::Command for Command {
                 }
             }
 
-            "TOPIC" => return not_implemented_err,
-            "NAMES" => return not_implemented_err,
-            "LIST" => return not_implemented_err,
-            "INVITE" => return not_implemented_err,
-            "KICK" => return not_implemented_err,
+            "TOPIC" => {
+                if r.parameters.len() >= 1 {
+                    let channel = parsing::channel_name(r.parameters[0])?;
+                    let text = if r.parameters.len() >= 2 {
+                        Some(
+                            parsing::skip_maybe_trailing(&r.parameters[1..].join(SEPARATOR))
+                                .to_string(),
+                        )
+                    } else {
+                        None
+                    };
+
+                    return Ok(Command::Topic {
+                        channel: channel.to_string(),
+                        text,
+                    });
+                }
+            }
+            "NAMES" => {
+                if r.parameters.len() <= 2 {
+                    return Ok(Command::Names {
+                        channels: r.parameters.get(0).map(|channels| {
+                            channels
+                                .split(crate::LIST_ITEM_DELIMITER)
+                                .map(|channel| channel.to_string())
+                                .collect()
+                        }),
+                        server: r.parameters.get(1).map(|server| server.to_string()),
+                    });
+                }
+            }
+            "LIST" => {
+                if r.parameters.len() <= 2 {
+                    return Ok(Command::List {
+                        channels: r.parameters.get(0).map(|channels| {
+                            channels
+                                .split(crate::LIST_ITEM_DELIMITER)
+                                .map(|channel| channel.to_string())
+                                .collect()
+                        }),
+                        server: r.parameters.get(1).map(|server| server.to_string()),
+                    });
+                }
+            }
+            "INVITE" => {
+                if r.parameters.len() == 2 {
+                    return Ok(Command::Invite {
+                        user: parsing::nick_name(r.parameters[0])?.to_string(),
+                        channel: parsing::channel_name(r.parameters[1])?.to_string(),
+                    });
+                }
+            }
+            "KICK" => {
+                if r.parameters.len() >= 2 {
+                    let list = |data: &str| {
+                        data.split(crate::LIST_ITEM_DELIMITER)
+                            .map(|item| item.to_string())
+                            .collect::<Vec<String>>()
+                    };
+
+                    let channels = list(r.parameters[0]);
+
+                    for channel in channels.iter() {
+                        validate::channel_name(channel)?;
+                    }
+
+                    let reason = if r.parameters.len() >= 3 {
+                        Some(
+                            parsing::skip_maybe_trailing(&r.parameters[2..].join(SEPARATOR))
+                                .to_string(),
+                        )
+                    } else {
+                        None
+                    };
+
+                    return Ok(Command::Kick {
+                        channels,
+                        users: list(r.parameters[1]),
+                        reason,
+                    });
+                }
+            }
 
             // Messages
             "PRIVMSG" => {
@@ -318,32 +617,20 @@ impl crate::command::Command for Command {
                   msgto      =/ nickname / ( nickname "!" user "@" host )
                 */
 
-                if r.parameters.len() >= 2 && &r.parameters[1][..1] == TRAILING_DELIMITER {
-                    let target_parts = r.parameters[0]
-                        .split(crate::LIST_ITEM_DELIMITER)
-                        .collect::<Vec<&str>>();
+                if r.parameters.len() >= 2 && r.parameters[1].starts_with(TRAILING_DELIMITER) {
+                    let targets = parsing::msg_target(r.parameters[0])?;
                     let text = format!(
                         "{}",
                         parsing::skip_maybe_trailing(&r.parameters[1..].join(crate::SEPARATOR))
                     );
 
-                    let targets = target_parts
-                        .iter()
-                        .filter(|part| {
-                            validate::channel_name(part)
-                                .or(validate::nick_name(part))
-                                .is_ok()
-                        })
-                        .map(|part| format!("{}", part))
-                        .collect::<Vec<String>>();
-
                     return Ok(Command::PrivMsg { targets, text });
                 }
             }
             "NOTICE" => {
                 if r.parameters.len() >= 2 {
                     return Ok(Command::Notice {
-                        target: r.parameters[0].to_string(),
+                        target: parsing::msg_to(r.parameters[0])?,
                         text: parsing::skip_maybe_trailing(
                             &r.parameters[1..].join(crate::SEPARATOR),
                         )
@@ -354,11 +641,36 @@ impl crate::command::Command for Command {
             }
 
             // Server
-            "MOTD" => return not_implemented_err,
+            "MOTD" => {
+                if r.parameters.len() <= 1 {
+                    return Ok(Command::Motd {
+                        server: r.parameters.get(0).map(|server| server.to_string()),
+                    });
+                }
+            }
             "LUSERS" => return not_implemented_err,
             "VERSION" => return not_implemented_err,
-            "STATS" => return not_implemented_err,
-            "LINKS" => return not_implemented_err,
+            "STATS" => {
+                if r.parameters.len() <= 2 {
+                    return Ok(Command::Stats {
+                        query: r.parameters.get(0).map(|q| q.to_string()),
+                        server: r.parameters.get(1).map(|s| s.to_string()),
+                    });
+                }
+            }
+            "LINKS" => {
+                if r.parameters.len() <= 2 {
+                    let (server, mask) = match r.parameters.len() {
+                        2 => (Some(r.parameters[0]), Some(r.parameters[1])),
+                        _ => (None, r.parameters.get(0).cloned()),
+                    };
+
+                    return Ok(Command::Links {
+                        server: server.map(|server| server.to_string()),
+                        mask: mask.map(|mask| mask.to_string()),
+                    });
+                }
+            }
             "TIME" => return not_implemented_err,
             "CONNECT" => return not_implemented_err,
             "TRACE" => return not_implemented_err,
@@ -370,12 +682,43 @@ impl crate::command::Command for Command {
             "SQUERY" => return not_implemented_err,
 
             // User
-            "WHO" => return not_implemented_err,
-            "WHOIS" => return not_implemented_err,
+            "WHO" => {
+                if r.parameters.len() >= 1 && r.parameters.len() <= 2 {
+                    return Ok(Command::Who {
+                        mask: r.parameters[0].to_string(),
+                        operators_only: r.parameters.get(1) == Some(&"o"),
+                    });
+                }
+            }
+            "WHOIS" => {
+                if r.parameters.len() >= 1 && r.parameters.len() <= 2 {
+                    let (server, masks) = if r.parameters.len() == 2 {
+                        (Some(r.parameters[0].to_string()), r.parameters[1])
+                    } else {
+                        (None, r.parameters[0])
+                    };
+
+                    return Ok(Command::WhoIs {
+                        server,
+                        masks: masks
+                            .split(crate::LIST_ITEM_DELIMITER)
+                            .map(|mask| mask.to_string())
+                            .collect(),
+                    });
+                }
+            }
             "WHOWAS" => return not_implemented_err,
 
             // Misc
-            "KILL" => return not_implemented_err,
+            "KILL" => {
+                if r.parameters.len() >= 2 {
+                    return Ok(Command::Kill {
+                        user: parsing::nick_name(r.parameters[0])?.to_string(),
+                        reason: parsing::skip_maybe_trailing(&r.parameters[1..].join(SEPARATOR))
+                            .to_string(),
+                    });
+                }
+            }
             "PING" => {
                 if r.parameters.len() >= 1 {
                     return Ok(Command::Ping {
@@ -387,7 +730,17 @@ impl crate::command::Command for Command {
                     });
                 }
             }
-            "PONG" => {}
+            "PONG" => {
+                if r.parameters.len() >= 1 {
+                    return Ok(Command::Pong {
+                        server1: parsing::skip_maybe_trailing(r.parameters[0]).to_string(),
+                        server2: r
+                            .parameters
+                            .get(1)
+                            .map(|s| parsing::skip_maybe_trailing(s).to_string()),
+                    });
+                }
+            }
             "ERROR" => {
                 if r.parameters.len() >= 1 {
                     return Ok(Command::ErrorMsg {
@@ -399,7 +752,257 @@ impl crate::command::Command for Command {
                 }
             }
 
+            // Optional
+            "REHASH" if r.parameters.is_empty() => return Ok(Command::Rehash),
+            "ISON" => {
+                if r.parameters.len() >= 1 {
+                    return Ok(Command::IsOn {
+                        nicks: parsing::skip_maybe_trailing(&r.parameters.join(SEPARATOR))
+                            .split_whitespace()
+                            .map(|nick| nick.to_string())
+                            .collect(),
+                    });
+                }
+            }
+
+            // Server bans
+            "KLINE" | "DLINE" => {
+                let duration = r.parameters.get(0).and_then(|p| p.parse().ok());
+                let p = &r.parameters[duration.map_or(0, |_| 1)..];
+
+                if p.len() >= 1 && !p[0].starts_with(TRAILING_DELIMITER) {
+                    let mask = p[0].to_string();
+                    let reason = parsing::skip_maybe_trailing(&p[1..].join(SEPARATOR)).to_string();
+
+                    return Ok(if r.command == "KLINE" {
+                        Command::KLine {
+                            duration,
+                            mask,
+                            reason,
+                        }
+                    } else {
+                        Command::DLine {
+                            duration,
+                            mask,
+                            reason,
+                        }
+                    });
+                }
+            }
+            "UNKLINE" if r.parameters.len() == 1 => {
+                return Ok(Command::UnKLine {
+                    mask: r.parameters[0].to_string(),
+                });
+            }
+            "UNDLINE" if r.parameters.len() == 1 => {
+                return Ok(Command::UnDLine {
+                    mask: r.parameters[0].to_string(),
+                });
+            }
+
+            // IRCv3
+            "MONITOR" => {
+                if r.parameters.len() == 2 {
+                    let targets = parsing::skip_maybe_trailing(r.parameters[1])
+                        .split(crate::LIST_ITEM_DELIMITER)
+                        .map(|target| target.to_string())
+                        .collect();
+
+                    match r.parameters[0] {
+                        "+" => return Ok(Command::MonitorAdd { targets }),
+                        "-" => return Ok(Command::MonitorRemove { targets }),
+                        _ => (),
+                    }
+                }
+            }
+            "CHGHOST" => {
+                if r.parameters.len() == 2 {
+                    return Ok(Command::ChgHost {
+                        user: r.parameters[0].to_string(),
+                        host: parsing::skip_maybe_trailing(r.parameters[1]).to_string(),
+                    });
+                }
+            }
+            "CAP" => {
+                let caps = |params: &[&str]| {
+                    parsing::skip_maybe_trailing(&params.join(SEPARATOR))
+                        .split_whitespace()
+                        .map(|cap| cap.to_string())
+                        .collect::<Vec<String>>()
+                };
+
+                // sent by clients without a target
+                match r.parameters.get(0) {
+                    Some(&"LS") if r.parameters.len() <= 2 => {
+                        return Ok(Command::CapLs {
+                            version: r.parameters.get(1).map(|v| v.to_string()),
+                        });
+                    }
+                    Some(&"LIST") if r.parameters.len() == 1 => return Ok(Command::CapList),
+                    Some(&"REQ") if r.parameters.len() >= 2 => {
+                        return Ok(Command::CapReq {
+                            caps: caps(&r.parameters[1..]),
+                        });
+                    }
+                    Some(&"END") => return Ok(Command::CapEnd),
+                    _ => (),
+                }
+
+                // sent by servers, starting with the target
+                if r.parameters.len() >= 3 {
+                    let more = r.parameters.len() >= 4 && r.parameters[2] == "*";
+                    let caps = caps(&r.parameters[if more { 3 } else { 2 }..]);
+
+                    match r.parameters[1] {
+                        "LS" => return Ok(Command::CapLsReply { caps, more }),
+                        "LIST" => return Ok(Command::CapListReply { caps, more }),
+                        "ACK" => return Ok(Command::CapAck { caps }),
+                        "NAK" => return Ok(Command::CapNak { caps }),
+                        "NEW" => return Ok(Command::CapNew { caps }),
+                        "DEL" => return Ok(Command::CapDel { caps }),
+                        _ => (),
+                    }
+                }
+            }
+            "AUTHENTICATE" => {
+                if r.parameters.len() == 1 {
+                    return Ok(Command::Authenticate {
+                        data: parsing::skip_maybe_trailing(r.parameters[0]).to_string(),
+                    });
+                }
+            }
+            "ACCOUNT" => {
+                if r.parameters.len() == 1 {
+                    let account = parsing::skip_maybe_trailing(r.parameters[0]);
+
+                    return Ok(Command::Account {
+                        account: Some(account)
+                            .filter(|account| *account != "*")
+                            .map(|account| account.to_string()),
+                    });
+                }
+            }
+            "CHATHISTORY" => {
+                let p = &r.parameters;
+
+                if p.len() == 4 && p[0].eq_ignore_ascii_case("TARGETS") {
+                    let start = history::Reference::parse(p[1]);
+                    let end = history::Reference::parse(p[2]);
+
+                    if let (
+                        Some(history::Reference::Timestamp(start)),
+                        Some(history::Reference::Timestamp(end)),
+                        Ok(limit),
+                    ) = (start, end, p[3].parse())
+                    {
+                        return Ok(Command::ChatHistoryTargets { start, end, limit });
+                    }
+                } else if p.len() == 3 && p[0].eq_ignore_ascii_case("TARGETS") {
+                    if let Some(time) = time::parse_time(parsing::skip_maybe_trailing(p[2])) {
+                        return Ok(Command::ChatHistoryTarget {
+                            target: p[1].to_string(),
+                            time,
+                        });
+                    }
+                } else if p.len() >= 3 {
+                    if let Some(range) = Range::parse(p[0], &p[2..]) {
+                        return Ok(Command::ChatHistory {
+                            target: p[1].to_string(),
+                            range,
+                        });
+                    }
+                }
+            }
+            "FAIL" => {
+                if r.parameters.len() >= 3 {
+                    let p = &r.parameters;
+                    let end = p
+                        .iter()
+                        .position(|param| param.starts_with(TRAILING_DELIMITER))
+                        .unwrap_or(p.len() - 1)
+                        .max(2);
+
+                    return Ok(Command::Fail {
+                        command: p[0].to_string(),
+                        code: p[1].to_string(),
+                        context: p[2..end].iter().map(|param| param.to_string()).collect(),
+                        description: parsing::skip_maybe_trailing(&p[end..].join(SEPARATOR))
+                            .to_string(),
+                    });
+                }
+            }
+            "BATCH" => {
+                if r.parameters.len() >= 1 {
+                    let reference = r.parameters[0];
+
+                    if reference.starts_with('+') && r.parameters.len() >= 2 {
+                        return Ok(Command::Batch {
+                            reference: reference.to_string(),
+                            kind: Some(r.parameters[1].to_string()),
+                            params: r.parameters[2..]
+                                .iter()
+                                .map(|param| parsing::skip_maybe_trailing(param).to_string())
+                                .collect(),
+                        });
+                    } else if reference.starts_with('-') && r.parameters.len() == 1 {
+                        return Ok(Command::Batch {
+                            reference: reference.to_string(),
+                            kind: None,
+                            params: Vec::new(),
+                        });
+                    }
+                }
+            }
+
             // Replies
+            "001" => {
+                if r.parameters.len() >= 2 {
+                    return Ok(Command::Welcome {
+                        nick: r.parameters[0].to_string(),
+                        text: parsing::skip_maybe_trailing(
+                            &r.parameters[1..].join(crate::SEPARATOR),
+                        )
+                            .to_string(),
+                    });
+                }
+            }
+            "221" => {
+                if r.parameters.len() >= 2 {
+                    if let Ok(modes) = Mode::parse_list(parsing::skip_maybe_trailing(
+                        &r.parameters[1..].join(""),
+                    )) {
+                        return Ok(Command::UModeIs { modes });
+                    }
+                }
+            }
+            "303" => {
+                if r.parameters.len() >= 1 {
+                    return Ok(Command::IsOnReply {
+                        nicks: parsing::skip_maybe_trailing(&r.parameters[1..].join(SEPARATOR))
+                            .split_whitespace()
+                            .map(|nick| nick.to_string())
+                            .collect(),
+                    });
+                }
+            }
+            "352" => {
+                if r.parameters.len() >= 9 {
+                    let hops = parsing::skip_maybe_trailing(r.parameters[7]).parse::<u32>();
+
+                    if let Ok(hops) = hops {
+                        return Ok(Command::WhoReply {
+                            channel: r.parameters[1].to_string(),
+                            user: r.parameters[2].to_string(),
+                            host: r.parameters[3].to_string(),
+                            server: r.parameters[4].to_string(),
+                            nick: r.parameters[5].to_string(),
+                            flags: r.parameters[6].to_string(),
+                            hops,
+                            real_name: r.parameters[8..].join(SEPARATOR),
+                        });
+                    }
+                }
+            }
             "375" => return Ok(Command::MotdStart.into()),
             "372" => {
                 if r.parameters.len() >= 3 && r.parameters[1] == ":-" {
@@ -409,6 +1012,77 @@ impl crate::command::Command for Command {
                 }
             }
             "376" => return Ok(Command::MotdEnd.into()),
+            "396" => {
+                if r.parameters.len() >= 2 {
+                    return Ok(Command::VisibleHost {
+                        host: parsing::skip_maybe_trailing(r.parameters[1]).to_string(),
+                    });
+                }
+            }
+
+            // Errors
+            "422" => return Ok(Command::NoMotd),
+            "432" | "433" | "436" | "437" => {
+                // first parameter is our current nick (or `*` while unregistered)
+                if r.parameters.len() >= 2 {
+                    let name = parsing::skip_maybe_trailing(r.parameters[1]).to_string();
+
+                    return Ok(match r.command {
+                        "432" => Command::ErroneusNickname { nick: name },
+                        "433" => Command::NicknameInUse { nick: name },
+                        "436" => Command::NickCollision { nick: name },
+                        _ => Command::UnavailResource { name },
+                    });
+                }
+            }
+            "464" => return Ok(Command::PasswdMismatch),
+            "465" => {
+                return Ok(Command::YoureBannedCreep {
+                    text: parsing::skip_maybe_trailing(
+                        &r.parameters.get(1..).unwrap_or_default().join(crate::SEPARATOR),
+                    )
+                        .to_string(),
+                });
+            }
+            "730" | "731" => {
+                if r.parameters.len() >= 2 {
+                    // RPL_MONONLINE lists full masks, RPL_MONOFFLINE only nicks
+                    let targets = parsing::skip_maybe_trailing(&r.parameters[1..].join(SEPARATOR))
+                        .split(crate::LIST_ITEM_DELIMITER)
+                        .map(|target| {
+                            target
+                                .split(crate::origin::IDENT_SEPARATOR)
+                                .next()
+                                .unwrap_or_default()
+                                .to_string()
+                        })
+                        .collect();
+
+                    return Ok(if r.command == "730" {
+                        Command::MonOnline { targets }
+                    } else {
+                        Command::MonOffline { targets }
+                    });
+                }
+            }
+
+            code if code.len() == 3 && code.chars().all(|c| c.is_ascii_digit()) => {
+                let mut params = Vec::new();
+
+                for (pos, param) in r.parameters.iter().enumerate() {
+                    if param.starts_with(TRAILING_DELIMITER) {
+                        params.push(r.parameters[pos..].join(SEPARATOR)[1..].to_string());
+                        break;
+                    }
+
+                    params.push(param.to_string());
+                }
+
+                return Ok(Command::Numeric {
+                    code: code.parse().unwrap_or_default(),
+                    params,
+                });
+            }
 
             _ => return Err(error::IllegalClientCommandError::new(r.command.to_string())),
         };
@@ -438,11 +1112,14 @@ impl ToString for Command {
                 ref password,
             } => format!("OPER {} {}", name, password),
             &UMode {
-                /*ref name,
-                ref modes,*/..
+                ref name,
+                ref modes,
             } => {
-                //format!("MODE {} {}", name, modes.to_string())
-                format!("")
+                if modes.is_empty() {
+                    format!("MODE {}", name)
+                } else {
+                    format!("MODE {} {}", name, Mode::to_list_string(modes))
+                }
             }
             &Service {
                 ref name,
@@ -469,6 +1146,7 @@ impl ToString for Command {
                 "JOIN {}{}",
                 channels.join(","),
                 Some(keys.join(","))
+                    .filter(|kl| !kl.is_empty())
                     .map(|kl| format!(" {}", kl))
                     .unwrap_or_default()
             ),
@@ -486,9 +1164,15 @@ impl ToString for Command {
                     .unwrap_or_default()
             ),
             &CMode {
-                /*ref channel,
-                ref modes,*/..
-            } => format!(""),
+                ref channel,
+                ref modes,
+            } => {
+                if modes.is_empty() {
+                    format!("MODE {}", channel)
+                } else {
+                    format!("MODE {} {}", channel, channel::Mode::to_list_string(modes))
+                }
+            }
             &Topic {
                 ref channel,
                 ref text,
@@ -503,8 +1187,11 @@ impl ToString for Command {
                 ref channels,
                 ref server,
             } => format!(
-                "{}{}",
-                channels.as_ref().map(|cl| cl.join(",")).unwrap_or_default(),
+                "NAMES{}{}",
+                channels
+                    .as_ref()
+                    .map(|cl| format!(" {}", cl.join(",")))
+                    .unwrap_or_default(),
                 server
                     .as_ref()
                     .map(|s| format!(" {}", s))
@@ -514,8 +1201,11 @@ impl ToString for Command {
                 ref channels,
                 ref server,
             } => format!(
-                "{}{}",
-                channels.as_ref().map(|cl| cl.join(",")).unwrap_or_default(),
+                "LIST{}{}",
+                channels
+                    .as_ref()
+                    .map(|cl| format!(" {}", cl.join(",")))
+                    .unwrap_or_default(),
                 server
                     .as_ref()
                     .map(|s| format!(" {}", s))
@@ -530,7 +1220,7 @@ impl ToString for Command {
                 ref users,
                 ref reason,
             } => format!(
-                "{} {}{}",
+                "KICK {} {}{}",
                 channels.join(","),
                 users.join(","),
                 reason
@@ -543,11 +1233,19 @@ impl ToString for Command {
             &PrivMsg {
                 ref targets,
                 ref text,
-            } => format!("PRIVMSG {} :{}", targets.join(","), text),
+            } => format!(
+                "PRIVMSG {} :{}",
+                targets
+                    .iter()
+                    .map(|target| target.to_string())
+                    .collect::<Vec<String>>()
+                    .join(","),
+                text
+            ),
             &Notice {
                 ref target,
                 ref text,
-            } => format!("NOTICE {} :{}", target, text),
+            } => format!("NOTICE {} :{}", target.to_string(), text),
 
             // Server
             &Motd { ref server } => format!(
@@ -590,9 +1288,18 @@ impl ToString for Command {
                     .unwrap_or_default()
             ),
             &Links {
-                /*ref server,
-                ref mask,*/..
-            } => format!(""), // none | mask | server, mask (in order)
+                ref server,
+                ref mask,
+            } => format!(
+                "LINKS{}{}",
+                server
+                    .as_ref()
+                    .map(|s| format!(" {}", s))
+                    .unwrap_or_default(),
+                mask.as_ref()
+                    .map(|m| format!(" {}", m))
+                    .unwrap_or_default()
+            ),
             &Time { ref server } => format!(
                 "TIME{}",
                 server
@@ -641,14 +1348,20 @@ impl ToString for Command {
 
             // User
             &Who {
-                /*ref mask,
-                ref operators_only,*/
-                ..
-            } => format!(""),
+                ref mask,
+                ref operators_only,
+            } => format!("WHO {}{}", mask, if *operators_only { " o" } else { "" }),
             &WhoIs {
-                /*ref server,
-                ref masks,*/..
-            } => format!(""),
+                ref server,
+                ref masks,
+            } => format!(
+                "WHOIS {}{}",
+                server
+                    .as_ref()
+                    .map(|s| format!("{} ", s))
+                    .unwrap_or_default(),
+                masks.join(",")
+            ),
             &WhoWas {
                 /*ref users,
                 ref count,
@@ -684,11 +1397,154 @@ impl ToString for Command {
             ),
             &ErrorMsg { ref text } => format!("ERROR :{}", text),
 
+            // Optional
+            &Rehash => format!("REHASH"),
+            &IsOn { ref nicks } => format!("ISON {}", nicks.join(SEPARATOR)),
+
+            // Server bans
+            &KLine {
+                ref duration,
+                ref mask,
+                ref reason,
+            } => format!("KLINE {}{} :{}", minutes(duration), mask, reason),
+            &UnKLine { ref mask } => format!("UNKLINE {}", mask),
+            &DLine {
+                ref duration,
+                ref mask,
+                ref reason,
+            } => format!("DLINE {}{} :{}", minutes(duration), mask, reason),
+            &UnDLine { ref mask } => format!("UNDLINE {}", mask),
+
+            // IRCv3
+            &MonitorAdd { ref targets } => format!("MONITOR + {}", targets.join(",")),
+            &MonitorRemove { ref targets } => format!("MONITOR - {}", targets.join(",")),
+            &ChgHost { ref user, ref host } => format!("CHGHOST {} {}", user, host),
+            &CapLs { ref version } => format!(
+                "CAP LS{}",
+                version
+                    .as_ref()
+                    .map(|v| format!(" {}", v))
+                    .unwrap_or_default()
+            ),
+            &CapList => format!("CAP LIST"),
+            &CapReq { ref caps } => format!("CAP REQ :{}", caps.join(SEPARATOR)),
+            &CapEnd => format!("CAP END"),
+            &CapLsReply { ref caps, ref more } => format!(
+                "CAP * LS {}:{}",
+                if *more { "* " } else { "" },
+                caps.join(SEPARATOR)
+            ),
+            &CapListReply { ref caps, ref more } => format!(
+                "CAP * LIST {}:{}",
+                if *more { "* " } else { "" },
+                caps.join(SEPARATOR)
+            ),
+            &CapAck { ref caps } => format!("CAP * ACK :{}", caps.join(SEPARATOR)),
+            &CapNak { ref caps } => format!("CAP * NAK :{}", caps.join(SEPARATOR)),
+            &CapNew { ref caps } => format!("CAP * NEW :{}", caps.join(SEPARATOR)),
+            &CapDel { ref caps } => format!("CAP * DEL :{}", caps.join(SEPARATOR)),
+            &Batch {
+                ref reference,
+                ref kind,
+                ref params,
+            } => format!(
+                "BATCH {}{}{}",
+                reference,
+                kind.as_ref().map(|k| format!(" {}", k)).unwrap_or_default(),
+                params
+                    .iter()
+                    .map(|p| format!(" {}", p))
+                    .collect::<String>()
+            ),
+            &Authenticate { ref data } => format!("AUTHENTICATE {}", data),
+            &Account { ref account } => {
+                format!("ACCOUNT {}", account.as_ref().map_or("*", |a| a.as_str()))
+            }
+            &ExtendedJoin {
+                ref channel,
+                ref account,
+                ref real_name,
+            } => format!(
+                "JOIN {} {} :{}",
+                channel,
+                account.as_ref().map_or("*", |a| a.as_str()),
+                real_name
+            ),
+            &ChatHistory {
+                ref target,
+                ref range,
+            } => format!(
+                "CHATHISTORY {} {} {}",
+                range.subcommand(),
+                target,
+                range.params().join(SEPARATOR)
+            ),
+            &ChatHistoryTargets { start, end, limit } => format!(
+                "CHATHISTORY TARGETS {} {} {}",
+                history::Reference::Timestamp(start).to_string(),
+                history::Reference::Timestamp(end).to_string(),
+                limit
+            ),
+            &ChatHistoryTarget {
+                ref target,
+                time,
+            } => format!(
+                "CHATHISTORY TARGETS {} {}",
+                target,
+                time::format_time(time)
+            ),
+            &Fail {
+                ref command,
+                ref code,
+                ref context,
+                ref description,
+            } => format!(
+                "FAIL {} {} {}:{}",
+                command,
+                code,
+                context
+                    .iter()
+                    .map(|c| format!("{} ", c))
+                    .collect::<String>(),
+                description
+            ),
+
+            &Numeric {
+                ref code,
+                ref params,
+            } => {
+                let mut line = format!("{:03}", code);
+
+                for (pos, param) in params.iter().enumerate() {
+                    let trailing = pos + 1 == params.len()
+                        && (param.is_empty()
+                        || param.contains(' ')
+                        || param.starts_with(TRAILING_DELIMITER));
+
+                    line.push_str(SEPARATOR);
+
+                    if trailing {
+                        line.push_str(TRAILING_DELIMITER);
+                    }
+
+                    line.push_str(param);
+                }
+
+                line
+            }
+
             _ => format!(""),
         }
     }
 }
 
+/// The optional duration of server bans, with the separator to the mask
+fn minutes(duration: &Option<u64>) -> String {
+    duration
+        .map(|minutes| format!("{} ", minutes))
+        .unwrap_or_default()
+}
+
 impl ToMessage<Command> for Command {
     fn into_message(self) -> Message<Command> {
         Message::from(self)
@@ -1,15 +1,753 @@
 use std::error::Error;
 
+use crate::{
+    command::{RawCommand, TRAILING_DELIMITER},
+    message::{Message, ToMessage},
+    mode::{channel, user, Mode},
+    origin::Origin,
+    parsing,
+    server::Server,
+    target::MessageTarget,
+    user::User,
+    validate, LIST_ITEM_DELIMITER, SEPARATOR,
+};
+
 pub mod error {
-    impl_error!(IllegalServerCommandError {});
+    impl_error!(IllegalServerCommandError { cmd: String });
+}
+
+/// Separates a channel from the modes the user gets in a server `JOIN`
+pub const JOIN_MODES_SEPARATOR: char = '\x07';
+
+/// Protocol version sent with `PASS`
+pub const VERSION: &str = "0210";
+
+/// Flags sent with `PASS`, `IRC|` means no special implementation
+pub const FLAGS: &str = "IRC|";
+
+#[derive(Debug, Clone, PartialEq)]
+/// Member of a channel as sent in `NJOIN`
+pub struct Member {
+    pub nick: String,
+    pub creator: bool,
+    pub operator: bool,
+    pub voice: bool,
+}
+
+impl Member {
+    pub fn parse(data: &str) -> Result<Self, Box<Error>> {
+        let creator = data.starts_with("@@");
+        let rest = if creator { &data[2..] } else { data };
+
+        let operator = creator || rest.starts_with('@');
+        let rest = if operator && !creator { &rest[1..] } else { rest };
+
+        let voice = rest.starts_with('+');
+        let nick = if voice { &rest[1..] } else { rest };
+
+        validate::nick_name(nick)?;
+
+        Ok(Member {
+            nick: nick.to_string(),
+            creator,
+            operator,
+            voice,
+        })
+    }
+}
+
+impl ToString for Member {
+    fn to_string(&self) -> String {
+        format!(
+            "{}{}{}",
+            if self.creator {
+                "@@"
+            } else if self.operator {
+                "@"
+            } else {
+                ""
+            },
+            if self.voice { "+" } else { "" },
+            self.nick
+        )
+    }
 }
 
 // RFC 2813
-#[derive(Debug)]
-pub enum Command {}
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // Connection
+    Pass {
+        password: String,
+        version: Option<String>,
+        flags: Option<String>,
+        options: Vec<String>,
+    },
+    Server {
+        name: String,
+        hop_count: u32,
+        token: u32,
+        info: String,
+    },
+    /// Introduces a user to the network (`NICK` with all parameters), the timestamp of the nick
+    /// follows the modes if the server sends one
+    NewUser {
+        nick: String,
+        hop_count: u32,
+        user: String,
+        host: String,
+        token: u32,
+        modes: Vec<Mode<user::Mode>>,
+        timestamp: Option<u64>,
+        real_name: String,
+    },
+    /// Nick change of a known user, optionally with the time of the change
+    Nick {
+        name: String,
+        timestamp: Option<u64>,
+    },
+    Service {
+        name: String,
+        token: u32,
+        distribution: String,
+        kind: String,
+        hop_count: u32,
+        info: String,
+    },
+    Quit {
+        reason: Option<String>,
+    },
+    SQuit {
+        server: String,
+        comment: String,
+    },
+
+    // Channel
+    /// Channels with the modes the user gets there, like `#channel^Gov`
+    Join {
+        channels: Vec<(String, String)>,
+    },
+    NJoin {
+        channel: String,
+        members: Vec<Member>,
+    },
+    Part {
+        channels: Vec<String>,
+        reason: Option<String>,
+    },
+    CMode {
+        channel: String,
+        modes: Vec<Mode<channel::Mode>>,
+    },
+    UMode {
+        name: String,
+        modes: Vec<Mode<user::Mode>>,
+    },
+    Topic {
+        channel: String,
+        text: String,
+    },
+    Invite {
+        nick: String,
+        channel: String,
+    },
+    Kick {
+        channel: String,
+        user: String,
+        reason: Option<String>,
+    },
+
+    // Messages
+    PrivMsg {
+        target: MessageTarget,
+        text: String,
+    },
+    Notice {
+        target: MessageTarget,
+        text: String,
+    },
+
+    // Misc
+    Kill {
+        user: String,
+        comment: String,
+    },
+    /// Account the user logged into, `None` after logging out. Not part of RFC 2813, it
+    /// carries logins to the other servers.
+    Account {
+        account: Option<String>,
+    },
+    Ping {
+        server1: String,
+        server2: Option<String>,
+    },
+    Pong {
+        server1: String,
+        server2: Option<String>,
+    },
+    ErrorMsg {
+        text: String,
+    },
+    /// Reply to a user on another server, the first parameter is the target
+    Numeric {
+        code: u16,
+        params: Vec<String>,
+    },
+}
+
+/// Joins the parameters from `pos` on, without the leading `:`
+fn trailing(params: &[&str], pos: usize) -> String {
+    parsing::skip_maybe_trailing(&params[pos..].join(SEPARATOR)).to_string()
+}
+
+fn number<T: std::str::FromStr>(data: &str) -> Result<T, Box<Error>> {
+    data.parse()
+        .map_err(|_| error::IllegalServerCommandError::new(data.to_string()))
+}
+
+impl crate::command::Command for Command {
+    fn try_from<'a>(r: RawCommand<'a>) -> Result<Self, Box<Error>> {
+        let p = &r.parameters;
+
+        match r.command {
+            // Connection
+            "PASS" if !p.is_empty() => {
+                return Ok(Command::Pass {
+                    password: parsing::skip_maybe_trailing(p[0]).to_string(),
+                    version: p.get(1).map(|v| v.to_string()),
+                    flags: p.get(2).map(|f| f.to_string()),
+                    options: p.iter().skip(3).map(|o| o.to_string()).collect(),
+                });
+            }
+            "SERVER" if p.len() >= 4 => {
+                return Ok(Command::Server {
+                    name: parsing::server_name(p[0])?.to_string(),
+                    hop_count: number(p[1])?,
+                    token: number(p[2])?,
+                    info: trailing(p, 3),
+                });
+            }
+            "NICK" if p.len() >= 7 => {
+                let timestamp = Some(p[6])
+                    .filter(|_| p.len() >= 8)
+                    .and_then(|timestamp| timestamp.parse().ok());
+
+                return Ok(Command::NewUser {
+                    nick: parsing::nick_name(p[0])?.to_string(),
+                    hop_count: number(p[1])?,
+                    user: parsing::user_name(p[2])?.to_string(),
+                    host: p[3].to_string(),
+                    token: number(p[4])?,
+                    modes: Mode::parse_list(p[5])?,
+                    timestamp,
+                    real_name: trailing(p, if timestamp.is_some() { 7 } else { 6 }),
+                });
+            }
+            "NICK" if p.len() <= 2 && !p.is_empty() => {
+                return Ok(Command::Nick {
+                    name: parsing::nick_name(parsing::skip_maybe_trailing(p[0]))?.to_string(),
+                    timestamp: match p.get(1) {
+                        Some(timestamp) => Some(number(parsing::skip_maybe_trailing(timestamp))?),
+                        None => None,
+                    },
+                });
+            }
+            "SERVICE" if p.len() >= 6 => {
+                return Ok(Command::Service {
+                    name: p[0].to_string(),
+                    token: number(p[1])?,
+                    distribution: p[2].to_string(),
+                    kind: p[3].to_string(),
+                    hop_count: number(p[4])?,
+                    info: trailing(p, 5),
+                });
+            }
+            "QUIT" => {
+                return Ok(Command::Quit {
+                    reason: Some(trailing(p, 0)).filter(|r| !r.is_empty()),
+                });
+            }
+            "SQUIT" if p.len() >= 2 => {
+                return Ok(Command::SQuit {
+                    server: p[0].to_string(),
+                    comment: trailing(p, 1),
+                });
+            }
+
+            // Channel
+            "JOIN" if !p.is_empty() => {
+                let mut channels = Vec::new();
+
+                for channel in parsing::skip_maybe_trailing(p[0]).split(LIST_ITEM_DELIMITER) {
+                    let (name, modes) = match channel.find(JOIN_MODES_SEPARATOR) {
+                        Some(pos) => (&channel[..pos], &channel[pos + 1..]),
+                        None => (channel, ""),
+                    };
+
+                    validate::channel_name(name)?;
+                    channels.push((name.to_string(), modes.to_string()));
+                }
+
+                return Ok(Command::Join { channels });
+            }
+            "NJOIN" if p.len() >= 2 => {
+                let members = trailing(p, 1)
+                    .split(LIST_ITEM_DELIMITER)
+                    .map(Member::parse)
+                    .collect::<Result<Vec<Member>, Box<Error>>>()?;
+
+                return Ok(Command::NJoin {
+                    channel: parsing::channel_name(p[0])?.to_string(),
+                    members,
+                });
+            }
+            "PART" if !p.is_empty() => {
+                return Ok(Command::Part {
+                    channels: p[0].split(LIST_ITEM_DELIMITER).map(|c| c.to_string()).collect(),
+                    reason: Some(trailing(p, 1)).filter(|r| !r.is_empty()),
+                });
+            }
+            "MODE" if p.len() >= 2 && validate::channel_name(p[0]).is_ok() => {
+                let params = p[2..]
+                    .iter()
+                    .map(|param| parsing::skip_maybe_trailing(param))
+                    .collect::<Vec<&str>>();
+
+                return Ok(Command::CMode {
+                    channel: p[0].to_string(),
+                    modes: channel::Mode::parse_list(parsing::skip_maybe_trailing(p[1]), &params)?,
+                });
+            }
+            "MODE" if p.len() >= 2 => {
+                return Ok(Command::UMode {
+                    name: parsing::nick_name(p[0])?.to_string(),
+                    modes: Mode::parse_list(parsing::skip_maybe_trailing(p[1]))?,
+                });
+            }
+            "TOPIC" if p.len() >= 2 => {
+                return Ok(Command::Topic {
+                    channel: parsing::channel_name(p[0])?.to_string(),
+                    text: trailing(p, 1),
+                });
+            }
+            "INVITE" if p.len() >= 2 => {
+                return Ok(Command::Invite {
+                    nick: parsing::nick_name(p[0])?.to_string(),
+                    channel: parsing::channel_name(parsing::skip_maybe_trailing(p[1]))?
+                        .to_string(),
+                });
+            }
+            "KICK" if p.len() >= 2 => {
+                return Ok(Command::Kick {
+                    channel: parsing::channel_name(p[0])?.to_string(),
+                    user: p[1].to_string(),
+                    reason: Some(trailing(p, 2)).filter(|r| !r.is_empty()),
+                });
+            }
+
+            // Messages
+            "PRIVMSG" if p.len() >= 2 => {
+                return Ok(Command::PrivMsg {
+                    target: parsing::msg_to(p[0])?,
+                    text: trailing(p, 1),
+                });
+            }
+            "NOTICE" if p.len() >= 2 => {
+                return Ok(Command::Notice {
+                    target: parsing::msg_to(p[0])?,
+                    text: trailing(p, 1),
+                });
+            }
+
+            // Misc
+            "KILL" if p.len() >= 2 => {
+                return Ok(Command::Kill {
+                    user: p[0].to_string(),
+                    comment: trailing(p, 1),
+                });
+            }
+            "ACCOUNT" if p.len() == 1 => {
+                let account = parsing::skip_maybe_trailing(p[0]);
+
+                return Ok(Command::Account {
+                    account: Some(account)
+                        .filter(|account| *account != "*")
+                        .map(|account| account.to_string()),
+                });
+            }
+            "PING" if !p.is_empty() => {
+                return Ok(Command::Ping {
+                    server1: parsing::skip_maybe_trailing(p[0]).to_string(),
+                    server2: p.get(1).map(|s| parsing::skip_maybe_trailing(s).to_string()),
+                });
+            }
+            "PONG" if !p.is_empty() => {
+                return Ok(Command::Pong {
+                    server1: parsing::skip_maybe_trailing(p[0]).to_string(),
+                    server2: p.get(1).map(|s| parsing::skip_maybe_trailing(s).to_string()),
+                });
+            }
+            "ERROR" => {
+                return Ok(Command::ErrorMsg {
+                    text: trailing(p, 0),
+                });
+            }
+            code if code.len() == 3 && code.chars().all(|c| c.is_ascii_digit()) => {
+                let mut params = Vec::new();
+
+                for (pos, param) in p.iter().enumerate() {
+                    if param.starts_with(TRAILING_DELIMITER) {
+                        params.push(trailing(p, pos));
+                        break;
+                    }
+
+                    params.push(param.to_string());
+                }
+
+                return Ok(Command::Numeric {
+                    code: number(code)?,
+                    params,
+                });
+            }
+
+            _ => (),
+        }
+
+        Err(error::IllegalServerCommandError::new(r.command.to_string()))
+    }
+}
+
+fn optional(data: &Option<String>) -> String {
+    data.as_ref()
+        .map(|d| format!(" {}", d))
+        .unwrap_or_default()
+}
+
+fn optional_trailing(data: &Option<String>) -> String {
+    data.as_ref()
+        .map(|d| format!(" :{}", d))
+        .unwrap_or_default()
+}
+
+impl ToString for Command {
+    fn to_string(&self) -> String {
+        use Command::*;
+
+        match self {
+            // Connection
+            &Pass {
+                ref password,
+                ref version,
+                ref flags,
+                ref options,
+            } => format!(
+                "PASS {}{}{}{}",
+                password,
+                optional(version),
+                optional(flags),
+                options.iter().map(|o| format!(" {}", o)).collect::<String>()
+            ),
+            &Server {
+                ref name,
+                ref hop_count,
+                ref token,
+                ref info,
+            } => format!("SERVER {} {} {} :{}", name, hop_count, token, info),
+            &NewUser {
+                ref nick,
+                ref hop_count,
+                ref user,
+                ref host,
+                ref token,
+                ref modes,
+                ref timestamp,
+                ref real_name,
+            } => format!(
+                "NICK {} {} {} {} {} {}{} :{}",
+                nick,
+                hop_count,
+                user,
+                host,
+                token,
+                if modes.is_empty() {
+                    "+".to_string()
+                } else {
+                    Mode::to_list_string(modes)
+                },
+                timestamp.map(|t| format!(" {}", t)).unwrap_or_default(),
+                real_name
+            ),
+            &Nick {
+                ref name,
+                timestamp: None,
+            } => format!("NICK :{}", name),
+            &Nick {
+                ref name,
+                timestamp: Some(ref timestamp),
+            } => format!("NICK {} :{}", name, timestamp),
+            &Service {
+                ref name,
+                ref token,
+                ref distribution,
+                ref kind,
+                ref hop_count,
+                ref info,
+            } => format!(
+                "SERVICE {} {} {} {} {} :{}",
+                name, token, distribution, kind, hop_count, info
+            ),
+            &Quit { ref reason } => format!("QUIT{}", optional_trailing(reason)),
+            &SQuit {
+                ref server,
+                ref comment,
+            } => format!("SQUIT {} :{}", server, comment),
+
+            // Channel
+            &Join { ref channels } => format!(
+                "JOIN {}",
+                channels
+                    .iter()
+                    .map(|(name, modes)| if modes.is_empty() {
+                        name.to_string()
+                    } else {
+                        format!("{}{}{}", name, JOIN_MODES_SEPARATOR, modes)
+                    })
+                    .collect::<Vec<String>>()
+                    .join(LIST_ITEM_DELIMITER)
+            ),
+            &NJoin {
+                ref channel,
+                ref members,
+            } => format!(
+                "NJOIN {} :{}",
+                channel,
+                members
+                    .iter()
+                    .map(|m| m.to_string())
+                    .collect::<Vec<String>>()
+                    .join(LIST_ITEM_DELIMITER)
+            ),
+            &Part {
+                ref channels,
+                ref reason,
+            } => format!(
+                "PART {}{}",
+                channels.join(LIST_ITEM_DELIMITER),
+                optional_trailing(reason)
+            ),
+            &CMode {
+                ref channel,
+                ref modes,
+            } => format!("MODE {} {}", channel, channel::Mode::to_list_string(modes)),
+            &UMode {
+                ref name,
+                ref modes,
+            } => format!("MODE {} {}", name, Mode::to_list_string(modes)),
+            &Topic {
+                ref channel,
+                ref text,
+            } => format!("TOPIC {} :{}", channel, text),
+            &Invite {
+                ref nick,
+                ref channel,
+            } => format!("INVITE {} {}", nick, channel),
+            &Kick {
+                ref channel,
+                ref user,
+                ref reason,
+            } => format!("KICK {} {}{}", channel, user, optional_trailing(reason)),
+
+            // Messages
+            &PrivMsg {
+                ref target,
+                ref text,
+            } => format!("PRIVMSG {} :{}", target.to_string(), text),
+            &Notice {
+                ref target,
+                ref text,
+            } => format!("NOTICE {} :{}", target.to_string(), text),
+
+            // Misc
+            &Kill {
+                ref user,
+                ref comment,
+            } => format!("KILL {} :{}", user, comment),
+            &Account { ref account } => {
+                format!("ACCOUNT {}", account.as_ref().map_or("*", |a| a.as_str()))
+            }
+            &Ping {
+                ref server1,
+                ref server2,
+            } => format!("PING {}{}", server1, optional(server2)),
+            &Pong {
+                ref server1,
+                ref server2,
+            } => format!("PONG {}{}", server1, optional(server2)),
+            &ErrorMsg { ref text } => format!("ERROR :{}", text),
+            &Numeric {
+                ref code,
+                ref params,
+            } => {
+                let mut line = format!("{:03}", code);
+
+                for (pos, param) in params.iter().enumerate() {
+                    line.push_str(SEPARATOR);
+
+                    if pos + 1 == params.len() {
+                        line.push_str(TRAILING_DELIMITER);
+                    }
+
+                    line.push_str(param);
+                }
+
+                line
+            }
+        }
+    }
+}
+
+impl ToMessage<Command> for Command {
+    fn into_message(self) -> Message<Command> {
+        Message::from(self)
+    }
+}
 
 impl Command {
-    pub fn parse(_cmd: &str, _params: Option<&str>) -> Result<Self, Box<Error>> {
-        Err(error::IllegalServerCommandError::new())
+    /// Builds the `PASS` a server sends before its `SERVER` line
+    pub fn pass(password: &str) -> Self {
+        Command::Pass {
+            password: password.to_string(),
+            version: Some(VERSION.to_string()),
+            flags: Some(FLAGS.to_string()),
+            options: Vec::new(),
+        }
+    }
+
+    /// The user a `NewUser` introduces as origin of the messages
+    pub fn origin(&self) -> Option<Origin> {
+        match self {
+            Command::NewUser {
+                ref nick,
+                ref user,
+                ref host,
+                ..
+            } => Some(Origin::User {
+                nick: nick.to_string(),
+                user: Some(user.to_string()),
+                host: Some(host.to_string()),
+            }),
+            _ => None,
+        }
     }
 }
+
+/// `NICK` introducing a user of the network known to `server` to a neighbour
+pub fn introduce(server: &Server, user: &User) -> Command {
+    let origin = user.origin();
+    let home = user
+        .server()
+        .and_then(|name| server.server(name))
+        .unwrap_or(server);
+
+    Command::NewUser {
+        nick: origin.nick().unwrap_or_default().to_string(),
+        hop_count: home.hop_count() + 1,
+        user: origin.user().unwrap_or_default().to_string(),
+        host: origin.host().unwrap_or_default().to_string(),
+        token: home.token(),
+        modes: user.modes().clone(),
+        timestamp: Some(user.timestamp()),
+        real_name: user.real_name().to_string(),
+    }
+}
+
+/// State a server sends after the link is established: the servers it knows, all users, then
+/// the channels with their members, modes and topics
+pub fn burst(server: &Server) -> Vec<Message<Command>> {
+    let mut messages = Vec::new();
+
+    for other in server.servers() {
+        let uplink = Origin::Server {
+            name: other.uplink().unwrap_or_else(|| server.name()).to_string(),
+        };
+
+        messages.push(Message::new(
+            uplink,
+            Command::Server {
+                name: other.name().to_string(),
+                hop_count: other.hop_count() + 1,
+                token: other.token(),
+                info: other.info().to_string(),
+            },
+        ));
+    }
+
+    for user in server.users() {
+        let user = user.borrow();
+
+        messages.push(Message::from(introduce(server, &user)));
+
+        if let Some(account) = user.account() {
+            messages.push(Message::new(
+                user.origin().clone(),
+                Command::Account {
+                    account: Some(account.to_string()),
+                },
+            ));
+        }
+    }
+
+    for channel in server.channels() {
+        let channel = channel.borrow();
+
+        let members = channel
+            .members()
+            .iter()
+            .filter_map(|member| {
+                let user = member.user().upgrade()?;
+                let nick = user.borrow().origin().nick().unwrap_or_default().to_string();
+
+                Some(Member {
+                    nick,
+                    creator: false,
+                    operator: member.is_operator(),
+                    voice: member.is_voiced(),
+                })
+            })
+            .collect::<Vec<Member>>();
+
+        messages.push(Message::from(Command::NJoin {
+            channel: channel.name().to_string(),
+            members,
+        }));
+
+        if !channel.modes().is_empty() {
+            messages.push(Message::new(
+                server.origin().clone(),
+                Command::CMode {
+                    channel: channel.name().to_string(),
+                    modes: channel.modes().clone(),
+                },
+            ));
+        }
+
+        if let Some(topic) = channel.topic() {
+            let setter = channel.topic_setter().unwrap_or_default();
+
+            let origin = match server.user(setter) {
+                Some(user) => user.borrow().origin().clone(),
+                None => server.origin().clone(),
+            };
+
+            messages.push(Message::new(
+                origin,
+                Command::Topic {
+                    channel: channel.name().to_string(),
+                    text: topic.to_string(),
+                },
+            ));
+        }
+    }
+
+    messages
+}
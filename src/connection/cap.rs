@@ -0,0 +1,169 @@
+use std::error::Error;
+
+use crate::{
+    command::client::Command::{self, *},
+    message::Message,
+    stream::ClientStream,
+};
+
+pub const VERSION: &str = "302";
+
+pub const VALUE_SEPARATOR: char = '=';
+
+#[derive(Debug, Clone, PartialEq)]
+/// IRCv3 capability as advertised by `CAP LS` (`name` or `name=value`)
+pub struct Capability {
+    name: String,
+    value: Option<String>,
+}
+
+impl Capability {
+    pub fn parse(data: &str) -> Self {
+        match data.find(VALUE_SEPARATOR) {
+            Some(pos) => Capability {
+                name: data[..pos].to_string(),
+                value: Some(data[pos + 1..].to_string()),
+            },
+            None => Capability {
+                name: data.to_string(),
+                value: None,
+            },
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_ref().map(|v| v.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+/// Client side capability negotiation. Requests the wanted capabilities out of those the server
+/// offers and ends the negotiation once it answered.
+pub struct Negotiation {
+    requested: Vec<String>,
+    available: Vec<Capability>,
+    enabled: Vec<Capability>,
+    done: bool,
+}
+
+impl Negotiation {
+    pub fn new(requested: Vec<String>) -> Self {
+        Negotiation {
+            done: requested.is_empty(),
+            requested,
+            ..Default::default()
+        }
+    }
+}
+
+impl Negotiation {
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    pub fn enabled(&self) -> &Vec<Capability> {
+        &self.enabled
+    }
+
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.enabled.iter().any(|cap| cap.name() == name)
+    }
+
+    /// Value the server advertised for an enabled capability
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.enabled
+            .iter()
+            .find(|cap| cap.name() == name)
+            .and_then(|cap| cap.value())
+    }
+}
+
+impl Negotiation {
+    pub fn start(&self, stream: &ClientStream) -> Result<(), Box<Error>> {
+        if !self.done {
+            stream.send(CapLs {
+                version: Some(VERSION.to_string()),
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn handle(
+        &mut self,
+        stream: &ClientStream,
+        message: &Message<Command>,
+    ) -> Result<(), Box<Error>> {
+        match message.command() {
+            CapLsReply { caps, more } if !self.done => {
+                self.available
+                    .extend(caps.iter().map(|cap| Capability::parse(cap)));
+
+                if !more {
+                    let available = &self.available;
+                    let wanted = self
+                        .requested
+                        .iter()
+                        .filter(|name| available.iter().any(|cap| cap.name() == name.as_str()))
+                        .cloned()
+                        .collect::<Vec<String>>();
+
+                    if wanted.is_empty() {
+                        self.end(stream)?;
+                    } else {
+                        stream.send(CapReq { caps: wanted })?;
+                    }
+                }
+            }
+
+            CapAck { caps } => {
+                for name in caps {
+                    if name.starts_with('-') {
+                        self.enabled.retain(|cap| cap.name() != &name[1..]);
+                    } else if !self.is_enabled(name) {
+                        let cap = self
+                            .available
+                            .iter()
+                            .find(|cap| cap.name() == name.as_str())
+                            .cloned()
+                            .unwrap_or_else(|| Capability::parse(name));
+
+                        self.enabled.push(cap);
+                    }
+                }
+
+                self.end(stream)?;
+            }
+
+            CapNak { .. } => self.end(stream)?,
+
+            CapNew { caps } => {
+                self.available
+                    .extend(caps.iter().map(|cap| Capability::parse(cap)));
+            }
+
+            CapDel { caps } => {
+                self.available.retain(|cap| !caps.iter().any(|name| name == cap.name()));
+                self.enabled.retain(|cap| !caps.iter().any(|name| name == cap.name()));
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    fn end(&mut self, stream: &ClientStream) -> Result<(), Box<Error>> {
+        if !self.done {
+            self.done = true;
+
+            stream.send(CapEnd)?;
+        }
+
+        Ok(())
+    }
+}
@@ -1,35 +1,72 @@
 use crate::{
-    stream::ClientStream,
+    stream::{Accept, ClientStream, Dial, Handshake},
     user::User,
     channel::Channel,
     server::Server,
     origin::Origin,
-    command::client::Command::{*, User as _User},
+    command::client::Command::{self, *},
+    connection::{
+        cap::Negotiation,
+        nick::{Alternatives, Fallback, Regain, RegainMethod},
+        policy::{Candidate, ConnectPolicy},
+        registration::{self, Registration},
+        tracking,
+    },
+    ctcp::{Ctcp, Responder},
+    encoding::Encoding,
+    history::{self, Range},
+    limits,
+    message::{Message, ToMessage},
+    split,
+    target::MessageTarget,
     utils::Defaults,
 };
 
 use std::{
-    net::ToSocketAddrs
+    cell::Cell,
+    collections::VecDeque,
+    convert::TryFrom,
+    net::ToSocketAddrs,
+    time::{Duration, Instant},
 };
 
 pub use crate::stream::Port;
 
+/// Common `USERLEN` plus the `~` added for missing ident responses, used until the real one is
+/// known
+const USER_NAME_ESTIMATE: usize = 11;
+
+pub const MULTILINE: &str = "draft/multiline";
+pub const MULTILINE_CONCAT: &str = "draft/multiline-concat";
+
+/// Time the server has to answer a `CHATHISTORY` request
+pub const HISTORY_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub mod error {
     impl_error!(MissingParameterError {parameter: String});
     impl_error!(ConnectionError {error: Box<std::error::Error>});
 
     impl_error!(InvalidPassword {});
     impl_error!(Error {message: String});
+
+    impl_error!(CapabilityNotEnabledError {capability: String});
+    impl_error!(HistoryError {code: String, description: String});
+    impl_error!(HistoryTimeoutError {});
 }
 
 #[derive(Default)]
 pub struct Builder {
     user: Option<User>,
-    //password: Option<String>,
-    host: String,
-    ports: Vec<Port>,
-    ports_filter: Vec<Box<Fn(&mut Vec<Port>)>>,
+    password: Option<String>,
+    policy: ConnectPolicy,
     timeout: Option<std::time::Duration>,
+    registration_timeout: Option<std::time::Duration>,
+    alt_nicks: Vec<String>,
+    nick_fallback: Option<Fallback>,
+    regain: Option<RegainMethod>,
+    caps: Vec<String>,
+    ctcp: Option<Responder>,
+    encoding: Option<Encoding>,
 }
 
 impl Builder {
@@ -40,76 +77,63 @@ impl Builder {
         self
     }
 
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+
+        self
+    }
+
+    pub fn policy(mut self, policy: ConnectPolicy) -> Self {
+        self.policy = policy;
+
+        self
+    }
+
     pub fn host(mut self, host: &str) -> Self {
-        self.host = host.to_string();
+        self.policy = self.policy.host(host);
+
+        self
+    }
+
+    /// Multiple host names of the same network, tried in order after those added before
+    pub fn hosts(mut self, hosts: Vec<&str>) -> Self {
+        self.policy = self.policy.hosts(hosts);
 
         self
     }
 
     pub fn ports(mut self, ports: Vec<Port>) -> Self {
-        self.ports = ports;
+        self.policy = self.policy.ports(ports);
 
         self
     }
 
     pub fn port(mut self, port: Port) -> Self {
-        self.ports.push(port);
+        self.policy = self.policy.port(port);
 
         self
     }
 
     pub fn secure_only(mut self) -> Self {
-        self.ports_filter.push(Box::new(move |ports| {
-            for i in ports.len()..0 {
-                if let Port::Insecure(_) = ports[i] {
-                    ports.remove(i);
-                }
-            }
-        }));
+        self.policy = self.policy.secure_only();
 
         self
     }
 
     pub fn insecure_only(mut self) -> Self {
-        self.ports_filter.push(Box::new(move |ports| {
-            for i in ports.len()..0 {
-                if let Port::Secure(_) = ports[i] {
-                    ports.remove(i);
-                }
-            }
-        }));
+        self.policy = self.policy.insecure_only();
 
         self
     }
 
     pub fn priortize_secure(mut self) -> Self {
-        self.ports_filter.push(Box::new(move |ports| {
-            ports.sort_by(|c1, c2| {
-                if c1.secure() && c2.insecure() {
-                    std::cmp::Ordering::Less
-                } else if c1.insecure() && c2.secure() {
-                    std::cmp::Ordering::Greater
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            })
-        }));
+        self.policy = self.policy.prioritize_secure();
 
         self
     }
 
     pub fn priortize_insecure(mut self) -> Self {
-        self.ports_filter.push(Box::new(move |ports| {
-            ports.sort_by(|c1, c2| {
-                if c1.secure() && c2.insecure() {
-                    std::cmp::Ordering::Greater
-                } else if c1.insecure() && c2.secure() {
-                    std::cmp::Ordering::Less
-                } else {
-                    std::cmp::Ordering::Equal
-                }
-            })
-        }));
+        self.policy = self.policy.prioritize_insecure();
 
         self
     }
@@ -120,29 +144,255 @@ impl Builder {
         self
     }
 
-    pub fn build(mut self) -> Result<Client, Box<std::error::Error>> {
-        for filter in self.ports_filter {
-            filter(&mut self.ports);
+    /// How long to wait for RPL_WELCOME, defaults to `registration::DEFAULT_TIMEOUT`
+    pub fn registration_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.registration_timeout = Some(timeout);
+
+        self
+    }
+
+    /// Nicks to try in order if the server rejects the one of `user`
+    pub fn alt_nicks(mut self, nicks: Vec<&str>) -> Self {
+        self.alt_nicks = nicks.iter().map(|nick| nick.to_string()).collect();
+
+        self
+    }
+
+    /// Generates further nicks once `alt_nicks` are used up
+    pub fn nick_fallback(mut self, fallback: Fallback) -> Self {
+        self.nick_fallback = Some(fallback);
+
+        self
+    }
+
+    /// Tries to get the primary nick back if registration ended up with an alternative
+    pub fn regain(mut self, method: RegainMethod) -> Self {
+        self.regain = Some(method);
+
+        self
+    }
+
+    /// IRCv3 capabilities to request, if the server offers them
+    pub fn capabilities(mut self, caps: Vec<&str>) -> Self {
+        self.caps = caps.iter().map(|cap| cap.to_string()).collect();
+
+        self
+    }
+
+    /// Answers CTCP queries (VERSION, PING, ..) automatically
+    pub fn ctcp(mut self, responder: Responder) -> Self {
+        self.ctcp = Some(responder);
+
+        self
+    }
+
+    /// Encoding of the lines read and sent, UTF-8 with Latin-1 fallback by default
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+
+        self
+    }
+
+    /// Connects and registers, blocking until done
+    pub fn build(self) -> Result<Client, Box<std::error::Error>> {
+        let mut connecting = self.start()?;
+
+        loop {
+            connecting = match connecting.poll()? {
+                Progress::Done(client) => return Ok(*client),
+                Progress::Connecting(connecting) => connecting,
+            };
+
+            std::thread::sleep(Duration::from_millis(10));
         }
+    }
 
+    /// Starts connecting without waiting, `Connecting::poll` carries on
+    pub fn start(self) -> Result<Connecting, Box<std::error::Error>> {
         let user = match self.user {
             Some(user) => user,
             None => return Err(error::MissingParameterError::new(format!("User")))
         };
 
-        let mut last_error = None;
+        let primary = user.origin().nick().unwrap_or_default().to_string();
+
+        let registration = Registration::new(
+            &user,
+            self.password.as_ref().map(|p| p.as_str()),
+            self.registration_timeout.unwrap_or(registration::DEFAULT_TIMEOUT),
+        )
+            .alternatives(
+                Alternatives::new(&primary)
+                    .alternatives(self.alt_nicks)
+                    .fallback(self.nick_fallback),
+            )
+            .capabilities(self.caps);
+
+        let mut candidates: VecDeque<Candidate> = self.policy.candidates()?.into();
+
+        // there is at least one
+        let first = candidates.pop_front().unwrap();
+
+        Ok(Connecting {
+            state: Step::Dialing(ClientStream::dial(first.host(), first.port(), self.timeout)),
+            setup: Box::new(Setup {
+                candidates,
+                timeout: self.timeout,
+                encoding: self.encoding.unwrap_or_default(),
+                myself: user,
+                server: Server::default(),
+                registration,
+                regain: self.regain.map(|method| Regain::new(&primary, method)),
+                ctcp: self.ctcp,
+            }),
+        })
+    }
+}
+
+/// A client on its way to being registered
+pub enum Progress {
+    Done(Box<Client>),
+    Connecting(Connecting),
+}
+
+/// A connection `Builder::start` is setting up. Candidates are tried in order until one takes
+/// the connection, registration failures end it.
+pub struct Connecting {
+    state: Step,
+    setup: Box<Setup>,
+}
+
+enum Step {
+    Dialing(Dial),
+    Handshaking(Handshake),
+    Registering(ClientStream),
+}
+
+/// What the client is made of once registered
+struct Setup {
+    candidates: VecDeque<Candidate>,
+    timeout: Option<Duration>,
+    encoding: Encoding,
+    myself: User,
+    server: Server,
+    registration: Registration,
+    regain: Option<Regain>,
+    ctcp: Option<Responder>,
+}
+
+impl Connecting {
+    /// Takes the next step without blocking
+    pub fn poll(self) -> Result<Progress, Box<std::error::Error>> {
+        let Connecting { state, mut setup } = self;
+
+        let state = match state {
+            Step::Dialing(mut dial) => match dial.poll() {
+                Ok(Some(accept)) => setup.accepted(accept)?,
+                Ok(None) => Step::Dialing(dial),
+                Err(e) => setup.next(e)?,
+            },
+
+            Step::Handshaking(handshake) => match handshake.resume() {
+                Ok(accept) => setup.accepted(accept)?,
+                Err(e) => setup.next(e)?,
+            },
+
+            Step::Registering(stream) => {
+                if setup.register(&stream)? {
+                    return Ok(Progress::Done(Box::new(setup.finish(stream)?)));
+                }
+
+                Step::Registering(stream)
+            }
+        };
+
+        Ok(Progress::Connecting(Connecting { state, setup }))
+    }
+}
+
+impl Setup {
+    /// Dials the next candidate, or fails with `error` if there is none left
+    fn next(&mut self, error: Box<std::error::Error>) -> Result<Step, Box<std::error::Error>> {
+        match self.candidates.pop_front() {
+            Some(candidate) => Ok(Step::Dialing(ClientStream::dial(
+                candidate.host(),
+                candidate.port(),
+                self.timeout,
+            ))),
+            None => Err(error),
+        }
+    }
+
+    fn accepted(&mut self, accept: Accept<Command>) -> Result<Step, Box<std::error::Error>> {
+        match accept {
+            Accept::Done(stream) => {
+                stream.set_encoding(self.encoding);
+                self.registration.start(&stream)?;
+
+                Ok(Step::Registering(stream))
+            }
 
-        for port in self.ports {
-            match ClientStream::connect(&self.host, port, self.timeout) {
-                Ok(stream) => {
-                    return Client::initialize(stream, user, None)
+            Accept::Handshaking(handshake) => Ok(Step::Handshaking(handshake)),
+        }
+    }
+
+    /// Handles what the server sent so far, `true` once registered
+    fn register(&mut self, stream: &ClientStream) -> Result<bool, Box<std::error::Error>> {
+        // a server which keeps sending lines without welcoming us times out as well
+        while *self.registration.check_timeout()? != registration::State::Registered {
+            match stream.read()? {
+                Some(message) => {
+                    self.registration.handle(stream, &message)?;
+                    tracking::track(
+                        &mut self.server,
+                        self.myself.origin().nick().unwrap_or_default(),
+                        &message,
+                    );
+                    Client::track(&mut self.myself, &message);
                 }
 
-                Err(e) => last_error = Some(e)
+                None => return Ok(false),
             }
         }
 
-        Err(last_error.unwrap())
+        Ok(true)
+    }
+
+    fn finish(self: Box<Self>, stream: ClientStream) -> Result<Client, Box<std::error::Error>> {
+        let Setup {
+            mut myself,
+            mut server,
+            mut registration,
+            regain,
+            ctcp,
+            ..
+        } = *self;
+
+        if let Origin::User { ref mut nick, .. } = myself.origin_mut() {
+            *nick = registration.nick().to_string();
+        }
+
+        server.set_origin(registration.server_origin().cloned().unwrap_or_default());
+        server.set_motd(registration.motd().map(|motd| motd.to_string()));
+
+        // the WHO reply tells us our user and host, in case RPL_WELCOME didn't
+        if myself.origin().user().is_none() || myself.origin().host().is_none() {
+            stream.send(Who {
+                mask: registration.nick().to_string(),
+                operators_only: false,
+            })?;
+        }
+
+        Ok(Client {
+            myself,
+            stream,
+            server,
+            regain,
+            caps: registration.take_capabilities(),
+            batches: Cell::new(0),
+            ctcp,
+            queue: VecDeque::new(),
+        })
     }
 }
 
@@ -150,6 +400,12 @@ pub struct Client {
     stream: ClientStream,
     myself: User,
     server: Server,
+    regain: Option<Regain>,
+    caps: Negotiation,
+    batches: Cell<usize>,
+    ctcp: Option<Responder>,
+    /// Messages read while waiting for a reply, `read` returns them first
+    queue: VecDeque<Message<Command>>,
 }
 
 impl Client {
@@ -159,51 +415,394 @@ impl Client {
 }
 
 impl Client {
-    fn initialize(stream: ClientStream, myself: User, password: Option<&str>) -> Result<Self, Box<std::error::Error>> {
-        let mut server_motd = String::new();
-        let mut server_origin = None;
-
-        stream
-            .send(Nick {
-                name: myself.origin().nick().unwrap().to_string()
-            })?
-            .send(_User {
-                name: myself.origin().user().unwrap().to_string(),
-                modes: vec![],
-                real_name: myself.real_name().to_string(),
-            })?;
+    pub fn myself(&self) -> &User {
+        &self.myself
+    }
 
-        if let Some(password) = password {
-            stream.send(Pass { password: password.to_string() })?; // TODO: react on response!
-        }
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
 
-        let queued = stream
-            .iter()
-            .filter(|m| m.is_some())
-            .map(|m| m.unwrap());
+    pub fn capabilities(&self) -> &Negotiation {
+        &self.caps
+    }
 
-        for message in queued {
-            match message.command() {
-                MotdStart { .. } => server_origin = Some(message.origin().clone()),
-                MotdBody { text } => {
-                    server_motd.push_str(&format!("{}\n", text));
+    pub fn nick(&self) -> &str {
+        self.myself.origin().nick().unwrap_or_default()
+    }
+
+    /// Length of the prefix (`:nick!user@host `) servers put in front of our messages when
+    /// relaying them. Unknown parts are estimated with their maximum length.
+    pub fn prefix_len(&self) -> usize {
+        let origin = self.myself.origin();
+
+        let nick = origin.nick().map(|nick| nick.len()).unwrap_or(limits::NICK_NAME);
+        let user = origin.user().map(|user| user.len()).unwrap_or(USER_NAME_ESTIMATE);
+        let host = origin.host().map(|host| host.len()).unwrap_or(limits::HOST_NAME);
+
+        // `:`, `!`, `@` and the trailing space
+        nick + user + host + 4
+    }
+
+    pub fn send<T>(&self, msg_or_cmd: T) -> Result<&Self, Box<std::error::Error>>
+        where
+            T: ToMessage<Command> + std::fmt::Debug,
+    {
+        self.stream.send(msg_or_cmd)?;
+
+        Ok(self)
+    }
+
+    /// Sends `text` to `target` as `PRIVMSG`s, split so that every line fits into `limits::LINE`
+    pub fn privmsg(&self, target: &str, text: &str) -> Result<&Self, Box<std::error::Error>> {
+        self.send_text(target, text, false)
+    }
+
+    /// Sends `text` to `target` as `NOTICE`s, split so that every line fits into `limits::LINE`
+    pub fn notice(&self, target: &str, text: &str) -> Result<&Self, Box<std::error::Error>> {
+        self.send_text(target, text, true)
+    }
+
+    /// Sends a CTCP ACTION (`/me`) to `target`
+    pub fn action(&self, target: &str, text: &str) -> Result<&Self, Box<std::error::Error>> {
+        self.send(Ctcp::Action(text.to_string()).query(MessageTarget::parse(target)?))
+    }
+
+    fn send_text(
+        &self,
+        target: &str,
+        text: &str,
+        notice: bool,
+    ) -> Result<&Self, Box<std::error::Error>> {
+        let parsed = MessageTarget::parse(target)?;
+        let command = if notice { "NOTICE" } else { "PRIVMSG" };
+        let max = split::available(command, target, self.prefix_len());
+
+        let line = |text: &str| {
+            if notice {
+                Notice {
+                    target: parsed.clone(),
+                    text: text.to_string(),
                 }
-                MotdEnd { .. } => break,
-                ErrorMsg { text } => return Err(error::Error::new(text.to_string())),
+            } else {
+                PrivMsg {
+                    targets: vec![parsed.clone()],
+                    text: text.to_string(),
+                }
+            }
+        };
+
+        if let Some((max_bytes, max_lines)) = self.multiline_limits() {
+            let chunks = split::split_concat(text, max);
+            let total = chunks.iter().map(|chunk| chunk.text().len()).sum::<usize>();
+
+            if chunks.len() > 1 && chunks.len() <= max_lines && total <= max_bytes {
+                let reference = format!("ml{}", self.batches.get());
+                self.batches.set(self.batches.get() + 1);
+
+                self.stream.send(Batch {
+                    reference: format!("+{}", reference),
+                    kind: Some(MULTILINE.to_string()),
+                    params: vec![target.to_string()],
+                })?;
+
+                for chunk in chunks {
+                    let mut message = Message::from(line(chunk.text()))
+                        .with_tag("batch", Some(&reference));
+
+                    if chunk.concat() {
+                        message = message.with_tag(MULTILINE_CONCAT, None);
+                    }
+
+                    self.stream.send(message)?;
+                }
+
+                self.stream.send(Batch {
+                    reference: format!("-{}", reference),
+                    kind: None,
+                    params: Vec::new(),
+                })?;
+
+                return Ok(self);
+            }
+        }
+
+        for chunk in split::split(text, max) {
+            self.stream.send(line(chunk.text()))?;
+        }
+
+        Ok(self)
+    }
+
+    /// `max-bytes` and `max-lines` of `draft/multiline`, if it is enabled
+    fn multiline_limits(&self) -> Option<(usize, usize)> {
+        if !self.caps.is_enabled(MULTILINE) {
+            return None;
+        }
+
+        let mut max_bytes = 0;
+        let mut max_lines = usize::max_value();
+
+        for limit in self.caps.value(MULTILINE).unwrap_or_default().split(',') {
+            let mut parts = limit.splitn(2, '=');
+
+            match (parts.next(), parts.next().and_then(|v| v.parse::<usize>().ok())) {
+                (Some("max-bytes"), Some(value)) => max_bytes = value,
+                (Some("max-lines"), Some(value)) => max_lines = value,
                 _ => (),
             }
         }
 
-        let mut server = Server::new(server_origin.unwrap());
-        server.set_motd(Some(server_motd));
+        Some((max_bytes, max_lines))
+    }
 
-        Ok(Client {
-            myself,
-            stream,
-            server,
+    /// Reads the next message, after taking care of the connection housekeeping (PING, own nick
+    /// changes, regaining the primary nick)
+    pub fn read(&mut self) -> Result<Option<Message<Command>>, Box<std::error::Error>> {
+        if let Some(message) = self.queue.pop_front() {
+            return Ok(Some(message));
+        }
+
+        self.receive()
+    }
+
+    /// Fetches messages of a channel or a private conversation with `CHATHISTORY`, oldest first.
+    /// Blocks until the server sent them all, other messages wait for `read`.
+    pub fn history(
+        &mut self,
+        target: &str,
+        range: Range,
+    ) -> Result<Vec<Message<Command>>, Box<std::error::Error>> {
+        self.require(history::CAPABILITY)?;
+        self.send(ChatHistory {
+            target: target.to_string(),
+            range,
+        })?;
+
+        let casemapping = self.server.casemapping();
+
+        self.await_batch(|kind, params| {
+            kind == history::BATCH
+                && params
+                    .first()
+                    .map_or(false, |name| casemapping.eq(name, target))
         })
     }
 
+    /// Channels and nicks with messages between the timestamps (milliseconds since the epoch)
+    /// together with the time of their latest message, oldest first
+    pub fn history_targets(
+        &mut self,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) -> Result<Vec<(String, u64)>, Box<std::error::Error>> {
+        self.require(history::CAPABILITY)?;
+        self.send(ChatHistoryTargets { start, end, limit })?;
+
+        let messages = self.await_batch(|kind, _| kind == history::TARGETS_BATCH)?;
+
+        Ok(messages
+            .into_iter()
+            .filter_map(|message| match message.command() {
+                ChatHistoryTarget { target, time } => Some((target.to_string(), *time)),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn require(&self, capability: &str) -> Result<(), Box<std::error::Error>> {
+        if self.caps.is_enabled(capability) {
+            Ok(())
+        } else {
+            Err(error::CapabilityNotEnabledError::new(
+                capability.to_string(),
+            ))
+        }
+    }
+
+    /// Reads until the first batch `matches` accepts is complete and returns its messages. A
+    /// `FAIL CHATHISTORY` before it starts ends the wait.
+    fn await_batch<F>(
+        &mut self,
+        matches: F,
+    ) -> Result<Vec<Message<Command>>, Box<std::error::Error>>
+        where
+            F: Fn(&str, &[String]) -> bool,
+    {
+        let deadline = Instant::now() + HISTORY_TIMEOUT;
+        let mut reference: Option<String> = None;
+        let mut messages = Vec::new();
+
+        loop {
+            let message = match self.receive()? {
+                Some(message) => message,
+                None if Instant::now() >= deadline => return Err(error::HistoryTimeoutError::new()),
+                None => {
+                    std::thread::sleep(Duration::from_millis(10));
+                    continue;
+                }
+            };
+
+            let in_batch =
+                reference.is_some() && message.tag("batch") == Some(reference.as_deref());
+
+            match message.command() {
+                Batch {
+                    reference: start,
+                    kind: Some(kind),
+                    params,
+                } if reference.is_none() && start.starts_with('+') && matches(kind, params) => {
+                    reference = Some(start[1..].to_string());
+                }
+
+                Batch {
+                    reference: end,
+                    kind: None,
+                    ..
+                } if end.starts_with('-') && reference.as_deref() == Some(&end[1..]) => {
+                    return Ok(messages);
+                }
+
+                Fail {
+                    command,
+                    code,
+                    description,
+                    ..
+                } if reference.is_none() && command == "CHATHISTORY" => {
+                    return Err(error::HistoryError::new(
+                        code.to_string(),
+                        description.to_string(),
+                    ));
+                }
+
+                _ if in_batch => messages.push(message),
+
+                _ => self.queue.push_back(message),
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<Option<Message<Command>>, Box<std::error::Error>> {
+        if let Some(ref mut regain) = self.regain {
+            let current = self.myself.origin().nick().unwrap_or_default();
+
+            regain.poll(&self.stream, current)?;
+        }
+
+        let message = match self.stream.read()? {
+            Some(message) => message,
+            None => return Ok(None),
+        };
+
+        self.caps.handle(&self.stream, &message)?;
+
+        if let Some(ref mut ctcp) = self.ctcp {
+            ctcp.handle(&self.stream, &message)?;
+        }
+
+        if let Some(ref mut regain) = self.regain {
+            let current = self.myself.origin().nick().unwrap_or_default();
+
+            regain.handle(&self.stream, &message, current)?;
+        }
+
+        match message.command() {
+            Ping { server1, server2 } => {
+                self.stream.send(Pong {
+                    server1: server1.to_string(),
+                    server2: server2.clone(),
+                })?;
+            }
+
+            _ => (),
+        }
+
+        tracking::track(
+            &mut self.server,
+            self.myself.origin().nick().unwrap_or_default(),
+            &message,
+        );
+        Client::track(&mut self.myself, &message);
+
+        Ok(Some(message))
+    }
+
+    /// Learns our own nick, hostmask and modes from what the server tells us
+    fn track(myself: &mut User, message: &Message<Command>) {
+        let is_myself = message.origin().nick().is_some()
+            && message.origin().nick() == myself.origin().nick();
+
+        let (nick, user, host) = match myself.origin_mut() {
+            Origin::User {
+                ref mut nick,
+                ref mut user,
+                ref mut host,
+            } => (nick, user, host),
+            _ => return,
+        };
+
+        match message.command() {
+            Welcome { nick: name, text } => {
+                *nick = name.to_string();
+
+                // most servers end RPL_WELCOME with our full `nick!user@host`
+                let mask = text.split_whitespace().last().unwrap_or_default();
+
+                if let Ok(Origin::User {
+                              user: Some(_user),
+                              host: Some(_host),
+                              ..
+                          }) = Origin::try_from(mask)
+                {
+                    *user = Some(_user);
+                    *host = Some(_host);
+                }
+            }
+
+            Nick { name } if is_myself => *nick = name.to_string(),
+
+            ChgHost {
+                user: _user,
+                host: _host,
+            } if is_myself => {
+                *user = Some(_user.to_string());
+                *host = Some(_host.to_string());
+            }
+
+            VisibleHost { host: _host } => {
+                // some servers send `user@host`
+                match _host.rfind(crate::origin::HOST_SEPARATOR) {
+                    Some(pos) => {
+                        *user = Some(_host[..pos].to_string());
+                        *host = Some(_host[pos + 1..].to_string());
+                    }
+                    None => *host = Some(_host.to_string()),
+                }
+            }
+
+            WhoReply {
+                nick: _nick,
+                user: _user,
+                host: _host,
+                ..
+            } if _nick == nick => {
+                *user = Some(_user.to_string());
+                *host = Some(_host.to_string());
+            }
+
+            UModeIs { modes } => myself.set_modes(modes.clone()),
+
+            UMode { name, modes } if name == nick => {
+                for mode in modes {
+                    myself.apply_mode(mode);
+                }
+            }
+
+            _ => (),
+        }
+    }
+
     pub fn disconnect(self) {
         let _ = self.stream.send(Quit {
             reason: None
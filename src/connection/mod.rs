@@ -1 +1,6 @@
-pub mod client;
\ No newline at end of file
+pub mod cap;
+pub mod client;
+pub mod nick;
+pub mod policy;
+pub mod registration;
+pub mod tracking;
@@ -0,0 +1,241 @@
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command::{self, *},
+    limits,
+    message::Message,
+    stream::ClientStream,
+    target::MessageTarget,
+};
+
+pub const DEFAULT_ISON_INTERVAL: Duration = Duration::from_secs(30);
+
+const NICK_SERV: &str = "NickServ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// How to generate nicks once the explicit alternatives are used up
+pub enum Fallback {
+    /// `nick_`, `nick__`, `nick___`
+    Underscore,
+    /// `nick1` up to `nick9`
+    Digits,
+}
+
+impl Fallback {
+    fn max(&self) -> usize {
+        match self {
+            Fallback::Underscore => 3,
+            Fallback::Digits => 9,
+        }
+    }
+
+    fn generate(&self, primary: &str, attempt: usize) -> String {
+        let suffix = match self {
+            Fallback::Underscore => "_".repeat(attempt),
+            Fallback::Digits => format!("{}", attempt),
+        };
+
+        // shorten the primary nick instead of exceeding the limit
+        let mut keep = limits::NICK_NAME
+            .saturating_sub(suffix.len())
+            .min(primary.len());
+
+        while !primary.is_char_boundary(keep) {
+            keep -= 1;
+        }
+
+        format!("{}{}", &primary[..keep], suffix)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Hands out the nicks to try during registration: the primary nick, the explicit
+/// alternatives in order and finally generated ones.
+pub struct Alternatives {
+    primary: String,
+    alternatives: Vec<String>,
+    fallback: Option<Fallback>,
+    used: usize,
+    generated: usize,
+}
+
+impl Alternatives {
+    pub fn new(primary: &str) -> Self {
+        Alternatives {
+            primary: primary.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn alternatives(mut self, alternatives: Vec<String>) -> Self {
+        self.alternatives = alternatives;
+
+        self
+    }
+
+    pub fn fallback(mut self, fallback: Option<Fallback>) -> Self {
+        self.fallback = fallback;
+
+        self
+    }
+}
+
+impl Alternatives {
+    pub fn primary(&self) -> &str {
+        self.primary.as_str()
+    }
+
+    /// Next nick to try after the current one got rejected
+    pub fn next(&mut self) -> Option<String> {
+        if self.used < self.alternatives.len() {
+            self.used += 1;
+
+            return Some(self.alternatives[self.used - 1].to_string());
+        }
+
+        match self.fallback {
+            Some(fallback) if self.generated < fallback.max() => {
+                self.generated += 1;
+
+                Some(fallback.generate(&self.primary, self.generated))
+            }
+
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegainMethod {
+    /// Polls with `ISON` and changes the nick as soon as it is free
+    IsOn { interval: Duration },
+    /// Watches the nick with `MONITOR` (requires server support)
+    Monitor,
+    /// Asks NickServ to disconnect whoever uses the nick, then takes it
+    Ghost { password: String },
+    /// Asks NickServ to hand the nick over directly
+    Regain { password: String },
+}
+
+#[derive(Debug)]
+/// Tries to get the primary nick back after registering with an alternative
+pub struct Regain {
+    primary: String,
+    method: RegainMethod,
+    started: bool,
+    last_attempt: Option<Instant>,
+}
+
+impl Regain {
+    pub fn new(primary: &str, method: RegainMethod) -> Self {
+        Regain {
+            primary: primary.to_string(),
+            method,
+            started: false,
+            last_attempt: None,
+        }
+    }
+}
+
+impl Regain {
+    pub fn primary(&self) -> &str {
+        self.primary.as_str()
+    }
+
+    /// Sends whatever is due. `current` is the nick we are using right now.
+    pub fn poll(&mut self, stream: &ClientStream, current: &str) -> Result<(), Box<Error>> {
+        if current == self.primary {
+            return Ok(());
+        }
+
+        let started = self.started;
+        self.started = true;
+
+        match self.method {
+            RegainMethod::IsOn { interval } => {
+                let due = self
+                    .last_attempt
+                    .map(|last| last.elapsed() >= interval)
+                    .unwrap_or(true);
+
+                if due {
+                    self.last_attempt = Some(Instant::now());
+
+                    stream.send(IsOn {
+                        nicks: vec![self.primary.to_string()],
+                    })?;
+                }
+            }
+
+            RegainMethod::Monitor if !started => {
+                stream.send(MonitorAdd {
+                    targets: vec![self.primary.to_string()],
+                })?;
+            }
+
+            RegainMethod::Ghost { ref password } if !started => {
+                stream
+                    .send(PrivMsg {
+                        targets: vec![MessageTarget::Nick(NICK_SERV.to_string())],
+                        text: format!("GHOST {} {}", self.primary, password),
+                    })?
+                    .send(Nick {
+                        name: self.primary.to_string(),
+                    })?;
+            }
+
+            RegainMethod::Regain { ref password } if !started => {
+                stream.send(PrivMsg {
+                    targets: vec![MessageTarget::Nick(NICK_SERV.to_string())],
+                    text: format!("REGAIN {} {}", self.primary, password),
+                })?;
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    pub fn handle(
+        &mut self,
+        stream: &ClientStream,
+        message: &Message<Command>,
+        current: &str,
+    ) -> Result<(), Box<Error>> {
+        let primary = self.primary.as_str();
+
+        match message.command() {
+            IsOnReply { nicks } if current != primary => {
+                if !nicks.iter().any(|nick| nick == primary) {
+                    stream.send(Nick {
+                        name: primary.to_string(),
+                    })?;
+                }
+            }
+
+            MonOffline { targets } if current != primary => {
+                if targets.iter().any(|target| target == primary) {
+                    stream.send(Nick {
+                        name: primary.to_string(),
+                    })?;
+                }
+            }
+
+            Nick { name } if name == primary && message.origin().nick() == Some(current) => {
+                if self.method == RegainMethod::Monitor {
+                    stream.send(MonitorRemove {
+                        targets: vec![primary.to_string()],
+                    })?;
+                }
+            }
+
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::error::Error;
+
+use crate::{stream::Port, utils::Defaults};
+
+pub mod error {
+    impl_error!(NoCandidates {});
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single connection target: host name, port and whether TLS is used (encoded in the `Port`)
+pub struct Candidate {
+    host: String,
+    port: Port,
+}
+
+impl Candidate {
+    pub fn new(host: &str, port: Port) -> Self {
+        Candidate {
+            host: host.to_string(),
+            port,
+        }
+    }
+}
+
+impl Candidate {
+    pub fn host(&self) -> &str {
+        self.host.as_str()
+    }
+
+    pub fn port(&self) -> Port {
+        self.port
+    }
+
+    pub fn secure(&self) -> bool {
+        self.port.secure()
+    }
+
+    pub fn insecure(&self) -> bool {
+        self.port.insecure()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    Secure,
+    Insecure,
+}
+
+#[derive(Default)]
+/// Decides which host/port combinations are tried, and in which order.
+///
+/// Explicitly added candidates come first, followed by every host combined with every port.
+/// If no port was given `Port::defaults()` is used instead.
+pub struct ConnectPolicy {
+    candidates: Vec<Candidate>,
+    hosts: Vec<String>,
+    ports: Vec<Port>,
+    filters: Vec<Box<Fn(&Candidate) -> bool>>,
+    priority: Option<Priority>,
+}
+
+impl ConnectPolicy {
+    pub fn new() -> Self {
+        ConnectPolicy::default()
+    }
+}
+
+impl ConnectPolicy {
+    pub fn candidate(mut self, host: &str, port: Port) -> Self {
+        self.candidates.push(Candidate::new(host, port));
+
+        self
+    }
+
+    pub fn host(mut self, host: &str) -> Self {
+        self.hosts.push(host.to_string());
+
+        self
+    }
+
+    /// Appends like `host`, in order
+    pub fn hosts(mut self, hosts: Vec<&str>) -> Self {
+        self.hosts.extend(hosts.iter().map(|host| host.to_string()));
+
+        self
+    }
+
+    pub fn port(mut self, port: Port) -> Self {
+        self.ports.push(port);
+
+        self
+    }
+
+    /// Appends like `port`, in order
+    pub fn ports(mut self, ports: Vec<Port>) -> Self {
+        self.ports.extend(ports);
+
+        self
+    }
+
+    /// Only keeps candidates for which `filter` returns `true`
+    pub fn filter<F>(mut self, filter: F) -> Self
+        where
+            F: Fn(&Candidate) -> bool + 'static,
+    {
+        self.filters.push(Box::new(filter));
+
+        self
+    }
+
+    pub fn secure_only(self) -> Self {
+        self.filter(|candidate| candidate.secure())
+    }
+
+    pub fn insecure_only(self) -> Self {
+        self.filter(|candidate| candidate.insecure())
+    }
+
+    pub fn prioritize(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+
+        self
+    }
+
+    pub fn prioritize_secure(self) -> Self {
+        self.prioritize(Priority::Secure)
+    }
+
+    pub fn prioritize_insecure(self) -> Self {
+        self.prioritize(Priority::Insecure)
+    }
+}
+
+impl ConnectPolicy {
+    /// Ordered list of candidates to connect to. Fails with `NoCandidates` if nothing is left.
+    pub fn candidates(&self) -> Result<Vec<Candidate>, Box<Error>> {
+        let ports = if self.ports.is_empty() {
+            Port::defaults()
+        } else {
+            self.ports.clone()
+        };
+
+        let mut candidates = self.candidates.clone();
+
+        for host in &self.hosts {
+            for port in &ports {
+                candidates.push(Candidate::new(host, *port));
+            }
+        }
+
+        candidates.retain(|candidate| self.filters.iter().all(|filter| filter(candidate)));
+
+        // sorting is stable, so the order within secure and insecure candidates is kept
+        match self.priority {
+            Some(Priority::Secure) => candidates.sort_by_key(|candidate| candidate.insecure()),
+            Some(Priority::Insecure) => candidates.sort_by_key(|candidate| candidate.secure()),
+            None => (),
+        }
+
+        if candidates.is_empty() {
+            Err(error::NoCandidates::new())
+        } else {
+            Ok(candidates)
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command::{self, *},
+    connection::{cap::Negotiation, nick::Alternatives},
+    message::Message,
+    origin::Origin,
+    stream::ClientStream,
+    user::User,
+};
+
+pub mod error {
+    impl_error!(RegistrationTimeoutError {});
+    impl_error!(ErroneusNicknameError { nick: String });
+    impl_error!(NicknameInUseError { nick: String });
+    impl_error!(NickCollisionError { nick: String });
+    impl_error!(NickUnavailableError { nick: String });
+    impl_error!(PasswordMismatchError {});
+    impl_error!(BannedError { reason: String });
+    impl_error!(ClosedError { reason: String });
+}
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+    /// Nothing has been sent yet
+    Connected,
+    /// PASS, NICK and USER are sent, waiting for RPL_WELCOME
+    Pending,
+    /// RPL_WELCOME received, waiting for the end of the MOTD
+    Welcomed,
+    /// End of MOTD (or ERR_NOMOTD) received
+    Registered,
+}
+
+#[derive(Debug)]
+/// Drives the connection registration (RFC 2812 3.1)
+///
+/// Capability negotiation is started first, then `PASS` is sent, followed by `NICK` and `USER`.
+/// RPL_WELCOME confirms the nick, but the connection only counts as registered once the MOTD
+/// ended (or ERR_NOMOTD arrived), or the timeout expired after RPL_WELCOME.
+pub struct Registration {
+    state: State,
+    nick: String,
+    nicks: Alternatives,
+    user: String,
+    real_name: String,
+    password: Option<String>,
+    timeout: Duration,
+    deadline: Option<Instant>,
+    server_origin: Option<Origin>,
+    motd: Option<String>,
+    caps: Negotiation,
+}
+
+impl Registration {
+    pub fn new(myself: &User, password: Option<&str>, timeout: Duration) -> Self {
+        let nick = myself.origin().nick().unwrap_or_default();
+
+        Registration {
+            state: State::Connected,
+            nick: nick.to_string(),
+            nicks: Alternatives::new(nick),
+            user: myself
+                .origin()
+                .user()
+                .or(myself.origin().nick())
+                .unwrap_or_default()
+                .to_string(),
+            real_name: myself.real_name().to_string(),
+            password: password.map(|p| p.to_string()),
+            timeout,
+            deadline: None,
+            server_origin: None,
+            motd: None,
+            caps: Negotiation::default(),
+        }
+    }
+
+    /// Capabilities to request before registering
+    pub fn capabilities(mut self, caps: Vec<String>) -> Self {
+        self.caps = Negotiation::new(caps);
+
+        self
+    }
+
+    /// Nicks to fall back to if the server rejects the requested one
+    pub fn alternatives(mut self, nicks: Alternatives) -> Self {
+        self.nick = nicks.primary().to_string();
+        self.nicks = nicks;
+
+        self
+    }
+}
+
+impl Registration {
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.state == State::Registered
+    }
+
+    /// The nick as confirmed by the server (or the one requested, before RPL_WELCOME)
+    pub fn nick(&self) -> &str {
+        self.nick.as_str()
+    }
+
+    pub fn server_origin(&self) -> Option<&Origin> {
+        self.server_origin.as_ref()
+    }
+
+    pub fn motd(&self) -> Option<&str> {
+        self.motd.as_ref().map(|motd| motd.as_str())
+    }
+
+    /// Hands over the capability negotiation, which keeps track of `CAP NEW`/`CAP DEL` later on
+    pub fn take_capabilities(&mut self) -> Negotiation {
+        std::mem::replace(&mut self.caps, Negotiation::default())
+    }
+}
+
+impl Registration {
+    pub fn start(&mut self, stream: &ClientStream) -> Result<(), Box<Error>> {
+        // the server holds back registration until `CAP END`
+        self.caps.start(stream)?;
+
+        if let Some(ref password) = self.password {
+            stream.send(Pass {
+                password: password.to_string(),
+            })?;
+        }
+
+        stream
+            .send(Nick {
+                name: self.nick.to_string(),
+            })?
+            .send(Command::User {
+                name: self.user.to_string(),
+                modes: vec![],
+                real_name: self.real_name.to_string(),
+            })?;
+
+        self.state = State::Pending;
+        self.deadline = Some(Instant::now() + self.timeout);
+
+        Ok(())
+    }
+
+    pub fn handle(
+        &mut self,
+        stream: &ClientStream,
+        message: &Message<Command>,
+    ) -> Result<&State, Box<Error>> {
+        self.caps.handle(stream, message)?;
+
+        match message.command() {
+            // some servers send a cookie which has to be answered before RPL_WELCOME
+            Ping { server1, server2 } => {
+                stream.send(Pong {
+                    server1: server1.to_string(),
+                    server2: server2.clone(),
+                })?;
+            }
+
+            Welcome { nick, .. } => {
+                self.nick = nick.to_string();
+                self.server_origin = Some(message.origin().clone());
+                self.state = State::Welcomed;
+            }
+
+            MotdStart => {
+                if self.server_origin.is_none() {
+                    self.server_origin = Some(message.origin().clone());
+                }
+
+                self.motd = Some(String::new());
+            }
+            MotdBody { text } => {
+                self.motd
+                    .get_or_insert_with(String::new)
+                    .push_str(&format!("{}\n", text));
+            }
+            MotdEnd | NoMotd if self.state == State::Welcomed => self.state = State::Registered,
+
+            ErroneusNickname { .. }
+            | NicknameInUse { .. }
+            | NickCollision { .. }
+            | UnavailResource { .. } if self.state == State::Pending => {
+                self.retry(stream, message.command())?;
+            }
+            PasswdMismatch => return Err(error::PasswordMismatchError::new()),
+            YoureBannedCreep { text } => return Err(error::BannedError::new(text.to_string())),
+            ErrorMsg { text } => return Err(error::ClosedError::new(text.to_string())),
+
+            _ => (),
+        }
+
+        Ok(&self.state)
+    }
+
+    /// Continues with the next alternative nick, or fails if none is left
+    fn retry(&mut self, stream: &ClientStream, rejection: &Command) -> Result<(), Box<Error>> {
+        if let Some(next) = self.nicks.next() {
+            self.nick = next;
+
+            stream.send(Nick {
+                name: self.nick.to_string(),
+            })?;
+
+            return Ok(());
+        }
+
+        Err(match rejection {
+            ErroneusNickname { nick } => error::ErroneusNicknameError::new(nick.to_string()),
+            NicknameInUse { nick } => error::NicknameInUseError::new(nick.to_string()),
+            NickCollision { nick } => error::NickCollisionError::new(nick.to_string()),
+            UnavailResource { name } => error::NickUnavailableError::new(name.to_string()),
+            _ => error::NickUnavailableError::new(self.nick.to_string()),
+        })
+    }
+
+    /// Fails if RPL_WELCOME did not arrive in time. A missing end of MOTD is tolerated.
+    pub fn check_timeout(&mut self) -> Result<&State, Box<Error>> {
+        let expired = self
+            .deadline
+            .map(|deadline| Instant::now() >= deadline)
+            .unwrap_or(false);
+
+        if expired {
+            match self.state {
+                State::Welcomed => self.state = State::Registered,
+                State::Registered => (),
+                _ => return Err(error::RegistrationTimeoutError::new()),
+            }
+        }
+
+        Ok(&self.state)
+    }
+}
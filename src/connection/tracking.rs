@@ -0,0 +1,284 @@
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+use crate::{
+    casemapping::CaseMapping,
+    channel::{Channel, RcChannel},
+    command::client::Command::{self, *},
+    message::Message,
+    mode::channel,
+    origin::Origin,
+    server::Server,
+    user::{RcUser, User},
+};
+
+const RPL_ISUPPORT: u16 = 5;
+const RPL_TOPIC: u16 = 332;
+const RPL_TOPICWHOTIME: u16 = 333;
+const RPL_NAMREPLY: u16 = 353;
+
+/// Status prefixes `NAMES` replies may put in front of a nick, the ones before `%` count as
+/// operator
+const PREFIXES: &[char] = &['~', '&', '@', '%', '+'];
+
+/// Keeps the channels we are on up to date with their members, statuses, modes and topics, and
+/// the accounts of the members if the server tells them. `nick` is our own nick as it was before
+/// the message.
+pub fn track(server: &mut Server, nick: &str, message: &Message<Command>) {
+    let origin = message.origin();
+    let from = origin.nick().unwrap_or_default();
+    let is_myself = server.casemapping().eq(from, nick);
+
+    match message.command() {
+        Join { channels, .. } => {
+            for name in channels {
+                join(server, origin, name, is_myself);
+            }
+        }
+
+        ExtendedJoin {
+            channel,
+            account,
+            real_name,
+        } => {
+            if let Some(user) = join(server, origin, channel, is_myself) {
+                let mut user = user.borrow_mut();
+
+                user.set_real_name(real_name);
+                user.set_account(account.clone());
+            }
+        }
+
+        Part { channels, .. } => {
+            for name in channels {
+                leave(server, from, name, is_myself);
+            }
+        }
+
+        // either one channel for all users or a channel for each user
+        Kick {
+            channels, users, ..
+        } => {
+            for (pos, kicked) in users.iter().enumerate() {
+                if let Some(name) = channels.get(pos).or_else(|| channels.first()) {
+                    let is_myself = server.casemapping().eq(kicked, nick);
+
+                    leave(server, kicked, name, is_myself);
+                }
+            }
+        }
+
+        Quit { .. } => {
+            if let Some(user) = server.user(from) {
+                for weak in user.borrow().channels() {
+                    if let Some(channel) = weak.upgrade() {
+                        channel.borrow_mut().remove_user(&user);
+                    }
+                }
+
+                server.remove_user(&user);
+            }
+        }
+
+        Nick { name } => {
+            if let Some(user) = server.user(from) {
+                if let Origin::User { ref mut nick, .. } = user.borrow_mut().origin_mut() {
+                    *nick = name.to_string();
+                }
+            }
+        }
+
+        // account-notify
+        Account { account } => {
+            if let Some(user) = server.user(from) {
+                user.borrow_mut().set_account(account.clone());
+            }
+        }
+
+        Topic {
+            channel,
+            text: Some(text),
+        } => {
+            if let Some(channel) = server.channel(channel) {
+                let setter = origin.nick().unwrap_or_else(|| server.name());
+
+                channel.borrow_mut().set_topic(text, setter);
+            }
+        }
+
+        CMode { channel, modes } => {
+            if let Some(channel) = server.channel(channel) {
+                for mode in modes {
+                    status(server, &channel, mode);
+                }
+            }
+        }
+
+        Numeric { code, params } => match (*code, params.as_slice()) {
+            (RPL_ISUPPORT, _) => {
+                let casemapping = params
+                    .iter()
+                    .filter_map(|param| param.strip_prefix("CASEMAPPING="))
+                    .find_map(|name| CaseMapping::parse(name).ok());
+
+                if let Some(casemapping) = casemapping {
+                    server.set_casemapping(casemapping);
+                }
+            }
+
+            (RPL_TOPIC, [_, channel, topic]) => {
+                if let Some(channel) = server.channel(channel) {
+                    channel.borrow_mut().set_topic(topic, "");
+                }
+            }
+
+            (RPL_TOPICWHOTIME, [_, channel, setter, time, ..]) => {
+                if let Some(channel) = server.channel(channel) {
+                    channel
+                        .borrow_mut()
+                        .set_topic_details(setter, time.parse().unwrap_or_default());
+                }
+            }
+
+            (RPL_NAMREPLY, [_, _, channel, names]) => {
+                if let Some(channel) = server.channel(channel) {
+                    for name in names.split_whitespace() {
+                        member(server, &channel, name);
+                    }
+                }
+            }
+
+            _ => (),
+        },
+
+        _ => (),
+    }
+}
+
+/// The tracked user with the nick of the origin, added if it's new. A known user and host
+/// replace the ones tracked so far.
+fn user(server: &mut Server, origin: &Origin) -> Option<RcUser> {
+    let nick = origin.nick()?;
+
+    match server.user(nick) {
+        Some(user) => {
+            if let Origin::User {
+                user: Some(_),
+                host: Some(_),
+                ..
+            } = origin
+            {
+                *user.borrow_mut().origin_mut() = origin.clone();
+            }
+
+            Some(user)
+        }
+        None => {
+            let user = Rc::new(RefCell::new(User::new(origin.clone(), "")));
+
+            server.users_mut().push(user.clone());
+
+            Some(user)
+        }
+    }
+}
+
+/// Our own joins add the channel, other joins only count for channels we are on
+fn join(server: &mut Server, origin: &Origin, name: &str, is_myself: bool) -> Option<RcUser> {
+    let channel = match server.channel(name) {
+        Some(channel) => channel,
+        None if is_myself => {
+            let channel = Rc::new(RefCell::new(Channel::new(name)));
+
+            server.channels_mut().push(channel.clone());
+
+            channel
+        }
+        None => return None,
+    };
+
+    let user = user(server, origin)?;
+
+    channel.borrow_mut().add_user(&user, false);
+    user.borrow_mut().add_channel(&channel);
+
+    Some(user)
+}
+
+/// Parts and kicks, leaving ourselves forgets the whole channel. Users sharing no channel with
+/// us anymore are forgotten as well.
+fn leave(server: &mut Server, nick: &str, name: &str, is_myself: bool) {
+    let channel = match server.channel(name) {
+        Some(channel) => channel,
+        None => return,
+    };
+
+    if is_myself {
+        for weak in channel.borrow().users() {
+            if let Some(user) = weak.upgrade() {
+                user.borrow_mut().remove_channel(&channel);
+            }
+        }
+
+        server.remove_channel(&channel);
+    } else if let Some(user) = server.user(nick) {
+        channel.borrow_mut().remove_user(&user);
+        user.borrow_mut().remove_channel(&channel);
+    }
+
+    server.users_mut().retain(|user| {
+        user.borrow()
+            .channels()
+            .iter()
+            .any(|weak| weak.upgrade().is_some())
+    });
+}
+
+/// Operator and voice belong to the members, other modes to the channel
+fn status(server: &Server, channel: &RcChannel, mode: &crate::mode::Mode<channel::Mode>) {
+    let (nick, operator) = match mode.mode() {
+        channel::Mode::Operator { nick } => (nick, true),
+        channel::Mode::Voice { nick } => (nick, false),
+        _ => {
+            channel.borrow_mut().apply_mode(mode);
+
+            return;
+        }
+    };
+
+    if let Some(user) = server.user(nick) {
+        if let Some(member) = channel.borrow_mut().member_mut(&user) {
+            if operator {
+                member.set_operator(mode.granted());
+            } else {
+                member.set_voice(mode.granted());
+            }
+        }
+    }
+}
+
+/// Adds a member listed in `NAMES` as `@nick` or, with `userhost-in-names`, `@nick!user@host`
+fn member(server: &mut Server, channel: &RcChannel, name: &str) {
+    let mask = name.trim_start_matches(PREFIXES);
+    let prefixes = &name[..name.len() - mask.len()];
+
+    let origin = match Origin::try_from(mask) {
+        Ok(origin) => origin,
+        Err(_) => return,
+    };
+
+    let user = match user(server, &origin) {
+        Some(user) => user,
+        None => return,
+    };
+
+    let mut channel_ref = channel.borrow_mut();
+
+    channel_ref.add_user(&user, false);
+
+    if let Some(member) = channel_ref.member_mut(&user) {
+        member.set_operator(prefixes.contains(|c| PREFIXES[..3].contains(&c)));
+        member.set_voice(prefixes.contains('+'));
+    }
+
+    user.borrow_mut().add_channel(channel);
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command::{self, *},
+    message::Message,
+    origin::Origin,
+    stream::ClientStream,
+    target::MessageTarget,
+    time,
+};
+
+pub const DELIMITER: char = '\x01';
+
+const LOW_LEVEL_QUOTE: char = '\x10';
+
+/// Quotes NUL, CR, LF and `\x10` so the text survives the IRC line protocol
+pub fn low_level_quote(data: &str) -> String {
+    let mut quoted = String::with_capacity(data.len());
+
+    for c in data.chars() {
+        match c {
+            '\0' => quoted.push_str("\x100"),
+            '\n' => quoted.push_str("\x10n"),
+            '\r' => quoted.push_str("\x10r"),
+            LOW_LEVEL_QUOTE => quoted.push_str("\x10\x10"),
+            _ => quoted.push(c),
+        }
+    }
+
+    quoted
+}
+
+/// Unknown escapes drop the quote character and keep the following one
+pub fn low_level_dequote(data: &str) -> String {
+    let mut dequoted = String::with_capacity(data.len());
+    let mut chars = data.chars();
+
+    while let Some(c) = chars.next() {
+        if c != LOW_LEVEL_QUOTE {
+            dequoted.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('0') => dequoted.push('\0'),
+            Some('n') => dequoted.push('\n'),
+            Some('r') => dequoted.push('\r'),
+            Some(next) => dequoted.push(next),
+            None => (),
+        }
+    }
+
+    dequoted
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A CTCP message. Queries usually come without parameters (except `PING`), replies with.
+pub enum Ctcp {
+    Action(String),
+    Version(Option<String>),
+    Ping(Option<String>),
+    Time(Option<String>),
+    ClientInfo(Option<String>),
+    Source(Option<String>),
+    UserInfo(Option<String>),
+    Other {
+        command: String,
+        params: Option<String>,
+    },
+}
+
+impl Ctcp {
+    /// Parses the text of a `PRIVMSG` or `NOTICE`, `None` if it isn't a CTCP message
+    pub fn parse(text: &str) -> Option<Self> {
+        if !text.starts_with(DELIMITER) {
+            return None;
+        }
+
+        // the closing delimiter is optional, some clients leave it out
+        let body = text[1..].trim_end_matches(DELIMITER);
+        let body = low_level_dequote(body);
+
+        let (command, params) = match body.find(' ') {
+            Some(pos) => (&body[..pos], Some(body[pos + 1..].to_string())),
+            None => (body.as_str(), None),
+        };
+
+        if command.is_empty() {
+            return None;
+        }
+
+        let params = params.filter(|p| !p.is_empty());
+
+        Some(match command.to_uppercase().as_str() {
+            "ACTION" => Ctcp::Action(params.unwrap_or_default()),
+            "VERSION" => Ctcp::Version(params),
+            "PING" => Ctcp::Ping(params),
+            "TIME" => Ctcp::Time(params),
+            "CLIENTINFO" => Ctcp::ClientInfo(params),
+            "SOURCE" => Ctcp::Source(params),
+            "USERINFO" => Ctcp::UserInfo(params),
+            _ => Ctcp::Other {
+                command: command.to_string(),
+                params,
+            },
+        })
+    }
+}
+
+impl Ctcp {
+    pub fn command(&self) -> &str {
+        match self {
+            Ctcp::Action(_) => "ACTION",
+            Ctcp::Version(_) => "VERSION",
+            Ctcp::Ping(_) => "PING",
+            Ctcp::Time(_) => "TIME",
+            Ctcp::ClientInfo(_) => "CLIENTINFO",
+            Ctcp::Source(_) => "SOURCE",
+            Ctcp::UserInfo(_) => "USERINFO",
+            Ctcp::Other { ref command, .. } => command.as_str(),
+        }
+    }
+
+    pub fn params(&self) -> Option<&str> {
+        match self {
+            Ctcp::Action(ref text) => Some(text.as_str()),
+            Ctcp::Version(ref params)
+            | Ctcp::Ping(ref params)
+            | Ctcp::Time(ref params)
+            | Ctcp::ClientInfo(ref params)
+            | Ctcp::Source(ref params)
+            | Ctcp::UserInfo(ref params)
+            | Ctcp::Other { ref params, .. } => params.as_ref().map(|p| p.as_str()),
+        }
+    }
+
+    /// `PRIVMSG` carrying this CTCP as a query (or an action)
+    pub fn query(&self, target: MessageTarget) -> Command {
+        PrivMsg {
+            targets: vec![target],
+            text: self.to_string(),
+        }
+    }
+
+    /// `NOTICE` carrying this CTCP as a reply
+    pub fn reply(&self, target: MessageTarget) -> Command {
+        Notice {
+            target,
+            text: self.to_string(),
+        }
+    }
+}
+
+/// Builds the quoted CTCP message including its delimiters
+impl ToString for Ctcp {
+    fn to_string(&self) -> String {
+        let body = match self.params() {
+            Some(params) => format!("{} {}", self.command(), params),
+            None => self.command().to_string(),
+        };
+
+        format!("{}{}{}", DELIMITER, low_level_quote(&body), DELIMITER)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// Received through `PRIVMSG`, actions included
+    Query {
+        from: Origin,
+        target: MessageTarget,
+        ctcp: Ctcp,
+    },
+    /// Received through `NOTICE`
+    Reply {
+        from: Origin,
+        target: MessageTarget,
+        ctcp: Ctcp,
+    },
+}
+
+impl Event {
+    pub fn from_message(message: &Message<Command>) -> Option<Self> {
+        match message.command() {
+            PrivMsg { targets, text } => {
+                let target = targets.get(0)?;
+
+                Ctcp::parse(text).map(|ctcp| Event::Query {
+                    from: message.origin().clone(),
+                    target: target.clone(),
+                    ctcp,
+                })
+            }
+
+            Notice { target, text } => Ctcp::parse(text).map(|ctcp| Event::Reply {
+                from: message.origin().clone(),
+                target: target.clone(),
+                ctcp,
+            }),
+
+            _ => None,
+        }
+    }
+}
+
+impl Event {
+    pub fn from(&self) -> &Origin {
+        match self {
+            Event::Query { ref from, .. } | Event::Reply { ref from, .. } => from,
+        }
+    }
+
+    pub fn ctcp(&self) -> &Ctcp {
+        match self {
+            Event::Query { ref ctcp, .. } | Event::Reply { ref ctcp, .. } => ctcp,
+        }
+    }
+}
+
+/// Answers CTCP queries automatically
+///
+/// Replies are rate limited, both in total and per sender, so nobody can make us flood a target
+/// by spoofing queries (or flood us off the server).
+pub struct Responder {
+    version: Option<String>,
+    source: Option<String>,
+    user_info: Option<String>,
+    time: bool,
+    ping: bool,
+    limit: usize,
+    window: Duration,
+    per_sender: Duration,
+    sent: VecDeque<Instant>,
+    senders: HashMap<String, Instant>,
+}
+
+impl Default for Responder {
+    fn default() -> Self {
+        Responder {
+            version: Some(format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
+            source: None,
+            user_info: None,
+            time: true,
+            ping: true,
+            limit: 4,
+            window: Duration::from_secs(10),
+            per_sender: Duration::from_secs(5),
+            sent: VecDeque::new(),
+            senders: HashMap::new(),
+        }
+    }
+}
+
+impl Responder {
+    pub fn new() -> Self {
+        Responder::default()
+    }
+
+    /// `None` disables VERSION replies
+    pub fn version(mut self, version: Option<&str>) -> Self {
+        self.version = version.map(|v| v.to_string());
+
+        self
+    }
+
+    pub fn source(mut self, source: Option<&str>) -> Self {
+        self.source = source.map(|s| s.to_string());
+
+        self
+    }
+
+    pub fn user_info(mut self, user_info: Option<&str>) -> Self {
+        self.user_info = user_info.map(|u| u.to_string());
+
+        self
+    }
+
+    pub fn time(mut self, enabled: bool) -> Self {
+        self.time = enabled;
+
+        self
+    }
+
+    pub fn ping(mut self, enabled: bool) -> Self {
+        self.ping = enabled;
+
+        self
+    }
+
+    /// At most `limit` replies within `window`
+    pub fn rate_limit(mut self, limit: usize, window: Duration) -> Self {
+        self.limit = limit;
+        self.window = window;
+
+        self
+    }
+
+    /// Minimum time between two replies to the same sender
+    pub fn per_sender(mut self, interval: Duration) -> Self {
+        self.per_sender = interval;
+
+        self
+    }
+}
+
+impl Responder {
+    /// Names of the queries answered, for `CLIENTINFO`
+    fn supported(&self) -> Vec<&str> {
+        let mut supported = vec!["ACTION", "CLIENTINFO"];
+
+        if self.ping {
+            supported.push("PING");
+        }
+        if self.source.is_some() {
+            supported.push("SOURCE");
+        }
+        if self.time {
+            supported.push("TIME");
+        }
+        if self.user_info.is_some() {
+            supported.push("USERINFO");
+        }
+        if self.version.is_some() {
+            supported.push("VERSION");
+        }
+
+        supported
+    }
+
+    fn answer(&self, ctcp: &Ctcp) -> Option<Ctcp> {
+        match ctcp {
+            Ctcp::Version(_) => self.version.clone().map(|v| Ctcp::Version(Some(v))),
+            Ctcp::Ping(token) if self.ping => Some(Ctcp::Ping(token.clone())),
+            Ctcp::Time(_) if self.time => Some(Ctcp::Time(Some(time::format_seconds(time::now())))),
+            Ctcp::ClientInfo(_) => Some(Ctcp::ClientInfo(Some(self.supported().join(" ")))),
+            Ctcp::Source(_) => self.source.clone().map(|s| Ctcp::Source(Some(s))),
+            Ctcp::UserInfo(_) => self.user_info.clone().map(|u| Ctcp::UserInfo(Some(u))),
+            _ => None,
+        }
+    }
+
+    /// Whether a reply to `sender` fits into both limits right now, counting it if so
+    fn allow(&mut self, sender: &str) -> bool {
+        let now = Instant::now();
+        let window = self.window;
+        let per_sender = self.per_sender;
+
+        while self
+            .sent
+            .front()
+            .map(|sent| now.duration_since(*sent) >= window)
+            .unwrap_or(false)
+        {
+            self.sent.pop_front();
+        }
+
+        self.senders
+            .retain(|_, last| now.duration_since(*last) < per_sender);
+
+        if self.sent.len() >= self.limit || self.senders.contains_key(sender) {
+            return false;
+        }
+
+        self.sent.push_back(now);
+        self.senders.insert(sender.to_string(), now);
+
+        true
+    }
+
+    /// Replies to the query in `message`, if there is one and the limits allow it. The query
+    /// itself stays in the message, `Event::from_message` gets it out.
+    pub fn handle(
+        &mut self,
+        stream: &ClientStream,
+        message: &Message<Command>,
+    ) -> Result<(), Box<Error>> {
+        if let Some(Event::Query { from, ctcp, .. }) = Event::from_message(message) {
+            if let (Some(nick), Some(reply)) = (from.nick(), self.answer(&ctcp)) {
+                if self.allow(&nick.to_lowercase()) {
+                    stream.send(reply.reply(MessageTarget::Nick(nick.to_string())))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use super::error::TimeoutError;
+
+/// Line based DCC CHAT connection. Lines end with `\n`, a preceding `\r` is dropped.
+pub struct Chat {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Chat {
+    pub fn new(stream: TcpStream) -> Result<Self, Box<Error>> {
+        let writer = stream.try_clone()?;
+
+        Ok(Chat {
+            reader: BufReader::new(stream),
+            writer,
+        })
+    }
+}
+
+impl Chat {
+    /// `None` waits forever
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), Box<Error>> {
+        self.writer.set_read_timeout(timeout)?;
+
+        Ok(())
+    }
+
+    pub fn send(&mut self, text: &str) -> Result<(), Box<Error>> {
+        for line in text.lines() {
+            self.writer.write_all(line.as_bytes())?;
+            self.writer.write_all(b"\n")?;
+        }
+
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Next line, `None` once the peer closed the chat. Invalid UTF-8 is replaced.
+    pub fn read_line(&mut self) -> Result<Option<String>, Box<Error>> {
+        let mut line = Vec::new();
+
+        match self.reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(None),
+            Ok(_) => (),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                return Err(TimeoutError::new());
+            }
+            Err(e) => return Err(Box::new(e)),
+        }
+
+        let line = String::from_utf8_lossy(&line);
+
+        Ok(Some(line.trim_end_matches('\n').trim_end_matches('\r').to_string()))
+    }
+}
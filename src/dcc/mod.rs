@@ -0,0 +1,286 @@
+use std::{
+    error::Error,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use crate::ctcp::Ctcp;
+
+pub mod chat;
+pub mod transfer;
+
+pub mod error {
+    impl_error!(IllegalOfferError { offer: String });
+    impl_error!(SizeMismatchError {
+        expected: u64,
+        actual: u64
+    });
+    impl_error!(SizeLimitError { size: u64, limit: u64 });
+    impl_error!(TimeoutError {});
+}
+
+pub const COMMAND: &str = "DCC";
+
+#[derive(Debug, Clone, PartialEq)]
+/// DCC request as sent in a CTCP `DCC` query
+///
+/// Passive (reverse) offers use port 0 and carry a token. The receiving side answers with the
+/// same offer, but with its own address, port and the token.
+pub enum Offer {
+    Send {
+        file_name: String,
+        address: IpAddr,
+        port: u16,
+        size: Option<u64>,
+        token: Option<String>,
+    },
+    Chat {
+        address: IpAddr,
+        port: u16,
+        token: Option<String>,
+    },
+    Resume {
+        file_name: String,
+        port: u16,
+        position: u64,
+        token: Option<String>,
+    },
+    Accept {
+        file_name: String,
+        port: u16,
+        position: u64,
+        token: Option<String>,
+    },
+}
+
+/// IPv4 addresses are sent as a single decimal number, IPv6 ones in their text form
+fn parse_address(data: &str) -> Option<IpAddr> {
+    if let Ok(number) = data.parse::<u32>() {
+        Some(IpAddr::V4(Ipv4Addr::from(number)))
+    } else {
+        data.parse::<IpAddr>().ok()
+    }
+}
+
+fn address_to_string(address: &IpAddr) -> String {
+    match address {
+        IpAddr::V4(v4) => format!("{}", u32::from(*v4)),
+        IpAddr::V6(v6) => format!("{}", v6),
+    }
+}
+
+/// Splits the parameters, honouring a quoted file name in front
+fn split_params(data: &str) -> Option<(String, Vec<&str>)> {
+    let data = data.trim_start();
+
+    if data.starts_with('"') {
+        let end = data[1..].find('"')? + 1;
+
+        Some((
+            data[1..end].to_string(),
+            data[end + 1..].split_whitespace().collect(),
+        ))
+    } else {
+        let mut params = data.split_whitespace();
+        let first = params.next()?;
+
+        Some((first.to_string(), params.collect()))
+    }
+}
+
+fn quote_file_name(file_name: &str) -> String {
+    if file_name.contains(' ') {
+        format!("\"{}\"", file_name)
+    } else {
+        file_name.to_string()
+    }
+}
+
+impl Offer {
+    /// Parses the parameters of a CTCP `DCC` query, like `SEND file.txt 2130706433 5000 1024`
+    pub fn parse(data: &str) -> Result<Self, Box<Error>> {
+        let illegal = || error::IllegalOfferError::new(data.to_string());
+
+        let data = data.trim();
+        let kind_end = data.find(' ').ok_or_else(illegal)?;
+        let kind = data[..kind_end].to_uppercase();
+
+        let (first, params) = split_params(&data[kind_end + 1..]).ok_or_else(illegal)?;
+        let token = |pos: usize| params.get(pos).map(|t| t.to_string());
+
+        let offer = match kind.as_str() {
+            "SEND" if params.len() >= 2 => Offer::Send {
+                file_name: first,
+                address: parse_address(params[0]).ok_or_else(illegal)?,
+                port: params[1].parse().map_err(|_| illegal())?,
+                size: match params.get(2) {
+                    Some(size) => Some(size.parse().map_err(|_| illegal())?),
+                    None => None,
+                },
+                token: token(3),
+            },
+
+            // the first parameter is the protocol, always "chat"
+            "CHAT" if params.len() >= 2 => Offer::Chat {
+                address: parse_address(params[0]).ok_or_else(illegal)?,
+                port: params[1].parse().map_err(|_| illegal())?,
+                token: token(2),
+            },
+
+            "RESUME" | "ACCEPT" if params.len() >= 2 => {
+                let port = params[0].parse().map_err(|_| illegal())?;
+                let position = params[1].parse().map_err(|_| illegal())?;
+
+                if kind == "RESUME" {
+                    Offer::Resume {
+                        file_name: first,
+                        port,
+                        position,
+                        token: token(2),
+                    }
+                } else {
+                    Offer::Accept {
+                        file_name: first,
+                        port,
+                        position,
+                        token: token(2),
+                    }
+                }
+            }
+
+            _ => return Err(illegal()),
+        };
+
+        Ok(offer)
+    }
+
+    /// `None` if the CTCP message isn't a DCC request
+    pub fn from_ctcp(ctcp: &Ctcp) -> Option<Result<Self, Box<Error>>> {
+        match ctcp {
+            Ctcp::Other {
+                ref command,
+                params: Some(ref params),
+            } if command.eq_ignore_ascii_case(COMMAND) => Some(Offer::parse(params)),
+            _ => None,
+        }
+    }
+
+    pub fn to_ctcp(&self) -> Ctcp {
+        Ctcp::Other {
+            command: COMMAND.to_string(),
+            params: Some(self.to_string()),
+        }
+    }
+}
+
+impl Offer {
+    /// Passive offers ask the other side to listen instead
+    pub fn is_passive(&self) -> bool {
+        match self {
+            Offer::Send { port, token, .. } | Offer::Chat { port, token, .. } => {
+                *port == 0 && token.is_some()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            Offer::Send { ref token, .. }
+            | Offer::Chat { ref token, .. }
+            | Offer::Resume { ref token, .. }
+            | Offer::Accept { ref token, .. } => token.as_ref().map(|t| t.as_str()),
+        }
+    }
+
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            Offer::Send { ref file_name, .. }
+            | Offer::Resume { ref file_name, .. }
+            | Offer::Accept { ref file_name, .. } => Some(file_name.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The offered file name without any path, safe to create in a download directory
+    pub fn safe_file_name(&self) -> Option<String> {
+        let name = self
+            .file_name()?
+            .rsplit(|c| c == '/' || c == '\\')
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_control())
+            .collect::<String>();
+
+        match name.trim_start_matches('.') {
+            "" => None,
+            trimmed => Some(trimmed.to_string()),
+        }
+    }
+}
+
+/// Builds the parameters of the CTCP `DCC` query
+impl ToString for Offer {
+    fn to_string(&self) -> String {
+        let token = |token: &Option<String>| {
+            token
+                .as_ref()
+                .map(|t| format!(" {}", t))
+                .unwrap_or_default()
+        };
+
+        match self {
+            Offer::Send {
+                ref file_name,
+                ref address,
+                ref port,
+                ref size,
+                token: ref _token,
+            } => format!(
+                "SEND {} {} {}{}{}",
+                quote_file_name(file_name),
+                address_to_string(address),
+                port,
+                size.map(|s| format!(" {}", s)).unwrap_or_default(),
+                token(_token)
+            ),
+
+            Offer::Chat {
+                ref address,
+                ref port,
+                token: ref _token,
+            } => format!(
+                "CHAT chat {} {}{}",
+                address_to_string(address),
+                port,
+                token(_token)
+            ),
+
+            Offer::Resume {
+                ref file_name,
+                ref port,
+                ref position,
+                token: ref _token,
+            } => format!(
+                "RESUME {} {} {}{}",
+                quote_file_name(file_name),
+                port,
+                position,
+                token(_token)
+            ),
+
+            Offer::Accept {
+                ref file_name,
+                ref port,
+                ref position,
+                token: ref _token,
+            } => format!(
+                "ACCEPT {} {} {}{}",
+                quote_file_name(file_name),
+                port,
+                position,
+                token(_token)
+            ),
+        }
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::error::{SizeLimitError, SizeMismatchError, TimeoutError};
+
+pub const BUFFER_SIZE: usize = 16 * 1024;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+const ACCEPT_POLL: Duration = Duration::from_millis(20);
+
+const ACK_SIZE: usize = 4;
+
+/// Listening side of a DCC connection, used for our own offers and to answer passive ones
+pub struct Listener {
+    listener: TcpListener,
+}
+
+impl Listener {
+    /// Binds to a port chosen by the system
+    pub fn bind(address: IpAddr) -> Result<Self, Box<Error>> {
+        Listener::bind_port(address, 0)
+    }
+
+    pub fn bind_port(address: IpAddr, port: u16) -> Result<Self, Box<Error>> {
+        let listener = TcpListener::bind(SocketAddr::new(address, port))?;
+
+        Ok(Listener { listener })
+    }
+}
+
+impl Listener {
+    pub fn address(&self) -> Result<SocketAddr, Box<Error>> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn port(&self) -> Result<u16, Box<Error>> {
+        Ok(self.address()?.port())
+    }
+
+    /// Waits for the peer to connect, DCC listeners only ever accept one connection
+    pub fn accept(&self, timeout: Duration) -> Result<TcpStream, Box<Error>> {
+        let deadline = Instant::now() + timeout;
+
+        self.listener.set_nonblocking(true)?;
+
+        let stream = loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(TimeoutError::new());
+                    }
+
+                    thread::sleep(ACCEPT_POLL);
+                }
+                Err(e) => return Err(Box::new(e)),
+            }
+        };
+
+        stream.set_nonblocking(false)?;
+
+        Ok(stream)
+    }
+}
+
+pub fn connect(address: IpAddr, port: u16, timeout: Duration) -> Result<TcpStream, Box<Error>> {
+    match TcpStream::connect_timeout(&SocketAddr::new(address, port), timeout) {
+        Ok(stream) => Ok(stream),
+        Err(ref e) if is_timeout(e) => Err(TimeoutError::new()),
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+fn is_timeout(error: &io::Error) -> bool {
+    error.kind() == ErrorKind::WouldBlock || error.kind() == ErrorKind::TimedOut
+}
+
+fn map_io(error: io::Error) -> Box<Error> {
+    if is_timeout(&error) {
+        TimeoutError::new()
+    } else {
+        Box::new(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    transferred: u64,
+    total: Option<u64>,
+}
+
+impl Progress {
+    /// Bytes of the file done, the resume position included
+    pub fn transferred(&self) -> u64 {
+        self.transferred
+    }
+
+    /// Size of the whole file, if it was announced
+    pub fn total(&self) -> Option<u64> {
+        self.total
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.total.map(|t| self.transferred >= t).unwrap_or(false)
+    }
+}
+
+/// A file transfer over an established DCC connection
+///
+/// Both sides count positions from the start of the file, so a resumed transfer starts at the
+/// position agreed on with `RESUME`/`ACCEPT`. The receiver acknowledges every chunk with the
+/// position reached as 32 bit big endian number, as the protocol demands.
+pub struct Transfer {
+    stream: TcpStream,
+    position: u64,
+    size: Option<u64>,
+    limit: Option<u64>,
+    timeout: Duration,
+}
+
+impl Transfer {
+    pub fn new(stream: TcpStream) -> Self {
+        Transfer {
+            stream,
+            position: 0,
+            size: None,
+            limit: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Position to resume at. The reader or writer has to be at this position already.
+    pub fn position(mut self, position: u64) -> Self {
+        self.position = position;
+
+        self
+    }
+
+    /// Size of the whole file as offered
+    pub fn size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+
+        self
+    }
+
+    /// Largest file accepted when receiving
+    pub fn limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+
+        self
+    }
+
+    /// Maximum time without any data before the transfer is aborted
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+
+        self
+    }
+}
+
+impl Transfer {
+    fn progress(&self) -> Progress {
+        Progress {
+            transferred: self.position,
+            total: self.size,
+        }
+    }
+
+    fn check_limit(&self, size: u64) -> Result<(), Box<Error>> {
+        match self.limit {
+            Some(limit) if size > limit => Err(SizeLimitError::new(size, limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Receives the file into `writer` and returns the final position.
+    /// Fails if the peer sends more or less than the offered size.
+    pub fn receive<W, F>(mut self, mut writer: W, mut on_progress: F) -> Result<u64, Box<Error>>
+        where
+            W: Write,
+            F: FnMut(Progress),
+    {
+        if let Some(size) = self.size {
+            self.check_limit(size)?;
+
+            if self.position >= size {
+                return Ok(self.position);
+            }
+        }
+
+        self.stream.set_read_timeout(Some(self.timeout))?;
+
+        let mut buffer = vec![0; BUFFER_SIZE];
+
+        loop {
+            let read = match self.stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(map_io(e)),
+            };
+
+            let position = self.position + read as u64;
+
+            match self.size {
+                Some(size) if position > size => {
+                    return Err(SizeMismatchError::new(size, position));
+                }
+                None => self.check_limit(position)?,
+                _ => (),
+            }
+
+            writer.write_all(&buffer[..read])?;
+            self.position = position;
+
+            // only the lower 32 bits fit, peers compare them the same way
+            self.stream
+                .write_all(&(position as u32).to_be_bytes())
+                .map_err(map_io)?;
+
+            on_progress(self.progress());
+
+            if self.size == Some(position) {
+                break;
+            }
+        }
+
+        writer.flush()?;
+
+        match self.size {
+            Some(size) if self.position != size => {
+                Err(SizeMismatchError::new(size, self.position))
+            }
+            _ => Ok(self.position),
+        }
+    }
+
+    /// Sends everything `reader` has left and waits until the peer acknowledged all of it.
+    /// Returns the final position.
+    pub fn send<R, F>(mut self, mut reader: R, mut on_progress: F) -> Result<u64, Box<Error>>
+        where
+            R: Read,
+            F: FnMut(Progress),
+    {
+        self.stream.set_write_timeout(Some(self.timeout))?;
+
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut acks = Acks::default();
+
+        loop {
+            let read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(Box::new(e)),
+            };
+
+            self.stream.write_all(&buffer[..read]).map_err(map_io)?;
+            self.position += read as u64;
+
+            // keep the acknowledgements from piling up in the receive buffer
+            self.stream.set_nonblocking(true)?;
+            let drained = acks.read(&mut self.stream, false);
+            self.stream.set_nonblocking(false)?;
+            drained?;
+
+            on_progress(self.progress());
+        }
+
+        if let Some(size) = self.size {
+            if self.position != size {
+                return Err(SizeMismatchError::new(size, self.position));
+            }
+        }
+
+        self.stream.set_read_timeout(Some(self.timeout))?;
+
+        while acks.last != Some(self.position as u32) {
+            if !acks.read(&mut self.stream, true)? {
+                // closed without the final acknowledgement, which some clients do
+                break;
+            }
+        }
+
+        Ok(self.position)
+    }
+}
+
+#[derive(Default)]
+struct Acks {
+    pending: Vec<u8>,
+    last: Option<u32>,
+}
+
+impl Acks {
+    /// Reads the acknowledgements that arrived, `false` once the peer closed the connection.
+    /// Non-blocking reads stop as soon as nothing is left, blocking ones after the first read.
+    fn read(&mut self, stream: &mut TcpStream, blocking: bool) -> Result<bool, Box<Error>> {
+        let mut buffer = [0; 64];
+
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => return Ok(false),
+                Ok(read) => {
+                    self.pending.extend_from_slice(&buffer[..read]);
+
+                    while self.pending.len() >= ACK_SIZE {
+                        let ack = self.pending.drain(..ACK_SIZE).collect::<Vec<u8>>();
+
+                        self.last = Some(u32::from_be_bytes([ack[0], ack[1], ack[2], ack[3]]));
+                    }
+
+                    if blocking {
+                        return Ok(true);
+                    }
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock && !blocking => return Ok(true),
+                Err(e) => return Err(map_io(e)),
+            }
+        }
+    }
+}
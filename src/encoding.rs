@@ -0,0 +1,90 @@
+use std::error::Error;
+
+pub mod error {
+    impl_error!(InvalidEncodingError { encoding: String });
+    impl_error!(UnknownEncodingError { label: String });
+    impl_error!(UnmappableCharacterError {
+        encoding: String,
+        text: String
+    });
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Text encoding of a connection, used for both incoming and outgoing lines
+pub enum Encoding {
+    /// Lines that aren't valid UTF-8 are skipped when read
+    Utf8,
+    /// UTF-8, but lines that aren't valid UTF-8 are read as Latin-1. Sends UTF-8.
+    Utf8Latin1,
+    /// A fixed, usually legacy, encoding like `windows-1252` or `koi8-r`
+    Legacy(&'static encoding_rs::Encoding),
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Utf8Latin1
+    }
+}
+
+impl Encoding {
+    /// Legacy encoding by its WHATWG label, e.g. `latin1`, `cp1251` or `iso-2022-jp`
+    pub fn legacy(label: &str) -> Result<Self, Box<Error>> {
+        match encoding_rs::Encoding::for_label(label.as_bytes()) {
+            Some(encoding) if encoding == encoding_rs::UTF_8 => Ok(Encoding::Utf8),
+            Some(encoding) => Ok(Encoding::Legacy(encoding)),
+            None => Err(error::UnknownEncodingError::new(label.to_string())),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Latin1 => "UTF-8/ISO-8859-1",
+            Encoding::Legacy(encoding) => encoding.name(),
+        }
+    }
+}
+
+impl Encoding {
+    /// Decodes a complete line
+    pub fn decode(&self, data: &[u8]) -> Result<String, Box<Error>> {
+        match self {
+            Encoding::Utf8 => match std::str::from_utf8(data) {
+                Ok(text) => Ok(text.to_string()),
+                Err(_) => Err(error::InvalidEncodingError::new(self.name().to_string())),
+            },
+
+            // every byte is a valid Latin-1 character, mapped to the code point of the same value
+            Encoding::Utf8Latin1 => match std::str::from_utf8(data) {
+                Ok(text) => Ok(text.to_string()),
+                Err(_) => Ok(data.iter().map(|b| *b as char).collect()),
+            },
+
+            Encoding::Legacy(encoding) => {
+                match encoding.decode_without_bom_handling_and_without_replacement(data) {
+                    Some(text) => Ok(text.into_owned()),
+                    None => Err(error::InvalidEncodingError::new(self.name().to_string())),
+                }
+            }
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Result<Vec<u8>, Box<Error>> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Latin1 => Ok(text.as_bytes().to_vec()),
+
+            Encoding::Legacy(encoding) => {
+                let (data, _, unmappable) = encoding.encode(text);
+
+                if unmappable {
+                    Err(error::UnmappableCharacterError::new(
+                        self.name().to_string(),
+                        text.to_string(),
+                    ))
+                } else {
+                    Ok(data.into_owned())
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,544 @@
+pub const BOLD: char = '\x02';
+pub const COLOR: char = '\x03';
+pub const HEX_COLOR: char = '\x04';
+pub const RESET: char = '\x0F';
+pub const MONOSPACE: char = '\x11';
+pub const REVERSE: char = '\x16';
+pub const ITALIC: char = '\x1D';
+pub const STRIKETHROUGH: char = '\x1E';
+pub const UNDERLINE: char = '\x1F';
+
+/// Codes switching a style on and off
+pub const TOGGLES: &str = "\x02\x11\x16\x1D\x1E\x1F";
+
+/// RGB values of the mIRC colors 0 to 98 (99 is the default color)
+const PALETTE: [u32; 99] = [
+    0xffffff, 0x000000, 0x00007f, 0x009300, 0xff0000, 0x7f0000, 0x9c009c, 0xfc7f00,
+    0xffff00, 0x00fc00, 0x009393, 0x00ffff, 0x0000fc, 0xff00ff, 0x7f7f7f, 0xd2d2d2,
+    0x470000, 0x472100, 0x474700, 0x324700, 0x004700, 0x00472c, 0x004747, 0x002747,
+    0x000047, 0x2e0047, 0x470047, 0x47002a, 0x740000, 0x743a00, 0x747400, 0x517400,
+    0x007400, 0x007449, 0x007474, 0x004074, 0x000074, 0x4b0074, 0x740074, 0x740045,
+    0xb50000, 0xb56300, 0xb5b500, 0x7db500, 0x00b500, 0x00b571, 0x00b5b5, 0x0063b5,
+    0x0000b5, 0x7500b5, 0xb500b5, 0xb5006b, 0xff0000, 0xff8c00, 0xffff00, 0xb2ff00,
+    0x00ff00, 0x00ffa0, 0x00ffff, 0x008cff, 0x0000ff, 0xa500ff, 0xff00ff, 0xff0098,
+    0xff5959, 0xffb459, 0xffff71, 0xcfff60, 0x6fff6f, 0x65ffc9, 0x6dffff, 0x59b4ff,
+    0x5959ff, 0xc459ff, 0xff66ff, 0xff59bc, 0xff9c9c, 0xffd39c, 0xffff9c, 0xe2ff9c,
+    0x9cff9c, 0x9cffdb, 0x9cffff, 0x9cd3ff, 0x9c9cff, 0xdc9cff, 0xff9cff, 0xff94d3,
+    0x000000, 0x131313, 0x282828, 0x363636, 0x4d4d4d, 0x656565, 0x818181, 0x9f9f9f,
+    0xbcbcbc, 0xe2e2e2, 0xffffff,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    /// mIRC color number (`\x03`), 0 to 98
+    Index(u8),
+    /// Hex color (`\x04`)
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    pub const WHITE: Color = Color::Index(0);
+    pub const BLACK: Color = Color::Index(1);
+    pub const BLUE: Color = Color::Index(2);
+    pub const GREEN: Color = Color::Index(3);
+    pub const RED: Color = Color::Index(4);
+    pub const BROWN: Color = Color::Index(5);
+    pub const MAGENTA: Color = Color::Index(6);
+    pub const ORANGE: Color = Color::Index(7);
+    pub const YELLOW: Color = Color::Index(8);
+    pub const LIGHT_GREEN: Color = Color::Index(9);
+    pub const CYAN: Color = Color::Index(10);
+    pub const LIGHT_CYAN: Color = Color::Index(11);
+    pub const LIGHT_BLUE: Color = Color::Index(12);
+    pub const PINK: Color = Color::Index(13);
+    pub const GREY: Color = Color::Index(14);
+    pub const LIGHT_GREY: Color = Color::Index(15);
+
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Index(index) => {
+                let rgb = PALETTE[(index as usize).min(PALETTE.len() - 1)];
+
+                ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    fn html(&self) -> String {
+        let (r, g, b) = self.rgb();
+
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    }
+
+    fn ansi(&self, background: bool) -> String {
+        let (r, g, b) = self.rgb();
+
+        format!("{};2;{};{};{}", if background { 48 } else { 38 }, r, g, b)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    pub monospace: bool,
+    pub reverse: bool,
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+}
+
+impl Style {
+    pub fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    fn toggle(&mut self, code: char) {
+        match code {
+            BOLD => self.bold = !self.bold,
+            ITALIC => self.italic = !self.italic,
+            UNDERLINE => self.underline = !self.underline,
+            STRIKETHROUGH => self.strikethrough = !self.strikethrough,
+            MONOSPACE => self.monospace = !self.monospace,
+            REVERSE => self.reverse = !self.reverse,
+            _ => (),
+        }
+    }
+
+    /// Codes switching from plain text to this style
+    pub(crate) fn codes(&self) -> String {
+        let mut codes = String::new();
+
+        let toggles = [
+            (self.bold, BOLD),
+            (self.italic, ITALIC),
+            (self.underline, UNDERLINE),
+            (self.strikethrough, STRIKETHROUGH),
+            (self.monospace, MONOSPACE),
+            (self.reverse, REVERSE),
+        ];
+
+        for (enabled, code) in toggles.iter() {
+            if *enabled {
+                codes.push(*code);
+            }
+        }
+
+        match (self.foreground, self.background) {
+            (Some(Color::Index(fg)), Some(Color::Index(bg))) => {
+                codes.push_str(&format!("{}{:02},{:02}", COLOR, fg, bg))
+            }
+            (Some(Color::Index(fg)), None) => codes.push_str(&format!("{}{:02}", COLOR, fg)),
+            (None, Some(Color::Index(bg))) => codes.push_str(&format!("{}99,{:02}", COLOR, bg)),
+            (fg, bg) if fg.is_some() || bg.is_some() => {
+                // mixing index and hex colors, hex covers both
+                let hex = |color: Option<Color>| {
+                    let (r, g, b) = color.unwrap_or(Color::BLACK).rgb();
+
+                    format!("{:02X}{:02X}{:02X}", r, g, b)
+                };
+
+                codes.push(HEX_COLOR);
+                codes.push_str(&hex(fg.or(Some(Color::BLACK))));
+
+                if bg.is_some() {
+                    codes.push(',');
+                    codes.push_str(&hex(bg));
+                }
+            }
+            _ => (),
+        }
+
+        codes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Text with one style
+pub struct Span {
+    text: String,
+    style: Style,
+}
+
+impl Span {
+    pub fn new(text: &str, style: Style) -> Self {
+        Span {
+            text: text.to_string(),
+            style,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn style(&self) -> &Style {
+        &self.style
+    }
+}
+
+/// Parses `count` decimal or hex digits of a color, returns the value and the length consumed
+fn parse_number(data: &str, digits: usize, radix: u32) -> Option<(u32, usize)> {
+    let len = data
+        .chars()
+        .take(digits)
+        .take_while(|c| c.is_digit(radix))
+        .count();
+
+    // hex colors need all six digits
+    if len == 0 || (radix == 16 && len != digits) {
+        return None;
+    }
+
+    u32::from_str_radix(&data[..len], radix)
+        .ok()
+        .map(|value| (value, len))
+}
+
+/// Parses `fg[,bg]` following a color code. Returns the colors (`None` for "default") and the
+/// length consumed, or `None` if there are no colors, which resets them.
+fn parse_colors(data: &str, hex: bool) -> Option<(Option<Color>, Option<Option<Color>>, usize)> {
+    let (digits, radix) = if hex { (6, 16) } else { (2, 10) };
+
+    let color = |value: u32| {
+        if hex {
+            Some(Color::Rgb((value >> 16) as u8, (value >> 8) as u8, value as u8))
+        } else if value < 99 {
+            Some(Color::Index(value as u8))
+        } else {
+            None
+        }
+    };
+
+    let (fg, fg_len) = parse_number(data, digits, radix)?;
+
+    let rest = &data[fg_len..];
+
+    if rest.starts_with(',') {
+        if let Some((bg, bg_len)) = parse_number(&rest[1..], digits, radix) {
+            return Some((color(fg), Some(color(bg)), fg_len + 1 + bg_len));
+        }
+    }
+
+    Some((color(fg), None, fg_len))
+}
+
+/// Length of the formatting code at the start of `text`, colors included, `0` if there is none
+pub(crate) fn code_len(text: &str) -> usize {
+    match text.chars().next() {
+        Some(c) if c == COLOR || c == HEX_COLOR => {
+            1 + parse_colors(&text[1..], c == HEX_COLOR).map_or(0, |(_, _, len)| len)
+        }
+        Some(c) if c == RESET || TOGGLES.contains(c) => 1,
+        _ => 0,
+    }
+}
+
+/// Parses formatted text into styled spans
+pub fn parse(text: &str) -> Vec<Span> {
+    scan(text).0
+}
+
+/// The style in effect at the end of `text`, codes after the last character included
+pub fn style_after(text: &str) -> Style {
+    scan(text).1
+}
+
+fn scan(text: &str) -> (Vec<Span>, Style) {
+    let mut spans = Vec::new();
+    let mut style = Style::default();
+    let mut current = String::new();
+    let mut pos = 0;
+
+    let mut flush = |current: &mut String, style: &Style| {
+        if !current.is_empty() {
+            spans.push(Span {
+                text: std::mem::replace(current, String::new()),
+                style: style.clone(),
+            });
+        }
+    };
+
+    while let Some(c) = text[pos..].chars().next() {
+        pos += c.len_utf8();
+
+        match c {
+            RESET => {
+                flush(&mut current, &style);
+                style = Style::default();
+            }
+
+            COLOR | HEX_COLOR => {
+                flush(&mut current, &style);
+
+                match parse_colors(&text[pos..], c == HEX_COLOR) {
+                    Some((fg, bg, len)) => {
+                        style.foreground = fg;
+
+                        if let Some(bg) = bg {
+                            style.background = bg;
+                        }
+
+                        pos += len;
+                    }
+
+                    None => {
+                        style.foreground = None;
+                        style.background = None;
+                    }
+                }
+            }
+
+            _ if TOGGLES.contains(c) => {
+                flush(&mut current, &style);
+                style.toggle(c);
+            }
+
+            _ => current.push(c),
+        }
+    }
+
+    flush(&mut current, &style);
+
+    (spans, style)
+}
+
+/// Removes all formatting codes
+pub fn strip(text: &str) -> String {
+    parse(text)
+        .iter()
+        .map(|span| span.text())
+        .collect::<String>()
+}
+
+/// Builds formatted text from spans
+pub fn to_irc(spans: &[Span]) -> String {
+    let mut text = String::new();
+    let mut styled = false;
+
+    for span in spans {
+        if styled {
+            text.push(RESET);
+        }
+
+        text.push_str(&span.style.codes());
+        text.push_str(&span.text);
+
+        styled = !span.style.is_plain();
+    }
+
+    if styled {
+        text.push(RESET);
+    }
+
+    text
+}
+
+/// Renders spans with ANSI terminal escape sequences (24 bit colors)
+pub fn to_ansi(spans: &[Span]) -> String {
+    let mut text = String::new();
+
+    for span in spans {
+        let style = &span.style;
+        let mut codes = Vec::new();
+
+        if style.bold {
+            codes.push("1".to_string());
+        }
+        if style.italic {
+            codes.push("3".to_string());
+        }
+        if style.underline {
+            codes.push("4".to_string());
+        }
+        if style.reverse {
+            codes.push("7".to_string());
+        }
+        if style.strikethrough {
+            codes.push("9".to_string());
+        }
+        if let Some(fg) = style.foreground {
+            codes.push(fg.ansi(false));
+        }
+        if let Some(bg) = style.background {
+            codes.push(bg.ansi(true));
+        }
+
+        if codes.is_empty() {
+            text.push_str(&span.text);
+        } else {
+            text.push_str(&format!("\x1b[{}m{}\x1b[0m", codes.join(";"), span.text));
+        }
+    }
+
+    text
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Renders spans as HTML, styled spans become `<span style="..">`
+pub fn to_html(spans: &[Span]) -> String {
+    let mut html = String::new();
+
+    for span in spans {
+        let style = &span.style;
+        let mut css = Vec::new();
+
+        if style.bold {
+            css.push("font-weight:bold".to_string());
+        }
+        if style.italic {
+            css.push("font-style:italic".to_string());
+        }
+
+        match (style.underline, style.strikethrough) {
+            (true, true) => css.push("text-decoration:underline line-through".to_string()),
+            (true, false) => css.push("text-decoration:underline".to_string()),
+            (false, true) => css.push("text-decoration:line-through".to_string()),
+            _ => (),
+        }
+
+        if style.monospace {
+            css.push("font-family:monospace".to_string());
+        }
+
+        let (fg, bg) = if style.reverse {
+            (
+                Some(style.background.unwrap_or(Color::WHITE)),
+                Some(style.foreground.unwrap_or(Color::BLACK)),
+            )
+        } else {
+            (style.foreground, style.background)
+        };
+
+        if let Some(fg) = fg {
+            css.push(format!("color:{}", fg.html()));
+        }
+        if let Some(bg) = bg {
+            css.push(format!("background-color:{}", bg.html()));
+        }
+
+        if css.is_empty() {
+            html.push_str(&escape_html(&span.text));
+        } else {
+            html.push_str(&format!(
+                "<span style=\"{}\">{}</span>",
+                css.join(";"),
+                escape_html(&span.text)
+            ));
+        }
+    }
+
+    html
+}
+
+#[derive(Debug, Default)]
+/// Builds formatted text piece by piece
+///
+/// ```ignore
+/// let text = Builder::new()
+///     .text("plain ")
+///     .bold()
+///     .color(Color::RED, None)
+///     .text("bold red")
+///     .build();
+/// ```
+pub struct Builder {
+    spans: Vec<Span>,
+    style: Style,
+}
+
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.spans.push(Span::new(text, self.style.clone()));
+
+        self
+    }
+
+    pub fn span(mut self, span: Span) -> Self {
+        self.spans.push(span);
+
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.style.bold = !self.style.bold;
+
+        self
+    }
+
+    pub fn italic(mut self) -> Self {
+        self.style.italic = !self.style.italic;
+
+        self
+    }
+
+    pub fn underline(mut self) -> Self {
+        self.style.underline = !self.style.underline;
+
+        self
+    }
+
+    pub fn strikethrough(mut self) -> Self {
+        self.style.strikethrough = !self.style.strikethrough;
+
+        self
+    }
+
+    pub fn monospace(mut self) -> Self {
+        self.style.monospace = !self.style.monospace;
+
+        self
+    }
+
+    pub fn reverse(mut self) -> Self {
+        self.style.reverse = !self.style.reverse;
+
+        self
+    }
+
+    pub fn color(mut self, foreground: Color, background: Option<Color>) -> Self {
+        self.style.foreground = Some(foreground);
+        self.style.background = background;
+
+        self
+    }
+
+    pub fn reset(mut self) -> Self {
+        self.style = Style::default();
+
+        self
+    }
+}
+
+impl Builder {
+    pub fn spans(&self) -> &Vec<Span> {
+        &self.spans
+    }
+
+    /// Text with mIRC formatting codes
+    pub fn build(&self) -> String {
+        to_irc(&self.spans)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::time;
+
+/// Capability clients enable to use `CHATHISTORY` (IRCv3)
+pub const CAPABILITY: &str = "draft/chathistory";
+
+/// Batch type the messages of a `CHATHISTORY` reply come in
+pub const BATCH: &str = "chathistory";
+
+/// Batch type of the `CHATHISTORY TARGETS` reply
+pub const TARGETS_BATCH: &str = "draft/chathistory-targets";
+
+pub const TIMESTAMP_PREFIX: &str = "timestamp=";
+
+pub const MSGID_PREFIX: &str = "msgid=";
+
+/// Message a `CHATHISTORY` range starts or ends at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// Milliseconds since the epoch
+    Timestamp(u64),
+    MsgId(String),
+}
+
+impl Reference {
+    /// Parses `timestamp=2019-01-04T14:33:26.123Z` or `msgid=id`
+    pub fn parse(data: &str) -> Option<Self> {
+        if let Some(time) = data.strip_prefix(TIMESTAMP_PREFIX) {
+            time::parse_time(time).map(Reference::Timestamp)
+        } else if let Some(id) = data.strip_prefix(MSGID_PREFIX) {
+            Some(id)
+                .filter(|id| !id.is_empty())
+                .map(|id| Reference::MsgId(id.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+impl ToString for Reference {
+    fn to_string(&self) -> String {
+        match self {
+            Reference::Timestamp(millis) => format!("{}{}", TIMESTAMP_PREFIX, time::format_time(*millis)),
+            Reference::MsgId(ref id) => format!("{}{}", MSGID_PREFIX, id),
+        }
+    }
+}
+
+/// Which messages of a target `CHATHISTORY` asks for, at most `limit` of them
+#[derive(Debug, Clone, PartialEq)]
+pub enum Range {
+    /// The most recent messages, only those after the reference if there is one
+    Latest {
+        after: Option<Reference>,
+        limit: usize,
+    },
+    Before {
+        reference: Reference,
+        limit: usize,
+    },
+    After {
+        reference: Reference,
+        limit: usize,
+    },
+    /// Messages on both sides of the reference, including it
+    Around {
+        reference: Reference,
+        limit: usize,
+    },
+    /// Messages between the references, the ones closest to `start` if there are too many
+    Between {
+        start: Reference,
+        end: Reference,
+        limit: usize,
+    },
+}
+
+impl Range {
+    /// Parses the parameters following the target of a `CHATHISTORY` subcommand
+    pub fn parse(subcommand: &str, params: &[&str]) -> Option<Self> {
+        let limit = params.last()?.parse().ok()?;
+
+        match (subcommand.to_ascii_uppercase().as_str(), params.len()) {
+            ("LATEST", 2) => Some(Range::Latest {
+                after: match params[0] {
+                    "*" => None,
+                    reference => Some(Reference::parse(reference)?),
+                },
+                limit,
+            }),
+            ("BEFORE", 2) => Some(Range::Before {
+                reference: Reference::parse(params[0])?,
+                limit,
+            }),
+            ("AFTER", 2) => Some(Range::After {
+                reference: Reference::parse(params[0])?,
+                limit,
+            }),
+            ("AROUND", 2) => Some(Range::Around {
+                reference: Reference::parse(params[0])?,
+                limit,
+            }),
+            ("BETWEEN", 3) => Some(Range::Between {
+                start: Reference::parse(params[0])?,
+                end: Reference::parse(params[1])?,
+                limit,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn subcommand(&self) -> &'static str {
+        match self {
+            Range::Latest { .. } => "LATEST",
+            Range::Before { .. } => "BEFORE",
+            Range::After { .. } => "AFTER",
+            Range::Around { .. } => "AROUND",
+            Range::Between { .. } => "BETWEEN",
+        }
+    }
+
+    /// References and limit as sent after the target
+    pub fn params(&self) -> Vec<String> {
+        let mut params = match self {
+            Range::Latest { after: None, .. } => vec!["*".to_string()],
+            Range::Latest {
+                after: Some(ref reference),
+                ..
+            }
+            | Range::Before { ref reference, .. }
+            | Range::After { ref reference, .. }
+            | Range::Around { ref reference, .. } => vec![reference.to_string()],
+            Range::Between {
+                ref start, ref end, ..
+            } => vec![start.to_string(), end.to_string()],
+        };
+
+        params.push(self.limit().to_string());
+
+        params
+    }
+
+    pub fn limit(&self) -> usize {
+        match self {
+            Range::Latest { limit, .. }
+            | Range::Before { limit, .. }
+            | Range::After { limit, .. }
+            | Range::Around { limit, .. }
+            | Range::Between { limit, .. } => *limit,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use super::{error, sasl::Credentials};
+use crate::{casemapping::CaseMapping, time};
+
+/// Account of a registered nick, named after it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Account {
+    name: String,
+    credentials: Credentials,
+    registered: u64,
+}
+
+impl Account {
+    pub fn new(name: &str, password: &str) -> Self {
+        Account {
+            name: name.to_string(),
+            credentials: Credentials::new(password),
+            registered: time::now(),
+        }
+    }
+}
+
+impl Account {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn credentials(&self) -> &Credentials {
+        &self.credentials
+    }
+
+    /// Seconds since the epoch when the nick was registered
+    pub fn registered(&self) -> u64 {
+        self.registered
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        self.credentials.verify(password)
+    }
+}
+
+/// Status ChanServ gives accounts on the access list when they join
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    Operator,
+    Voice,
+}
+
+impl Access {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "op" => Some(Access::Operator),
+            "voice" => Some(Access::Voice),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Operator => "op",
+            Access::Voice => "voice",
+        }
+    }
+}
+
+/// Channel registered with ChanServ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registration {
+    channel: String,
+    founder: String,
+    access: Vec<(String, Access)>,
+    registered: u64,
+}
+
+impl Registration {
+    pub fn new(channel: &str, founder: &str) -> Self {
+        Registration {
+            channel: channel.to_string(),
+            founder: founder.to_string(),
+            access: Vec::new(),
+            registered: time::now(),
+        }
+    }
+}
+
+impl Registration {
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Account which registered the channel
+    pub fn founder(&self) -> &str {
+        &self.founder
+    }
+
+    pub fn access(&self) -> &Vec<(String, Access)> {
+        &self.access
+    }
+
+    pub fn registered(&self) -> u64 {
+        self.registered
+    }
+
+    /// The founder operates the channel, everyone else gets what the access list says
+    pub fn access_of(&self, account: &str, mapping: CaseMapping) -> Option<Access> {
+        if mapping.eq(&self.founder, account) {
+            return Some(Access::Operator);
+        }
+
+        self.access
+            .iter()
+            .find(|(other, _)| mapping.eq(other, account))
+            .map(|(_, access)| *access)
+    }
+
+    /// Puts the account on the access list or takes it off with `None`. Returns whether it was
+    /// on the list before.
+    pub fn set_access(
+        &mut self,
+        account: &str,
+        access: Option<Access>,
+        mapping: CaseMapping,
+    ) -> bool {
+        let known = self
+            .access
+            .iter()
+            .any(|(other, _)| mapping.eq(other, account));
+
+        self.access.retain(|(other, _)| !mapping.eq(other, account));
+
+        if let Some(access) = access {
+            self.access.push((account.to_string(), access));
+        }
+
+        known
+    }
+}
+
+/// Where the services keep accounts and channel registrations, looking names up case
+/// insensitively
+pub trait AccountStore {
+    fn account(&self, name: &str) -> Option<Account>;
+
+    /// Adds the account or replaces the one with the same name
+    fn save_account(&mut self, account: Account) -> Result<(), Box<Error>>;
+
+    fn registration(&self, channel: &str) -> Option<Registration>;
+
+    /// Adds the registration or replaces the one of the same channel
+    fn save_registration(&mut self, registration: Registration) -> Result<(), Box<Error>>;
+
+    fn remove_registration(&mut self, channel: &str) -> Result<(), Box<Error>>;
+}
+
+/// Store kept in memory, written to a text file on every change
+pub struct FileStore {
+    path: Option<PathBuf>,
+    mapping: CaseMapping,
+    accounts: Vec<Account>,
+    registrations: Vec<Registration>,
+}
+
+impl FileStore {
+    /// Reads the store `save` wrote, a missing file is an empty store
+    pub fn open(path: &Path, mapping: CaseMapping) -> Result<Self, Box<Error>> {
+        let mut store = FileStore::memory(mapping);
+        store.path = Some(path.to_path_buf());
+
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(store),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        for (number, line) in data.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if store.parse(line).is_none() {
+                return Err(error::AccountFileError::new(number + 1));
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Store which isn't written anywhere
+    pub fn memory(mapping: CaseMapping) -> Self {
+        FileStore {
+            path: None,
+            mapping,
+            accounts: Vec::new(),
+            registrations: Vec::new(),
+        }
+    }
+
+    /// One entry per line: `A name registered credentials` for accounts, `C channel registered
+    /// founder` for channels followed by `X channel account op|voice` for their access list
+    fn save(&self) -> Result<(), Box<Error>> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+
+        let mut data = String::new();
+
+        for account in self.accounts.iter() {
+            data.push_str(&format!(
+                "A {} {} {}\n",
+                account.name,
+                account.registered,
+                account.credentials.to_string()
+            ));
+        }
+
+        for registration in self.registrations.iter() {
+            data.push_str(&format!(
+                "C {} {} {}\n",
+                registration.channel, registration.registered, registration.founder
+            ));
+
+            for (account, access) in registration.access.iter() {
+                data.push_str(&format!(
+                    "X {} {} {}\n",
+                    registration.channel,
+                    account,
+                    access.name()
+                ));
+            }
+        }
+
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    fn parse(&mut self, line: &str) -> Option<()> {
+        let fields = line.split(' ').collect::<Vec<&str>>();
+
+        match (fields[0], fields.len()) {
+            ("A", 4) => self.accounts.push(Account {
+                name: fields[1].to_string(),
+                registered: fields[2].parse().ok()?,
+                credentials: Credentials::parse(fields[3])?,
+            }),
+            ("C", 4) => self.registrations.push(Registration {
+                channel: fields[1].to_string(),
+                registered: fields[2].parse().ok()?,
+                founder: fields[3].to_string(),
+                access: Vec::new(),
+            }),
+            ("X", 4) => {
+                let access = Access::parse(fields[3])?;
+                let registration = self.registrations.last_mut()?;
+
+                if registration.channel != fields[1] {
+                    return None;
+                }
+
+                registration.access.push((fields[2].to_string(), access));
+            }
+            _ => return None,
+        }
+
+        Some(())
+    }
+}
+
+impl AccountStore for FileStore {
+    fn account(&self, name: &str) -> Option<Account> {
+        self.accounts
+            .iter()
+            .find(|account| self.mapping.eq(&account.name, name))
+            .cloned()
+    }
+
+    fn save_account(&mut self, account: Account) -> Result<(), Box<Error>> {
+        let mapping = self.mapping;
+
+        self.accounts
+            .retain(|other| !mapping.eq(&other.name, &account.name));
+        self.accounts.push(account);
+
+        self.save()
+    }
+
+    fn registration(&self, channel: &str) -> Option<Registration> {
+        self.registrations
+            .iter()
+            .find(|registration| self.mapping.eq(&registration.channel, channel))
+            .cloned()
+    }
+
+    fn save_registration(&mut self, registration: Registration) -> Result<(), Box<Error>> {
+        let mapping = self.mapping;
+
+        self.registrations
+            .retain(|other| !mapping.eq(&other.channel, &registration.channel));
+        self.registrations.push(registration);
+
+        self.save()
+    }
+
+    fn remove_registration(&mut self, channel: &str) -> Result<(), Box<Error>> {
+        let mapping = self.mapping;
+
+        self.registrations
+            .retain(|registration| !mapping.eq(&registration.channel, channel));
+
+        self.save()
+    }
+}
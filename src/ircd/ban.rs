@@ -0,0 +1,204 @@
+use std::{
+    error::Error,
+    fs,
+    io::ErrorKind,
+    net::IpAddr,
+    path::Path,
+    time::Duration,
+};
+
+use super::error;
+use crate::{
+    casemapping::CaseMapping,
+    origin::{Mask, Origin},
+    time,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    /// Bans `user@host` masks when registering
+    KLine,
+    /// Bans IP addresses or CIDR ranges when connecting
+    DLine,
+}
+
+impl Kind {
+    pub fn letter(self) -> char {
+        match self {
+            Kind::KLine => 'K',
+            Kind::DLine => 'D',
+        }
+    }
+
+    fn from_letter(letter: &str) -> Option<Self> {
+        match letter {
+            "K" => Some(Kind::KLine),
+            "D" => Some(Kind::DLine),
+            _ => None,
+        }
+    }
+}
+
+/// Server wide ban, permanent unless it has an expiry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ban {
+    kind: Kind,
+    mask: String,
+    reason: String,
+    setter: String,
+    set: u64,
+    expires: Option<u64>,
+}
+
+impl Ban {
+    pub fn new(kind: Kind, mask: &str, reason: &str, setter: &str) -> Self {
+        Ban {
+            kind,
+            mask: mask.to_string(),
+            reason: reason.to_string(),
+            setter: setter.to_string(),
+            set: time::now(),
+            expires: None,
+        }
+    }
+
+    /// Makes the ban temporary
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.expires = Some(self.set + duration.as_secs());
+
+        self
+    }
+}
+
+impl Ban {
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    pub fn mask(&self) -> &str {
+        &self.mask
+    }
+
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    pub fn setter(&self) -> &str {
+        &self.setter
+    }
+
+    /// Seconds since the epoch when the ban was set
+    pub fn set(&self) -> u64 {
+        self.set
+    }
+
+    pub fn expires(&self) -> Option<u64> {
+        self.expires
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    /// K-lines match the user, D-lines the address it connected from
+    pub fn matches(
+        &self,
+        origin: Option<&Origin>,
+        address: Option<IpAddr>,
+        mapping: CaseMapping,
+    ) -> bool {
+        match self.kind {
+            Kind::KLine => origin.map_or(false, |origin| {
+                Mask::new(&format!("*!{}", self.mask))
+                    .casemapping(mapping)
+                    .matches(origin)
+            }),
+            Kind::DLine => address.map_or(false, |address| {
+                Mask::new(&format!("*!*@{}", self.mask)).matches_parts("", "", &address.to_string())
+            }),
+        }
+    }
+}
+
+/// Whether the D-line mask is an address, a CIDR range or a wildcard pattern of one
+pub fn is_address_mask(mask: &str) -> bool {
+    if let Some(pos) = mask.find('/') {
+        return mask[..pos].parse::<IpAddr>().is_ok() && mask[pos + 1..].parse::<u8>().is_ok();
+    }
+
+    !mask.is_empty()
+        && mask
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || c == '.' || c == ':' || c == '*' || c == '?')
+}
+
+/// Reads bans written by `save`, a missing file has none
+pub fn load(path: &Path) -> Result<Vec<Ban>, Box<Error>> {
+    let data = match fs::read_to_string(path) {
+        Ok(data) => data,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Box::new(e)),
+    };
+
+    let mut bans = Vec::new();
+
+    for (number, line) in data.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse(line) {
+            Some(ban) => bans.push(ban),
+            None => return Err(error::BanFileError::new(number + 1)),
+        }
+    }
+
+    Ok(bans)
+}
+
+/// One ban per line: `K|D set expires setter mask :reason`, with an expiry of `0` for permanent
+/// bans
+pub fn save(path: &Path, bans: &[Ban]) -> Result<(), Box<Error>> {
+    let data: String = bans
+        .iter()
+        .map(|ban| {
+            format!(
+                "{} {} {} {} {} :{}\n",
+                ban.kind.letter(),
+                ban.set,
+                ban.expires.unwrap_or_default(),
+                ban.setter,
+                ban.mask,
+                ban.reason
+            )
+        })
+        .collect();
+
+    fs::write(path, data)?;
+
+    Ok(())
+}
+
+fn parse(line: &str) -> Option<Ban> {
+    let (fields, reason) = match line.find(" :") {
+        Some(pos) => (&line[..pos], &line[pos + 2..]),
+        None => return None,
+    };
+
+    let fields = fields.split(' ').collect::<Vec<&str>>();
+
+    if fields.len() != 5 {
+        return None;
+    }
+
+    let expires = fields[2].parse().ok()?;
+
+    Some(Ban {
+        kind: Kind::from_letter(fields[0])?,
+        set: fields[1].parse().ok()?,
+        expires: Some(expires).filter(|expires| *expires != 0),
+        setter: fields[3].to_string(),
+        mask: fields[4].to_string(),
+        reason: reason.to_string(),
+    })
+}
@@ -0,0 +1,136 @@
+use super::{sasl::MECHANISMS, Ircd};
+use crate::{command::client::Command, history, message::Message};
+
+/// Capabilities offered to every client, `sasl` comes with an account store
+const CAPABILITIES: &[&str] = &[
+    "account-notify",
+    "account-tag",
+    "batch",
+    history::CAPABILITY,
+    "extended-join",
+    "message-tags",
+    "server-time",
+];
+
+impl Ircd {
+    /// `CAP` negotiation (IRCv3.2), registration waits for `CAP END` once it started
+    pub(super) fn cap(&mut self, index: usize, command: Command) {
+        let registered = self.connections[index].user.is_some();
+
+        match command {
+            Command::CapLs { version } => {
+                self.connections[index].negotiating = !registered;
+
+                let version = version.and_then(|version| version.parse::<u32>().ok());
+                let caps = self
+                    .capabilities()
+                    .into_iter()
+                    .map(|cap| match version {
+                        Some(version) if version >= 302 && cap == "sasl" => {
+                            format!("{}={}", cap, MECHANISMS)
+                        }
+                        _ => cap.to_string(),
+                    })
+                    .collect();
+
+                self.send(index, Command::CapLsReply { caps, more: false });
+            }
+            Command::CapList => {
+                let caps = self.connections[index].caps.clone();
+
+                self.send(index, Command::CapListReply { caps, more: false });
+            }
+            Command::CapReq { caps } => {
+                self.connections[index].negotiating = !registered;
+
+                let offered = self.capabilities();
+
+                // all or nothing
+                if !caps
+                    .iter()
+                    .all(|cap| offered.contains(&cap.trim_start_matches('-')))
+                {
+                    return self.send(index, Command::CapNak { caps });
+                }
+
+                for cap in caps.iter() {
+                    let enabled = &mut self.connections[index].caps;
+
+                    if cap.starts_with('-') {
+                        enabled.retain(|other| other != &cap[1..]);
+                    } else if !enabled.contains(cap) {
+                        enabled.push(cap.to_string());
+                    }
+                }
+
+                self.send(index, Command::CapAck { caps });
+            }
+            Command::CapEnd => self.connections[index].negotiating = false,
+            _ => (),
+        }
+    }
+
+    fn capabilities(&self) -> Vec<&'static str> {
+        let mut caps = CAPABILITIES.to_vec();
+
+        if self.accounts.is_some() {
+            caps.push("sasl");
+        }
+
+        caps
+    }
+
+    /// Fits a message to what the client enabled: `msgid` and `time` tags need `message-tags`
+    /// and `server-time`. For messages from users `extended-join` adds the account and real
+    /// name to joins, `account-tag` tags the message with the account.
+    pub(super) fn adapt(&self, index: usize, mut message: Message<Command>) -> Message<Command> {
+        let connection = &self.connections[index];
+
+        if !connection.has_cap("message-tags") {
+            message = message.without_tag("msgid");
+        }
+
+        if !connection.has_cap("server-time") {
+            message = message.without_tag("time");
+        }
+
+        if connection.caps.is_empty() {
+            return message;
+        }
+
+        let user = match message
+            .origin()
+            .nick()
+            .and_then(|nick| self.server.user(nick))
+        {
+            Some(user) => user,
+            None => return message,
+        };
+
+        let user = user.borrow();
+        let account = user.account().map(|account| account.to_string());
+
+        let message = match message.command() {
+            Command::Join { ref channels, .. }
+                if channels.len() == 1 && connection.has_cap("extended-join") =>
+            {
+                Message::new(
+                    message.origin().clone(),
+                    Command::ExtendedJoin {
+                        channel: channels[0].clone(),
+                        account: account.clone(),
+                        real_name: user.real_name().to_string(),
+                    },
+                )
+            }
+            _ => message,
+        };
+
+        match account {
+            Some(ref account) if connection.has_cap("account-tag") => {
+                message.with_tag("account", Some(account))
+            }
+            _ => message,
+        }
+    }
+}
@@ -0,0 +1,1669 @@
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+use super::{ban::Kind, reply, Ircd, VERSION};
+use crate::{
+    channel::{Channel, RcChannel},
+    command::{client::Command, server},
+    message::{Message, MessageRef},
+    mode::{self, channel, user, Parseable},
+    origin::{
+        mask::{self, ExtBan},
+        Mask,
+    },
+    target::MessageTarget,
+    time,
+    user::RcUser,
+    validate,
+};
+
+/// Commands the server handles, for telling missing parameters from unknown commands
+const KNOWN: &[&str] = &[
+    "PASS", "NICK", "USER", "QUIT", "JOIN", "PART", "MODE", "TOPIC", "NAMES", "LIST", "INVITE",
+    "KICK", "PRIVMSG", "NOTICE", "MOTD", "LINKS", "STATS", "WHO", "WHOIS", "OPER", "KILL",
+    "KLINE", "UNKLINE", "DLINE", "UNDLINE", "REHASH", "PING", "PONG", "CAP", "AUTHENTICATE",
+    "CHATHISTORY",
+];
+
+/// Room left for the names in a `RPL_NAMREPLY`
+const NAMES_LENGTH: usize = 400;
+
+impl Ircd {
+    pub(super) fn handle(&mut self, index: usize, line: &str) {
+        let message = match MessageRef::parse(line) {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+
+        // servers link through the client port as well
+        if self.connections[index].user.is_none() && message.command() == "SERVER" {
+            self.connections[index].link = Some(line.to_string());
+
+            return;
+        }
+
+        match Message::<Command>::try_from(message) {
+            Ok(parsed) => self.dispatch(index, message.command(), parsed.command().clone()),
+            Err(_) => self.parse_error(index, &message),
+        }
+    }
+
+    /// Replies to lines the client command parser rejected
+    fn parse_error(&mut self, index: usize, message: &MessageRef) {
+        let command = message.command().to_ascii_uppercase();
+        let params = message.params().collect::<Vec<&str>>();
+
+        match command.as_str() {
+            "NICK" if params.is_empty() => {
+                self.numeric(index, reply::ERR_NONICKNAMEGIVEN, &["No nickname given"])
+            }
+            "NICK" => self.numeric(
+                index,
+                reply::ERR_ERRONEUSNICKNAME,
+                &[params[0], "Erroneous nickname"],
+            ),
+            "NOTICE" => (),
+            _ if self.connections[index].user.is_none() && !is_registration(&command) => self
+                .numeric(
+                    index,
+                    reply::ERR_NOTREGISTERED,
+                    &["You have not registered"],
+                ),
+            "PRIVMSG" if params.is_empty() => self.numeric(
+                index,
+                reply::ERR_NORECIPIENT,
+                &["No recipient given (PRIVMSG)"],
+            ),
+            "PRIVMSG" if params.len() == 1 => {
+                self.numeric(index, reply::ERR_NOTEXTTOSEND, &["No text to send"])
+            }
+            "PRIVMSG" => self.numeric(
+                index,
+                reply::ERR_NOSUCHNICK,
+                &[params[0], "No such nick/channel"],
+            ),
+            "CHATHISTORY" => self.send(
+                index,
+                Command::Fail {
+                    command,
+                    code: "INVALID_PARAMS".to_string(),
+                    context: params.iter().take(1).map(|p| p.to_string()).collect(),
+                    description: "Invalid parameters".to_string(),
+                },
+            ),
+            "JOIN" | "PART" | "TOPIC" if !params.is_empty() => self.numeric(
+                index,
+                reply::ERR_NOSUCHCHANNEL,
+                &[params[0], "No such channel"],
+            ),
+            _ if KNOWN.contains(&command.as_str()) => self.numeric(
+                index,
+                reply::ERR_NEEDMOREPARAMS,
+                &[&command, "Not enough parameters"],
+            ),
+            _ => self.numeric(
+                index,
+                reply::ERR_UNKNOWNCOMMAND,
+                &[&command, "Unknown command"],
+            ),
+        }
+    }
+
+    fn dispatch(&mut self, index: usize, word: &str, command: Command) {
+        let user = match self.connections[index].user.clone() {
+            Some(user) => user,
+            None => return self.register(index, word, command),
+        };
+
+        match command {
+            Command::Nick { name } => self.nick(index, &user, &name),
+            Command::User { .. } | Command::Pass { .. } => self.numeric(
+                index,
+                reply::ERR_ALREADYREGISTRED,
+                &["You may not reregister"],
+            ),
+            Command::Quit { reason } => self.quit(index, reason),
+
+            Command::Join { channels, keys } => self.join(index, &user, channels, keys),
+            Command::Join0 {} => {
+                let channels = user
+                    .borrow()
+                    .channels()
+                    .iter()
+                    .filter_map(|channel| channel.upgrade())
+                    .map(|channel| channel.borrow().name().to_string())
+                    .collect();
+
+                self.part(index, &user, channels, None)
+            }
+            Command::Part { channels, reason } => self.part(index, &user, channels, reason),
+            Command::Topic { channel, text } => self.topic(index, &user, &channel, text),
+            Command::Names { channels, .. } => self.names(index, &user, channels),
+            Command::List { channels, .. } => self.list(index, &user, channels),
+            Command::Invite {
+                user: nick,
+                channel,
+            } => self.invite(index, &user, &nick, &channel),
+            Command::Kick {
+                channels,
+                users,
+                reason,
+            } => self.kick(index, &user, channels, users, reason),
+            Command::CMode { channel, modes } => self.channel_mode(index, &user, &channel, modes),
+            Command::UMode { name, modes } => self.user_mode(index, &user, &name, modes),
+
+            Command::PrivMsg { targets, text } => {
+                for target in targets {
+                    self.message(index, &user, target, &text, false);
+                }
+            }
+            Command::Notice { target, text } => self.message(index, &user, target, &text, true),
+
+            Command::Who {
+                mask,
+                operators_only,
+            } => self.who(index, &user, &mask, operators_only),
+            Command::WhoIs { masks, .. } => self.whois(index, &user, masks),
+            Command::Motd { .. } => self.motd(index),
+            Command::Links { .. } => self.links_reply(index),
+            Command::Stats { query, .. } => self.stats(index, query),
+
+            Command::Oper { name, password } => self.oper(index, &user, &name, &password),
+            Command::Kill { user: nick, reason } => self.kill_user(index, &user, &nick, &reason),
+            Command::KLine {
+                duration,
+                mask,
+                reason,
+            } => self.add_ban(index, &user, Kind::KLine, duration, &mask, &reason),
+            Command::DLine {
+                duration,
+                mask,
+                reason,
+            } => self.add_ban(index, &user, Kind::DLine, duration, &mask, &reason),
+            Command::UnKLine { mask } => self.remove_ban(index, Kind::KLine, &mask),
+            Command::UnDLine { mask } => self.remove_ban(index, Kind::DLine, &mask),
+            Command::Rehash => self.rehash_command(index),
+
+            Command::CapLs { .. } | Command::CapList | Command::CapReq { .. } | Command::CapEnd => {
+                self.cap(index, command)
+            }
+            Command::Authenticate { data } => self.authenticate(index, &data),
+
+            Command::ChatHistory { target, range } => {
+                self.chathistory(index, &user, &target, range)
+            }
+            Command::ChatHistoryTargets { start, end, limit } => {
+                self.chathistory_targets(index, &user, start, end, limit)
+            }
+
+            Command::Ping { server1, .. } => self.pong(index, server1),
+            Command::Pong { .. } => (),
+
+            _ => self.numeric(index, reply::ERR_UNKNOWNCOMMAND, &[word, "Unknown command"]),
+        }
+    }
+
+    fn register(&mut self, index: usize, word: &str, command: Command) {
+        match command {
+            Command::Pass { password } => self.connections[index].password = Some(password),
+            Command::Nick { name } => {
+                if name.len() > self.limits.nick_length {
+                    self.numeric(
+                        index,
+                        reply::ERR_ERRONEUSNICKNAME,
+                        &[&name, "Erroneous nickname"],
+                    );
+                } else if self.nick_in_use(index, &name) {
+                    self.numeric(
+                        index,
+                        reply::ERR_NICKNAMEINUSE,
+                        &[&name, "Nickname is already in use"],
+                    );
+                } else {
+                    self.connections[index].nick = Some(name);
+                }
+            }
+            Command::User {
+                name, real_name, ..
+            } => self.connections[index].user_name = Some((name, real_name)),
+            Command::Quit { reason } => return self.quit(index, reason),
+            Command::CapLs { .. } | Command::CapList | Command::CapReq { .. } | Command::CapEnd => {
+                self.cap(index, command)
+            }
+            Command::Authenticate { data } => self.authenticate(index, &data),
+            Command::Ping { server1, .. } => self.pong(index, server1),
+            Command::Pong { .. } => (),
+            _ if KNOWN.contains(&word.to_ascii_uppercase().as_str()) => self.numeric(
+                index,
+                reply::ERR_NOTREGISTERED,
+                &["You have not registered"],
+            ),
+            _ => self.numeric(index, reply::ERR_UNKNOWNCOMMAND, &[word, "Unknown command"]),
+        }
+
+        self.try_register(index);
+    }
+
+    pub(super) fn welcome(&mut self, index: usize) {
+        let name = self.name().to_string();
+        let origin = match self.connections[index].user {
+            Some(ref user) => user.borrow().origin().to_string(),
+            None => return,
+        };
+
+        self.numeric(
+            index,
+            reply::RPL_WELCOME,
+            &[&format!(
+                "Welcome to the Internet Relay Network {}",
+                &origin[1..]
+            )],
+        );
+        self.numeric(
+            index,
+            reply::RPL_YOURHOST,
+            &[&format!(
+                "Your host is {}, running version {}",
+                name, VERSION
+            )],
+        );
+        self.numeric(
+            index,
+            reply::RPL_CREATED,
+            &[&format!(
+                "This server was created at {} (unix time)",
+                self.created
+            )],
+        );
+        self.numeric(
+            index,
+            reply::RPL_MYINFO,
+            &[&name, VERSION, "iowrOs", "beIiklmnopstv"],
+        );
+        self.numeric(
+            index,
+            reply::RPL_ISUPPORT,
+            &[
+                &format!("CASEMAPPING={}", self.server.casemapping().name()),
+                "CHANTYPES=#&+!",
+                "CHANMODES=beI,k,l,imnpst",
+                "PREFIX=(ov)@+",
+                "STATUSMSG=@+",
+                &format!("CHANLIMIT=#&+!:{}", self.limits.channels),
+                &format!("NICKLEN={}", self.limits.nick_length),
+                &format!("CHANNELLEN={}", self.limits.channel_length),
+                &format!("TOPICLEN={}", self.limits.topic_length),
+                &format!("CHATHISTORY={}", self.limits.history),
+                "MSGREFTYPES=timestamp,msgid",
+                "are supported by this server",
+            ],
+        );
+
+        self.motd(index);
+    }
+
+    fn motd(&mut self, index: usize) {
+        let motd = match self.server.motd() {
+            Some(motd) => motd.to_string(),
+            None => return self.numeric(index, reply::ERR_NOMOTD, &["MOTD File is missing"]),
+        };
+
+        let start = format!("- {} Message of the day - ", self.name());
+        self.numeric(index, reply::RPL_MOTDSTART, &[&start]);
+
+        for line in motd.lines() {
+            self.numeric(index, reply::RPL_MOTD, &[&format!("- {}", line)]);
+        }
+
+        self.numeric(index, reply::RPL_ENDOFMOTD, &["End of MOTD command"]);
+    }
+
+    fn quit(&mut self, index: usize, reason: Option<String>) {
+        self.connections[index].quit = Some(match reason {
+            Some(reason) => format!("Quit: {}", reason),
+            None => "Client Quit".to_string(),
+        });
+    }
+
+    fn pong(&mut self, index: usize, token: String) {
+        self.send(
+            index,
+            Command::Pong {
+                server1: self.name().to_string(),
+                server2: Some(token),
+            },
+        );
+    }
+
+    fn nick(&mut self, index: usize, user: &RcUser, name: &str) {
+        if name.len() > self.limits.nick_length {
+            return self.numeric(
+                index,
+                reply::ERR_ERRONEUSNICKNAME,
+                &[name, "Erroneous nickname"],
+            );
+        }
+
+        if self.nick_in_use(index, name) {
+            return self.numeric(
+                index,
+                reply::ERR_NICKNAMEINUSE,
+                &[name, "Nickname is already in use"],
+            );
+        }
+
+        let origin = user.borrow().origin().clone();
+
+        if origin.nick() == Some(name) {
+            return;
+        }
+
+        let message = Message::new(
+            origin,
+            Command::Nick {
+                name: name.to_string(),
+            },
+        );
+
+        self.send(index, message.clone());
+
+        for peer in self.peers(user) {
+            self.send_to(&peer, message.clone());
+        }
+
+        let timestamp = time::now();
+
+        self.propagate(
+            Message::new(
+                message.origin().clone(),
+                server::Command::Nick {
+                    name: name.to_string(),
+                    timestamp: Some(timestamp),
+                },
+            ),
+            None,
+        );
+
+        user.borrow_mut().set_timestamp(timestamp);
+
+        if let crate::origin::Origin::User { ref mut nick, .. } = user.borrow_mut().origin_mut() {
+            *nick = name.to_string();
+        }
+    }
+
+    fn join(&mut self, index: usize, user: &RcUser, channels: Vec<String>, keys: Vec<String>) {
+        for (position, name) in channels.into_iter().enumerate() {
+            if name.len() > self.limits.channel_length {
+                self.numeric(index, reply::ERR_BADCHANNAME, &[&name, "Illegal channel name"]);
+                continue;
+            }
+
+            let joined = user.borrow().channels().len();
+
+            if joined >= self.limits.channels && !user.borrow().in_channel(&name) {
+                self.numeric(
+                    index,
+                    reply::ERR_TOOMANYCHANNELS,
+                    &[&name, "You have joined too many channels"],
+                );
+                continue;
+            }
+
+            let channel = match self.server.channel(&name) {
+                Some(channel) => channel,
+                None => {
+                    let channel = Rc::new(RefCell::new(Channel::new(&name)));
+                    self.server.channels_mut().push(channel.clone());
+
+                    channel
+                }
+            };
+
+            if channel.borrow().is_member(user) {
+                continue;
+            }
+
+            let key = keys.get(position).map(|key| key.as_str());
+
+            if let Some((code, mode)) = self.join_error(&channel, user, key) {
+                let name = channel.borrow().name().to_string();
+                let text = format!("Cannot join channel (+{})", mode);
+
+                self.numeric(index, code, &[&name, &text]);
+                continue;
+            }
+
+            // whoever creates the channel operates it
+            let operator = channel.borrow().members().is_empty();
+
+            channel.borrow_mut().add_user(user, operator);
+            channel.borrow_mut().remove_invite(user);
+            user.borrow_mut().add_channel(&channel);
+
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                Command::Join {
+                    channels: vec![channel.borrow().name().to_string()],
+                    keys: vec![],
+                },
+            );
+
+            let modes = if operator { "o" } else { "" };
+
+            self.propagate(
+                Message::new(
+                    message.origin().clone(),
+                    server::Command::Join {
+                        channels: vec![(channel.borrow().name().to_string(), modes.to_string())],
+                    },
+                ),
+                None,
+            );
+            self.broadcast(&channel, message, None);
+            self.give_access(&channel, user);
+
+            if channel.borrow().topic().is_some() {
+                self.topic_reply(index, &channel);
+            }
+
+            self.names_reply(index, user, &channel, true);
+        }
+    }
+
+    /// The reply and the mode keeping the user out of an existing channel
+    fn join_error(
+        &self,
+        channel: &RcChannel,
+        user: &RcUser,
+        key: Option<&str>,
+    ) -> Option<(u16, char)> {
+        let channel = channel.borrow();
+        let origin = user.borrow().origin().clone();
+        let mapping = self.server.casemapping();
+
+        if channel.is_banned(&origin, mapping) {
+            return Some((reply::ERR_BANNEDFROMCHAN, 'b'));
+        }
+
+        if channel.has_mode(&channel::Mode::InviteOnly)
+            && !channel.is_invited(user)
+            && !channel.is_invite_excepted(&origin, mapping)
+        {
+            return Some((reply::ERR_INVITEONLYCHAN, 'i'));
+        }
+
+        if channel.key().is_some() && channel.key() != key {
+            return Some((reply::ERR_BADCHANNELKEY, 'k'));
+        }
+
+        if channel
+            .limit()
+            .map_or(false, |limit| channel.members().len() >= limit)
+        {
+            return Some((reply::ERR_CHANNELISFULL, 'l'));
+        }
+
+        None
+    }
+
+    fn part(&mut self, index: usize, user: &RcUser, channels: Vec<String>, reason: Option<String>) {
+        for name in channels {
+            let channel = match self.member_channel(index, user, &name) {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                Command::Part {
+                    channels: vec![channel.borrow().name().to_string()],
+                    reason: reason.clone(),
+                },
+            );
+
+            self.announce(&message);
+            self.broadcast(&channel, message, None);
+            self.leave(user, &channel);
+        }
+    }
+
+    fn topic(&mut self, index: usize, user: &RcUser, name: &str, text: Option<String>) {
+        let text = match text {
+            Some(text) => text,
+            None => {
+                return match self.server.channel(name) {
+                    Some(channel) => self.topic_reply(index, &channel),
+                    None => {
+                        self.numeric(index, reply::ERR_NOSUCHCHANNEL, &[name, "No such channel"])
+                    }
+                }
+            }
+        };
+
+        let channel = match self.member_channel(index, user, name) {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        let mut text = text;
+
+        if text.len() > self.limits.topic_length {
+            let mut end = self.limits.topic_length;
+
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            text.truncate(end);
+        }
+
+        if channel.borrow().has_mode(&channel::Mode::TopicLock) {
+            let operator = channel
+                .borrow()
+                .member(user)
+                .map_or(false, |member| member.is_operator());
+
+            if !operator {
+                let name = channel.borrow().name().to_string();
+
+                return self.numeric(
+                    index,
+                    reply::ERR_CHANOPRIVSNEEDED,
+                    &[&name, "You're not channel operator"],
+                );
+            }
+        }
+
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+        channel.borrow_mut().set_topic(&text, &nick);
+
+        let message = Message::new(
+            user.borrow().origin().clone(),
+            Command::Topic {
+                channel: channel.borrow().name().to_string(),
+                text: Some(text),
+            },
+        );
+
+        self.announce(&message);
+        self.broadcast(&channel, message, None);
+    }
+
+    fn topic_reply(&mut self, index: usize, channel: &RcChannel) {
+        let channel = channel.borrow();
+
+        match channel.topic() {
+            Some(topic) => {
+                self.numeric(index, reply::RPL_TOPIC, &[channel.name(), topic]);
+                self.numeric(
+                    index,
+                    reply::RPL_TOPICWHOTIME,
+                    &[
+                        channel.name(),
+                        channel.topic_setter().unwrap_or_default(),
+                        &channel.topic_time().to_string(),
+                    ],
+                );
+            }
+            None => self.numeric(
+                index,
+                reply::RPL_NOTOPIC,
+                &[channel.name(), "No topic is set"],
+            ),
+        }
+    }
+
+    fn names(&mut self, index: usize, user: &RcUser, channels: Option<Vec<String>>) {
+        match channels {
+            Some(channels) => {
+                for name in channels {
+                    match self.server.channel(&name) {
+                        Some(channel) => self.names_reply(index, user, &channel, true),
+                        None => self.numeric(
+                            index,
+                            reply::RPL_ENDOFNAMES,
+                            &[&name, "End of NAMES list"],
+                        ),
+                    }
+                }
+            }
+            None => {
+                for channel in self.server.channels().clone() {
+                    self.names_reply(index, user, &channel, false);
+                }
+
+                self.numeric(index, reply::RPL_ENDOFNAMES, &["*", "End of NAMES list"]);
+            }
+        }
+    }
+
+    fn names_reply(&mut self, index: usize, viewer: &RcUser, channel: &RcChannel, end: bool) {
+        let channel = channel.borrow();
+        let member = channel.is_member(viewer);
+
+        let kind = if channel.has_mode(&channel::Mode::Secret) {
+            "@"
+        } else if channel.has_mode(&channel::Mode::Private) {
+            "*"
+        } else {
+            "="
+        };
+
+        if member || kind == "=" {
+            let mut names = Vec::new();
+
+            for other in channel.members() {
+                let user = match other.user().upgrade() {
+                    Some(user) => user,
+                    None => continue,
+                };
+
+                if member || self.is_visible(viewer, &user) {
+                    names.push(format!(
+                        "{}{}",
+                        other.prefix().map(|p| p.to_string()).unwrap_or_default(),
+                        user.borrow().origin().nick().unwrap_or_default()
+                    ));
+                }
+            }
+
+            let mut line = String::new();
+
+            for name in names {
+                if !line.is_empty() && line.len() + name.len() >= NAMES_LENGTH {
+                    self.numeric(index, reply::RPL_NAMREPLY, &[kind, channel.name(), &line]);
+                    line.clear();
+                }
+
+                if !line.is_empty() {
+                    line.push_str(crate::SEPARATOR);
+                }
+
+                line.push_str(&name);
+            }
+
+            if !line.is_empty() {
+                self.numeric(index, reply::RPL_NAMREPLY, &[kind, channel.name(), &line]);
+            }
+        }
+
+        if end {
+            self.numeric(
+                index,
+                reply::RPL_ENDOFNAMES,
+                &[channel.name(), "End of NAMES list"],
+            );
+        }
+    }
+
+    fn list(&mut self, index: usize, user: &RcUser, channels: Option<Vec<String>>) {
+        let channels = match channels {
+            Some(names) => names
+                .iter()
+                .filter_map(|name| self.server.channel(name))
+                .collect(),
+            None => self.server.channels().clone(),
+        };
+
+        for channel in channels {
+            let channel = channel.borrow();
+            let member = channel.is_member(user);
+
+            // secret channels don't exist for outsiders, private ones hide their details
+            if channel.has_mode(&channel::Mode::Secret) && !member {
+                continue;
+            }
+
+            let (name, topic) =
+                if channel.has_mode(&channel::Mode::Private) && !member {
+                    ("Prv", "")
+                } else {
+                    (channel.name(), channel.topic().unwrap_or_default())
+                };
+
+            self.numeric(
+                index,
+                reply::RPL_LIST,
+                &[name, &channel.members().len().to_string(), topic],
+            );
+        }
+
+        self.numeric(index, reply::RPL_LISTEND, &["End of LIST"]);
+    }
+
+    fn invite(&mut self, index: usize, user: &RcUser, nick: &str, name: &str) {
+        let target = match self.server.user(nick) {
+            Some(target) => target,
+            None => {
+                return self.numeric(index, reply::ERR_NOSUCHNICK, &[nick, "No such nick/channel"])
+            }
+        };
+
+        let nick = target
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        // inviting to channels which don't exist is fine, there's nothing to check
+        let channel = match self.server.channel(name) {
+            Some(_) => {
+                let channel = match self.member_channel(index, user, name) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+
+                let invite_only = channel.borrow().has_mode(&channel::Mode::InviteOnly);
+
+                if invite_only && self.operated_channel(index, user, name).is_none() {
+                    return;
+                }
+
+                if channel.borrow().is_member(&target) {
+                    return self.numeric(
+                        index,
+                        reply::ERR_USERONCHANNEL,
+                        &[&nick, name, "is already on channel"],
+                    );
+                }
+
+                channel.borrow_mut().invite(&target);
+
+                Some(channel)
+            }
+            None => None,
+        };
+
+        let name = channel
+            .map(|channel| channel.borrow().name().to_string())
+            .unwrap_or_else(|| name.to_string());
+
+        self.numeric(index, reply::RPL_INVITING, &[&name, &nick]);
+
+        let message = Message::new(
+            user.borrow().origin().clone(),
+            Command::Invite {
+                user: nick,
+                channel: name,
+            },
+        );
+
+        self.deliver(&target, message);
+    }
+
+    fn kick(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        channels: Vec<String>,
+        users: Vec<String>,
+        reason: Option<String>,
+    ) {
+        // either one channel and many users or pairs of both
+        let pairs = if channels.len() == 1 {
+            users
+                .into_iter()
+                .map(|nick| (channels[0].clone(), nick))
+                .collect()
+        } else if channels.len() == users.len() {
+            channels
+                .into_iter()
+                .zip(users)
+                .collect::<Vec<(String, String)>>()
+        } else {
+            return self.numeric(
+                index,
+                reply::ERR_NEEDMOREPARAMS,
+                &["KICK", "Not enough parameters"],
+            );
+        };
+
+        let reason = reason.unwrap_or_else(|| {
+            user.borrow()
+                .origin()
+                .nick()
+                .unwrap_or_default()
+                .to_string()
+        });
+
+        for (name, nick) in pairs {
+            let channel = match self.operated_channel(index, user, &name) {
+                Some(channel) => channel,
+                None => continue,
+            };
+
+            let target = match self.server.user(&nick) {
+                Some(ref target) if channel.borrow().is_member(target) => target.clone(),
+                _ => {
+                    self.numeric(
+                        index,
+                        reply::ERR_USERNOTINCHANNEL,
+                        &[&nick, &name, "They aren't on that channel"],
+                    );
+                    continue;
+                }
+            };
+
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                Command::Kick {
+                    channels: vec![channel.borrow().name().to_string()],
+                    users: vec![target
+                        .borrow()
+                        .origin()
+                        .nick()
+                        .unwrap_or_default()
+                        .to_string()],
+                    reason: Some(reason.clone()),
+                },
+            );
+
+            self.announce(&message);
+            self.broadcast(&channel, message, None);
+            self.leave(&target, &channel);
+        }
+    }
+
+    fn channel_mode(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        name: &str,
+        modes: Vec<mode::Mode<channel::Mode>>,
+    ) {
+        let channel = match self.server.channel(name) {
+            Some(channel) => channel,
+            None => {
+                return self.numeric(index, reply::ERR_NOSUCHCHANNEL, &[name, "No such channel"])
+            }
+        };
+
+        let name = channel.borrow().name().to_string();
+
+        if modes.is_empty() {
+            let flags = channel
+                .borrow()
+                .modes()
+                .iter()
+                .filter(|m| !m.mode().is_list())
+                .cloned()
+                .collect::<Vec<mode::Mode<channel::Mode>>>();
+
+            let mut params = vec![name.clone(), mode::Mode::to_list_string(&flags)];
+
+            // only members get to see the key
+            if channel.borrow().is_member(user) {
+                params.extend(flags.iter().filter_map(|m| m.mode().param()));
+            }
+
+            if params[1].is_empty() {
+                params[1].push('+');
+            }
+
+            let params = params.iter().map(|p| p.as_str()).collect::<Vec<&str>>();
+
+            return self.numeric(index, reply::RPL_CHANNELMODEIS, &params);
+        }
+
+        let operator = channel
+            .borrow()
+            .member(user)
+            .map_or(false, |member| member.is_operator());
+
+        let mut applied = Vec::new();
+        let mut denied = false;
+
+        for change in modes {
+            let granted = change.granted();
+
+            match change.mode().clone() {
+                channel::Mode::Other(c) => {
+                    let text = format!("is unknown mode char to me for {}", name);
+                    self.numeric(index, reply::ERR_UNKNOWNMODE, &[&c.to_string(), &text]);
+                }
+
+                ref list if list.is_list() && list.param().is_none() => {
+                    self.list_reply(index, &channel, list)
+                }
+
+                _ if !operator => denied = true,
+
+                channel::Mode::Key { key: None } | channel::Mode::Limit { value: None }
+                    if granted =>
+                {
+                    self.numeric(
+                        index,
+                        reply::ERR_NEEDMOREPARAMS,
+                        &["MODE", "Not enough parameters"],
+                    )
+                }
+
+                channel::Mode::Operator { nick } | channel::Mode::Voice { nick } => {
+                    let target = match self.server.user(&nick) {
+                        Some(target) => target,
+                        None => {
+                            self.numeric(
+                                index,
+                                reply::ERR_NOSUCHNICK,
+                                &[&nick, "No such nick/channel"],
+                            );
+                            continue;
+                        }
+                    };
+
+                    let nick = target
+                        .borrow()
+                        .origin()
+                        .nick()
+                        .unwrap_or_default()
+                        .to_string();
+                    let operator_mode = change.mode().symbol() == 'o';
+
+                    if let Some(member) = channel.borrow_mut().member_mut(&target) {
+                        if operator_mode && member.is_operator() != granted {
+                            member.set_operator(granted);
+                            applied
+                                .push(mode::Mode::new(granted, channel::Mode::Operator { nick }));
+                        } else if !operator_mode && member.is_voiced() != granted {
+                            member.set_voice(granted);
+                            applied.push(mode::Mode::new(granted, channel::Mode::Voice { nick }));
+                        }
+
+                        continue;
+                    }
+
+                    self.numeric(
+                        index,
+                        reply::ERR_USERNOTINCHANNEL,
+                        &[&nick, &name, "They aren't on that channel"],
+                    );
+                }
+
+                channel::Mode::Ban { mask: Some(m) } => self.apply_mode(
+                    &channel,
+                    &mut applied,
+                    granted,
+                    channel::Mode::Ban {
+                        mask: Some(normalize(&m)),
+                    },
+                ),
+                channel::Mode::Exception { mask: Some(m) } => self.apply_mode(
+                    &channel,
+                    &mut applied,
+                    granted,
+                    channel::Mode::Exception {
+                        mask: Some(normalize(&m)),
+                    },
+                ),
+                channel::Mode::InviteException { mask: Some(m) } => self.apply_mode(
+                    &channel,
+                    &mut applied,
+                    granted,
+                    channel::Mode::InviteException {
+                        mask: Some(normalize(&m)),
+                    },
+                ),
+
+                other => self.apply_mode(&channel, &mut applied, granted, other),
+            }
+        }
+
+        if denied {
+            self.numeric(
+                index,
+                reply::ERR_CHANOPRIVSNEEDED,
+                &[&name, "You're not channel operator"],
+            );
+        }
+
+        if !applied.is_empty() {
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                Command::CMode {
+                    channel: name,
+                    modes: applied,
+                },
+            );
+
+            self.announce(&message);
+            self.broadcast(&channel, message, None);
+        }
+    }
+
+    fn apply_mode(
+        &self,
+        channel: &RcChannel,
+        applied: &mut Vec<mode::Mode<channel::Mode>>,
+        granted: bool,
+        mode: channel::Mode,
+    ) {
+        let change = mode::Mode::new(granted, mode);
+
+        if channel.borrow_mut().apply_mode(&change) {
+            applied.push(change);
+        }
+    }
+
+    fn list_reply(&mut self, index: usize, channel: &RcChannel, list: &channel::Mode) {
+        let (item, end, text) = match list {
+            channel::Mode::Ban { .. } => (
+                reply::RPL_BANLIST,
+                reply::RPL_ENDOFBANLIST,
+                "End of channel ban list",
+            ),
+            channel::Mode::Exception { .. } => (
+                reply::RPL_EXCEPTLIST,
+                reply::RPL_ENDOFEXCEPTLIST,
+                "End of channel exception list",
+            ),
+            _ => (
+                reply::RPL_INVITELIST,
+                reply::RPL_ENDOFINVITELIST,
+                "End of channel invite list",
+            ),
+        };
+
+        let name = channel.borrow().name().to_string();
+
+        for mask in channel.borrow().masks(list.symbol()) {
+            self.numeric(index, item, &[&name, &mask]);
+        }
+
+        self.numeric(index, end, &[&name, text]);
+    }
+
+    fn user_mode(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        name: &str,
+        modes: Vec<mode::Mode<user::Mode>>,
+    ) {
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        if !self.server.casemapping().eq(name, &nick) {
+            return self.numeric(
+                index,
+                reply::ERR_USERSDONTMATCH,
+                &["Cannot change mode for other users"],
+            );
+        }
+
+        if modes.is_empty() {
+            let modes = mode::Mode::to_list_string(user.borrow().modes());
+            let modes = if modes.is_empty() {
+                "+".to_string()
+            } else {
+                modes
+            };
+
+            return self.numeric(index, reply::RPL_UMODEIS, &[&modes]);
+        }
+
+        let mut applied = Vec::new();
+
+        for change in modes {
+            let present = user.borrow().has_mode(change.mode());
+
+            let allowed = match change.mode() {
+                user::Mode::Invisible | user::Mode::Wallops | user::Mode::Noticeable => true,
+                // operator status is given by OPER only, restrictions can't be lifted
+                user::Mode::Operator | user::Mode::LocalOperator => !change.granted(),
+                user::Mode::Restricted => change.granted(),
+                // set by AWAY
+                user::Mode::Away => false,
+                user::Mode::Other(_) => {
+                    self.numeric(index, reply::ERR_UMODEUNKNOWNFLAG, &["Unknown MODE flag"]);
+                    false
+                }
+            };
+
+            if allowed && present != change.granted() {
+                // dropping operator status drops the privileges as well
+                if *change.mode() == user::Mode::Operator {
+                    self.connections[index].oper = None;
+                }
+
+                user.borrow_mut().apply_mode(&change);
+                applied.push(change);
+            }
+        }
+
+        if !applied.is_empty() {
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                Command::UMode {
+                    name: nick,
+                    modes: applied,
+                },
+            );
+
+            self.announce(&message);
+            self.send(index, message);
+        }
+    }
+
+    fn message(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        target: MessageTarget,
+        text: &str,
+        notice: bool,
+    ) {
+        // NOTICE never gets automatic replies
+        let error = |ircd: &Ircd, code, params: &[&str]| {
+            if !notice {
+                ircd.numeric(index, code, params);
+            }
+        };
+
+        if text.is_empty() {
+            return error(self, reply::ERR_NOTEXTTOSEND, &["No text to send"]);
+        }
+
+        let command = if notice {
+            Command::Notice {
+                target: target.clone(),
+                text: text.to_string(),
+            }
+        } else {
+            Command::PrivMsg {
+                targets: vec![target.clone()],
+                text: text.to_string(),
+            }
+        };
+
+        let message = Message::new(user.borrow().origin().clone(), command);
+        let target_name = target.to_string();
+
+        match target {
+            MessageTarget::Channel(ref name)
+            | MessageTarget::ChannelStatus {
+                channel: ref name, ..
+            } => {
+                let channel = match self.server.channel(name) {
+                    Some(channel) => channel,
+                    None => {
+                        return error(
+                            self,
+                            reply::ERR_NOSUCHNICK,
+                            &[&target_name, "No such nick/channel"],
+                        )
+                    }
+                };
+
+                if !self.can_send(&channel, user) {
+                    let name = channel.borrow().name().to_string();
+
+                    return error(
+                        self,
+                        reply::ERR_CANNOTSENDTOCHAN,
+                        &[&name, "Cannot send to channel"],
+                    );
+                }
+
+                let status = match target {
+                    MessageTarget::ChannelStatus { status, .. } => Some(status),
+                    _ => None,
+                };
+
+                // messages to the members with a status aren't for everyone's history
+                let message = if status.is_none() {
+                    let name = channel.borrow().name().to_string();
+
+                    self.record_channel(&name, message)
+                } else {
+                    message
+                };
+
+                for receiver in self.receivers(&channel, status) {
+                    if !Rc::ptr_eq(&receiver, user) {
+                        self.send_to(&receiver, message.clone());
+                    }
+                }
+
+                if let Some(message) = super::link::relay(&message) {
+                    self.forward(&channel, message, None);
+                }
+            }
+
+            MessageTarget::Nick(ref nick) if self.is_service(nick) => {
+                if !notice {
+                    self.service_message(index, user, nick, text);
+                }
+            }
+
+            MessageTarget::ServerMask(_) | MessageTarget::HostMask(_) => error(
+                self,
+                reply::ERR_NOPRIVILEGES,
+                &["Permission Denied- You're not an IRC operator"],
+            ),
+
+            MessageTarget::UserServer { ref server, .. }
+                if !server.eq_ignore_ascii_case(self.name())
+                    && self.server.server(server).is_none() =>
+            {
+                error(self, reply::ERR_NOSUCHSERVER, &[server, "No such server"])
+            }
+
+            _ => match self.find_user(&target) {
+                Some(receiver) => {
+                    let message = self.record_private(user, &receiver, message);
+
+                    self.deliver(&receiver, message)
+                }
+                None => error(
+                    self,
+                    reply::ERR_NOSUCHNICK,
+                    &[&target_name, "No such nick/channel"],
+                ),
+            },
+        }
+    }
+
+    /// The single user a nick or `user%host` like target stands for
+    pub(super) fn find_user(&self, target: &MessageTarget) -> Option<RcUser> {
+        let (user_name, host) = match target {
+            MessageTarget::Nick(ref nick) => return self.server.user(nick),
+            MessageTarget::NickMask {
+                ref nick,
+                ref user,
+                ref host,
+            } => {
+                let found = self.server.user(nick)?;
+                let matches = {
+                    let origin = found.borrow();
+                    let origin = origin.origin();
+
+                    origin.user() == Some(user.as_str())
+                        && origin
+                            .host()
+                            .map_or(false, |h| h.eq_ignore_ascii_case(host))
+                };
+
+                return Some(found).filter(|_| matches);
+            }
+            MessageTarget::UserServer {
+                ref user, ref host, ..
+            } => (user, host.as_ref()),
+            MessageTarget::UserHost { ref user, ref host } => (user, Some(host)),
+            _ => return None,
+        };
+
+        let found = self
+            .server
+            .users()
+            .iter()
+            .filter(|found| {
+                let found = found.borrow();
+                let origin = found.origin();
+
+                origin.user() == Some(user_name.as_str())
+                    && host.map_or(true, |host| {
+                        origin
+                            .host()
+                            .map_or(false, |h| h.eq_ignore_ascii_case(host))
+                    })
+            })
+            .cloned()
+            .collect::<Vec<RcUser>>();
+
+        // ambiguous targets don't get delivered
+        if found.len() == 1 {
+            found.into_iter().next()
+        } else {
+            None
+        }
+    }
+
+    fn who(&mut self, index: usize, user: &RcUser, mask: &str, operators_only: bool) {
+        let casemapping = self.server.casemapping();
+        let mut replies: Vec<(String, RcUser, String)> = Vec::new();
+
+        if validate::channel_name(mask).is_ok() {
+            if let Some(channel) = self.server.channel(mask) {
+                let channel = channel.borrow();
+                let member = channel.is_member(user);
+                let hidden = channel.has_mode(&channel::Mode::Secret)
+                    || channel.has_mode(&channel::Mode::Private);
+
+                for other in channel.members().iter().filter(|_| member || !hidden) {
+                    if let Some(found) = other.user().upgrade() {
+                        if member || self.is_visible(user, &found) {
+                            let prefix = other.prefix().map(|p| p.to_string()).unwrap_or_default();
+
+                            replies.push((channel.name().to_string(), found, prefix));
+                        }
+                    }
+                }
+            }
+        } else {
+            for found in self.server.users() {
+                let matches = {
+                    let found = found.borrow();
+                    let origin = found.origin();
+
+                    let fields = vec![
+                        origin.nick().unwrap_or_default(),
+                        origin.host().unwrap_or_default(),
+                        found.real_name(),
+                        found.server().unwrap_or_else(|| self.name()),
+                    ];
+
+                    mask == "0"
+                        || fields
+                            .iter()
+                            .any(|field| mask::matches(mask, field, casemapping))
+                };
+
+                if matches && self.is_visible(user, found) {
+                    replies.push(("*".to_string(), found.clone(), String::new()));
+                }
+            }
+        }
+
+        for (channel, found, prefix) in replies {
+            let found = found.borrow();
+
+            if operators_only && !found.has_mode(&user::Mode::Operator) {
+                continue;
+            }
+
+            let flags = format!(
+                "{}{}{}",
+                if found.has_mode(&user::Mode::Away) {
+                    "G"
+                } else {
+                    "H"
+                },
+                if found.has_mode(&user::Mode::Operator) {
+                    "*"
+                } else {
+                    ""
+                },
+                prefix
+            );
+
+            let origin = found.origin();
+            let hop_count = found
+                .server()
+                .and_then(|name| self.server.server(name))
+                .map_or(0, |server| server.hop_count());
+
+            self.numeric(
+                index,
+                reply::RPL_WHOREPLY,
+                &[
+                    &channel,
+                    origin.user().unwrap_or_default(),
+                    origin.host().unwrap_or_default(),
+                    found.server().unwrap_or_else(|| self.name()),
+                    origin.nick().unwrap_or_default(),
+                    &flags,
+                    &format!("{} {}", hop_count, found.real_name()),
+                ],
+            );
+        }
+
+        self.numeric(index, reply::RPL_ENDOFWHO, &[mask, "End of WHO list"]);
+    }
+
+    fn whois(&mut self, index: usize, viewer: &RcUser, masks: Vec<String>) {
+        for mask in masks {
+            let found = match self.server.user(&mask) {
+                Some(found) => found,
+                None => {
+                    self.numeric(
+                        index,
+                        reply::ERR_NOSUCHNICK,
+                        &[&mask, "No such nick/channel"],
+                    );
+                    continue;
+                }
+            };
+
+            let found = found.borrow();
+            let origin = found.origin();
+            let nick = origin.nick().unwrap_or_default();
+
+            self.numeric(
+                index,
+                reply::RPL_WHOISUSER,
+                &[
+                    nick,
+                    origin.user().unwrap_or_default(),
+                    origin.host().unwrap_or_default(),
+                    "*",
+                    found.real_name(),
+                ],
+            );
+
+            let channels = found
+                .channels()
+                .iter()
+                .filter_map(|channel| channel.upgrade())
+                .filter(|channel| {
+                    let channel = channel.borrow();
+
+                    channel.is_member(viewer)
+                        || !(channel.has_mode(&channel::Mode::Secret)
+                            || channel.has_mode(&channel::Mode::Private))
+                })
+                .map(|channel| {
+                    let channel = channel.borrow();
+                    let prefix = self
+                        .server
+                        .user(nick)
+                        .and_then(|user| channel.member(&user).and_then(|m| m.prefix()))
+                        .map(|p| p.to_string())
+                        .unwrap_or_default();
+
+                    format!("{}{}", prefix, channel.name())
+                })
+                .collect::<Vec<String>>();
+
+            if !channels.is_empty() {
+                self.numeric(
+                    index,
+                    reply::RPL_WHOISCHANNELS,
+                    &[nick, &channels.join(crate::SEPARATOR)],
+                );
+            }
+
+            let home = found
+                .server()
+                .and_then(|name| self.server.server(name))
+                .unwrap_or(&self.server);
+
+            self.numeric(
+                index,
+                reply::RPL_WHOISSERVER,
+                &[nick, home.name(), home.info()],
+            );
+
+            if found.has_mode(&user::Mode::Operator) {
+                self.numeric(
+                    index,
+                    reply::RPL_WHOISOPERATOR,
+                    &[nick, "is an IRC operator"],
+                );
+            }
+
+            if let Some(account) = found.account() {
+                self.numeric(
+                    index,
+                    reply::RPL_WHOISACCOUNT,
+                    &[nick, account, "is logged in as"],
+                );
+            }
+
+            self.numeric(index, reply::RPL_ENDOFWHOIS, &[nick, "End of WHOIS list"]);
+        }
+    }
+}
+
+impl Ircd {
+    fn links_reply(&mut self, index: usize) {
+        self.numeric(
+            index,
+            reply::RPL_LINKS,
+            &[
+                self.name(),
+                self.name(),
+                &format!("0 {}", self.server.info()),
+            ],
+        );
+
+        for other in self.server.servers() {
+            self.numeric(
+                index,
+                reply::RPL_LINKS,
+                &[
+                    other.name(),
+                    other.uplink().unwrap_or_default(),
+                    &format!("{} {}", other.hop_count(), other.info()),
+                ],
+            );
+        }
+
+        self.numeric(index, reply::RPL_ENDOFLINKS, &["*", "End of LINKS list"]);
+    }
+}
+
+impl Ircd {
+    /// Sends the message to all members of the channel
+    pub(super) fn broadcast(&self, channel: &RcChannel, message: Message<Command>, except: Option<&RcUser>) {
+        let members = channel
+            .borrow()
+            .users()
+            .iter()
+            .filter_map(|member| member.upgrade())
+            .collect::<Vec<RcUser>>();
+
+        for member in members {
+            if except.map_or(true, |except| !Rc::ptr_eq(except, &member)) {
+                self.send_to(&member, message.clone());
+            }
+        }
+    }
+
+    /// Local and remote members, all of them or the ones with the status (`@` or `+`)
+    pub(super) fn receivers(&self, channel: &RcChannel, status: Option<char>) -> Vec<RcUser> {
+        channel
+            .borrow()
+            .members()
+            .iter()
+            .filter(|member| match status {
+                Some('+') => member.is_voiced() || member.is_operator(),
+                Some(_) => member.is_operator(),
+                None => true,
+            })
+            .filter_map(|member| member.user().upgrade())
+            .collect()
+    }
+
+    /// Removes the user from the channel and the channel once it's empty
+    pub(super) fn leave(&mut self, user: &RcUser, channel: &RcChannel) {
+        channel.borrow_mut().remove_user(user);
+        user.borrow_mut().remove_channel(channel);
+
+        if channel.borrow().members().is_empty() {
+            self.server.remove_channel(channel);
+        }
+    }
+
+    /// The channel if it exists and the user is in it, replies with the error otherwise
+    fn member_channel(&self, index: usize, user: &RcUser, name: &str) -> Option<RcChannel> {
+        let channel = match self.server.channel(name) {
+            Some(channel) => channel,
+            None => {
+                self.numeric(index, reply::ERR_NOSUCHCHANNEL, &[name, "No such channel"]);
+                return None;
+            }
+        };
+
+        if !channel.borrow().is_member(user) {
+            let name = channel.borrow().name().to_string();
+            self.numeric(
+                index,
+                reply::ERR_NOTONCHANNEL,
+                &[&name, "You're not on that channel"],
+            );
+
+            return None;
+        }
+
+        Some(channel)
+    }
+
+    /// Like `member_channel`, the user has to operate the channel as well
+    fn operated_channel(&self, index: usize, user: &RcUser, name: &str) -> Option<RcChannel> {
+        let channel = self.member_channel(index, user, name)?;
+
+        let operator = channel
+            .borrow()
+            .member(user)
+            .map_or(false, |member| member.is_operator());
+
+        if !operator {
+            let name = channel.borrow().name().to_string();
+            self.numeric(
+                index,
+                reply::ERR_CHANOPRIVSNEEDED,
+                &[&name, "You're not channel operator"],
+            );
+
+            return None;
+        }
+
+        Some(channel)
+    }
+
+    /// Voice and operator status override `+m` and bans, others need to be members with `+n`
+    fn can_send(&self, channel: &RcChannel, user: &RcUser) -> bool {
+        let channel = channel.borrow();
+        let origin = user.borrow().origin().clone();
+
+        let restricted = channel.has_mode(&channel::Mode::Moderated)
+            || channel.is_quieted(&origin, self.server.casemapping());
+
+        match channel.member(user) {
+            Some(member) if member.is_operator() || member.is_voiced() => true,
+            Some(_) => !restricted,
+            None => !channel.has_mode(&channel::Mode::NoExternal) && !restricted,
+        }
+    }
+
+    /// Invisible users only show up for users sharing a channel with them
+    fn is_visible(&self, viewer: &RcUser, user: &RcUser) -> bool {
+        Rc::ptr_eq(viewer, user)
+            || !user.borrow().has_mode(&user::Mode::Invisible)
+            || self.peers(viewer).iter().any(|peer| Rc::ptr_eq(peer, user))
+    }
+}
+
+/// Fills in the missing parts of plain masks, extended bans are kept as they are
+fn normalize(mask: &str) -> String {
+    match ExtBan::parse(mask) {
+        Some(_) => mask.to_string(),
+        None => Mask::new(mask).to_string(),
+    }
+}
+
+fn is_registration(command: &str) -> bool {
+    match command {
+        "PASS" | "NICK" | "USER" | "QUIT" | "PING" | "PONG" | "CAP" | "AUTHENTICATE" => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,497 @@
+use std::{error::Error, fs, path::Path, time::Duration};
+
+use native_tls::Identity;
+use toml::{Table, Value};
+
+use super::{
+    error,
+    oper::{Class, Oper, Privilege},
+    sasl::Credentials,
+    Builder, PING_INTERVAL, PING_TIMEOUT,
+};
+use crate::{casemapping::CaseMapping, limits, origin::mask, parsing};
+
+/// Limits the server enforces and advertises in `RPL_ISUPPORT`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// `NICKLEN`, at most `limits::NICK_NAME`
+    pub nick_length: usize,
+    /// `CHANNELLEN`, at most `limits::CHANNEL_NAME`
+    pub channel_length: usize,
+    /// `TOPICLEN`, longer topics get cut
+    pub topic_length: usize,
+    /// `CHANLIMIT`, channels a user may be in at once
+    pub channels: usize,
+    /// `CHATHISTORY`, messages kept for each channel and conversation and the most a request
+    /// gets
+    pub history: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            nick_length: limits::NICK_NAME,
+            channel_length: limits::CHANNEL_NAME,
+            topic_length: 390,
+            channels: 20,
+            history: 100,
+        }
+    }
+}
+
+/// Settings for the clients connecting from matching hosts, the first matching class applies
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionClass {
+    name: String,
+    hosts: Vec<String>,
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    max_clients: Option<usize>,
+}
+
+impl ConnectionClass {
+    pub fn new(name: &str) -> Self {
+        ConnectionClass {
+            name: name.to_string(),
+            hosts: Vec::new(),
+            ping_interval: PING_INTERVAL,
+            ping_timeout: PING_TIMEOUT,
+            max_clients: None,
+        }
+    }
+
+    /// Host or IP masks like `192.0.2.*`, a class without any matches every host
+    pub fn host(mut self, mask: &str) -> Self {
+        self.hosts.push(mask.to_string());
+
+        self
+    }
+
+    /// Idle time before a ping and the time to answer it
+    pub fn pinging(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.ping_interval = interval;
+        self.ping_timeout = timeout;
+
+        self
+    }
+
+    /// Connections the class takes at once
+    pub fn limit(mut self, max: usize) -> Self {
+        self.max_clients = Some(max);
+
+        self
+    }
+}
+
+impl ConnectionClass {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn hosts(&self) -> &Vec<String> {
+        &self.hosts
+    }
+
+    pub fn ping_interval(&self) -> Duration {
+        self.ping_interval
+    }
+
+    pub fn ping_timeout(&self) -> Duration {
+        self.ping_timeout
+    }
+
+    pub fn max_clients(&self) -> Option<usize> {
+        self.max_clients
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.hosts.is_empty()
+            || self
+                .hosts
+                .iter()
+                .any(|pattern| mask::matches(pattern, host, CaseMapping::default()))
+    }
+}
+
+/// Reads a TOML configuration into a builder, relative paths start at the file's directory
+///
+/// ```toml
+/// [server]
+/// name = "irc.example.org"
+/// info = "Example server"
+/// motd = "motd.txt"
+/// password = "letmein"
+/// ban_file = "bans.txt"
+/// account_file = "accounts.txt"
+///
+/// [limits]
+/// nick_length = 9
+/// channel_length = 50
+/// topic_length = 390
+/// channels = 20
+/// history = 100
+///
+/// [[listen]]
+/// address = "0.0.0.0:6667"
+///
+/// [[listen]]
+/// address = "0.0.0.0:6697"
+/// tls = { identity = "identity.p12", password = "" }
+///
+/// [[class]]
+/// name = "users"
+/// hosts = ["*"]
+/// ping_interval = 120
+/// ping_timeout = 60
+/// max_clients = 100
+///
+/// [[oper_class]]
+/// name = "admins"
+/// privileges = ["kill", "ban", "rehash"]
+///
+/// [[oper]]
+/// name = "admin"
+/// password = "4096:..."
+/// hosts = ["*@127.0.0.1"]
+/// class = "admins"
+///
+/// [[link]]
+/// name = "irc2.example.org"
+/// password = "secret"
+/// ```
+pub fn load(path: &Path) -> Result<Builder, Box<Error>> {
+    let root = fs::read_to_string(path)?.parse::<Table>()?;
+    let directory = path.parent().unwrap_or_else(|| Path::new("."));
+
+    check_keys(
+        &root,
+        "",
+        &[
+            "server",
+            "limits",
+            "listen",
+            "class",
+            "oper_class",
+            "oper",
+            "link",
+        ],
+    )?;
+
+    let mut builder = Builder::default();
+
+    let server = match root.get("server") {
+        Some(Value::Table(server)) => server,
+        Some(_) => return Err(invalid("server", "has to be a table")),
+        None => return Err(invalid("server", "is missing")),
+    };
+
+    check_keys(
+        server,
+        "server",
+        &[
+            "name",
+            "info",
+            "motd",
+            "password",
+            "ban_file",
+            "account_file",
+        ],
+    )?;
+
+    let name = required_string(server, "server", "name")?;
+
+    if parsing::server_name(&name).is_err() {
+        return Err(invalid("server.name", "is not a valid server name"));
+    }
+
+    builder = builder.name(&name);
+
+    if let Some(info) = string(server, "server", "info")? {
+        builder = builder.info(&info);
+    }
+
+    if let Some(motd) = string(server, "server", "motd")? {
+        let motd = fs::read_to_string(directory.join(motd))
+            .map_err(|e| invalid("server.motd", &e.to_string()))?;
+
+        builder = builder.motd(&motd);
+    }
+
+    if let Some(password) = string(server, "server", "password")? {
+        builder = builder.password(&password);
+    }
+
+    if let Some(ban_file) = string(server, "server", "ban_file")? {
+        builder = builder.ban_file(directory.join(ban_file));
+    }
+
+    if let Some(account_file) = string(server, "server", "account_file")? {
+        builder = builder.account_file(directory.join(account_file));
+    }
+
+    builder = builder.limits(load_limits(&root)?);
+
+    for (path, listener) in tables(&root, "listen")? {
+        check_keys(listener, &path, &["address", "tls"])?;
+
+        let address = required_string(listener, &path, "address")?;
+
+        if address
+            .rfind(':')
+            .and_then(|pos| address[pos + 1..].parse::<u16>().ok())
+            .is_none()
+        {
+            return Err(invalid(&format!("{}.address", path), "needs a port"));
+        }
+
+        let tls = match listener.get("tls") {
+            Some(Value::Table(tls)) => tls,
+            Some(_) => return Err(invalid(&format!("{}.tls", path), "has to be a table")),
+            None => {
+                builder = builder.listen(&address);
+                continue;
+            }
+        };
+
+        let path = format!("{}.tls", path);
+        check_keys(tls, &path, &["identity", "password"])?;
+
+        let identity = required_string(tls, &path, "identity")?;
+        let password = string(tls, &path, "password")?.unwrap_or_default();
+
+        let identity = fs::read(directory.join(identity))
+            .map_err(|e| e.to_string())
+            .and_then(|data| Identity::from_pkcs12(&data, &password).map_err(|e| e.to_string()))
+            .map_err(|e| invalid(&format!("{}.identity", path), &e))?;
+
+        builder = builder.listen_tls(&address, identity);
+    }
+
+    for (path, class) in tables(&root, "class")? {
+        check_keys(
+            class,
+            &path,
+            &[
+                "name",
+                "hosts",
+                "ping_interval",
+                "ping_timeout",
+                "max_clients",
+            ],
+        )?;
+
+        let mut connection_class = ConnectionClass::new(&required_string(class, &path, "name")?);
+
+        for host in strings(class, &path, "hosts")? {
+            connection_class = connection_class.host(&host);
+        }
+
+        let interval = integer(class, &path, "ping_interval")?;
+        let timeout = integer(class, &path, "ping_timeout")?;
+
+        connection_class = connection_class.pinging(
+            interval.map_or(PING_INTERVAL, Duration::from_secs),
+            timeout.map_or(PING_TIMEOUT, Duration::from_secs),
+        );
+
+        if let Some(max) = integer(class, &path, "max_clients")? {
+            connection_class = connection_class.limit(max as usize);
+        }
+
+        builder = builder.connection_class(connection_class);
+    }
+
+    let mut classes = Vec::new();
+
+    for (path, class) in tables(&root, "oper_class")? {
+        check_keys(class, &path, &["name", "privileges"])?;
+
+        let mut oper_class = Class::new(&required_string(class, &path, "name")?);
+
+        for privilege in strings(class, &path, "privileges")? {
+            match Privilege::parse(&privilege) {
+                Some(privilege) => oper_class = oper_class.privilege(privilege),
+                None => {
+                    return Err(invalid(
+                        &format!("{}.privileges", path),
+                        &format!("has an unknown privilege {}", privilege),
+                    ))
+                }
+            }
+        }
+
+        classes.push(oper_class.name().to_string());
+        builder = builder.class(oper_class);
+    }
+
+    for (path, oper) in tables(&root, "oper")? {
+        check_keys(oper, &path, &["name", "password", "hosts", "class"])?;
+
+        let password = required_string(oper, &path, "password")?;
+        let class = required_string(oper, &path, "class")?;
+
+        if Credentials::parse(&password).is_none() {
+            return Err(invalid(
+                &format!("{}.password", path),
+                "has to be a hash made with --mkpasswd",
+            ));
+        }
+
+        if !classes.contains(&class) {
+            return Err(invalid(
+                &format!("{}.class", path),
+                &format!("names no oper_class {}", class),
+            ));
+        }
+
+        let mut block = Oper::new(&required_string(oper, &path, "name")?, &password, &class);
+
+        for host in strings(oper, &path, "hosts")? {
+            block = block.host(&host);
+        }
+
+        builder = builder.oper(block);
+    }
+
+    for (path, link) in tables(&root, "link")? {
+        check_keys(link, &path, &["name", "password"])?;
+
+        builder = builder.link(
+            &required_string(link, &path, "name")?,
+            &required_string(link, &path, "password")?,
+        );
+    }
+
+    Ok(builder.config_file(path))
+}
+
+fn load_limits(root: &Table) -> Result<Limits, Box<Error>> {
+    let mut limits = Limits::default();
+
+    let table = match root.get("limits") {
+        Some(Value::Table(table)) => table,
+        Some(_) => return Err(invalid("limits", "has to be a table")),
+        None => return Ok(limits),
+    };
+
+    check_keys(
+        table,
+        "limits",
+        &[
+            "nick_length",
+            "channel_length",
+            "topic_length",
+            "channels",
+            "history",
+        ],
+    )?;
+
+    let fields: [(&str, &mut usize, usize); 5] = [
+        ("nick_length", &mut limits.nick_length, limits::NICK_NAME),
+        (
+            "channel_length",
+            &mut limits.channel_length,
+            limits::CHANNEL_NAME,
+        ),
+        ("topic_length", &mut limits.topic_length, limits::MESSAGE),
+        ("channels", &mut limits.channels, usize::MAX),
+        ("history", &mut limits.history, usize::MAX),
+    ];
+
+    for (key, field, max) in fields {
+        if let Some(value) = integer(table, "limits", key)? {
+            if value == 0 || value as usize > max {
+                return Err(invalid(
+                    &format!("limits.{}", key),
+                    &format!("has to be between 1 and {}", max),
+                ));
+            }
+
+            *field = value as usize;
+        }
+    }
+
+    Ok(limits)
+}
+
+fn invalid(key: &str, reason: &str) -> Box<Error> {
+    error::ConfigError::new(key.to_string(), reason.to_string())
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Typos shouldn't go unnoticed
+fn check_keys(table: &Table, path: &str, known: &[&str]) -> Result<(), Box<Error>> {
+    match table.keys().find(|key| !known.contains(&key.as_str())) {
+        Some(key) => Err(invalid(&join(path, key), "is unknown")),
+        None => Ok(()),
+    }
+}
+
+fn string(table: &Table, path: &str, key: &str) -> Result<Option<String>, Box<Error>> {
+    match table.get(key) {
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        Some(_) => Err(invalid(&join(path, key), "has to be a string")),
+        None => Ok(None),
+    }
+}
+
+fn required_string(table: &Table, path: &str, key: &str) -> Result<String, Box<Error>> {
+    string(table, path, key)?.ok_or_else(|| invalid(&join(path, key), "is missing"))
+}
+
+fn integer(table: &Table, path: &str, key: &str) -> Result<Option<u64>, Box<Error>> {
+    match table.get(key) {
+        Some(Value::Integer(value)) if *value >= 0 => Ok(Some(*value as u64)),
+        Some(_) => Err(invalid(&join(path, key), "has to be a positive integer")),
+        None => Ok(None),
+    }
+}
+
+fn strings(table: &Table, path: &str, key: &str) -> Result<Vec<String>, Box<Error>> {
+    let values = match table.get(key) {
+        Some(Value::Array(values)) => values,
+        Some(_) => return Err(invalid(&join(path, key), "has to be an array of strings")),
+        None => return Ok(Vec::new()),
+    };
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| match value {
+            Value::String(value) => Ok(value.clone()),
+            _ => Err(invalid(
+                &format!("{}[{}]", join(path, key), index),
+                "has to be a string",
+            )),
+        })
+        .collect()
+}
+
+/// The tables of an array like `[[listen]]` with their paths like `listen[0]`
+fn tables<'a>(root: &'a Table, key: &str) -> Result<Vec<(String, &'a Table)>, Box<Error>> {
+    let values = match root.get(key) {
+        Some(Value::Array(values)) => values,
+        Some(_) => return Err(invalid(key, "has to be an array of tables")),
+        None => return Ok(Vec::new()),
+    };
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let path = format!("{}[{}]", key, index);
+
+            match value {
+                Value::Table(table) => Ok((path, table)),
+                _ => Err(invalid(&path, "has to be a table")),
+            }
+        })
+        .collect()
+}
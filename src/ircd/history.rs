@@ -0,0 +1,374 @@
+use std::collections::{HashMap, VecDeque};
+
+use super::Ircd;
+use crate::{
+    casemapping::CaseMapping,
+    command::client::Command,
+    history::{self, Range, Reference},
+    message::Message,
+    time,
+    user::RcUser,
+    validate,
+};
+
+/// Message kept for `CHATHISTORY`, tagged with its `msgid` and `time`
+struct Entry {
+    id: String,
+    time: u64,
+    message: Message<Command>,
+}
+
+/// Messages of a channel or a private conversation, oldest first
+struct Log {
+    /// The channel, or the nicks on both sides of the conversation
+    names: Vec<String>,
+    entries: VecDeque<Entry>,
+}
+
+impl Log {
+    /// Where the messages before the reference end and those after it start, `None` for an
+    /// unknown `msgid`
+    fn bounds(&self, reference: &Reference) -> Option<(usize, usize)> {
+        match reference {
+            Reference::MsgId(ref id) => self
+                .entries
+                .iter()
+                .position(|entry| &entry.id == id)
+                .map(|pos| (pos, pos + 1)),
+            Reference::Timestamp(time) => Some((
+                self.entries
+                    .iter()
+                    .position(|entry| entry.time >= *time)
+                    .unwrap_or(self.entries.len()),
+                self.entries
+                    .iter()
+                    .position(|entry| entry.time > *time)
+                    .unwrap_or(self.entries.len()),
+            )),
+        }
+    }
+
+    /// Positions of the messages in the range, `limit` caps the one the range asks for
+    fn select(&self, range: &Range, limit: usize) -> (usize, usize) {
+        let length = self.entries.len();
+        let limit = limit.min(range.limit());
+
+        let bounds = match range {
+            Range::Latest { after: None, .. } => Some((length.saturating_sub(limit), length)),
+            Range::Latest {
+                after: Some(ref reference),
+                ..
+            } => self
+                .bounds(reference)
+                .map(|(_, after)| (after.max(length.saturating_sub(limit)), length)),
+            Range::Before { ref reference, .. } => self
+                .bounds(reference)
+                .map(|(before, _)| (before.saturating_sub(limit), before)),
+            Range::After { ref reference, .. } => self
+                .bounds(reference)
+                .map(|(_, after)| (after, (after + limit).min(length))),
+            Range::Around { ref reference, .. } => self.bounds(reference).map(|(before, _)| {
+                let start = before.saturating_sub(limit / 2);
+
+                (start, (start + limit).min(length))
+            }),
+            Range::Between {
+                ref start, ref end, ..
+            } => match (self.bounds(start), self.bounds(end)) {
+                (Some((_, after)), Some((before, _))) if after <= before => {
+                    Some((after, (after + limit).min(before)))
+                }
+                // the range runs backwards
+                (Some((before, _)), Some((_, after))) if after <= before => {
+                    Some((before.saturating_sub(limit).max(after), before))
+                }
+                _ => None,
+            },
+        };
+
+        bounds.unwrap_or((0, 0))
+    }
+}
+
+/// Messages local users sent or got, kept for each channel and conversation
+pub(super) struct History {
+    logs: HashMap<String, Log>,
+    /// Start of every `msgid`, keeps them unique across restarts
+    prefix: String,
+    next_id: u64,
+    batches: u64,
+}
+
+impl History {
+    pub(super) fn new() -> Self {
+        History {
+            logs: HashMap::new(),
+            prefix: format!("{:x}", time::now_millis()),
+            next_id: 0,
+            batches: 0,
+        }
+    }
+
+    fn key(names: &[&str], mapping: CaseMapping) -> String {
+        let mut names = names
+            .iter()
+            .map(|name| mapping.to_lower(name))
+            .collect::<Vec<String>>();
+
+        names.sort();
+
+        names.join(" ")
+    }
+
+    /// Tags the message with a new `msgid` and the current time and keeps it, the oldest
+    /// message goes once the log holds `limit`
+    fn record(
+        &mut self,
+        names: &[&str],
+        message: Message<Command>,
+        mapping: CaseMapping,
+        limit: usize,
+    ) -> Message<Command> {
+        let id = format!("{}-{}", self.prefix, self.next_id);
+        let time = time::now_millis();
+
+        self.next_id += 1;
+
+        let message = message
+            .with_tag("msgid", Some(&id))
+            .with_tag("time", Some(&time::format_time(time)));
+
+        let log = self
+            .logs
+            .entry(History::key(names, mapping))
+            .or_insert_with(|| Log {
+                names: names.iter().map(|name| name.to_string()).collect(),
+                entries: VecDeque::new(),
+            });
+
+        while log.entries.len() >= limit {
+            log.entries.pop_front();
+        }
+
+        log.entries.push_back(Entry {
+            id,
+            time,
+            message: message.clone(),
+        });
+
+        message
+    }
+
+    fn messages(
+        &self,
+        names: &[&str],
+        range: &Range,
+        mapping: CaseMapping,
+        limit: usize,
+    ) -> Vec<Message<Command>> {
+        let log = match self.logs.get(&History::key(names, mapping)) {
+            Some(log) => log,
+            None => return Vec::new(),
+        };
+
+        let (start, end) = log.select(range, limit);
+
+        log.entries
+            .iter()
+            .skip(start)
+            .take(end.saturating_sub(start))
+            .map(|entry| entry.message.clone())
+            .collect()
+    }
+
+    fn next_batch(&mut self) -> String {
+        self.batches += 1;
+
+        format!("history{}", self.batches)
+    }
+}
+
+impl Ircd {
+    /// Keeps a message to a channel and tags it for the members who get it
+    pub(super) fn record_channel(
+        &mut self,
+        channel: &str,
+        message: Message<Command>,
+    ) -> Message<Command> {
+        let mapping = self.server.casemapping();
+        let limit = self.limits.history;
+
+        self.history.record(&[channel], message, mapping, limit)
+    }
+
+    /// Keeps a private message, the conversation shows up under both nicks
+    pub(super) fn record_private(
+        &mut self,
+        sender: &RcUser,
+        receiver: &RcUser,
+        message: Message<Command>,
+    ) -> Message<Command> {
+        let mapping = self.server.casemapping();
+        let limit = self.limits.history;
+        let sender = sender
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+        let receiver = receiver
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        self.history
+            .record(&[&sender, &receiver], message, mapping, limit)
+    }
+
+    /// `CHATHISTORY` for a channel the user is on or a nick the user talked to
+    pub(super) fn chathistory(&mut self, index: usize, user: &RcUser, target: &str, range: Range) {
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        let (target, names) = if validate::channel_name(target).is_ok() {
+            match self.server.channel(target) {
+                Some(ref channel) if channel.borrow().is_member(user) => {
+                    let name = channel.borrow().name().to_string();
+
+                    (name.clone(), vec![name])
+                }
+                _ => return self.history_error(index, range.subcommand(), target),
+            }
+        } else if validate::nick_name(target).is_ok() {
+            (target.to_string(), vec![nick, target.to_string()])
+        } else {
+            return self.history_error(index, range.subcommand(), target);
+        };
+
+        let names = names
+            .iter()
+            .map(|name| name.as_str())
+            .collect::<Vec<&str>>();
+        let messages = self.history.messages(
+            &names,
+            &range,
+            self.server.casemapping(),
+            self.limits.history,
+        );
+
+        let reference = self.history.next_batch();
+
+        self.send(
+            index,
+            Command::Batch {
+                reference: format!("+{}", reference),
+                kind: Some(history::BATCH.to_string()),
+                params: vec![target],
+            },
+        );
+
+        for message in messages {
+            self.send(index, message.with_tag("batch", Some(&reference)));
+        }
+
+        self.send(
+            index,
+            Command::Batch {
+                reference: format!("-{}", reference),
+                kind: None,
+                params: Vec::new(),
+            },
+        );
+    }
+
+    /// `CHATHISTORY TARGETS`, the channels and nicks with messages between the timestamps
+    pub(super) fn chathistory_targets(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        start: u64,
+        end: u64,
+        limit: usize,
+    ) {
+        let mapping = self.server.casemapping();
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+        let (from, to) = (start.min(end), start.max(end));
+
+        let mut targets = Vec::new();
+
+        for log in self.history.logs.values() {
+            let time = match log.entries.back() {
+                Some(entry) if entry.time > from && entry.time < to => entry.time,
+                _ => continue,
+            };
+
+            let target = match log.names.as_slice() {
+                [channel] => match self.server.channel(channel) {
+                    Some(ref found) if found.borrow().is_member(user) => channel.to_string(),
+                    _ => continue,
+                },
+                [a, b] if mapping.eq(a, &nick) => b.to_string(),
+                [a, b] if mapping.eq(b, &nick) => a.to_string(),
+                _ => continue,
+            };
+
+            targets.push((time, target));
+        }
+
+        targets.sort();
+        targets.truncate(limit.min(self.limits.history));
+
+        let reference = self.history.next_batch();
+
+        self.send(
+            index,
+            Command::Batch {
+                reference: format!("+{}", reference),
+                kind: Some(history::TARGETS_BATCH.to_string()),
+                params: Vec::new(),
+            },
+        );
+
+        for (time, target) in targets {
+            let message = Message::new(
+                self.server.origin().clone(),
+                Command::ChatHistoryTarget { target, time },
+            )
+            .with_tag("batch", Some(&reference));
+
+            self.send(index, message);
+        }
+
+        self.send(
+            index,
+            Command::Batch {
+                reference: format!("-{}", reference),
+                kind: None,
+                params: Vec::new(),
+            },
+        );
+    }
+
+    fn history_error(&self, index: usize, subcommand: &str, target: &str) {
+        self.send(
+            index,
+            Command::Fail {
+                command: "CHATHISTORY".to_string(),
+                code: "INVALID_TARGET".to_string(),
+                context: vec![subcommand.to_string(), target.to_string()],
+                description: "Messages could not be retrieved".to_string(),
+            },
+        );
+    }
+}
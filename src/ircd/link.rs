@@ -0,0 +1,936 @@
+use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+
+use super::{Ircd, Link};
+use crate::{
+    channel::{Channel, RcChannel},
+    command::{
+        self, client,
+        server::{self, Command, Member},
+    },
+    message::Message,
+    mode::{self, channel, Parseable},
+    origin::Origin,
+    server::Server,
+    target::MessageTarget,
+    user::{RcUser, User},
+};
+
+/// Converts between the client and the server protocol through the line both understand, tags
+/// stay on the local server
+pub(super) fn relay<A, B>(message: &Message<A>) -> Option<Message<B>>
+where
+    A: command::Command,
+    B: command::Command,
+{
+    Message::<B>::try_from(message.to_string().as_str())
+        .ok()
+        .map(|relayed| Message::new(relayed.origin().clone(), relayed.command().clone()))
+}
+
+/// Nick or server name of the origin
+fn source(origin: &Origin) -> Option<&str> {
+    match origin {
+        Origin::User { ref nick, .. } => Some(nick.as_str()),
+        Origin::Server { ref name } => Some(name.as_str()),
+        Origin::Connection => None,
+    }
+}
+
+impl Ircd {
+    pub(super) fn handle_link(&mut self, index: usize, line: &str) {
+        let message = match Message::<Command>::try_from(line) {
+            Ok(message) => message,
+            Err(_) => return,
+        };
+
+        match message.command().clone() {
+            Command::Ping { server1, .. } => self.send_link(
+                index,
+                Command::Pong {
+                    server1: self.name().to_string(),
+                    server2: Some(server1),
+                },
+            ),
+            Command::Pong { .. } => (),
+            Command::ErrorMsg { text } => self.links[index].quit = Some(text),
+
+            Command::Pass { password, .. } if self.links[index].name.is_none() => {
+                self.links[index].password = Some(password)
+            }
+            Command::Server {
+                name, token, info, ..
+            } if self.links[index].name.is_none() => self.accept_link(index, name, token, &info),
+
+            // nothing else counts before the peer is known
+            _ if self.links[index].name.is_none() => (),
+
+            _ => self.dispatch_link(index, message),
+        }
+    }
+
+    /// Checks the password of the server, answers with the local state and tells the network
+    fn accept_link(&mut self, index: usize, name: String, token: u32, info: &str) {
+        let password = self.link_password(&name);
+
+        if password.is_none() || password != self.links[index].password {
+            self.links[index].quit = Some("Bad password".to_string());
+
+            return;
+        }
+
+        if name.eq_ignore_ascii_case(self.name()) || self.server.server(&name).is_some() {
+            self.links[index].quit = Some(format!("Server {} already exists", name));
+
+            return;
+        }
+
+        if !self.links[index].outgoing {
+            self.send_link(index, Command::pass(&password.unwrap_or_default()));
+            self.send_link(
+                index,
+                Command::Server {
+                    name: self.name().to_string(),
+                    hop_count: 1,
+                    token: self.server.token(),
+                    info: self.server.info().to_string(),
+                },
+            );
+        }
+
+        for message in server::burst(&self.server) {
+            self.send_link(index, message);
+        }
+
+        self.links[index].name = Some(name.clone());
+        self.add_server(index, self.name().to_string(), name, 1, token, info);
+    }
+
+    fn dispatch_link(&mut self, index: usize, message: Message<Command>) {
+        let peer = self.links[index].name.clone().unwrap_or_default();
+        let source = source(message.origin()).unwrap_or(&peer).to_string();
+
+        match message.command().clone() {
+            Command::Server {
+                name,
+                hop_count,
+                token,
+                info,
+            } => {
+                if name.eq_ignore_ascii_case(self.name()) || self.server.server(&name).is_some() {
+                    self.links[index].quit = Some(format!("Server {} already exists", name));
+                } else {
+                    self.add_server(index, source, name, hop_count, token, &info);
+                }
+            }
+            Command::NewUser { .. } => self.new_user(index, message.command()),
+            Command::SQuit { server, comment } => self.remote_squit(index, &server, &comment),
+            Command::Kill { user, comment } => {
+                if let Some(target) = self.server.user(&user) {
+                    self.propagate(message, Some(index));
+                    self.kill(&target, &source, &comment);
+                }
+            }
+            Command::Join { .. } | Command::NJoin { .. } => self.remote_join(index, message),
+
+            Command::Numeric { params, .. } => {
+                let target = params.get(0).and_then(|nick| self.server.user(nick));
+
+                if let Some(target) = target {
+                    match self.user_route(&target) {
+                        Some(link) if link != index => self.send_link(link, message),
+                        Some(_) => (),
+                        None => {
+                            if let Some(message) = relay(&message) {
+                                self.send_to(&target, message);
+                            }
+                        }
+                    }
+                }
+            }
+
+            command => {
+                // the rest comes from users behind the link or servers
+                let user = match self.server.user(&source) {
+                    Some(user) => user,
+                    None if self.server.server(&source).is_some() => {
+                        return self.remote_server_command(index, &source, message)
+                    }
+                    None => return,
+                };
+
+                if self.user_route(&user) != Some(index) {
+                    return;
+                }
+
+                match command {
+                    Command::Nick { name, timestamp } => {
+                        self.remote_nick(index, &user, &name, timestamp)
+                    }
+                    Command::Quit { reason } => {
+                        self.propagate(message, Some(index));
+                        self.quit_user(&user, &reason.unwrap_or_default());
+                    }
+                    Command::UMode { modes, .. } => {
+                        for change in modes.iter() {
+                            user.borrow_mut().apply_mode(change);
+                        }
+
+                        self.propagate(message, Some(index));
+                    }
+                    Command::PrivMsg { target, text } => {
+                        self.remote_message(index, &user, target, message, &text)
+                    }
+                    Command::Notice { target, text } => {
+                        self.remote_message(index, &user, target, message, &text)
+                    }
+                    Command::Invite { nick, channel } => {
+                        self.remote_invite(index, &user, &nick, &channel, message)
+                    }
+                    Command::Account { account } => {
+                        user.borrow_mut().set_account(account);
+
+                        if let Some(notification) = relay(&message) {
+                            self.notify_account(&user, notification);
+                        }
+
+                        self.propagate(message, Some(index));
+                    }
+                    _ => {
+                        let origin = user.borrow().origin().clone();
+                        self.channel_change(index, origin, message);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Channel changes servers make, like modes after a netjoin
+    fn remote_server_command(&mut self, index: usize, name: &str, message: Message<Command>) {
+        if self.route(name) != Some(index) {
+            return;
+        }
+
+        let origin = Origin::Server {
+            name: name.to_string(),
+        };
+
+        self.channel_change(index, origin, message);
+    }
+
+    fn add_server(
+        &mut self,
+        index: usize,
+        uplink: String,
+        name: String,
+        hop_count: u32,
+        token: u32,
+        info: &str,
+    ) {
+        let mut server = Server::new(Origin::Server { name: name.clone() });
+
+        server.set_info(info);
+        server.set_hop_count(hop_count);
+        server.set_token(self.next_token);
+        server.set_uplink(Some(uplink.clone()));
+
+        self.next_token += 1;
+        self.links[index].tokens.push((token, name.clone()));
+
+        let message = Message::new(
+            Origin::Server { name: uplink },
+            Command::Server {
+                name,
+                hop_count: hop_count + 1,
+                token: server.token(),
+                info: info.to_string(),
+            },
+        );
+
+        self.server.servers_mut().push(server);
+        self.propagate(message, Some(index));
+    }
+
+    /// A user introduced by `NICK`, the older nick wins a collision
+    fn new_user(&mut self, index: usize, command: &Command) {
+        let (nick, timestamp) = match command {
+            Command::NewUser {
+                ref nick,
+                ref timestamp,
+                ..
+            } => (nick, *timestamp),
+            _ => return,
+        };
+
+        if let Some(existing) = self.server.user(nick) {
+            let known = existing.borrow().timestamp();
+
+            match timestamp {
+                Some(timestamp) if timestamp > known => return,
+                Some(timestamp) if timestamp < known => self.collide(&existing),
+                // without telling which is older both go
+                _ => {
+                    self.collide(&existing);
+                    self.propagate(
+                        Message::new(
+                            self.server.origin().clone(),
+                            Command::Kill {
+                                user: nick.to_string(),
+                                comment: format!("{} (Nick collision)", self.name()),
+                            },
+                        ),
+                        None,
+                    );
+
+                    return;
+                }
+            }
+        }
+
+        let (hop_count, token, modes, real_name) = match command {
+            Command::NewUser {
+                hop_count,
+                token,
+                ref modes,
+                ref real_name,
+                ..
+            } => (*hop_count, *token, modes, real_name),
+            _ => return,
+        };
+
+        let home = self.links[index]
+            .tokens
+            .iter()
+            .find(|(other, _)| *other == token)
+            .map(|(_, name)| name.clone())
+            .or_else(|| self.links[index].name.clone());
+
+        let mut user = User::new(command.origin().unwrap_or_default(), real_name);
+
+        user.set_modes(modes.clone());
+        user.set_server(home);
+        user.set_timestamp(timestamp.unwrap_or_default());
+
+        let user = Rc::new(RefCell::new(user));
+        self.server.users_mut().push(user.clone());
+
+        let mut introduction = server::introduce(&self.server, &user.borrow());
+
+        // the hops as seen by the peer plus the one to it
+        if let Command::NewUser {
+            hop_count: ref mut hops,
+            ..
+        } = introduction
+        {
+            *hops = hop_count + 1;
+        }
+
+        self.propagate(Message::from(introduction), Some(index));
+    }
+
+    fn remote_nick(&mut self, index: usize, user: &RcUser, name: &str, timestamp: Option<u64>) {
+        if let Some(existing) = self.server.user(name) {
+            if !Rc::ptr_eq(&existing, user) {
+                let known = existing.borrow().timestamp();
+
+                match timestamp {
+                    Some(timestamp) if timestamp > known => return self.collide(user),
+                    Some(timestamp) if timestamp < known => self.collide(&existing),
+                    _ => {
+                        self.collide(&existing);
+                        self.collide(user);
+                        self.propagate(
+                            Message::new(
+                                self.server.origin().clone(),
+                                Command::Kill {
+                                    user: name.to_string(),
+                                    comment: format!("{} (Nick collision)", self.name()),
+                                },
+                            ),
+                            None,
+                        );
+
+                        return;
+                    }
+                }
+            }
+        }
+
+        let origin = user.borrow().origin().clone();
+
+        let message = Message::new(
+            origin.clone(),
+            client::Command::Nick {
+                name: name.to_string(),
+            },
+        );
+
+        for peer in self.peers(user) {
+            self.send_to(&peer, message.clone());
+        }
+
+        self.propagate(
+            Message::new(
+                origin,
+                Command::Nick {
+                    name: name.to_string(),
+                    timestamp,
+                },
+            ),
+            Some(index),
+        );
+
+        user.borrow_mut()
+            .set_timestamp(timestamp.unwrap_or_default());
+
+        if let Origin::User { ref mut nick, .. } = user.borrow_mut().origin_mut() {
+            *nick = name.to_string();
+        }
+    }
+
+    /// Drops the loser of a nick collision, the other servers resolve it the same way
+    fn collide(&mut self, user: &RcUser) {
+        let name = self.name().to_string();
+
+        self.kill(user, &name, "Nick collision");
+    }
+
+    /// Disconnects a local user, forgets a remote one
+    pub(super) fn kill(&mut self, user: &RcUser, killer: &str, comment: &str) {
+        let reason = format!("Killed ({} ({}))", killer, comment);
+
+        if let Some(index) = self.index_of(user) {
+            let origin = match self.server.user(killer) {
+                Some(killer) => killer.borrow().origin().clone(),
+                None => Origin::Server {
+                    name: killer.to_string(),
+                },
+            };
+            let nick = user
+                .borrow()
+                .origin()
+                .nick()
+                .unwrap_or_default()
+                .to_string();
+
+            self.send(
+                index,
+                Message::new(
+                    origin,
+                    client::Command::Kill {
+                        user: nick,
+                        reason: comment.to_string(),
+                    },
+                ),
+            );
+
+            // the network learned about it already
+            self.connections[index].user = None;
+            self.connections[index].quit = Some(reason.clone());
+        }
+
+        self.quit_user(user, &reason);
+    }
+
+    fn remote_squit(&mut self, index: usize, name: &str, comment: &str) {
+        if let Some(link) = self.links.iter().position(|link| link.is(name)) {
+            self.links[link].quit = Some(comment.to_string());
+
+            return;
+        }
+
+        if self.route(name) != Some(index) {
+            return;
+        }
+
+        let uplink = self
+            .server
+            .server(name)
+            .and_then(|server| server.uplink())
+            .unwrap_or_default()
+            .to_string();
+
+        self.propagate(
+            Message::new(
+                Origin::Server {
+                    name: uplink.clone(),
+                },
+                Command::SQuit {
+                    server: name.to_string(),
+                    comment: comment.to_string(),
+                },
+            ),
+            Some(index),
+        );
+        self.netsplit(name, &format!("{} {}", uplink, name));
+    }
+
+    /// Drops the link and everything the network knew through it
+    pub(super) fn split(&mut self, index: usize, reason: &str) {
+        let link = self.links.remove(index);
+
+        if let Some(ref name) = link.name {
+            self.propagate(
+                Message::new(
+                    self.server.origin().clone(),
+                    Command::SQuit {
+                        server: name.to_string(),
+                        comment: reason.to_string(),
+                    },
+                ),
+                None,
+            );
+
+            let quit = format!("{} {}", self.name(), name);
+            self.netsplit(name, &quit);
+        }
+
+        let _ = link.stream.send(Command::ErrorMsg {
+            text: format!(
+                "Closing Link: {} ({})",
+                link.name.as_ref().map_or("*", |name| name.as_str()),
+                reason
+            ),
+        });
+
+        link.stream.close();
+    }
+
+    /// Forgets the server and those behind it, their users quit with the reason
+    fn netsplit(&mut self, name: &str, reason: &str) {
+        let names = self
+            .server
+            .remove_server(name)
+            .iter()
+            .map(|server| server.name().to_ascii_lowercase())
+            .collect::<Vec<String>>();
+
+        let users = self
+            .server
+            .users()
+            .iter()
+            .filter(|user| {
+                user.borrow()
+                    .server()
+                    .map_or(false, |server| names.contains(&server.to_ascii_lowercase()))
+            })
+            .cloned()
+            .collect::<Vec<RcUser>>();
+
+        for user in users {
+            self.quit_user(&user, reason);
+        }
+    }
+
+    /// `JOIN` of a user and `NJOIN` of a netjoin, local members see joins and the modes given
+    fn remote_join(&mut self, index: usize, message: Message<Command>) {
+        let joins = match message.command() {
+            Command::Join { ref channels } => match self
+                .server
+                .user(source(message.origin()).unwrap_or_default())
+            {
+                Some(ref user) if self.user_route(user) == Some(index) => channels
+                    .iter()
+                    .map(|(name, modes)| {
+                        let member = Member {
+                            nick: user
+                                .borrow()
+                                .origin()
+                                .nick()
+                                .unwrap_or_default()
+                                .to_string(),
+                            creator: false,
+                            operator: modes.contains('o'),
+                            voice: modes.contains('v'),
+                        };
+
+                        (name.clone(), vec![member])
+                    })
+                    .collect::<Vec<(String, Vec<Member>)>>(),
+                _ => return,
+            },
+            Command::NJoin {
+                ref channel,
+                ref members,
+            } => vec![(channel.clone(), members.clone())],
+            _ => return,
+        };
+
+        for (name, members) in joins {
+            let channel = match self.server.channel(&name) {
+                Some(channel) => channel,
+                None => {
+                    let channel = Rc::new(RefCell::new(Channel::new(&name)));
+                    self.server.channels_mut().push(channel.clone());
+
+                    channel
+                }
+            };
+
+            for member in members {
+                let user = match self.server.user(&member.nick) {
+                    Some(ref user) if !channel.borrow().is_member(user) => user.clone(),
+                    _ => continue,
+                };
+
+                channel.borrow_mut().add_user(&user, member.operator);
+                user.borrow_mut().add_channel(&channel);
+
+                if let Some(added) = channel.borrow_mut().member_mut(&user) {
+                    added.set_voice(member.voice);
+                }
+
+                let name = channel.borrow().name().to_string();
+                let origin = user.borrow().origin().clone();
+
+                self.broadcast(
+                    &channel,
+                    Message::new(
+                        origin,
+                        client::Command::Join {
+                            channels: vec![name.clone()],
+                            keys: vec![],
+                        },
+                    ),
+                    None,
+                );
+
+                let mut modes = Vec::new();
+
+                if member.operator {
+                    modes.push(mode::Mode::new(
+                        true,
+                        channel::Mode::Operator {
+                            nick: member.nick.clone(),
+                        },
+                    ));
+                }
+
+                if member.voice {
+                    modes.push(mode::Mode::new(
+                        true,
+                        channel::Mode::Voice {
+                            nick: member.nick.clone(),
+                        },
+                    ));
+                }
+
+                if !modes.is_empty() {
+                    let home = user
+                        .borrow()
+                        .server()
+                        .unwrap_or_else(|| self.name())
+                        .to_string();
+
+                    self.broadcast(
+                        &channel,
+                        Message::new(
+                            Origin::Server { name: home },
+                            client::Command::CMode {
+                                channel: name,
+                                modes,
+                            },
+                        ),
+                        None,
+                    );
+                }
+            }
+        }
+
+        self.propagate(message, Some(index));
+    }
+
+    /// `PART`, `KICK`, `TOPIC` and channel `MODE` of a user or server behind the link
+    fn channel_change(&mut self, index: usize, origin: Origin, message: Message<Command>) {
+        let name = match message.command() {
+            Command::Part { ref channels, .. } => {
+                for name in channels {
+                    let user = match self.server.user(source(&origin).unwrap_or_default()) {
+                        Some(user) => user,
+                        None => return,
+                    };
+
+                    if let Some(channel) = self.server.channel(name) {
+                        if channel.borrow().is_member(&user) {
+                            self.notify(&channel, &origin, &message);
+                            self.leave(&user, &channel);
+                        }
+                    }
+                }
+
+                return self.propagate(message, Some(index));
+            }
+            Command::Kick { ref channel, .. }
+            | Command::Topic { ref channel, .. }
+            | Command::CMode { ref channel, .. } => channel.clone(),
+            _ => return,
+        };
+
+        let channel = match self.server.channel(&name) {
+            Some(channel) => channel,
+            None => return,
+        };
+
+        match message.command() {
+            Command::Kick { ref user, .. } => {
+                if let Some(target) = self.server.user(user) {
+                    if channel.borrow().is_member(&target) {
+                        self.notify(&channel, &origin, &message);
+                        self.leave(&target, &channel);
+                    }
+                }
+            }
+            Command::Topic { ref text, .. } => {
+                let setter = source(&origin).unwrap_or_default();
+
+                channel.borrow_mut().set_topic(text, setter);
+                self.notify(&channel, &origin, &message);
+            }
+            Command::CMode { ref modes, .. } => {
+                let mut applied = Vec::new();
+
+                for change in modes {
+                    let granted = change.granted();
+
+                    match change.mode() {
+                        channel::Mode::Operator { ref nick }
+                        | channel::Mode::Voice { ref nick } => {
+                            let target = match self.server.user(nick) {
+                                Some(target) => target,
+                                None => continue,
+                            };
+
+                            let operator = change.mode().symbol() == 'o';
+
+                            if let Some(member) = channel.borrow_mut().member_mut(&target) {
+                                if operator && member.is_operator() != granted {
+                                    member.set_operator(granted);
+                                    applied.push(change.clone());
+                                } else if !operator && member.is_voiced() != granted {
+                                    member.set_voice(granted);
+                                    applied.push(change.clone());
+                                }
+                            }
+                        }
+                        _ => {
+                            if channel.borrow_mut().apply_mode(change) {
+                                applied.push(change.clone());
+                            }
+                        }
+                    }
+                }
+
+                // a netjoin repeats modes the channel has already
+                if !applied.is_empty() {
+                    let changed = Message::new(
+                        origin.clone(),
+                        Command::CMode {
+                            channel: name,
+                            modes: applied,
+                        },
+                    );
+
+                    self.notify(&channel, &origin, &changed);
+                }
+            }
+            _ => return,
+        }
+
+        self.propagate(message, Some(index));
+    }
+
+    /// Shows the change to the local members of the channel with the full origin
+    fn notify(&self, channel: &RcChannel, origin: &Origin, message: &Message<Command>) {
+        if let Some(message) = relay::<Command, client::Command>(message) {
+            let message = Message::new(origin.clone(), message.command().clone());
+
+            self.broadcast(channel, message, None);
+        }
+    }
+
+    /// Invites are only remembered by the server of the invited user, which checks joins
+    fn remote_invite(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        nick: &str,
+        name: &str,
+        message: Message<Command>,
+    ) {
+        let target = match self.server.user(nick) {
+            Some(target) => target,
+            None => return,
+        };
+
+        match self.user_route(&target) {
+            Some(link) if link != index => self.send_link(link, message),
+            Some(_) => (),
+            None => {
+                if let Some(channel) = self.server.channel(name) {
+                    channel.borrow_mut().invite(&target);
+                }
+
+                if let Some(local) = relay::<Command, client::Command>(&message) {
+                    let origin = user.borrow().origin().clone();
+                    self.send_to(&target, Message::new(origin, local.command().clone()));
+                }
+            }
+        }
+    }
+
+    fn remote_message(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        target: MessageTarget,
+        message: Message<Command>,
+        text: &str,
+    ) {
+        let origin = user.borrow().origin().clone();
+
+        let local = match relay::<Command, client::Command>(&message) {
+            Some(local) => Message::new(origin, local.command().clone()),
+            None => return,
+        };
+
+        match target {
+            MessageTarget::Channel(ref name)
+            | MessageTarget::ChannelStatus {
+                channel: ref name, ..
+            } => {
+                let channel = match self.server.channel(name) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+
+                let status = match target {
+                    MessageTarget::ChannelStatus { status, .. } => Some(status),
+                    _ => None,
+                };
+
+                let local = if status.is_none() {
+                    let name = channel.borrow().name().to_string();
+
+                    self.record_channel(&name, local)
+                } else {
+                    local
+                };
+
+                for receiver in self.receivers(&channel, status) {
+                    self.send_to(&receiver, local.clone());
+                }
+
+                self.forward(&channel, message, Some(index));
+            }
+            _ if text.is_empty() => (),
+            _ => {
+                if let Some(receiver) = self.find_user(&target) {
+                    match self.user_route(&receiver) {
+                        Some(link) if link != index => self.send_link(link, message),
+                        Some(_) => (),
+                        None => {
+                            let local = self.record_private(user, &receiver, local);
+
+                            self.send_to(&receiver, local)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Ircd {
+    pub(super) fn send_link<T>(&self, index: usize, message: T)
+    where
+        T: Into<Message<Command>>,
+    {
+        let mut message = message.into();
+
+        if message.origin().is_connection() {
+            message = Message::new(self.server.origin().clone(), message.command().clone());
+        }
+
+        // a dead link shows up on its next read
+        let _ = self.links[index].stream.send(message);
+    }
+
+    /// Sends the message to all registered links, but the one it came from
+    pub(super) fn propagate(&self, message: Message<Command>, except: Option<usize>) {
+        for (index, link) in self.links.iter().enumerate() {
+            if link.name.is_some() && Some(index) != except {
+                self.send_link(index, message.clone());
+            }
+        }
+    }
+
+    /// Tells the network about a change a local user made
+    pub(super) fn announce(&self, message: &Message<client::Command>) {
+        if let Some(message) = relay(message) {
+            self.propagate(message, None);
+        }
+    }
+
+    /// Sends the message to the links leading to members of the channel
+    pub(super) fn forward(
+        &self,
+        channel: &RcChannel,
+        message: Message<Command>,
+        except: Option<usize>,
+    ) {
+        let mut links = Vec::new();
+
+        for user in self.receivers(channel, None) {
+            if let Some(link) = self.user_route(&user) {
+                if Some(link) != except && !links.contains(&link) {
+                    links.push(link);
+                }
+            }
+        }
+
+        for link in links {
+            self.send_link(link, message.clone());
+        }
+    }
+
+    /// Sends the message to a local user or towards the server of a remote one
+    pub(super) fn deliver(&self, user: &RcUser, message: Message<client::Command>) {
+        match self.user_route(user) {
+            Some(link) => {
+                if let Some(message) = relay(&message) {
+                    self.send_link(link, message);
+                }
+            }
+            None => self.send_to(user, message),
+        }
+    }
+
+    /// The link leading to the server
+    fn route(&self, name: &str) -> Option<usize> {
+        let mut name = name;
+
+        // neighbours are linked through the local server
+        while let Some(uplink) = self.server.server(name).and_then(|server| server.uplink()) {
+            if uplink.eq_ignore_ascii_case(self.name()) {
+                break;
+            }
+
+            name = uplink;
+        }
+
+        self.links.iter().position(|link| link.is(name))
+    }
+
+    /// The link leading to the user, `None` for local users
+    fn user_route(&self, user: &RcUser) -> Option<usize> {
+        user.borrow().server().and_then(|name| self.route(name))
+    }
+}
+
+impl Link {
+    /// Whether the peer is the server `name`
+    fn is(&self, name: &str) -> bool {
+        self.name
+            .as_ref()
+            .map_or(false, |other| other.eq_ignore_ascii_case(name))
+    }
+}
@@ -0,0 +1,1099 @@
+use std::{
+    cell::RefCell,
+    error::Error,
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, TcpListener},
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use native_tls::{Identity, TlsAcceptor};
+
+use crate::{
+    casemapping::CaseMapping,
+    command::{client::Command, server},
+    message::Message,
+    origin::Origin,
+    server::Server,
+    stream::{self, Accept, ClientStream, Handshake, Port, ServerStream},
+    time,
+    user::{RcUser, User},
+};
+
+use self::{
+    account::{AccountStore, FileStore},
+    ban::Ban,
+    config::{ConnectionClass, Limits},
+    history::History,
+    oper::{Class, Oper},
+    sasl::Session,
+};
+
+pub mod account;
+pub mod ban;
+mod cap;
+mod commands;
+pub mod config;
+mod history;
+mod link;
+pub mod oper;
+pub mod reply;
+pub mod sasl;
+mod services;
+
+pub mod error {
+    impl_error!(NoListenerError {});
+    impl_error!(UnknownServerError { name: String });
+    impl_error!(UnknownClassError { name: String });
+    impl_error!(BanFileError { line: usize });
+    impl_error!(AccountFileError { line: usize });
+    impl_error!(ConfigError { key: String, reason: String });
+    impl_error!(NoConfigFileError {});
+}
+
+pub const DEFAULT_NAME: &str = "irc.localhost";
+
+pub const DEFAULT_INFO: &str = "np1th-irc server";
+
+pub const VERSION: &str = concat!("np1th-irc-", env!("CARGO_PKG_VERSION"));
+
+/// Time without any line from a client before it gets pinged
+pub const PING_INTERVAL: Duration = Duration::from_secs(120);
+
+/// Time a pinged client has to answer before it gets dropped
+pub const PING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time `run` sleeps between polls
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct Builder {
+    name: Option<String>,
+    info: Option<String>,
+    addresses: Vec<String>,
+    tls_addresses: Vec<(String, Identity)>,
+    motd: Option<String>,
+    password: Option<String>,
+    casemapping: Option<CaseMapping>,
+    ping_interval: Option<Duration>,
+    ping_timeout: Option<Duration>,
+    links: Vec<(String, String)>,
+    classes: Vec<Class>,
+    opers: Vec<Oper>,
+    ban_file: Option<PathBuf>,
+    limits: Limits,
+    connection_classes: Vec<ConnectionClass>,
+    config_file: Option<PathBuf>,
+    account_file: Option<PathBuf>,
+    accounts: Option<Box<AccountStore>>,
+}
+
+impl Builder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+
+        self
+    }
+
+    /// Description other servers and `LINKS` show
+    pub fn info(mut self, info: &str) -> Self {
+        self.info = Some(info.to_string());
+
+        self
+    }
+
+    /// Plain text listener like `0.0.0.0:6667`, may be called several times
+    pub fn listen(mut self, address: &str) -> Self {
+        self.addresses.push(address.to_string());
+
+        self
+    }
+
+    /// TLS listener like `0.0.0.0:6697`, may be called several times
+    pub fn listen_tls(mut self, address: &str, identity: Identity) -> Self {
+        self.tls_addresses.push((address.to_string(), identity));
+
+        self
+    }
+
+    pub fn motd(mut self, motd: &str) -> Self {
+        self.motd = Some(motd.to_string());
+
+        self
+    }
+
+    /// Connection password clients have to send with `PASS`
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+
+        self
+    }
+
+    pub fn casemapping(mut self, casemapping: CaseMapping) -> Self {
+        self.casemapping = Some(casemapping);
+
+        self
+    }
+
+    pub fn ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = Some(interval);
+
+        self
+    }
+
+    pub fn ping_timeout(mut self, timeout: Duration) -> Self {
+        self.ping_timeout = Some(timeout);
+
+        self
+    }
+
+    /// Allows the server `name` to link, both sides send `password` with `PASS`
+    pub fn link(mut self, name: &str, password: &str) -> Self {
+        self.links.push((name.to_string(), password.to_string()));
+
+        self
+    }
+
+    pub fn class(mut self, class: Class) -> Self {
+        self.classes.push(class);
+
+        self
+    }
+
+    /// Operator block, its class has to be added as well
+    pub fn oper(mut self, oper: Oper) -> Self {
+        self.opers.push(oper);
+
+        self
+    }
+
+    /// File K-lines and D-lines are loaded from and saved to
+    pub fn ban_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.ban_file = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+
+        self
+    }
+
+    /// Ping times and limits for clients from matching hosts, checked in the order added
+    pub fn connection_class(mut self, class: ConnectionClass) -> Self {
+        self.connection_classes.push(class);
+
+        self
+    }
+
+    /// Runs NickServ, ChanServ and SASL with the accounts kept in the file
+    pub fn account_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.account_file = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Runs NickServ, ChanServ and SASL with the accounts of another store, instead of a file
+    pub fn account_store(mut self, store: Box<AccountStore>) -> Self {
+        self.accounts = Some(store);
+
+        self
+    }
+
+    /// Where `Ircd::rehash` reads the configuration from
+    pub fn config_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.config_file = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Binds all listeners and loads the bans and accounts
+    pub fn build(self) -> Result<Ircd, Box<Error>> {
+        check_classes(&self.classes, &self.opers)?;
+
+        let bans = match self.ban_file {
+            Some(ref path) => ban::load(path)?,
+            None => Vec::new(),
+        };
+
+        let casemapping = self.casemapping.unwrap_or_default();
+
+        let accounts = match (self.accounts, self.account_file) {
+            (Some(store), _) => Some(store),
+            (None, Some(path)) => {
+                Some(Box::new(FileStore::open(&path, casemapping)?) as Box<AccountStore>)
+            }
+            (None, None) => None,
+        };
+
+        let mut listeners = Vec::new();
+
+        for address in self.addresses {
+            listeners.push(Listener::bind(&address, None)?);
+        }
+
+        for (address, identity) in self.tls_addresses {
+            listeners.push(Listener::bind(&address, Some(TlsAcceptor::new(identity)?))?);
+        }
+
+        if listeners.is_empty() {
+            return Err(error::NoListenerError::new());
+        }
+
+        let mut server = Server::new(Origin::Server {
+            name: self.name.unwrap_or_else(|| DEFAULT_NAME.to_string()),
+        });
+
+        server.set_motd(self.motd);
+        server.set_casemapping(casemapping);
+        server.set_info(&self.info.unwrap_or_else(|| DEFAULT_INFO.to_string()));
+        server.set_token(1);
+
+        Ok(Ircd {
+            server,
+            listeners,
+            handshakes: Vec::new(),
+            connections: Vec::new(),
+            links: Vec::new(),
+            link_passwords: self.links,
+            next_token: 2,
+            classes: self.classes,
+            opers: self.opers,
+            bans,
+            ban_file: self.ban_file,
+            limits: self.limits,
+            connection_classes: self.connection_classes,
+            config_file: self.config_file,
+            accounts,
+            history: History::new(),
+            password: self.password,
+            ping_interval: self.ping_interval.unwrap_or(PING_INTERVAL),
+            ping_timeout: self.ping_timeout.unwrap_or(PING_TIMEOUT),
+            created: time::now(),
+        })
+    }
+}
+
+fn write_error(e: Box<Error>) -> String {
+    if e.is::<stream::error::SendQueueExceededError>() {
+        "SendQ exceeded".to_string()
+    } else {
+        format!("Write error: {}", e)
+    }
+}
+
+fn check_classes(classes: &[Class], opers: &[Oper]) -> Result<(), Box<Error>> {
+    for oper in opers {
+        if !classes.iter().any(|class| class.name() == oper.class()) {
+            return Err(error::UnknownClassError::new(oper.class().to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+struct Listener {
+    /// Address as configured, to find the listener again when rehashing
+    address: String,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+}
+
+impl Listener {
+    fn bind(address: &str, tls: Option<TlsAcceptor>) -> Result<Self, Box<Error>> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Listener {
+            address: address.to_string(),
+            listener,
+            tls,
+        })
+    }
+}
+
+/// A client connection, registered once `user` is set
+struct Connection {
+    stream: ClientStream,
+    host: String,
+    address: Option<IpAddr>,
+    nick: Option<String>,
+    user_name: Option<(String, String)>,
+    password: Option<String>,
+    user: Option<RcUser>,
+    last_active: Instant,
+    pinged: bool,
+    quit: Option<String>,
+    /// `SERVER` line which turns the connection into a link
+    link: Option<String>,
+    /// Privileges of operators who used `OPER`
+    oper: Option<Class>,
+    /// Name of the connection class
+    class: Option<String>,
+    /// Capabilities enabled with `CAP REQ`
+    caps: Vec<String>,
+    /// Whether registration waits for `CAP END`
+    negotiating: bool,
+    sasl: Option<Session>,
+    /// Account logged into before registering, the user has it afterwards
+    account: Option<String>,
+}
+
+impl Connection {
+    fn new(stream: ClientStream) -> Self {
+        let host = match stream.peer_addr() {
+            // a host starting with `:` would break the prefix
+            Ok(address) if address.is_ipv6() => format!("0{}", address.ip()),
+            Ok(address) => address.ip().to_string(),
+            Err(_) => "unknown".to_string(),
+        };
+
+        let address = stream.peer_addr().ok().map(|address| address.ip());
+
+        Connection {
+            stream,
+            host,
+            address,
+            nick: None,
+            user_name: None,
+            password: None,
+            user: None,
+            last_active: Instant::now(),
+            pinged: false,
+            quit: None,
+            link: None,
+            oper: None,
+            class: None,
+            caps: Vec::new(),
+            negotiating: false,
+            sasl: None,
+            account: None,
+        }
+    }
+
+    fn has_cap(&self, cap: &str) -> bool {
+        self.caps.iter().any(|other| other == cap)
+    }
+}
+
+/// A connection to a neighbouring server, registered once `name` is set
+struct Link {
+    stream: ServerStream,
+    name: Option<String>,
+    password: Option<String>,
+    /// Whether the local server connected and so sent its `PASS` and `SERVER` first
+    outgoing: bool,
+    /// Tokens the peer introduced servers with
+    tokens: Vec<(u32, String)>,
+    last_active: Instant,
+    pinged: bool,
+    quit: Option<String>,
+}
+
+impl Link {
+    fn new(stream: ServerStream, password: Option<String>, outgoing: bool) -> Self {
+        Link {
+            stream,
+            name: None,
+            password,
+            outgoing,
+            tokens: Vec::new(),
+            last_active: Instant::now(),
+            pinged: false,
+            quit: None,
+        }
+    }
+}
+
+/// Single threaded IRC server, `poll` handles whatever happened since the last call
+pub struct Ircd {
+    server: Server,
+    listeners: Vec<Listener>,
+    /// Accepted TLS connections until their handshake is done
+    handshakes: Vec<Handshake>,
+    connections: Vec<Connection>,
+    links: Vec<Link>,
+    link_passwords: Vec<(String, String)>,
+    next_token: u32,
+    classes: Vec<Class>,
+    opers: Vec<Oper>,
+    bans: Vec<Ban>,
+    ban_file: Option<PathBuf>,
+    limits: Limits,
+    connection_classes: Vec<ConnectionClass>,
+    config_file: Option<PathBuf>,
+    /// Accounts and channel registrations, the services only run with a store
+    accounts: Option<Box<AccountStore>>,
+    history: History,
+    password: Option<String>,
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    created: u64,
+}
+
+impl Ircd {
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+
+    /// Builds the server from a TOML configuration, see `config::load`
+    pub fn from_config<P: AsRef<Path>>(path: P) -> Result<Self, Box<Error>> {
+        config::load(path.as_ref())?.build()
+    }
+}
+
+impl Ircd {
+    pub fn server(&self) -> &Server {
+        &self.server
+    }
+
+    pub fn name(&self) -> &str {
+        self.server.name()
+    }
+
+    /// Addresses actually bound, useful with port `0`
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        self.listeners
+            .iter()
+            .filter_map(|l| l.listener.local_addr().ok())
+            .collect()
+    }
+
+    /// Number of connections, registered or not
+    pub fn total(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// K-lines and D-lines in effect
+    pub fn bans(&self) -> &Vec<Ban> {
+        &self.bans
+    }
+
+    /// Links to the server `name`, which has to be allowed with `Builder::link`
+    pub fn connect(&mut self, host: &str, port: Port, name: &str) -> Result<(), Box<Error>> {
+        let password = match self.link_password(name) {
+            Some(password) => password,
+            None => return Err(error::UnknownServerError::new(name.to_string())),
+        };
+
+        let stream = ServerStream::connect(host, port, Some(CONNECT_TIMEOUT))?;
+
+        stream.send(server::Command::pass(&password))?;
+        stream.send(server::Command::Server {
+            name: self.name().to_string(),
+            hop_count: 1,
+            token: self.server.token(),
+            info: self.server.info().to_string(),
+        })?;
+
+        self.links.push(Link::new(stream, None, true));
+
+        Ok(())
+    }
+
+    /// Reloads the configuration file the server was built from. Clients and links stay
+    /// connected, listeners are only bound or closed if their address is new or gone. Nothing
+    /// changes if the configuration has errors.
+    pub fn rehash(&mut self) -> Result<(), Box<Error>> {
+        let path = match self.config_file.clone() {
+            Some(path) => path,
+            None => return Err(error::NoConfigFileError::new()),
+        };
+
+        self.reconfigure(config::load(&path)?)
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<Error>> {
+        loop {
+            self.poll()?;
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Accepts new connections, handles the lines read, pings idle clients and servers, forgets
+    /// expired bans, writes what waits for slow connections and drops the connections gone
+    pub fn poll(&mut self) -> Result<(), Box<Error>> {
+        self.accept();
+        self.read();
+        self.promote();
+        self.read_links();
+        self.ping();
+        self.expire_bans();
+        self.flush();
+        self.reap();
+
+        Ok(())
+    }
+}
+
+impl Ircd {
+    fn accept(&mut self) {
+        let count = self.connections.len();
+
+        let mut accepted = self
+            .handshakes
+            .drain(..)
+            .map(|handshake| handshake.resume())
+            .collect::<Vec<Result<Accept<Command>, Box<Error>>>>();
+
+        for listener in self.listeners.iter() {
+            loop {
+                let tcp_stream = match listener.listener.accept() {
+                    Ok((tcp_stream, _)) => tcp_stream,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => break,
+                };
+
+                accepted.push(ClientStream::accept(tcp_stream, listener.tls.as_ref()));
+            }
+        }
+
+        for result in accepted {
+            match result {
+                Ok(Accept::Done(stream)) => self.connections.push(Connection::new(stream)),
+                Ok(Accept::Handshaking(handshake)) => self.handshakes.push(handshake),
+                // a failed handshake only concerns that client
+                Err(_) => (),
+            }
+        }
+
+        // D-lines keep them from registering
+        for index in count..self.connections.len() {
+            match self.banned(index) {
+                Some(ban) => self.reject(index, &ban),
+                None => self.assign_class(index, true),
+            }
+        }
+    }
+
+    /// Puts the connection into the first matching connection class, with `limit` it has to have
+    /// room for it
+    fn assign_class(&mut self, index: usize, limit: bool) {
+        let host = match self.connections[index].address {
+            Some(address) => address.to_string(),
+            None => self.connections[index].host.clone(),
+        };
+
+        let class = match self
+            .connection_classes
+            .iter()
+            .find(|class| class.matches(&host))
+        {
+            Some(class) => class,
+            None => return self.connections[index].class = None,
+        };
+
+        let members = self
+            .connections
+            .iter()
+            .enumerate()
+            .filter(|(other, connection)| {
+                let name = connection.class.as_ref().map(|name| name.as_str());
+
+                *other != index && name == Some(class.name())
+            })
+            .count();
+
+        if limit && class.max_clients().map_or(false, |max| members >= max) {
+            self.connections[index].quit = Some("Too many connections in this class".to_string());
+
+            return;
+        }
+
+        self.connections[index].class = Some(class.name().to_string());
+    }
+
+    /// Ping interval and timeout of the connection's class
+    fn ping_times(&self, index: usize) -> (Duration, Duration) {
+        let class = self.connections[index].class.as_ref().and_then(|name| {
+            self.connection_classes
+                .iter()
+                .find(|class| class.name() == name)
+        });
+
+        match class {
+            Some(class) => (class.ping_interval(), class.ping_timeout()),
+            None => (self.ping_interval, self.ping_timeout),
+        }
+    }
+
+    /// Takes over everything the builder configures except for the name and the accounts
+    fn reconfigure(&mut self, builder: Builder) -> Result<(), Box<Error>> {
+        let name = builder.name.unwrap_or_else(|| DEFAULT_NAME.to_string());
+
+        if !name.eq_ignore_ascii_case(self.name()) {
+            return Err(error::ConfigError::new(
+                "server.name".to_string(),
+                "can't change without a restart".to_string(),
+            ));
+        }
+
+        check_classes(&builder.classes, &builder.opers)?;
+
+        let bans = match builder.ban_file {
+            Some(ref path) if builder.ban_file != self.ban_file => Some(ban::load(path)?),
+            _ => None,
+        };
+
+        self.rebind(builder.addresses, builder.tls_addresses)?;
+
+        // nothing fails from here on
+        self.server.set_motd(builder.motd);
+        self.server
+            .set_info(&builder.info.unwrap_or_else(|| DEFAULT_INFO.to_string()));
+        self.password = builder.password;
+        self.ping_interval = builder.ping_interval.unwrap_or(PING_INTERVAL);
+        self.ping_timeout = builder.ping_timeout.unwrap_or(PING_TIMEOUT);
+        self.link_passwords = builder.links;
+        self.limits = builder.limits;
+        self.opers = builder.opers;
+        self.classes = builder.classes;
+        self.connection_classes = builder.connection_classes;
+        self.ban_file = builder.ban_file;
+
+        if let Some(bans) = bans {
+            self.bans = bans;
+        }
+
+        // operators keep their status, with the privileges their class has now
+        for connection in self.connections.iter_mut() {
+            if let Some(old) = connection.oper.take() {
+                let class = self.classes.iter().find(|class| class.name() == old.name());
+
+                connection.oper = Some(class.cloned().unwrap_or_else(|| Class::new(old.name())));
+            }
+        }
+
+        for index in 0..self.connections.len() {
+            self.assign_class(index, false);
+        }
+
+        self.enforce_bans();
+
+        Ok(())
+    }
+
+    /// Keeps the listeners of addresses still wanted, with new TLS identities, and binds the
+    /// new ones
+    fn rebind(
+        &mut self,
+        addresses: Vec<String>,
+        tls_addresses: Vec<(String, Identity)>,
+    ) -> Result<(), Box<Error>> {
+        let mut wanted = addresses
+            .into_iter()
+            .map(|address| (address, None))
+            .collect::<Vec<(String, Option<TlsAcceptor>)>>();
+
+        for (address, identity) in tls_addresses {
+            wanted.push((address, Some(TlsAcceptor::new(identity)?)));
+        }
+
+        if wanted.is_empty() {
+            return Err(error::NoListenerError::new());
+        }
+
+        let mut bound = Vec::new();
+        let mut kept = Vec::new();
+
+        for (address, tls) in wanted {
+            if self.listeners.iter().any(|listener| listener.address == address) {
+                kept.push((address, tls));
+            } else {
+                bound.push(Listener::bind(&address, tls)?);
+            }
+        }
+
+        self.listeners
+            .retain(|listener| kept.iter().any(|(address, _)| *address == listener.address));
+
+        for (address, tls) in kept {
+            if let Some(listener) = self
+                .listeners
+                .iter_mut()
+                .find(|listener| listener.address == address)
+            {
+                listener.tls = tls;
+            }
+        }
+
+        self.listeners.extend(bound);
+
+        Ok(())
+    }
+
+    fn read(&mut self) {
+        for index in 0..self.connections.len() {
+            while self.connections[index].quit.is_none() && self.connections[index].link.is_none() {
+                let line = match self.connections[index].stream.read_line() {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let reason = if e.is::<stream::error::ConnectionClosedError>() {
+                            "Connection closed".to_string()
+                        } else {
+                            format!("Read error: {}", e)
+                        };
+
+                        self.connections[index].quit = Some(reason);
+                        break;
+                    }
+                };
+
+                self.connections[index].last_active = Instant::now();
+                self.connections[index].pinged = false;
+
+                self.handle(index, &line);
+            }
+        }
+    }
+
+    /// Turns connections which sent `SERVER` into links
+    fn promote(&mut self) {
+        let mut index = 0;
+
+        while index < self.connections.len() {
+            let line = match self.connections[index].link.take() {
+                Some(line) => line,
+                None => {
+                    index += 1;
+                    continue;
+                }
+            };
+
+            let connection = self.connections.remove(index);
+
+            self.links
+                .push(Link::new(connection.stream.convert(), connection.password, false));
+
+            let link = self.links.len() - 1;
+            self.handle_link(link, &line);
+        }
+    }
+
+    fn read_links(&mut self) {
+        for index in 0..self.links.len() {
+            while self.links[index].quit.is_none() {
+                let line = match self.links[index].stream.read_line() {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        let reason = if e.is::<stream::error::ConnectionClosedError>() {
+                            "Connection closed".to_string()
+                        } else {
+                            format!("Read error: {}", e)
+                        };
+
+                        self.links[index].quit = Some(reason);
+                        break;
+                    }
+                };
+
+                self.links[index].last_active = Instant::now();
+                self.links[index].pinged = false;
+
+                self.handle_link(index, &line);
+            }
+        }
+    }
+
+    fn ping(&mut self) {
+        for index in 0..self.connections.len() {
+            let idle = self.connections[index].last_active.elapsed();
+            let (interval, timeout) = self.ping_times(index);
+
+            if self.connections[index].pinged {
+                if idle >= interval + timeout {
+                    self.connections[index].quit =
+                        Some(format!("Ping timeout: {} seconds", idle.as_secs()));
+                }
+            } else if idle >= interval {
+                self.connections[index].pinged = true;
+                self.send(
+                    index,
+                    Command::Ping {
+                        server1: self.name().to_string(),
+                        server2: None,
+                    },
+                );
+            }
+        }
+
+        for index in 0..self.links.len() {
+            let idle = self.links[index].last_active.elapsed();
+
+            if self.links[index].pinged {
+                if idle >= self.ping_interval + self.ping_timeout {
+                    self.links[index].quit =
+                        Some(format!("Ping timeout: {} seconds", idle.as_secs()));
+                }
+            } else if idle >= self.ping_interval {
+                self.links[index].pinged = true;
+                self.send_link(
+                    index,
+                    server::Command::Ping {
+                        server1: self.name().to_string(),
+                        server2: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Drops connections which don't read what they get
+    fn flush(&mut self) {
+        for connection in self.connections.iter_mut() {
+            if let Err(e) = connection.stream.flush() {
+                connection.quit.get_or_insert(write_error(e));
+            }
+        }
+
+        for link in self.links.iter_mut() {
+            if let Err(e) = link.stream.flush() {
+                link.quit.get_or_insert(write_error(e));
+            }
+        }
+    }
+
+    fn reap(&mut self) {
+        let mut index = 0;
+
+        while index < self.connections.len() {
+            match self.connections[index].quit.clone() {
+                Some(reason) => self.disconnect(index, &reason),
+                None => index += 1,
+            }
+        }
+
+        index = 0;
+
+        while index < self.links.len() {
+            match self.links[index].quit.clone() {
+                Some(reason) => self.split(index, &reason),
+                None => index += 1,
+            }
+        }
+    }
+
+    /// Tells the network about the quit and closes the connection
+    fn disconnect(&mut self, index: usize, reason: &str) {
+        if let Some(user) = self.connections[index].user.clone() {
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                server::Command::Quit {
+                    reason: Some(reason.to_string()),
+                },
+            );
+
+            self.propagate(message, None);
+            self.quit_user(&user, reason);
+        }
+
+        let connection = self.connections.remove(index);
+
+        let _ = connection.stream.send(Command::ErrorMsg {
+            text: format!("Closing Link: {} ({})", connection.host, reason),
+        });
+
+        connection.stream.close();
+    }
+}
+
+impl Ircd {
+    fn send<T>(&self, index: usize, message: T)
+        where
+            T: Into<Message<Command>>,
+    {
+        let mut message = message.into();
+
+        if message.origin().is_connection() {
+            message = Message::new(self.server.origin().clone(), message.command().clone());
+        }
+
+        let message = self.adapt(index, message);
+
+        // a dead or stuck connection gets dropped by `flush`
+        let _ = self.connections[index].stream.send(message);
+    }
+
+    fn send_to(&self, user: &RcUser, message: Message<Command>) {
+        if let Some(index) = self.index_of(user) {
+            self.send(index, message);
+        }
+    }
+
+    fn link_password(&self, name: &str) -> Option<String> {
+        self.link_passwords
+            .iter()
+            .find(|(other, _)| other.eq_ignore_ascii_case(name))
+            .map(|(_, password)| password.clone())
+    }
+
+    /// Sends a numeric reply with the nick (or `*`) in front of the parameters
+    fn numeric(&self, index: usize, code: u16, params: &[&str]) {
+        let connection = &self.connections[index];
+
+        let target = match connection.user {
+            Some(ref user) => user.borrow().origin().nick().unwrap_or_default().to_string(),
+            None => connection.nick.clone().unwrap_or_else(|| "*".to_string()),
+        };
+
+        let mut all = vec![target];
+        all.extend(params.iter().map(|param| param.to_string()));
+
+        self.send(index, Command::Numeric { code, params: all });
+    }
+
+    /// Tells local users sharing a channel with the user about the quit and forgets the user
+    fn quit_user(&mut self, user: &RcUser, reason: &str) {
+        let message = Message::new(
+            user.borrow().origin().clone(),
+            Command::Quit {
+                reason: Some(reason.to_string()),
+            },
+        );
+
+        for peer in self.peers(user) {
+            self.send_to(&peer, message.clone());
+        }
+
+        for channel in user.borrow().channels() {
+            if let Some(channel) = channel.upgrade() {
+                channel.borrow_mut().remove_user(user);
+
+                if channel.borrow().members().is_empty() {
+                    self.server.remove_channel(&channel);
+                }
+            }
+        }
+
+        self.server.remove_user(user);
+    }
+
+    fn index_of(&self, user: &RcUser) -> Option<usize> {
+        self.connections.iter().position(|connection| match connection.user {
+            Some(ref other) => Rc::ptr_eq(other, user),
+            None => false,
+        })
+    }
+
+    /// Users sharing at least one channel with the user, each once
+    fn peers(&self, user: &RcUser) -> Vec<RcUser> {
+        let mut peers: Vec<RcUser> = Vec::new();
+
+        for channel in user.borrow().channels() {
+            if let Some(channel) = channel.upgrade() {
+                for member in channel.borrow().users() {
+                    if let Some(member) = member.upgrade() {
+                        let known = peers.iter().any(|peer| Rc::ptr_eq(peer, &member));
+
+                        if !known && !Rc::ptr_eq(&member, user) {
+                            peers.push(member);
+                        }
+                    }
+                }
+            }
+        }
+
+        peers
+    }
+
+    /// Whether the nick is taken by someone else, registered or not
+    fn nick_in_use(&self, index: usize, nick: &str) -> bool {
+        let casemapping = self.server.casemapping();
+
+        if self.is_service(nick) {
+            return true;
+        }
+
+        if let Some(user) = self.server.user(nick) {
+            return self.index_of(&user) != Some(index);
+        }
+
+        self.connections.iter().enumerate().any(|(other, connection)| {
+            other != index
+                && connection.user.is_none()
+                && connection.nick.as_ref().map_or(false, |n| casemapping.eq(n, nick))
+        })
+    }
+
+    /// Completes the registration once `NICK` and `USER` are known and capability negotiation
+    /// ended
+    fn try_register(&mut self, index: usize) {
+        if self.connections[index].negotiating {
+            return;
+        }
+
+        let (nick, user_name, real_name) = {
+            let connection = &self.connections[index];
+
+            match (&connection.nick, &connection.user_name) {
+                (Some(nick), Some((user_name, real_name))) => {
+                    (nick.clone(), user_name.clone(), real_name.clone())
+                }
+                _ => return,
+            }
+        };
+
+        if self.password.is_some() && self.connections[index].password != self.password {
+            self.numeric(index, reply::ERR_PASSWDMISMATCH, &["Password incorrect"]);
+            self.connections[index].quit = Some("Bad password".to_string());
+
+            return;
+        }
+
+        // another server may have introduced the nick meanwhile
+        if self.server.user(&nick).is_some() {
+            self.numeric(
+                index,
+                reply::ERR_NICKNAMEINUSE,
+                &[&nick, "Nickname is already in use"],
+            );
+            self.connections[index].nick = None;
+
+            return;
+        }
+
+        let origin = Origin::User {
+            nick,
+            user: Some(user_name),
+            host: Some(self.connections[index].host.clone()),
+        };
+
+        let user = Rc::new(RefCell::new(User::new(origin, &real_name)));
+        user.borrow_mut()
+            .set_account(self.connections[index].account.clone());
+
+        self.connections[index].user = Some(user.clone());
+
+        if let Some(ban) = self.banned(index) {
+            self.connections[index].user = None;
+
+            return self.reject(index, &ban);
+        }
+
+        self.server.users_mut().push(user.clone());
+
+        let introduction = server::introduce(&self.server, &user.borrow());
+        self.propagate(Message::from(introduction), None);
+
+        if let Some(account) = user.borrow().account() {
+            let message = Message::new(
+                user.borrow().origin().clone(),
+                server::Command::Account {
+                    account: Some(account.to_string()),
+                },
+            );
+
+            self.propagate(message, None);
+        }
+
+        self.welcome(index);
+    }
+}
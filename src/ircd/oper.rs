@@ -0,0 +1,553 @@
+use std::time::Duration;
+
+use super::{
+    ban::{self, Ban, Kind},
+    reply,
+    sasl::Credentials,
+    Ircd,
+};
+use crate::{
+    casemapping::CaseMapping,
+    command::{client::Command, server},
+    message::Message,
+    mode::{self, user},
+    origin::{Mask, Origin},
+    target::MessageTarget,
+    time,
+    user::RcUser,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Privilege {
+    /// `KILL`
+    Kill,
+    /// `KLINE`, `DLINE` and their removals
+    Ban,
+    /// `REHASH`
+    Rehash,
+}
+
+impl Privilege {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "kill" => Some(Privilege::Kill),
+            "ban" => Some(Privilege::Ban),
+            "rehash" => Some(Privilege::Rehash),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Privilege::Kill => "kill",
+            Privilege::Ban => "ban",
+            Privilege::Rehash => "rehash",
+        }
+    }
+}
+
+/// Named set of privileges operators are given
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    name: String,
+    privileges: Vec<Privilege>,
+}
+
+impl Class {
+    pub fn new(name: &str) -> Self {
+        Class {
+            name: name.to_string(),
+            privileges: Vec::new(),
+        }
+    }
+
+    pub fn privilege(mut self, privilege: Privilege) -> Self {
+        self.privileges.push(privilege);
+
+        self
+    }
+}
+
+impl Class {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn privileges(&self) -> &Vec<Privilege> {
+        &self.privileges
+    }
+
+    pub fn has(&self, privilege: Privilege) -> bool {
+        self.privileges.contains(&privilege)
+    }
+}
+
+/// Operator block `OPER` checks against, without hosts nobody can use it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Oper {
+    name: String,
+    password: String,
+    hosts: Vec<String>,
+    class: String,
+}
+
+impl Oper {
+    /// `password` is a hash made by `hash_password`
+    pub fn new(name: &str, password: &str, class: &str) -> Self {
+        Oper {
+            name: name.to_string(),
+            password: password.to_string(),
+            hosts: Vec::new(),
+            class: class.to_string(),
+        }
+    }
+
+    /// Allows `user@host` masks like `*@192.0.2.0/24`
+    pub fn host(mut self, mask: &str) -> Self {
+        self.hosts.push(mask.to_string());
+
+        self
+    }
+}
+
+impl Oper {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn hosts(&self) -> &Vec<String> {
+        &self.hosts
+    }
+
+    pub fn class(&self) -> &str {
+        &self.class
+    }
+
+    pub fn allows(&self, origin: &Origin, mapping: CaseMapping) -> bool {
+        self.hosts.iter().any(|host| {
+            Mask::new(&format!("*!{}", host))
+                .casemapping(mapping)
+                .matches(origin)
+        })
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        verify_password(password, &self.password)
+    }
+}
+
+/// Salted PBKDF2 as account passwords are kept, see `Credentials`, for oper blocks
+pub fn hash_password(password: &str) -> String {
+    Credentials::new(password).to_string()
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    Credentials::parse(hash).is_some_and(|credentials| credentials.verify(password))
+}
+
+impl Ircd {
+    pub(super) fn oper(&mut self, index: usize, user: &RcUser, name: &str, password: &str) {
+        let origin = user.borrow().origin().clone();
+        let mapping = self.server.casemapping();
+
+        let oper = match self
+            .opers
+            .iter()
+            .find(|oper| oper.name == name && oper.allows(&origin, mapping))
+        {
+            Some(oper) => oper.clone(),
+            None => {
+                return self.numeric(index, reply::ERR_NOOPERHOST, &["No O-lines for your host"])
+            }
+        };
+
+        if !oper.verify(password) {
+            return self.numeric(index, reply::ERR_PASSWDMISMATCH, &["Password incorrect"]);
+        }
+
+        self.connections[index].oper = self
+            .classes
+            .iter()
+            .find(|class| class.name == oper.class)
+            .cloned();
+
+        self.numeric(
+            index,
+            reply::RPL_YOUREOPER,
+            &["You are now an IRC operator"],
+        );
+
+        if user.borrow().has_mode(&user::Mode::Operator) {
+            return;
+        }
+
+        let granted = mode::Mode::new(true, user::Mode::Operator);
+        user.borrow_mut().apply_mode(&granted);
+
+        let message = Message::new(
+            origin.clone(),
+            Command::UMode {
+                name: origin.nick().unwrap_or_default().to_string(),
+                modes: vec![granted],
+            },
+        );
+
+        self.announce(&message);
+        self.send(index, message);
+    }
+
+    /// Whether the user is an operator with the privilege, tells the user if not
+    fn privileged(&self, index: usize, privilege: Privilege) -> bool {
+        match self.connections[index].oper {
+            Some(ref class) if class.has(privilege) => true,
+            Some(_) => {
+                self.numeric(
+                    index,
+                    reply::ERR_NOPRIVS,
+                    &[privilege.name(), "Insufficient oper privileges."],
+                );
+
+                false
+            }
+            None => {
+                self.numeric(
+                    index,
+                    reply::ERR_NOPRIVILEGES,
+                    &["Permission Denied- You're not an IRC operator"],
+                );
+
+                false
+            }
+        }
+    }
+
+    pub(super) fn kill_user(&mut self, index: usize, user: &RcUser, nick: &str, reason: &str) {
+        if !self.privileged(index, Privilege::Kill) {
+            return;
+        }
+
+        let target = match self.server.user(nick) {
+            Some(target) => target,
+            None => {
+                return self.numeric(
+                    index,
+                    reply::ERR_NOSUCHNICK,
+                    &[nick, "No such nick/channel"],
+                )
+            }
+        };
+
+        let origin = user.borrow().origin().clone();
+        let nick = target
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        let message = Message::new(
+            origin.clone(),
+            server::Command::Kill {
+                user: nick,
+                comment: reason.to_string(),
+            },
+        );
+
+        self.propagate(message, None);
+        self.kill(&target, origin.nick().unwrap_or_default(), reason);
+    }
+
+    pub(super) fn add_ban(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        kind: Kind,
+        duration: Option<u64>,
+        mask: &str,
+        reason: &str,
+    ) {
+        if !self.privileged(index, Privilege::Ban) {
+            return;
+        }
+
+        let mask = match kind {
+            Kind::KLine if !mask.contains('@') => format!("*@{}", mask),
+            _ => mask.to_string(),
+        };
+
+        if kind == Kind::DLine && !ban::is_address_mask(&mask) {
+            return self.notice(index, &format!("Invalid D-Line [{}]", mask));
+        }
+
+        let existing = self
+            .bans
+            .iter()
+            .find(|ban| ban.kind() == kind && ban.mask().eq_ignore_ascii_case(&mask))
+            .map(|ban| ban.reason().to_string());
+
+        if let Some(existing) = existing {
+            return self.notice(
+                index,
+                &format!(
+                    "[{}] already {}-Lined by [{}]",
+                    mask,
+                    kind.letter(),
+                    existing
+                ),
+            );
+        }
+
+        let reason = if reason.is_empty() {
+            "No reason"
+        } else {
+            reason
+        };
+        let setter = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        let mut ban = Ban::new(kind, &mask, reason, &setter);
+
+        let text = match duration.filter(|minutes| *minutes > 0) {
+            Some(minutes) => {
+                ban = ban.duration(Duration::from_secs(minutes * 60));
+
+                format!(
+                    "Added temporary {} min. {}-Line [{}]",
+                    minutes,
+                    kind.letter(),
+                    mask
+                )
+            }
+            None => format!("Added {}-Line [{}]", kind.letter(), mask),
+        };
+
+        self.bans.push(ban);
+        self.save_bans(index);
+        self.notice(index, &text);
+        self.enforce_bans();
+    }
+
+    pub(super) fn remove_ban(&mut self, index: usize, kind: Kind, mask: &str) {
+        if !self.privileged(index, Privilege::Ban) {
+            return;
+        }
+
+        let mask = match kind {
+            Kind::KLine if !mask.contains('@') => format!("*@{}", mask),
+            _ => mask.to_string(),
+        };
+
+        let count = self.bans.len();
+
+        self.bans
+            .retain(|ban| !(ban.kind() == kind && ban.mask().eq_ignore_ascii_case(&mask)));
+
+        if self.bans.len() == count {
+            return self.notice(
+                index,
+                &format!("No {}-Line for [{}] found", kind.letter(), mask),
+            );
+        }
+
+        self.save_bans(index);
+        self.notice(
+            index,
+            &format!("{}-Line for [{}] is removed", kind.letter(), mask),
+        );
+    }
+
+    pub(super) fn rehash_command(&mut self, index: usize) {
+        if !self.privileged(index, Privilege::Rehash) {
+            return;
+        }
+
+        let file = match self.config_file {
+            Some(ref path) => path.display().to_string(),
+            None => return self.notice(index, "There is no configuration file to rehash"),
+        };
+
+        self.numeric(index, reply::RPL_REHASHING, &[&file, "Rehashing"]);
+
+        if let Err(e) = self.rehash() {
+            self.notice(index, &format!("Rehash failed, keeping the configuration: {}", e));
+        }
+    }
+
+    pub(super) fn stats(&mut self, index: usize, query: Option<String>) {
+        let query = match query.as_ref().and_then(|query| query.chars().next()) {
+            Some(query) => query,
+            None => {
+                return self.numeric(
+                    index,
+                    reply::ERR_NEEDMOREPARAMS,
+                    &["STATS", "Not enough parameters"],
+                )
+            }
+        };
+
+        let restricted = "kKdDoO".contains(query);
+
+        if restricted && self.connections[index].oper.is_none() {
+            return self.numeric(
+                index,
+                reply::ERR_NOPRIVILEGES,
+                &["Permission Denied- You're not an IRC operator"],
+            );
+        }
+
+        match query {
+            'k' | 'K' | 'd' | 'D' => {
+                let kind = match query {
+                    'k' | 'K' => Kind::KLine,
+                    _ => Kind::DLine,
+                };
+
+                for ban in self.bans.iter().filter(|ban| ban.kind() == kind) {
+                    // lower case letters for temporary bans
+                    let letter = match ban.expires() {
+                        Some(_) => kind.letter().to_ascii_lowercase(),
+                        None => kind.letter(),
+                    }
+                    .to_string();
+
+                    match kind {
+                        Kind::KLine => {
+                            let (user, host) = match ban.mask().rfind('@') {
+                                Some(pos) => (&ban.mask()[..pos], &ban.mask()[pos + 1..]),
+                                None => ("*", ban.mask()),
+                            };
+
+                            self.numeric(
+                                index,
+                                reply::RPL_STATSKLINE,
+                                &[&letter, host, "*", user, ban.reason()],
+                            );
+                        }
+                        Kind::DLine => self.numeric(
+                            index,
+                            reply::RPL_STATSDLINE,
+                            &[&letter, ban.mask(), ban.reason()],
+                        ),
+                    }
+                }
+            }
+            'o' | 'O' => {
+                for oper in self.opers.iter() {
+                    for host in oper.hosts() {
+                        self.numeric(
+                            index,
+                            reply::RPL_STATSOLINE,
+                            &["O", host, "*", oper.name(), oper.class()],
+                        );
+                    }
+                }
+            }
+            'u' | 'U' => {
+                let up = time::now().saturating_sub(self.created);
+
+                self.numeric(
+                    index,
+                    reply::RPL_STATSUPTIME,
+                    &[&format!(
+                        "Server Up {} days {}:{:02}:{:02}",
+                        up / 86400,
+                        up % 86400 / 3600,
+                        up % 3600 / 60,
+                        up % 60
+                    )],
+                );
+            }
+            _ => (),
+        }
+
+        self.numeric(
+            index,
+            reply::RPL_ENDOFSTATS,
+            &[&query.to_string(), "End of STATS report"],
+        );
+    }
+
+    /// Drops connections the bans match
+    pub(super) fn enforce_bans(&mut self) {
+        for index in 0..self.connections.len() {
+            if self.connections[index].quit.is_some() {
+                continue;
+            }
+
+            if let Some(ban) = self.banned(index) {
+                self.reject(index, &ban);
+            }
+        }
+    }
+
+    /// The first ban matching the connection, K-lines only match registered ones
+    pub(super) fn banned(&self, index: usize) -> Option<Ban> {
+        let connection = &self.connections[index];
+        let user = connection.user.as_ref().map(|user| user.borrow());
+        let origin = user.as_ref().map(|user| user.origin());
+
+        self.bans
+            .iter()
+            .find(|ban| ban.matches(origin, connection.address, self.server.casemapping()))
+            .cloned()
+    }
+
+    pub(super) fn reject(&mut self, index: usize, ban: &Ban) {
+        self.numeric(
+            index,
+            reply::ERR_YOUREBANNEDCREEP,
+            &[&format!(
+                "You are banned from this server- {}",
+                ban.reason()
+            )],
+        );
+        self.connections[index].quit = Some(format!("{}-Lined", ban.kind().letter()));
+    }
+
+    /// Forgets expired bans
+    pub(super) fn expire_bans(&mut self) {
+        let now = time::now();
+        let count = self.bans.len();
+
+        self.bans.retain(|ban| !ban.is_expired(now));
+
+        if self.bans.len() != count {
+            if let Some(ref path) = self.ban_file {
+                let _ = ban::save(path, &self.bans);
+            }
+        }
+    }
+
+    /// Writes the bans to the ban file, telling the operator about failures
+    fn save_bans(&self, index: usize) {
+        if let Some(ref path) = self.ban_file {
+            if let Err(e) = ban::save(path, &self.bans) {
+                self.notice(index, &format!("Couldn't save bans: {}", e));
+            }
+        }
+    }
+
+    fn notice(&self, index: usize, text: &str) {
+        let nick = match self.connections[index].user {
+            Some(ref user) => user
+                .borrow()
+                .origin()
+                .nick()
+                .unwrap_or_default()
+                .to_string(),
+            None => "*".to_string(),
+        };
+
+        self.send(
+            index,
+            Command::Notice {
+                target: MessageTarget::Nick(nick),
+                text: text.to_string(),
+            },
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{account::AccountStore, reply, Ircd};
+use crate::{casemapping::CaseMapping, command::client::Command, time};
+
+/// Mechanisms `AUTHENTICATE` accepts, as advertised with the `sasl` capability
+pub const MECHANISMS: &str = "PLAIN,SCRAM-SHA-256";
+
+/// PBKDF2 iterations for new SCRAM credentials, the minimum RFC 7677 asks for
+pub const ITERATIONS: u32 = 4096;
+
+/// Longest base64 chunk in an `AUTHENTICATE` line, a chunk this long means more follow
+const CHUNK_LENGTH: usize = 400;
+
+/// Longest response a client may send, in base64
+const RESPONSE_LENGTH: usize = 8 * CHUNK_LENGTH;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Block size of SHA-256, for HMAC
+const BLOCK_SIZE: usize = 64;
+
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .chain([0, 0].iter())
+            .take(3)
+            .fold(0, |bits, byte| bits << 8 | u32::from(*byte));
+
+        for position in 0..4 {
+            if position <= chunk.len() {
+                encoded.push(BASE64[(bits >> (18 - 6 * position) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+pub fn decode_base64(data: &str) -> Option<Vec<u8>> {
+    // padding only ends the data
+    if data.len() % 4 != 0 || data.trim_end_matches('=').contains('=') {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(data.len() / 4 * 3);
+
+    for chunk in data.as_bytes().chunks(4) {
+        let padding = chunk.iter().filter(|byte| **byte == b'=').count();
+
+        if padding > 2 {
+            return None;
+        }
+
+        let mut bits = 0;
+
+        for byte in chunk {
+            let value = match BASE64.iter().position(|other| other == byte) {
+                Some(value) => value as u32,
+                None if *byte == b'=' => 0,
+                None => return None,
+            };
+
+            bits = bits << 6 | value;
+        }
+
+        decoded
+            .extend_from_slice(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8][..3 - padding]);
+    }
+
+    Some(decoded)
+}
+
+/// HMAC-SHA-256 (RFC 2104)
+pub fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut block = [0; BLOCK_SIZE];
+
+    if key.len() > BLOCK_SIZE {
+        let digest = Sha256::digest(key);
+        block[..digest.len()].copy_from_slice(&digest);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.iter().map(|byte| byte ^ 0x36).collect::<Vec<u8>>());
+    inner.update(data);
+
+    let mut outer = Sha256::new();
+    outer.update(block.iter().map(|byte| byte ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(inner.finalize());
+
+    outer.finalize().to_vec()
+}
+
+/// PBKDF2 with HMAC-SHA-256 (RFC 8018), deriving a single block as SCRAM needs
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+
+    let mut previous = hmac(password, &block);
+    let mut result = previous.clone();
+
+    for _ in 1..iterations {
+        previous = hmac(password, &previous);
+
+        for (byte, other) in result.iter_mut().zip(previous.iter()) {
+            *byte ^= other;
+        }
+    }
+
+    result
+}
+
+/// Bytes for salts and nonces
+fn random_bytes(count: usize) -> Vec<u8> {
+    let state = RandomState::new();
+
+    (0..count / 8 + 1)
+        .flat_map(|round| {
+            let mut hasher = state.build_hasher();
+            hasher.write_u64(time::now());
+            hasher.write_usize(round);
+
+            hasher.finish().to_le_bytes().to_vec()
+        })
+        .take(count)
+        .collect()
+}
+
+/// The same time for every wrong guess
+fn equal(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .zip(b.iter())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+/// What SCRAM-SHA-256 needs to check a password without knowing it (RFC 5802), PLAIN is
+/// checked against it as well
+#[derive(Debug, Clone, PartialEq)]
+pub struct Credentials {
+    salt: Vec<u8>,
+    iterations: u32,
+    stored_key: Vec<u8>,
+    server_key: Vec<u8>,
+}
+
+impl Credentials {
+    /// Credentials with a random salt
+    pub fn new(password: &str) -> Self {
+        Credentials::with_salt(password, &random_bytes(16), ITERATIONS)
+    }
+
+    pub fn with_salt(password: &str, salt: &[u8], iterations: u32) -> Self {
+        let salted_password = pbkdf2(password.as_bytes(), salt, iterations);
+        let client_key = hmac(&salted_password, b"Client Key");
+
+        Credentials {
+            salt: salt.to_vec(),
+            iterations,
+            stored_key: Sha256::digest(&client_key).to_vec(),
+            server_key: hmac(&salted_password, b"Server Key"),
+        }
+    }
+
+    /// Reads `iterations:salt:stored key:server key` as `to_string` writes it
+    pub fn parse(data: &str) -> Option<Self> {
+        let fields = data.split(':').collect::<Vec<&str>>();
+
+        if fields.len() != 4 {
+            return None;
+        }
+
+        Some(Credentials {
+            iterations: fields[0]
+                .parse()
+                .ok()
+                .filter(|iterations| *iterations > 0)?,
+            salt: decode_base64(fields[1])?,
+            stored_key: decode_base64(fields[2])?,
+            server_key: decode_base64(fields[3])?,
+        })
+    }
+}
+
+impl Credentials {
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    pub fn verify(&self, password: &str) -> bool {
+        let other = Credentials::with_salt(password, &self.salt, self.iterations);
+
+        equal(&other.stored_key, &self.stored_key)
+    }
+}
+
+impl ToString for Credentials {
+    fn to_string(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            self.iterations,
+            encode_base64(&self.salt),
+            encode_base64(&self.stored_key),
+            encode_base64(&self.server_key)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mechanism {
+    Plain,
+    ScramSha256,
+}
+
+impl Mechanism {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "PLAIN" => Some(Mechanism::Plain),
+            "SCRAM-SHA-256" => Some(Mechanism::ScramSha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
+        }
+    }
+}
+
+/// What the server does with a complete response
+enum Step {
+    /// Sends the challenge and waits for the next response
+    Challenge(Vec<u8>),
+    /// Logs the client into the account
+    Success(String),
+    Failure,
+}
+
+enum State {
+    Start,
+    /// SCRAM after the server-first-message, the authentication message so far is the
+    /// client-first-message-bare and the server-first-message
+    Proof {
+        account: String,
+        credentials: Credentials,
+        gs2_header: String,
+        nonce: String,
+        auth_message: String,
+    },
+    /// SCRAM after the server-final-message, waiting for the empty response
+    Verified {
+        account: String,
+    },
+}
+
+/// SASL exchange of a connection
+pub(super) struct Session {
+    mechanism: Mechanism,
+    state: State,
+    /// base64 chunks of the response received so far
+    response: String,
+}
+
+impl Session {
+    fn new(mechanism: Mechanism) -> Self {
+        Session {
+            mechanism,
+            state: State::Start,
+            response: String::new(),
+        }
+    }
+
+    fn step(&mut self, response: &[u8], store: &AccountStore, mapping: CaseMapping) -> Step {
+        let response = match String::from_utf8(response.to_vec()) {
+            Ok(response) => response,
+            Err(_) => return Step::Failure,
+        };
+
+        let state = std::mem::replace(&mut self.state, State::Start);
+
+        match (self.mechanism, state) {
+            (Mechanism::Plain, State::Start) => plain(&response, store, mapping),
+            (Mechanism::ScramSha256, State::Start) => self.client_first(&response, store),
+            (
+                Mechanism::ScramSha256,
+                State::Proof {
+                    account,
+                    credentials,
+                    gs2_header,
+                    nonce,
+                    auth_message,
+                },
+            ) => {
+                let proof = Proof {
+                    credentials: &credentials,
+                    gs2_header: &gs2_header,
+                    nonce: &nonce,
+                    auth_message: &auth_message,
+                };
+
+                match proof.verify(&response) {
+                    Some(signature) => {
+                        self.state = State::Verified { account };
+
+                        Step::Challenge(format!("v={}", encode_base64(&signature)).into_bytes())
+                    }
+                    None => Step::Failure,
+                }
+            }
+            (_, State::Verified { account }) if response.is_empty() => Step::Success(account),
+            _ => Step::Failure,
+        }
+    }
+
+    /// Answers `n,,n=user,r=nonce` with the salt, iterations and nonce of the server
+    fn client_first(&mut self, message: &str, store: &AccountStore) -> Step {
+        let mut parts = message.splitn(3, ',');
+
+        // channel binding isn't supported
+        let binding = parts.next().unwrap_or_default();
+        let authorization = parts.next().unwrap_or_default();
+        let bare = match parts.next() {
+            Some(bare) if binding == "n" || binding == "y" => bare,
+            _ => return Step::Failure,
+        };
+
+        let gs2_header = &message[..message.len() - bare.len()];
+
+        let (name, client_nonce) = match (attribute(bare, 'n'), attribute(bare, 'r')) {
+            (Some(name), Some(nonce)) if attribute(bare, 'm').is_none() => (unescape(name), nonce),
+            _ => return Step::Failure,
+        };
+
+        if !authorization.is_empty() && authorization != format!("a={}", name) {
+            return Step::Failure;
+        }
+
+        let account = match store.account(&name) {
+            Some(account) => account,
+            None => return Step::Failure,
+        };
+
+        let credentials = account.credentials().clone();
+        let nonce = format!("{}{}", client_nonce, encode_base64(&random_bytes(18)));
+        let server_first = format!(
+            "r={},s={},i={}",
+            nonce,
+            encode_base64(credentials.salt()),
+            credentials.iterations()
+        );
+
+        self.state = State::Proof {
+            account: account.name().to_string(),
+            credentials,
+            gs2_header: gs2_header.to_string(),
+            nonce,
+            auth_message: format!("{},{}", bare, server_first),
+        };
+
+        Step::Challenge(server_first.into_bytes())
+    }
+}
+
+/// `authzid\0authcid\0password`, the authorization identity has to be empty or the account
+fn plain(response: &str, store: &AccountStore, mapping: CaseMapping) -> Step {
+    let fields = response.split('\0').collect::<Vec<&str>>();
+
+    if fields.len() != 3 || (!fields[0].is_empty() && !mapping.eq(fields[0], fields[1])) {
+        return Step::Failure;
+    }
+
+    match store.account(fields[1]) {
+        Some(ref account) if account.verify(fields[2]) => Step::Success(account.name().to_string()),
+        _ => Step::Failure,
+    }
+}
+
+/// SCRAM state needed to check the client-final-message
+struct Proof<'a> {
+    credentials: &'a Credentials,
+    gs2_header: &'a str,
+    nonce: &'a str,
+    auth_message: &'a str,
+}
+
+impl<'a> Proof<'a> {
+    /// Checks `c=biws,r=nonce,p=proof` and returns the server signature
+    fn verify(&self, message: &str) -> Option<Vec<u8>> {
+        let position = message.rfind(",p=")?;
+        let without_proof = &message[..position];
+        let proof = decode_base64(&message[position + 3..])?;
+
+        if attribute(without_proof, 'c')? != encode_base64(self.gs2_header.as_bytes())
+            || attribute(without_proof, 'r')? != self.nonce
+        {
+            return None;
+        }
+
+        let auth_message = format!("{},{}", self.auth_message, without_proof);
+        let signature = hmac(&self.credentials.stored_key, auth_message.as_bytes());
+
+        if proof.len() != signature.len() {
+            return None;
+        }
+
+        let client_key = proof
+            .iter()
+            .zip(signature.iter())
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<u8>>();
+
+        if !equal(&Sha256::digest(&client_key), &self.credentials.stored_key) {
+            return None;
+        }
+
+        Some(hmac(&self.credentials.server_key, auth_message.as_bytes()))
+    }
+}
+
+/// Value of `key=value` in a SCRAM message
+fn attribute(message: &str, key: char) -> Option<&str> {
+    message
+        .split(',')
+        .find(|attribute| attribute.starts_with(key) && attribute[1..].starts_with('='))
+        .map(|attribute| &attribute[2..])
+}
+
+/// Undoes the escaping of `,` and `=` in SCRAM user names
+fn unescape(name: &str) -> String {
+    name.replace("=2C", ",").replace("=3D", "=")
+}
+
+impl Ircd {
+    pub(super) fn authenticate(&mut self, index: usize, data: &str) {
+        if self.accounts.is_none() {
+            return self.numeric(index, reply::ERR_SASLFAIL, &["SASL authentication failed"]);
+        }
+
+        if self.account_of(index).is_some() {
+            return self.numeric(
+                index,
+                reply::ERR_SASLALREADY,
+                &["You have already authenticated using SASL"],
+            );
+        }
+
+        let mut session = match self.connections[index].sasl.take() {
+            Some(session) => session,
+            None => {
+                match Mechanism::parse(data) {
+                    Some(mechanism) => {
+                        self.connections[index].sasl = Some(Session::new(mechanism));
+                        self.send_authenticate(index, &[]);
+                    }
+                    None => {
+                        self.numeric(
+                            index,
+                            reply::RPL_SASLMECHS,
+                            &[MECHANISMS, "are available SASL mechanisms"],
+                        );
+                        self.numeric(index, reply::ERR_SASLFAIL, &["SASL authentication failed"]);
+                    }
+                }
+
+                return;
+            }
+        };
+
+        if data == "*" {
+            return self.numeric(
+                index,
+                reply::ERR_SASLABORTED,
+                &["SASL authentication aborted"],
+            );
+        }
+
+        if data != "+" {
+            session.response.push_str(data);
+        }
+
+        if data.len() > CHUNK_LENGTH || session.response.len() > RESPONSE_LENGTH {
+            return self.numeric(index, reply::ERR_SASLTOOLONG, &["SASL message too long"]);
+        }
+
+        if data.len() == CHUNK_LENGTH {
+            self.connections[index].sasl = Some(session);
+
+            return;
+        }
+
+        let response = std::mem::take(&mut session.response);
+        let mapping = self.server.casemapping();
+
+        let step = match (decode_base64(&response), self.accounts.as_ref()) {
+            (Some(response), Some(store)) => session.step(&response, store.as_ref(), mapping),
+            _ => Step::Failure,
+        };
+
+        match step {
+            Step::Challenge(challenge) => {
+                self.send_authenticate(index, &challenge);
+                self.connections[index].sasl = Some(session);
+            }
+            Step::Success(account) => {
+                self.log_in(index, &account);
+                self.numeric(
+                    index,
+                    reply::RPL_SASLSUCCESS,
+                    &["SASL authentication successful"],
+                );
+            }
+            Step::Failure => {
+                self.numeric(index, reply::ERR_SASLFAIL, &["SASL authentication failed"])
+            }
+        }
+    }
+
+    /// Sends the data base64 encoded in chunks, ending with `+` if the last one is full
+    fn send_authenticate(&self, index: usize, data: &[u8]) {
+        let encoded = encode_base64(data);
+        let mut rest = encoded.as_str();
+
+        loop {
+            let (chunk, more) = rest.split_at(rest.len().min(CHUNK_LENGTH));
+            let data = if chunk.is_empty() { "+" } else { chunk };
+
+            self.send(
+                index,
+                Command::Authenticate {
+                    data: data.to_string(),
+                },
+            );
+
+            if chunk.len() < CHUNK_LENGTH {
+                break;
+            }
+
+            rest = more;
+        }
+    }
+}
@@ -0,0 +1,589 @@
+use std::rc::Rc;
+
+use super::{
+    account::{Access, Account, Registration},
+    reply, Ircd,
+};
+use crate::{
+    channel::RcChannel,
+    command::{client::Command, server},
+    message::Message,
+    mode::{self, channel},
+    origin::Origin,
+    target::MessageTarget,
+    user::RcUser,
+};
+
+pub const NICKSERV: &str = "NickServ";
+
+pub const CHANSERV: &str = "ChanServ";
+
+impl Ircd {
+    /// Whether the nick is one of the services, which only exist with an account store
+    pub(super) fn is_service(&self, nick: &str) -> bool {
+        let mapping = self.server.casemapping();
+
+        self.accounts.is_some() && (mapping.eq(nick, NICKSERV) || mapping.eq(nick, CHANSERV))
+    }
+
+    /// Runs a command sent to a service with `PRIVMSG`
+    pub(super) fn service_message(
+        &mut self,
+        index: usize,
+        user: &RcUser,
+        service: &str,
+        text: &str,
+    ) {
+        let words = text.split_whitespace().collect::<Vec<&str>>();
+        let command = words
+            .first()
+            .map(|word| word.to_ascii_uppercase())
+            .unwrap_or_default();
+
+        if self.server.casemapping().eq(service, NICKSERV) {
+            match command.as_str() {
+                "REGISTER" if words.len() == 2 => self.register_nick(index, user, words[1]),
+                "IDENTIFY" if words.len() == 2 || words.len() == 3 => {
+                    let nick = user
+                        .borrow()
+                        .origin()
+                        .nick()
+                        .unwrap_or_default()
+                        .to_string();
+                    let name = if words.len() == 3 { words[1] } else { &nick };
+
+                    self.identify(index, user, name, words[words.len() - 1])
+                }
+                "LOGOUT" => {
+                    let logged_in = user.borrow().account().is_some();
+
+                    if logged_in {
+                        self.log_out(index)
+                    } else {
+                        self.service_notice(index, NICKSERV, "You aren't logged in")
+                    }
+                }
+                "GHOST" if words.len() == 2 || words.len() == 3 => {
+                    self.ghost(index, user, words[1], words.get(2).cloned())
+                }
+                _ => self.service_notice(
+                    index,
+                    NICKSERV,
+                    "Commands: REGISTER <password>, IDENTIFY [account] <password>, LOGOUT, GHOST \
+                     <nick> [password]",
+                ),
+            }
+        } else {
+            match command.as_str() {
+                "REGISTER" if words.len() == 2 => self.register_channel(index, user, words[1]),
+                "ACCESS" if words.len() >= 3 => self.access(index, user, words[1], &words[2..]),
+                "DROP" if words.len() == 2 => self.drop_channel(index, user, words[1]),
+                _ => self.service_notice(
+                    index,
+                    CHANSERV,
+                    "Commands: REGISTER <channel>, ACCESS <channel> ADD <account> <op|voice>, \
+                     ACCESS <channel> DEL <account>, ACCESS <channel> LIST, DROP <channel>",
+                ),
+            }
+        }
+    }
+
+    /// Account the connection is logged into
+    pub(super) fn account_of(&self, index: usize) -> Option<String> {
+        let connection = &self.connections[index];
+
+        match connection.user {
+            Some(ref user) => user.borrow().account().map(|account| account.to_string()),
+            None => connection.account.clone(),
+        }
+    }
+
+    /// Logs the connection into the account, a user who registered already gets the status the
+    /// access lists give in the channels joined
+    pub(super) fn log_in(&mut self, index: usize, account: &str) {
+        let mask = self.mask(index);
+
+        self.numeric(
+            index,
+            reply::RPL_LOGGEDIN,
+            &[
+                &mask,
+                account,
+                &format!("You are now logged in as {}", account),
+            ],
+        );
+
+        let user = match self.connections[index].user.clone() {
+            Some(user) => user,
+            None => return self.connections[index].account = Some(account.to_string()),
+        };
+
+        user.borrow_mut().set_account(Some(account.to_string()));
+        self.account_changed(&user);
+
+        let channels = user.borrow().channels();
+
+        for channel in channels {
+            if let Some(channel) = channel.upgrade() {
+                self.give_access(&channel, &user);
+            }
+        }
+    }
+
+    pub(super) fn log_out(&mut self, index: usize) {
+        let mask = self.mask(index);
+
+        self.numeric(
+            index,
+            reply::RPL_LOGGEDOUT,
+            &[&mask, "You are now logged out"],
+        );
+
+        if let Some(user) = self.connections[index].user.clone() {
+            user.borrow_mut().set_account(None);
+            self.account_changed(&user);
+        }
+    }
+
+    /// Tells the network and local users asking for `account-notify` about a login or logout
+    fn account_changed(&self, user: &RcUser) {
+        let message = Message::new(
+            user.borrow().origin().clone(),
+            Command::Account {
+                account: user.borrow().account().map(|account| account.to_string()),
+            },
+        );
+
+        self.announce(&message);
+        self.notify_account(user, message);
+    }
+
+    /// Sends `ACCOUNT` to the user and everyone sharing a channel with it which enabled
+    /// `account-notify`
+    pub(super) fn notify_account(&self, user: &RcUser, message: Message<Command>) {
+        let mut receivers = self.peers(user);
+        receivers.push(user.clone());
+
+        for receiver in receivers {
+            if let Some(index) = self.index_of(&receiver) {
+                if self.connections[index].has_cap("account-notify") {
+                    self.send(index, message.clone());
+                }
+            }
+        }
+    }
+
+    /// Gives a local member the status the access list of the channel grants its account
+    pub(super) fn give_access(&mut self, channel: &RcChannel, user: &RcUser) {
+        let access = {
+            let account = user.borrow().account().map(|account| account.to_string());
+            let name = channel.borrow().name().to_string();
+
+            match (account, self.accounts.as_ref()) {
+                (Some(account), Some(store)) if self.index_of(user).is_some() => {
+                    store.registration(&name).and_then(|registration| {
+                        registration.access_of(&account, self.server.casemapping())
+                    })
+                }
+                _ => None,
+            }
+        };
+
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        let change = match channel.borrow_mut().member_mut(user) {
+            Some(ref mut member) if access == Some(Access::Operator) && !member.is_operator() => {
+                member.set_operator(true);
+
+                mode::Mode::new(true, channel::Mode::Operator { nick })
+            }
+            Some(ref mut member) if access == Some(Access::Voice) && !member.is_voiced() => {
+                member.set_voice(true);
+
+                mode::Mode::new(true, channel::Mode::Voice { nick })
+            }
+            _ => return,
+        };
+
+        let message = Message::new(
+            self.server.origin().clone(),
+            Command::CMode {
+                channel: channel.borrow().name().to_string(),
+                modes: vec![change],
+            },
+        );
+
+        self.announce(&message);
+        self.broadcast(channel, message, None);
+    }
+
+    /// `nick!user@host` of the connection, with `*` for the parts not known yet
+    fn mask(&self, index: usize) -> String {
+        let connection = &self.connections[index];
+
+        if let Some(ref user) = connection.user {
+            return user.borrow().origin().to_string()[1..].to_string();
+        }
+
+        format!(
+            "{}!{}@{}",
+            connection.nick.as_ref().map_or("*", |nick| nick.as_str()),
+            connection
+                .user_name
+                .as_ref()
+                .map_or("*", |(user_name, _)| user_name.as_str()),
+            connection.host
+        )
+    }
+
+    fn service_notice(&self, index: usize, service: &str, text: &str) {
+        let nick = self.connections[index]
+            .user
+            .as_ref()
+            .and_then(|user| user.borrow().origin().nick().map(|nick| nick.to_string()))
+            .unwrap_or_default();
+
+        let origin = Origin::User {
+            nick: service.to_string(),
+            user: Some(service.to_string()),
+            host: Some(self.name().to_string()),
+        };
+
+        self.send(
+            index,
+            Message::new(
+                origin,
+                Command::Notice {
+                    target: MessageTarget::Nick(nick),
+                    text: text.to_string(),
+                },
+            ),
+        );
+    }
+}
+
+// NickServ
+impl Ircd {
+    /// Registers the nick in use as account and logs into it
+    fn register_nick(&mut self, index: usize, user: &RcUser, password: &str) {
+        if let Some(account) = user.borrow().account() {
+            let text = format!("You are already logged in as {}", account);
+
+            return self.service_notice(index, NICKSERV, &text);
+        }
+
+        let nick = user
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+
+        let result = match self.accounts.as_mut() {
+            Some(store) if store.account(&nick).is_some() => {
+                let text = format!("{} is already registered", nick);
+
+                return self.service_notice(index, NICKSERV, &text);
+            }
+            Some(store) => store.save_account(Account::new(&nick, password)),
+            None => return,
+        };
+
+        match result {
+            Ok(()) => {
+                let text = format!("{} is now registered to you", nick);
+
+                self.service_notice(index, NICKSERV, &text);
+                self.log_in(index, &nick);
+            }
+            Err(e) => {
+                let text = format!("Registration failed: {}", e);
+
+                self.service_notice(index, NICKSERV, &text);
+            }
+        }
+    }
+
+    fn identify(&mut self, index: usize, user: &RcUser, name: &str, password: &str) {
+        if let Some(account) = user.borrow().account() {
+            let text = format!("You are already logged in as {}", account);
+
+            return self.service_notice(index, NICKSERV, &text);
+        }
+
+        match self.accounts.as_ref().and_then(|store| store.account(name)) {
+            Some(ref account) if account.verify(password) => self.log_in(index, account.name()),
+            Some(_) => self.service_notice(index, NICKSERV, "Invalid password"),
+            None => {
+                let text = format!("{} isn't registered", name);
+
+                self.service_notice(index, NICKSERV, &text)
+            }
+        }
+    }
+
+    /// Kills whoever uses the nick, for its owner or anyone knowing the password
+    fn ghost(&mut self, index: usize, user: &RcUser, nick: &str, password: Option<&str>) {
+        let target = match self.server.user(nick) {
+            Some(ref target) if Rc::ptr_eq(target, user) => {
+                return self.service_notice(index, NICKSERV, "You can't ghost yourself")
+            }
+            Some(target) => target,
+            None => {
+                let text = format!("{} isn't online", nick);
+
+                return self.service_notice(index, NICKSERV, &text);
+            }
+        };
+
+        let account = match self.accounts.as_ref().and_then(|store| store.account(nick)) {
+            Some(account) => account,
+            None => {
+                let text = format!("{} isn't registered", nick);
+
+                return self.service_notice(index, NICKSERV, &text);
+            }
+        };
+
+        let owner = user.borrow().account().map_or(false, |name| {
+            self.server.casemapping().eq(name, account.name())
+        });
+
+        if !owner && !password.map_or(false, |password| account.verify(password)) {
+            return self.service_notice(index, NICKSERV, "Access denied");
+        }
+
+        let nick = target
+            .borrow()
+            .origin()
+            .nick()
+            .unwrap_or_default()
+            .to_string();
+        let comment = format!(
+            "GHOST command used by {}",
+            user.borrow().origin().nick().unwrap_or_default()
+        );
+
+        self.propagate(
+            Message::new(
+                self.server.origin().clone(),
+                server::Command::Kill {
+                    user: nick.clone(),
+                    comment: comment.clone(),
+                },
+            ),
+            None,
+        );
+
+        let name = self.name().to_string();
+        self.kill(&target, &name, &comment);
+
+        let text = format!("{} has been ghosted", nick);
+        self.service_notice(index, NICKSERV, &text);
+    }
+}
+
+// ChanServ
+impl Ircd {
+    /// Registers a channel the user operates, its account becomes the founder
+    fn register_channel(&mut self, index: usize, user: &RcUser, name: &str) {
+        let account = match user.borrow().account() {
+            Some(account) => account.to_string(),
+            None => {
+                return self.service_notice(
+                    index,
+                    CHANSERV,
+                    "You need to be logged in to register channels",
+                )
+            }
+        };
+
+        let channel = match self.server.channel(name) {
+            Some(ref channel)
+                if channel
+                    .borrow()
+                    .member(user)
+                    .map_or(false, |member| member.is_operator()) =>
+            {
+                channel.borrow().name().to_string()
+            }
+            _ => {
+                let text = format!("You need to be a channel operator in {}", name);
+
+                return self.service_notice(index, CHANSERV, &text);
+            }
+        };
+
+        let result = match self.accounts.as_mut() {
+            Some(store) if store.registration(&channel).is_some() => {
+                let text = format!("{} is already registered", channel);
+
+                return self.service_notice(index, CHANSERV, &text);
+            }
+            Some(store) => store.save_registration(Registration::new(&channel, &account)),
+            None => return,
+        };
+
+        let text = match result {
+            Ok(()) => format!("{} is now registered to {}", channel, account),
+            Err(e) => format!("Registration failed: {}", e),
+        };
+
+        self.service_notice(index, CHANSERV, &text);
+    }
+
+    /// `ADD <account> <op|voice>`, `DEL <account>` or `LIST`, only the founder may change the
+    /// list
+    fn access(&mut self, index: usize, user: &RcUser, name: &str, words: &[&str]) {
+        let mapping = self.server.casemapping();
+
+        let mut registration = match self
+            .accounts
+            .as_ref()
+            .and_then(|store| store.registration(name))
+        {
+            Some(registration) => registration,
+            None => {
+                let text = format!("{} isn't registered", name);
+
+                return self.service_notice(index, CHANSERV, &text);
+            }
+        };
+
+        let channel = registration.channel().to_string();
+        let founder = user
+            .borrow()
+            .account()
+            .map_or(false, |account| mapping.eq(account, registration.founder()));
+
+        let subcommand = words[0].to_ascii_uppercase();
+
+        if subcommand == "LIST" {
+            let text = format!("{} is founded by {}", channel, registration.founder());
+            self.service_notice(index, CHANSERV, &text);
+
+            for (account, access) in registration.access() {
+                let text = format!("{} {}", account, access.name());
+                self.service_notice(index, CHANSERV, &text);
+            }
+
+            return self.service_notice(index, CHANSERV, "End of access list");
+        }
+
+        if !founder {
+            let text = format!("Only the founder of {} can change its access list", channel);
+
+            return self.service_notice(index, CHANSERV, &text);
+        }
+
+        let (account, access) = match (subcommand.as_str(), words.len()) {
+            ("ADD", 3) => match Access::parse(words[2]) {
+                Some(access) => (words[1], Some(access)),
+                None => {
+                    return self.service_notice(
+                        index,
+                        CHANSERV,
+                        "Syntax: ACCESS <channel> ADD <account> <op|voice>",
+                    )
+                }
+            },
+            ("DEL", 2) => (words[1], None),
+            _ => {
+                return self.service_notice(
+                    index,
+                    CHANSERV,
+                    "Syntax: ACCESS <channel> ADD <account> <op|voice>, ACCESS <channel> DEL \
+                     <account> or ACCESS <channel> LIST",
+                )
+            }
+        };
+
+        let account = match self
+            .accounts
+            .as_ref()
+            .and_then(|store| store.account(account))
+        {
+            Some(account) => account.name().to_string(),
+            None => {
+                let text = format!("{} isn't registered", account);
+
+                return self.service_notice(index, CHANSERV, &text);
+            }
+        };
+
+        let known = registration.set_access(&account, access, mapping);
+
+        if access.is_none() && !known {
+            let text = format!("{} isn't on the access list of {}", account, channel);
+
+            return self.service_notice(index, CHANSERV, &text);
+        }
+
+        let result = match self.accounts.as_mut() {
+            Some(store) => store.save_registration(registration),
+            None => return,
+        };
+
+        let text = match (result, access) {
+            (Err(e), _) => format!("Changing the access list failed: {}", e),
+            (Ok(()), Some(access)) => {
+                format!("{} now gets {} in {}", account, access.name(), channel)
+            }
+            (Ok(()), None) => format!("{} removed from the access list of {}", account, channel),
+        };
+
+        self.service_notice(index, CHANSERV, &text);
+
+        // members logged in already get their status right away
+        if let Some(channel) = self.server.channel(&channel) {
+            for member in self.receivers(&channel, None) {
+                self.give_access(&channel, &member);
+            }
+        }
+    }
+
+    fn drop_channel(&mut self, index: usize, user: &RcUser, name: &str) {
+        let mapping = self.server.casemapping();
+
+        let registration = match self
+            .accounts
+            .as_ref()
+            .and_then(|store| store.registration(name))
+        {
+            Some(registration) => registration,
+            None => {
+                let text = format!("{} isn't registered", name);
+
+                return self.service_notice(index, CHANSERV, &text);
+            }
+        };
+
+        let founder = user
+            .borrow()
+            .account()
+            .map_or(false, |account| mapping.eq(account, registration.founder()));
+
+        if !founder {
+            let text = format!("Only the founder of {} can drop it", registration.channel());
+
+            return self.service_notice(index, CHANSERV, &text);
+        }
+
+        let result = match self.accounts.as_mut() {
+            Some(store) => store.remove_registration(registration.channel()),
+            None => return,
+        };
+
+        let text = match result {
+            Ok(()) => format!("{} has been dropped", registration.channel()),
+            Err(e) => format!("Dropping {} failed: {}", registration.channel(), e),
+        };
+
+        self.service_notice(index, CHANSERV, &text);
+    }
+}
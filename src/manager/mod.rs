@@ -0,0 +1,219 @@
+use std::{collections::VecDeque, error::Error, thread, time::Duration};
+
+use crate::{
+    command::client::Command,
+    connection::client::{Builder, Client},
+    message::{Message, ToMessage},
+};
+
+pub use self::session::Session;
+
+mod session;
+
+pub mod error {
+    impl_error!(UnknownNetworkError { name: String });
+    impl_error!(DuplicateNetworkError { name: String });
+    impl_error!(NotConnectedError { name: String });
+}
+
+/// Time between attempts to connect to a network again
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Time `wait` sleeps between polls
+pub const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Sets up the client of a network, called again whenever the connection got lost
+pub type Connect = Box<FnMut() -> Builder>;
+
+/// Something that happened on one of the networks
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected {
+        network: String,
+    },
+    /// Tried again after the reconnect delay, unless reconnecting is off
+    ConnectFailed {
+        network: String,
+        reason: String,
+    },
+    /// Connecting again after the reconnect delay, unless reconnecting is off
+    Disconnected {
+        network: String,
+        reason: String,
+    },
+    Message {
+        network: String,
+        message: Box<Message<Command>>,
+    },
+}
+
+impl Event {
+    pub fn network(&self) -> &str {
+        match self {
+            Event::Connected { network }
+            | Event::ConnectFailed { network, .. }
+            | Event::Disconnected { network, .. }
+            | Event::Message { network, .. } => network.as_str(),
+        }
+    }
+}
+
+/// Keeps connections to several networks in one thread and puts what happens on them into one
+/// stream of events
+///
+/// Networks are added as `Session`s, keyed by their name, which tags the events and picks the
+/// network messages are sent to. Sessions connect on non-blocking sockets, so a slow network
+/// doesn't hold up the others, only looking up its host name blocks.
+#[derive(Default)]
+pub struct Manager {
+    sessions: Vec<Session>,
+    events: VecDeque<Event>,
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Manager::default()
+    }
+
+    /// Adds a network, replacing one with the same name
+    pub fn session(mut self, session: Session) -> Self {
+        self.sessions.retain(|other| other.name() != session.name());
+        self.sessions.push(session);
+
+        self
+    }
+}
+
+impl Manager {
+    /// Adds a network while running, it's connected to on the next poll
+    pub fn add(&mut self, session: Session) -> Result<(), Box<Error>> {
+        if self.get(session.name()).is_some() {
+            return Err(error::DuplicateNetworkError::new(
+                session.name().to_string(),
+            ));
+        }
+
+        self.sessions.push(session);
+
+        Ok(())
+    }
+
+    /// Disconnects from the network and forgets it
+    pub fn remove(&mut self, network: &str) -> Option<Session> {
+        let pos = self.sessions.iter().position(|s| s.name() == network)?;
+        let mut session = self.sessions.remove(pos);
+
+        session.disconnect();
+
+        Some(session)
+    }
+
+    /// Calls the network `name` from now on, events included
+    pub fn rename(&mut self, network: &str, name: &str) -> Result<(), Box<Error>> {
+        if self.get(name).is_some() {
+            return Err(error::DuplicateNetworkError::new(name.to_string()));
+        }
+
+        self.session_mut_for(network)?.set_name(name);
+
+        Ok(())
+    }
+
+    pub fn get(&self, network: &str) -> Option<&Session> {
+        self.sessions
+            .iter()
+            .find(|session| session.name() == network)
+    }
+
+    pub fn get_mut(&mut self, network: &str) -> Option<&mut Session> {
+        self.sessions
+            .iter_mut()
+            .find(|session| session.name() == network)
+    }
+
+    pub fn sessions(&self) -> &Vec<Session> {
+        &self.sessions
+    }
+
+    /// Names of the networks, in the order they were added
+    pub fn networks(&self) -> Vec<&str> {
+        self.sessions.iter().map(|session| session.name()).collect()
+    }
+
+    /// The client of the network, `None` if it's unknown or not connected
+    pub fn client(&self, network: &str) -> Option<&Client> {
+        self.get(network).and_then(|session| session.client())
+    }
+
+    /// Sends to the network called `network`
+    pub fn send<T>(&self, network: &str, msg_or_cmd: T) -> Result<(), Box<Error>>
+    where
+        T: ToMessage<Command> + std::fmt::Debug,
+    {
+        self.session_for(network)?.send(msg_or_cmd)
+    }
+
+    pub fn privmsg(&self, network: &str, target: &str, text: &str) -> Result<(), Box<Error>> {
+        self.session_for(network)?.privmsg(target, text)
+    }
+
+    pub fn notice(&self, network: &str, target: &str, text: &str) -> Result<(), Box<Error>> {
+        self.session_for(network)?.notice(target, text)
+    }
+
+    /// The next event, `None` if nothing happened on any network. Connecting happens here too,
+    /// a step per poll.
+    pub fn poll(&mut self) -> Option<Event> {
+        if self.events.is_empty() {
+            let mut events = Vec::new();
+
+            for session in self.sessions.iter_mut() {
+                session.poll(&mut events);
+            }
+
+            self.events.extend(events);
+        }
+
+        self.events.pop_front()
+    }
+
+    /// Waits for the next event
+    pub fn wait(&mut self) -> Event {
+        loop {
+            if let Some(event) = self.poll() {
+                return event;
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Passes every event to `handler` until it fails
+    pub fn run<F>(&mut self, mut handler: F) -> Result<(), Box<Error>>
+    where
+        F: FnMut(&mut Manager, Event) -> Result<(), Box<Error>>,
+    {
+        loop {
+            let event = self.wait();
+
+            handler(self, event)?;
+        }
+    }
+
+    /// Disconnects from every network
+    pub fn disconnect(&mut self) {
+        for session in self.sessions.iter_mut() {
+            session.disconnect();
+        }
+    }
+
+    fn session_for(&self, network: &str) -> Result<&Session, Box<Error>> {
+        self.get(network)
+            .ok_or_else(|| error::UnknownNetworkError::new(network.to_string()))
+    }
+
+    fn session_mut_for(&mut self, network: &str) -> Result<&mut Session, Box<Error>> {
+        self.get_mut(network)
+            .ok_or_else(|| error::UnknownNetworkError::new(network.to_string()))
+    }
+}
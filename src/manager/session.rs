@@ -0,0 +1,222 @@
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    command::client::Command,
+    connection::client::{Builder, Client, Connecting, Progress},
+    message::ToMessage,
+};
+
+use super::{error, Connect, Event, RECONNECT_DELAY};
+
+/// One network of a `Manager`: how to connect to it, what to join and the connection itself
+pub struct Session {
+    name: String,
+    connect: Connect,
+    /// Joined after connecting, the channels we were on when the connection got lost included
+    channels: Vec<String>,
+    reconnect: bool,
+    reconnect_delay: Duration,
+    client: Option<Client>,
+    /// The connection while it's set up
+    connecting: Option<Connecting>,
+    /// When to connect next while there is no connection
+    connect_at: Option<Instant>,
+}
+
+impl Session {
+    /// `connect` sets up the `Client`, like `|| Client::builder().host(..).user(..)`. It's called
+    /// on the next poll of the manager and again after the connection got lost.
+    pub fn new<F>(name: &str, connect: F) -> Self
+    where
+        F: FnMut() -> Builder + 'static,
+    {
+        Session {
+            name: name.to_string(),
+            connect: Box::new(connect),
+            channels: Vec::new(),
+            reconnect: true,
+            reconnect_delay: RECONNECT_DELAY,
+            client: None,
+            connecting: None,
+            connect_at: Some(Instant::now()),
+        }
+    }
+
+    /// Joined once connected, may be called several times
+    pub fn channel(mut self, channel: &str) -> Self {
+        self.channels.push(channel.to_string());
+
+        self
+    }
+
+    /// Whether to connect again after losing the connection or failing to connect, on by default
+    pub fn reconnect(mut self, enabled: bool) -> Self {
+        self.reconnect = enabled;
+
+        self
+    }
+
+    /// Defaults to `RECONNECT_DELAY`
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+
+        self
+    }
+}
+
+impl Session {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub(super) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// `None` while not connected
+    pub fn client(&self) -> Option<&Client> {
+        self.client.as_ref()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Whether the connection is still set up
+    pub fn is_connecting(&self) -> bool {
+        self.connecting.is_some()
+    }
+
+    pub fn send<T>(&self, msg_or_cmd: T) -> Result<(), Box<Error>>
+    where
+        T: ToMessage<Command> + std::fmt::Debug,
+    {
+        self.connected()?.send(msg_or_cmd)?;
+
+        Ok(())
+    }
+
+    pub fn privmsg(&self, target: &str, text: &str) -> Result<(), Box<Error>> {
+        self.connected()?.privmsg(target, text)?;
+
+        Ok(())
+    }
+
+    pub fn notice(&self, target: &str, text: &str) -> Result<(), Box<Error>> {
+        self.connected()?.notice(target, text)?;
+
+        Ok(())
+    }
+
+    /// Closes the connection without connecting again
+    pub fn disconnect(&mut self) {
+        if let Some(client) = self.client.take() {
+            client.disconnect();
+        }
+
+        self.connecting = None;
+        self.connect_at = None;
+    }
+
+    fn connected(&self) -> Result<&Client, Box<Error>> {
+        match self.client {
+            Some(ref client) => Ok(client),
+            None => Err(error::NotConnectedError::new(self.name.clone())),
+        }
+    }
+
+    /// Connects if it's time to, then reads everything the client has
+    pub(crate) fn poll(&mut self, events: &mut Vec<Event>) {
+        self.connect(events);
+
+        loop {
+            let result = match self.client {
+                Some(ref mut client) => client.read(),
+                None => return,
+            };
+
+            match result {
+                Ok(Some(message)) => events.push(Event::Message {
+                    network: self.name.clone(),
+                    message: Box::new(message),
+                }),
+                Ok(None) => return,
+                Err(e) => return self.lost(&e.to_string(), events),
+            }
+        }
+    }
+
+    /// Starts connecting if it's time to, or takes the next step of the connection set up
+    fn connect(&mut self, events: &mut Vec<Event>) {
+        let connecting = match self.connecting.take() {
+            Some(connecting) => connecting.poll(),
+            None => match self.connect_at {
+                Some(at) if self.client.is_none() && Instant::now() >= at => {
+                    self.connect_at = None;
+
+                    (self.connect)().start().and_then(Connecting::poll)
+                }
+                _ => return,
+            },
+        };
+
+        let client = match connecting {
+            Ok(Progress::Done(client)) => *client,
+            Ok(Progress::Connecting(connecting)) => return self.connecting = Some(connecting),
+            Err(e) => {
+                self.schedule();
+
+                return events.push(Event::ConnectFailed {
+                    network: self.name.clone(),
+                    reason: e.to_string(),
+                });
+            }
+        };
+
+        if !self.channels.is_empty() {
+            let _ = client.send(Command::Join {
+                channels: self.channels.clone(),
+                keys: Vec::new(),
+            });
+        }
+
+        self.client = Some(client);
+
+        events.push(Event::Connected {
+            network: self.name.clone(),
+        });
+    }
+
+    /// Forgets the connection and rejoins its channels on the next one
+    fn lost(&mut self, reason: &str, events: &mut Vec<Event>) {
+        if let Some(client) = self.client.take() {
+            for channel in client.server().channels() {
+                let name = channel.borrow().name().to_string();
+
+                if !self.channels.contains(&name) {
+                    self.channels.push(name);
+                }
+            }
+
+            client.disconnect();
+        }
+
+        self.schedule();
+
+        events.push(Event::Disconnected {
+            network: self.name.clone(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn schedule(&mut self) {
+        self.connect_at = if self.reconnect {
+            Some(Instant::now() + self.reconnect_delay)
+        } else {
+            None
+        };
+    }
+}
@@ -1,10 +1,116 @@
-use std::{convert::TryInto, error::Error};
+use std::{borrow::Cow, convert::TryInto, error::Error};
 
 use crate::{command, origin, END_OF_MESSAGE};
 
 pub mod error {
     impl_error!(IllegalMessageFormatError {});
     impl_error!(MessageTooLongError {});
+    impl_error!(IllegalTagError { tag: String });
+}
+
+pub const TAGS_PREFIX: char = '@';
+pub const TAG_DELIMITER: char = ';';
+pub const TAG_VALUE_SEPARATOR: char = '=';
+
+/// Maximum length of the tags section including `@` and the trailing space (IRCv3)
+pub const TAGS_LIMIT: usize = 8191;
+
+#[derive(Debug, Clone, PartialEq)]
+/// IRCv3 message tag (`key=value`)
+pub struct Tag {
+    key: String,
+    value: Option<String>,
+}
+
+impl Tag {
+    pub fn new(key: &str, value: Option<&str>) -> Self {
+        Tag {
+            key: key.to_string(),
+            value: value.map(|v| v.to_string()),
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        self.key.as_str()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_ref().map(|v| v.as_str())
+    }
+
+    fn escape(value: &str) -> String {
+        let mut escaped = String::with_capacity(value.len());
+
+        for c in value.chars() {
+            match c {
+                ';' => escaped.push_str("\\:"),
+                ' ' => escaped.push_str("\\s"),
+                '\\' => escaped.push_str("\\\\"),
+                '\r' => escaped.push_str("\\r"),
+                '\n' => escaped.push_str("\\n"),
+                _ => escaped.push(c),
+            }
+        }
+
+        escaped
+    }
+
+    fn unescape(value: &str) -> String {
+        let mut unescaped = String::with_capacity(value.len());
+        let mut chars = value.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                unescaped.push(c);
+                continue;
+            }
+
+            // a trailing backslash is dropped
+            match chars.next() {
+                Some(':') => unescaped.push(';'),
+                Some('s') => unescaped.push(' '),
+                Some('r') => unescaped.push('\r'),
+                Some('n') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => (),
+            }
+        }
+
+        unescaped
+    }
+
+    /// Parses the tags section without the leading `@`
+    pub fn parse_list(data: &str) -> Result<Vec<Tag>, Box<Error>> {
+        data.split(TAG_DELIMITER)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| {
+                let (key, value) = match tag.find(TAG_VALUE_SEPARATOR) {
+                    Some(pos) => (&tag[..pos], Some(Tag::unescape(&tag[pos + 1..]))),
+                    None => (tag, None),
+                };
+
+                if key.is_empty() || key.contains(|c: char| c.is_whitespace()) {
+                    return Err(error::IllegalTagError::new(tag.to_string()));
+                }
+
+                Ok(Tag {
+                    key: key.to_string(),
+                    // empty values are equal to missing ones
+                    value: value.filter(|v| !v.is_empty()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Builds a single tag
+impl ToString for Tag {
+    fn to_string(&self) -> String {
+        match self.value {
+            Some(ref value) => format!("{}{}{}", self.key, TAG_VALUE_SEPARATOR, Tag::escape(value)),
+            None => self.key.to_string(),
+        }
+    }
 }
 
 pub trait ToMessage<C>
@@ -14,14 +120,50 @@ pub trait ToMessage<C>
     fn into_message(self) -> Message<C>;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 /// IRC `Message` representation with an `Origin` and a `Command`
 pub struct Message<C> {
+    tags: Vec<Tag>,
     origin: origin::Origin,
     command: C,
 }
 
 impl<C> Message<C> {
+    pub fn new(origin: origin::Origin, command: C) -> Self {
+        Message {
+            tags: Vec::new(),
+            origin,
+            command,
+        }
+    }
+
+    pub fn with_tag(mut self, key: &str, value: Option<&str>) -> Self {
+        self.tags.retain(|tag| tag.key() != key);
+        self.tags.push(Tag::new(key, value));
+
+        self
+    }
+
+    pub fn without_tag(mut self, key: &str) -> Self {
+        self.tags.retain(|tag| tag.key() != key);
+
+        self
+    }
+}
+
+impl<C> Message<C> {
+    pub fn tags(&self) -> &Vec<Tag> {
+        &self.tags
+    }
+
+    /// Value of the tag `key`. `Some(None)` means the tag is present without a value.
+    pub fn tag(&self, key: &str) -> Option<Option<&str>> {
+        self.tags
+            .iter()
+            .find(|tag| tag.key() == key)
+            .map(|tag| tag.value())
+    }
+
     pub fn origin(&self) -> &origin::Origin {
         &self.origin
     }
@@ -39,6 +181,18 @@ impl<'a, C> std::convert::TryFrom<&'a str> for Message<C>
     type Error = Box<Error>;
 
     fn try_from(mut line: &'a str) -> Result<Self, Self::Error> {
+        let mut tags = Vec::new();
+
+        if line.starts_with(TAGS_PREFIX) {
+            let tags_end_pos = match line.find(crate::SEPARATOR) {
+                Some(pos) if pos < TAGS_LIMIT => pos,
+                _ => return Err(error::IllegalMessageFormatError::new()),
+            };
+
+            tags = Tag::parse_list(&line[1..tags_end_pos])?;
+            line = line[tags_end_pos + 1..].trim_start_matches(crate::SEPARATOR);
+        }
+
         if line.len() > crate::limits::LINE {
             return Err(error::IllegalMessageFormatError::new());
         }
@@ -48,20 +202,23 @@ impl<'a, C> std::convert::TryFrom<&'a str> for Message<C>
             line = &line[..line.len() - 2];
         }
 
-        if let Some(origin_end_pos) = line.find(crate::SEPARATOR) {
-            let (origin, command) = if !line.starts_with(origin::PREFIX) {
-                (origin::Origin::Connection, C::try_from(line.try_into()?)?)
-            } else {
-                (
-                    line[1..origin_end_pos].try_into()?,
-                    C::try_from(line[origin_end_pos + 1..].try_into()?)?,
-                )
-            };
-
-            Ok(Message { origin, command })
+        // commands without parameters have no separator at all
+        let (origin, command) = if !line.starts_with(origin::PREFIX) {
+            (origin::Origin::Connection, C::try_from(line.try_into()?)?)
+        } else if let Some(origin_end_pos) = line.find(crate::SEPARATOR) {
+            (
+                line[1..origin_end_pos].try_into()?,
+                C::try_from(line[origin_end_pos + 1..].try_into()?)?,
+            )
         } else {
-            Err(error::IllegalMessageFormatError::new())
-        }
+            return Err(error::IllegalMessageFormatError::new());
+        };
+
+        Ok(Message {
+            tags,
+            origin,
+            command,
+        })
     }
 }
 
@@ -74,10 +231,24 @@ impl<C> ToString for Message<C>
         let command = self.command.to_string();
         let origin = self.origin.to_string();
 
+        let tags = if self.tags.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "{}{} ",
+                TAGS_PREFIX,
+                self.tags
+                    .iter()
+                    .map(|tag| tag.to_string())
+                    .collect::<Vec<String>>()
+                    .join(";")
+            )
+        };
+
         if origin.is_empty() {
-            format!("{}{}", command, END_OF_MESSAGE)
+            format!("{}{}{}", tags, command, END_OF_MESSAGE)
         } else {
-            format!("{} {}{}", origin, command, END_OF_MESSAGE)
+            format!("{}{} {}{}", tags, origin, command, END_OF_MESSAGE)
         }
     }
 }
@@ -88,10 +259,7 @@ impl<C> From<C> for Message<C>
         C: command::Command,
 {
     fn from(command: C) -> Self {
-        Message {
-            origin: origin::Origin::default(),
-            command,
-        }
+        Message::new(origin::Origin::default(), command)
     }
 }
 
@@ -103,3 +271,241 @@ impl<C> ToMessage<C> for Message<C>
         self
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Message borrowing from the line it was parsed from
+///
+/// Parsing only splits the line into tags, prefix, command and parameters, nothing gets validated
+/// or copied. Converting it into a `Message` does the full validation.
+pub struct MessageRef<'a> {
+    line: &'a str,
+    tags: Option<&'a str>,
+    prefix: Option<&'a str>,
+    command: &'a str,
+    params: &'a str,
+}
+
+/// Splits off the part up to the next separator, skipping repeated separators after it
+fn split_part(data: &str) -> (&str, &str) {
+    match data.find(crate::SEPARATOR) {
+        Some(pos) => (
+            &data[..pos],
+            data[pos + 1..].trim_start_matches(crate::SEPARATOR),
+        ),
+        None => (data, ""),
+    }
+}
+
+impl<'a> MessageRef<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, Box<Error>> {
+        let line = line.trim_end_matches(|c| c == '\r' || c == '\n');
+        let mut rest = line;
+
+        let tags = if rest.starts_with(TAGS_PREFIX) {
+            let (tags, _rest) = split_part(&rest[1..]);
+
+            if tags.len() + 2 > TAGS_LIMIT {
+                return Err(error::IllegalMessageFormatError::new());
+            }
+
+            rest = _rest;
+            Some(tags)
+        } else {
+            None
+        };
+
+        let prefix = if rest.starts_with(crate::origin::PREFIX) {
+            let (prefix, _rest) = split_part(&rest[1..]);
+
+            rest = _rest;
+            Some(prefix)
+        } else {
+            None
+        };
+
+        let (command, params) = split_part(rest);
+
+        if command.is_empty() {
+            return Err(error::IllegalMessageFormatError::new());
+        }
+
+        Ok(MessageRef {
+            line,
+            tags,
+            prefix,
+            command,
+            params,
+        })
+    }
+}
+
+impl<'a> MessageRef<'a> {
+    /// The whole line without the end of message
+    pub fn line(&self) -> &'a str {
+        self.line
+    }
+
+    /// Tags with their values still escaped
+    pub fn tags(&self) -> TagsRef<'a> {
+        TagsRef {
+            rest: self.tags.unwrap_or_default(),
+        }
+    }
+
+    /// Unescaped value of the tag `key`, only allocates if the value contains escapes.
+    /// `Some(None)` means the tag is present without a value.
+    pub fn tag(&self, key: &str) -> Option<Option<Cow<'a, str>>> {
+        self.tags().find(|(k, _)| *k == key).map(|(_, value)| {
+            value.map(|v| {
+                if v.contains('\\') {
+                    Cow::Owned(Tag::unescape(v))
+                } else {
+                    Cow::Borrowed(v)
+                }
+            })
+        })
+    }
+
+    /// Prefix without the leading `:`
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.prefix
+    }
+
+    /// Nick (or server name) part of the prefix
+    pub fn nick(&self) -> Option<&'a str> {
+        self.prefix
+            .map(|prefix| prefix.split(|c| c == '!' || c == '@').next().unwrap_or(prefix))
+    }
+
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    /// Parameters, the trailing one without its `:`
+    pub fn params(&self) -> ParamsRef<'a> {
+        ParamsRef { rest: self.params }
+    }
+
+    pub fn param(&self, index: usize) -> Option<&'a str> {
+        self.params().nth(index)
+    }
+
+    /// Validates the parts and copies them into an owned `Message`
+    pub fn to_message<C>(&self) -> Result<Message<C>, Box<Error>>
+        where
+            C: command::Command,
+    {
+        let untagged = match self.tags {
+            Some(tags) => self.line[1 + tags.len()..].trim_start_matches(crate::SEPARATOR),
+            None => self.line,
+        };
+
+        if untagged.len() + END_OF_MESSAGE.len() > crate::limits::LINE {
+            return Err(error::IllegalMessageFormatError::new());
+        }
+
+        let tags = match self.tags {
+            Some(tags) => Tag::parse_list(tags)?,
+            None => Vec::new(),
+        };
+
+        let origin = match self.prefix {
+            Some(prefix) => prefix.try_into()?,
+            None => origin::Origin::Connection,
+        };
+
+        // the words as a command splits them, the trailing parameter still with its `:`
+        let parameters = if self.params.is_empty() {
+            Vec::new()
+        } else {
+            self.params.split(crate::SEPARATOR).collect()
+        };
+
+        let command = C::try_from(command::RawCommand {
+            command: self.command,
+            parameters,
+        })?;
+
+        Ok(Message {
+            tags,
+            origin,
+            command,
+        })
+    }
+}
+
+/// Validates and copies everything into an owned `Message`
+impl<'a, C> std::convert::TryFrom<MessageRef<'a>> for Message<C>
+    where
+        C: command::Command,
+{
+    type Error = Box<Error>;
+
+    fn try_from(message: MessageRef<'a>) -> Result<Self, Self::Error> {
+        message.to_message()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TagsRef<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for TagsRef<'a> {
+    type Item = (&'a str, Option<&'a str>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.rest.is_empty() {
+                return None;
+            }
+
+            let tag = match self.rest.find(TAG_DELIMITER) {
+                Some(pos) => {
+                    let tag = &self.rest[..pos];
+                    self.rest = &self.rest[pos + 1..];
+                    tag
+                }
+                None => std::mem::replace(&mut self.rest, ""),
+            };
+
+            if tag.is_empty() {
+                continue;
+            }
+
+            return Some(match tag.find(TAG_VALUE_SEPARATOR) {
+                Some(pos) if pos + 1 < tag.len() => (&tag[..pos], Some(&tag[pos + 1..])),
+                Some(pos) => (&tag[..pos], None),
+                None => (tag, None),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamsRef<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Iterator for ParamsRef<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start_matches(crate::SEPARATOR);
+
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+
+        if rest.starts_with(command::TRAILING_DELIMITER) {
+            self.rest = "";
+            return Some(&rest[1..]);
+        }
+
+        let (param, rest) = split_part(rest);
+        self.rest = rest;
+
+        Some(param)
+    }
+}
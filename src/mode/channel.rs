@@ -3,20 +3,177 @@ use std::error::Error;
 use crate::mode;
 
 #[derive(Debug, Clone, PartialEq)]
+/// Channel modes of RFC 2811. List modes without a mask ask for the list.
 pub enum Mode {
-    Limit { value: usize },
+    Ban { mask: Option<String> },
+    Exception { mask: Option<String> },
+    InviteOnly,
+    InviteException { mask: Option<String> },
+    Key { key: Option<String> },
+    Limit { value: Option<usize> },
+    Moderated,
+    NoExternal,
+    TopicLock,
+    Secret,
+    Private,
+    Operator { nick: String },
+    Voice { nick: String },
+    /// Any mode not defined by RFC 2811, always without parameter
+    Other(char),
 }
 
 impl mode::Parseable for Mode {
     type Target = Self;
 
+    /// Parses the mode letter alone, parameters are filled in by `parse_list`
     fn parse(data: &str) -> Result<Self::Target, Box<Error>> {
         if data.len() != 1 {
             Err(mode::error::IllegalModeError::new())
         } else {
             match data.chars().nth(0).unwrap() {
+                'b' => Ok(Mode::Ban { mask: None }),
+                'e' => Ok(Mode::Exception { mask: None }),
+                'i' => Ok(Mode::InviteOnly),
+                'I' => Ok(Mode::InviteException { mask: None }),
+                'k' => Ok(Mode::Key { key: None }),
+                'l' => Ok(Mode::Limit { value: None }),
+                'm' => Ok(Mode::Moderated),
+                'n' => Ok(Mode::NoExternal),
+                't' => Ok(Mode::TopicLock),
+                's' => Ok(Mode::Secret),
+                'p' => Ok(Mode::Private),
+                'o' => Ok(Mode::Operator {
+                    nick: String::new(),
+                }),
+                'v' => Ok(Mode::Voice {
+                    nick: String::new(),
+                }),
+                c if c.is_ascii_alphabetic() => Ok(Mode::Other(c)),
                 _ => Err(mode::error::IllegalModeError::new()),
             }
         }
     }
+
+    fn symbol(&self) -> char {
+        match self {
+            Mode::Ban { .. } => 'b',
+            Mode::Exception { .. } => 'e',
+            Mode::InviteOnly => 'i',
+            Mode::InviteException { .. } => 'I',
+            Mode::Key { .. } => 'k',
+            Mode::Limit { .. } => 'l',
+            Mode::Moderated => 'm',
+            Mode::NoExternal => 'n',
+            Mode::TopicLock => 't',
+            Mode::Secret => 's',
+            Mode::Private => 'p',
+            Mode::Operator { .. } => 'o',
+            Mode::Voice { .. } => 'v',
+            Mode::Other(c) => *c,
+        }
+    }
+}
+
+impl Mode {
+    pub fn param(&self) -> Option<String> {
+        match self {
+            Mode::Ban { ref mask }
+            | Mode::Exception { ref mask }
+            | Mode::InviteException { ref mask } => mask.clone(),
+            Mode::Key { ref key } => key.clone(),
+            Mode::Limit { ref value } => value.map(|v| v.to_string()),
+            Mode::Operator { ref nick } | Mode::Voice { ref nick } => Some(nick.to_string()),
+            _ => None,
+        }
+    }
+
+    pub fn is_list(&self) -> bool {
+        match self {
+            Mode::Ban { .. } | Mode::Exception { .. } | Mode::InviteException { .. } => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_status(&self) -> bool {
+        match self {
+            Mode::Operator { .. } | Mode::Voice { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the mode takes a parameter when granted or revoked
+    fn takes_param(&self, granted: bool) -> bool {
+        match self {
+            Mode::Limit { .. } => granted,
+            Mode::Other(_) | Mode::InviteOnly | Mode::Moderated | Mode::NoExternal => false,
+            Mode::TopicLock | Mode::Secret | Mode::Private => false,
+            _ => true,
+        }
+    }
+
+    fn with_param(self, param: &str) -> Result<Self, Box<Error>> {
+        Ok(match self {
+            Mode::Ban { .. } => Mode::Ban {
+                mask: Some(param.to_string()),
+            },
+            Mode::Exception { .. } => Mode::Exception {
+                mask: Some(param.to_string()),
+            },
+            Mode::InviteException { .. } => Mode::InviteException {
+                mask: Some(param.to_string()),
+            },
+            Mode::Key { .. } => Mode::Key {
+                key: Some(param.to_string()),
+            },
+            Mode::Limit { .. } => Mode::Limit {
+                value: Some(param.parse().map_err(|_| mode::error::IllegalModeError::new())?),
+            },
+            Mode::Operator { .. } => Mode::Operator {
+                nick: param.to_string(),
+            },
+            Mode::Voice { .. } => Mode::Voice {
+                nick: param.to_string(),
+            },
+            other => other,
+        })
+    }
+
+    /// Parses a mode string like `+ov-k` and takes the parameters the modes need from `params`,
+    /// in order. List modes without a parameter are list queries, any other missing parameter is
+    /// an error.
+    pub fn parse_list(modes: &str, params: &[&str]) -> Result<Vec<mode::Mode<Mode>>, Box<Error>> {
+        let mut params = params.iter();
+        let mut parsed = Vec::new();
+
+        for mode in mode::Mode::<Mode>::parse_list(modes)? {
+            let granted = mode.granted();
+            let mut mode = mode.mode().clone();
+
+            if mode.takes_param(granted) {
+                match params.next() {
+                    Some(param) => mode = mode.with_param(param)?,
+                    None if mode.is_list() => (),
+                    // some servers don't send the key when it gets removed
+                    None if !granted && mode == (Mode::Key { key: None }) => (),
+                    None => return Err(mode::error::IllegalModeError::new()),
+                }
+            }
+
+            parsed.push(mode::Mode::new(granted, mode));
+        }
+
+        Ok(parsed)
+    }
+
+    /// Builds the mode string followed by the parameters, like `+ov-k nick nick key`
+    pub fn to_list_string(modes: &[mode::Mode<Mode>]) -> String {
+        let mut data = mode::Mode::to_list_string(modes);
+
+        for param in modes.iter().filter_map(|mode| mode.mode().param()) {
+            data.push_str(crate::SEPARATOR);
+            data.push_str(&param);
+        }
+
+        data
+    }
 }
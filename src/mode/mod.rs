@@ -11,6 +11,8 @@ pub trait Parseable: std::fmt::Debug + Clone + PartialEq<Self> {
     type Target: Parseable;
 
     fn parse(data: &str) -> Result<Self::Target, Box<Error>>;
+
+    fn symbol(&self) -> char;
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -19,6 +21,20 @@ pub struct Mode<T: Parseable> {
     mode: T::Target,
 }
 
+impl<T: Parseable> Mode<T> {
+    pub fn new(granted: bool, mode: T::Target) -> Self {
+        Mode { granted, mode }
+    }
+
+    pub fn granted(&self) -> bool {
+        self.granted
+    }
+
+    pub fn mode(&self) -> &T::Target {
+        &self.mode
+    }
+}
+
 impl<T: Parseable> Mode<T> {
     pub fn parse(data: &str) -> Result<Self, Box<Error>> {
         let granted = match &data[..1] {
@@ -31,6 +47,52 @@ impl<T: Parseable> Mode<T> {
 
         Ok(Mode { granted, mode })
     }
+
+    /// Parses a mode string with several modes and signs, like `+iw-x`
+    pub fn parse_list(data: &str) -> Result<Vec<Self>, Box<Error>> {
+        let mut granted = None;
+        let mut modes = Vec::new();
+
+        for c in data.chars() {
+            match c {
+                '+' => granted = Some(true),
+                '-' => granted = Some(false),
+                _ => match granted {
+                    Some(granted) => modes.push(Mode {
+                        granted,
+                        mode: T::parse(&format!("{}", c))?,
+                    }),
+                    None => return Err(error::IllegalModeError::new()),
+                },
+            }
+        }
+
+        Ok(modes)
+    }
+
+    /// Builds a mode string like `+iw-x` from a list of modes
+    pub fn to_list_string(modes: &[Self]) -> String {
+        let mut granted = None;
+        let mut data = String::new();
+
+        for mode in modes {
+            if granted != Some(mode.granted) {
+                granted = Some(mode.granted);
+                data.push(if mode.granted { '+' } else { '-' });
+            }
+
+            data.push(mode.mode.symbol());
+        }
+
+        data
+    }
+}
+
+/// Builds a single mode, like `+i`
+impl<T: Parseable> ToString for Mode<T> {
+    fn to_string(&self) -> String {
+        format!("{}{}", if self.granted { '+' } else { '-' }, self.mode.symbol())
+    }
 }
 
 impl<T: Parseable> TryFrom<char> for Mode<T> {
@@ -11,6 +11,8 @@ pub enum Mode {
     Operator,
     LocalOperator,
     Noticeable,
+    /// Any mode not defined by RFC 2812 (cloaks, registered, ..)
+    Other(char),
 }
 
 impl mode::Parseable for Mode {
@@ -21,8 +23,29 @@ impl mode::Parseable for Mode {
             Err(mode::error::IllegalModeError::new())
         } else {
             match data.chars().nth(0).unwrap() {
+                'a' => Ok(Mode::Away),
+                'i' => Ok(Mode::Invisible),
+                'w' => Ok(Mode::Wallops),
+                'r' => Ok(Mode::Restricted),
+                'o' => Ok(Mode::Operator),
+                'O' => Ok(Mode::LocalOperator),
+                's' => Ok(Mode::Noticeable),
+                c if c.is_ascii_alphabetic() => Ok(Mode::Other(c)),
                 _ => Err(mode::error::IllegalModeError::new()),
             }
         }
     }
+
+    fn symbol(&self) -> char {
+        match self {
+            Mode::Away => 'a',
+            Mode::Invisible => 'i',
+            Mode::Wallops => 'w',
+            Mode::Restricted => 'r',
+            Mode::Operator => 'o',
+            Mode::LocalOperator => 'O',
+            Mode::Noticeable => 's',
+            Mode::Other(c) => *c,
+        }
+    }
 }
@@ -0,0 +1,444 @@
+use std::net::IpAddr;
+
+use super::{Origin, HOST_SEPARATOR, IDENT_SEPARATOR};
+use crate::casemapping::CaseMapping;
+
+pub const ANY: char = '*';
+pub const ONE: char = '?';
+pub const ESCAPE: char = '\\';
+
+pub const CIDR_SEPARATOR: char = '/';
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Any,
+    One,
+    Literal(char),
+}
+
+fn tokenize(pattern: &str) -> Vec<Token> {
+    let mut tokens = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            ANY => Token::Any,
+            ONE => Token::One,
+            // a trailing escape stands for itself
+            ESCAPE => Token::Literal(chars.next().unwrap_or(ESCAPE)),
+            _ => Token::Literal(c),
+        });
+    }
+
+    // consecutive wildcards match the same as a single one
+    tokens.dedup_by(|a, b| *a == Token::Any && *b == Token::Any);
+
+    tokens
+}
+
+/// Glob matching with backtracking to the last `*`
+fn glob(pattern: &[Token], text: &str, mapping: CaseMapping) -> bool {
+    let text = text.chars().collect::<Vec<char>>();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    let mut mark = 0;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(Token::One) => {
+                p += 1;
+                t += 1;
+            }
+            Some(Token::Literal(c))
+            if mapping.to_lower_char(*c) == mapping.to_lower_char(text[t]) =>
+                {
+                    p += 1;
+                    t += 1;
+                }
+            Some(Token::Any) => {
+                star = Some(p);
+                mark = t;
+                p += 1;
+            }
+            _ => match star {
+                Some(star) => {
+                    p = star + 1;
+                    mark += 1;
+                    t = mark;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|token| *token == Token::Any)
+}
+
+/// Whether `pattern` matches `text`, `*` matching any number of characters and `?` exactly one.
+/// `\` makes the following character literal.
+pub fn matches(pattern: &str, text: &str, mapping: CaseMapping) -> bool {
+    glob(&tokenize(pattern), text, mapping)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum HostPattern {
+    Glob(Vec<Token>),
+    Cidr(IpAddr, u8),
+}
+
+impl HostPattern {
+    fn parse(data: &str) -> Self {
+        if let Some(pos) = data.find(CIDR_SEPARATOR) {
+            if let (Ok(address), Ok(bits)) = (data[..pos].parse::<IpAddr>(), data[pos + 1..].parse())
+            {
+                let max = if address.is_ipv4() { 32 } else { 128 };
+
+                if bits <= max {
+                    return HostPattern::Cidr(address, bits);
+                }
+            }
+        }
+
+        HostPattern::Glob(tokenize(data))
+    }
+
+    fn matches(&self, host: &str, mapping: CaseMapping) -> bool {
+        match self {
+            HostPattern::Glob(ref tokens) => glob(tokens, host, mapping),
+            HostPattern::Cidr(network, bits) => match host.parse::<IpAddr>() {
+                Ok(address) => in_network(&address, network, *bits),
+                Err(_) => false,
+            },
+        }
+    }
+}
+
+fn in_network(address: &IpAddr, network: &IpAddr, bits: u8) -> bool {
+    let mask = |bits: u8, width: u8| -> u128 {
+        if bits == 0 {
+            0
+        } else {
+            !0u128 << (width - bits)
+        }
+    };
+
+    match (address, network) {
+        (IpAddr::V4(address), IpAddr::V4(network)) => {
+            let mask = mask(bits, 32) as u32;
+
+            u32::from(*address) & mask == u32::from(*network) & mask
+        }
+        (IpAddr::V6(address), IpAddr::V6(network)) => {
+            let mask = mask(bits, 128);
+
+            u128::from(*address) & mask == u128::from(*network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Hostmask (`nick!user@host`) with wildcards, as used for bans, invite and exception lists
+///
+/// Incomplete masks get the missing parts filled in the way servers do: `nick` becomes
+/// `nick!*@*`, `user@host` becomes `*!user@host`. The host part may also be a CIDR range like
+/// `192.0.2.0/24`, which only matches hosts that are IP addresses.
+pub struct Mask {
+    mask: String,
+    nick: Vec<Token>,
+    user: Vec<Token>,
+    host: HostPattern,
+    mapping: CaseMapping,
+}
+
+impl Mask {
+    pub fn new(data: &str) -> Self {
+        let nick_end = data.find(IDENT_SEPARATOR);
+        let host_start = data.rfind(HOST_SEPARATOR);
+
+        let (nick, user, host) = match (nick_end, host_start) {
+            (Some(nick_end), Some(host_start)) if nick_end < host_start => (
+                &data[..nick_end],
+                &data[nick_end + 1..host_start],
+                &data[host_start + 1..],
+            ),
+            (_, Some(host_start)) => ("", &data[..host_start], &data[host_start + 1..]),
+            (Some(nick_end), None) => (&data[..nick_end], &data[nick_end + 1..], ""),
+            (None, None) if data.contains(|c| c == '.' || c == ':') => ("", "", data),
+            (None, None) => (data, "", ""),
+        };
+
+        let or_any = |part: &str| {
+            if part.is_empty() {
+                ANY.to_string()
+            } else {
+                part.to_string()
+            }
+        };
+
+        let (nick, user, host) = (or_any(nick), or_any(user), or_any(host));
+
+        Mask {
+            mask: format!("{}{}{}{}{}", nick, IDENT_SEPARATOR, user, HOST_SEPARATOR, host),
+            nick: tokenize(&nick),
+            user: tokenize(&user),
+            host: HostPattern::parse(&host),
+            mapping: CaseMapping::default(),
+        }
+    }
+
+    /// Case mapping used to compare, `rfc1459` by default
+    pub fn casemapping(mut self, mapping: CaseMapping) -> Self {
+        self.mapping = mapping;
+
+        self
+    }
+
+    /// Ban mask for `origin` in the given style, `None` if it isn't a user with a host
+    pub fn ban(origin: &Origin, style: BanStyle) -> Option<Self> {
+        let nick = origin.nick()?;
+        let host = origin.host()?;
+        let user = origin.user().map(ban_user).unwrap_or_else(|| ANY.to_string());
+
+        let mask = match style {
+            BanStyle::Nick => format!("{}!*@*", nick),
+            BanStyle::Host => format!("*!*@{}", host),
+            BanStyle::UserHost => format!("*!{}@{}", user, host),
+            BanStyle::Domain => format!("*!*@{}", ban_domain(host)),
+            BanStyle::UserDomain => format!("*!{}@{}", user, ban_domain(host)),
+            BanStyle::Full => format!("{}!{}@{}", nick, user, host),
+        };
+
+        Some(Mask::new(&mask))
+    }
+}
+
+impl Mask {
+    pub fn as_str(&self) -> &str {
+        self.mask.as_str()
+    }
+
+    pub fn matches(&self, origin: &Origin) -> bool {
+        match origin {
+            Origin::User {
+                ref nick,
+                ref user,
+                ref host,
+            } => self.matches_parts(
+                nick,
+                user.as_ref().map(|u| u.as_str()).unwrap_or_default(),
+                host.as_ref().map(|h| h.as_str()).unwrap_or_default(),
+            ),
+            _ => false,
+        }
+    }
+
+    /// Matches a full `nick!user@host` without parsing it into an `Origin` first
+    pub fn matches_str(&self, data: &str) -> bool {
+        let (prefix, host) = match data.rfind(HOST_SEPARATOR) {
+            Some(pos) => (&data[..pos], &data[pos + 1..]),
+            None => (data, ""),
+        };
+
+        let (nick, user) = match prefix.find(IDENT_SEPARATOR) {
+            Some(pos) => (&prefix[..pos], &prefix[pos + 1..]),
+            None => (prefix, ""),
+        };
+
+        self.matches_parts(nick, user, host)
+    }
+
+    pub fn matches_parts(&self, nick: &str, user: &str, host: &str) -> bool {
+        glob(&self.nick, nick, self.mapping)
+            && glob(&self.user, user, self.mapping)
+            && self.host.matches(host, self.mapping)
+    }
+}
+
+/// Builds the normalized mask
+impl ToString for Mask {
+    fn to_string(&self) -> String {
+        self.mask.clone()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BanStyle {
+    /// `nick!*@*`
+    Nick,
+    /// `*!*@host`
+    Host,
+    /// `*!user@host`
+    UserHost,
+    /// `*!*@*.domain`, `*!*@192.0.2.*` or `*!*@2001:db8::/64`
+    Domain,
+    /// `*!user@*.domain`
+    UserDomain,
+    /// `nick!user@host`
+    Full,
+}
+
+/// Users without ident get a `~` in front, which shouldn't make a difference
+fn ban_user(user: &str) -> String {
+    if user.starts_with('~') {
+        format!("{}{}", ANY, &user[1..])
+    } else {
+        user.to_string()
+    }
+}
+
+fn ban_domain(host: &str) -> String {
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(address)) => {
+            let octets = address.octets();
+
+            format!("{}.{}.{}.{}", octets[0], octets[1], octets[2], ANY)
+        }
+        Ok(IpAddr::V6(address)) => {
+            let network = u128::from(address) & (!0u128 << 64);
+
+            format!("{}{}64", std::net::Ipv6Addr::from(network), CIDR_SEPARATOR)
+        }
+        Err(_) => {
+            let labels = host.split('.').collect::<Vec<&str>>();
+
+            if labels.len() > 2 {
+                format!("{}.{}", ANY, labels[1..].join("."))
+            } else {
+                host.to_string()
+            }
+        }
+    }
+}
+
+pub const EXTBAN_PREFIXES: &str = "$~";
+
+pub const EXTBAN_SEPARATOR: char = ':';
+
+#[derive(Debug, Clone, PartialEq)]
+/// Extended ban like `$a:account`, `$~a` (charybdis style) or `~q:nick!*@*`, `~a:account`
+/// (UnrealIRCd style)
+pub struct ExtBan {
+    prefix: char,
+    negated: bool,
+    kind: String,
+    value: Option<String>,
+}
+
+impl ExtBan {
+    /// `None` if `data` isn't an extended ban, so it's a plain mask
+    pub fn parse(data: &str) -> Option<Self> {
+        let prefix = data.chars().next().filter(|c| EXTBAN_PREFIXES.contains(*c))?;
+        let mut rest = &data[1..];
+
+        let negated = match rest.chars().next() {
+            Some('~') if prefix == '$' => true,
+            Some('!') => true,
+            _ => false,
+        };
+
+        if negated {
+            rest = &rest[1..];
+        }
+
+        let (kind, value) = match rest.find(EXTBAN_SEPARATOR) {
+            Some(pos) => (&rest[..pos], Some(rest[pos + 1..].to_string())),
+            None => (rest, None),
+        };
+
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+
+        // `~nick` style hosts aren't extended bans, those always need a value
+        if prefix == '~' && value.is_none() {
+            return None;
+        }
+
+        Some(ExtBan {
+            prefix,
+            negated,
+            kind: kind.to_string(),
+            value: value.filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+impl ExtBan {
+    pub fn prefix(&self) -> char {
+        self.prefix
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    pub fn kind(&self) -> &str {
+        self.kind.as_str()
+    }
+
+    pub fn value(&self) -> Option<&str> {
+        self.value.as_ref().map(|v| v.as_str())
+    }
+
+    pub fn is_account(&self) -> bool {
+        self.kind == "a" || self.kind.eq_ignore_ascii_case("account")
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.kind == "q" || self.kind.eq_ignore_ascii_case("quiet")
+    }
+
+    /// The mask of extended bans wrapping one, like quiets
+    pub fn mask(&self) -> Option<Mask> {
+        if self.is_quiet() {
+            self.value().map(Mask::new)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the ban applies to the user, `None` for types that can't be checked from the
+    /// origin and account alone
+    pub fn matches(
+        &self,
+        origin: &Origin,
+        account: Option<&str>,
+        mapping: CaseMapping,
+    ) -> Option<bool> {
+        let matched = if self.is_account() {
+            match (self.value(), account) {
+                (Some(pattern), Some(account)) => matches(pattern, account, mapping),
+                // without an account name any logged in user matches
+                (None, account) => account.is_some(),
+                (Some(_), None) => false,
+            }
+        } else if self.is_quiet() {
+            self.mask()?.casemapping(mapping).matches(origin)
+        } else {
+            return None;
+        };
+
+        Some(matched != self.negated)
+    }
+}
+
+/// Builds the extended ban
+impl ToString for ExtBan {
+    fn to_string(&self) -> String {
+        let negation = match (self.negated, self.prefix) {
+            (false, _) => "",
+            (true, '$') => "~",
+            (true, _) => "!",
+        };
+
+        match self.value {
+            Some(ref value) => format!(
+                "{}{}{}{}{}",
+                self.prefix, negation, self.kind, EXTBAN_SEPARATOR, value
+            ),
+            None => format!("{}{}{}", self.prefix, negation, self.kind),
+        }
+    }
+}
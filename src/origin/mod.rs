@@ -1,6 +1,10 @@
 use crate::validate;
 use std::error::Error;
 
+pub mod mask;
+
+pub use self::mask::{BanStyle, ExtBan, Mask};
+
 pub mod error {
     impl_error!(IllegalOriginFormatError {validation: Box<std::error::Error>});
 }
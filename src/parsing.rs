@@ -1,6 +1,8 @@
 use std::error::Error;
 
-use crate::{command::TRAILING_DELIMITER, validate, LIST_ITEM_DELIMITER, SEPARATOR};
+use crate::{
+    command::TRAILING_DELIMITER, target::MessageTarget, validate, LIST_ITEM_DELIMITER, SEPARATOR,
+};
 
 fn current_parameter(data: &str) -> &str {
     if let Some(pos) = data.find(SEPARATOR) {
@@ -24,7 +26,7 @@ pub fn skip_parameter(data: &str) -> &str {
 }
 
 pub fn skip_maybe_trailing(data: &str) -> &str {
-    if &data[..1] == TRAILING_DELIMITER {
+    if data.starts_with(TRAILING_DELIMITER) {
         &data[1..]
     } else {
         data
@@ -73,26 +75,13 @@ pub fn target(data: &str) -> Result<&str, Box<Error>> {
     (nick_name(data).or(server_name(data))).and_then(|_| Ok(data))
 }
 
-pub fn msg_target(data: &str) -> Result<Vec<&str>, Box<Error>> {
-    let _targets = current_parameter(data);
-    let targets = list(_targets);
-
-    let targets_iter = targets.iter().map(|target| msg_to(target));
-
-    let mut errors = targets_iter
-        .clone()
-        .filter(|r| r.is_err())
-        .map(|r| r.err().unwrap())
-        .collect::<Vec<Box<Error>>>();
-
-    if errors.is_empty() {
-        Ok(targets_iter.map(|t| t.unwrap()).collect())
-    } else {
-        Err(errors.pop().unwrap())
-    }
+pub fn msg_target(data: &str) -> Result<Vec<MessageTarget>, Box<Error>> {
+    list(current_parameter(data))
+        .into_iter()
+        .map(msg_to)
+        .collect()
 }
 
-pub fn msg_to(data: &str) -> Result<&str, Box<Error>> {
-    // TODO: 2 out of 6 implemented..
-    channel_name(data).or(nick_name(data))
+pub fn msg_to(data: &str) -> Result<MessageTarget, Box<Error>> {
+    MessageTarget::parse(data)
 }
@@ -0,0 +1,155 @@
+use std::{cell::RefCell, convert::TryFrom, error::Error, rc::Rc};
+
+use rhai::Engine;
+
+use crate::{command::client::Command, connection::client::Client, message::Message};
+
+/// Something a script asked for, done once the script returned
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Action {
+    Raw(String),
+    PrivMsg {
+        target: String,
+        text: String,
+    },
+    Notice {
+        target: String,
+        text: String,
+    },
+    Join {
+        channel: String,
+    },
+    Part {
+        channel: String,
+        reason: Option<String>,
+    },
+    Kick {
+        channel: String,
+        nick: String,
+        reason: Option<String>,
+    },
+}
+
+impl Action {
+    pub(super) fn run(self, client: &Client) -> Result<(), Box<Error>> {
+        match self {
+            Action::Raw(line) => {
+                client.send(Message::<Command>::try_from(line.as_str())?)?;
+            }
+            Action::PrivMsg { target, text } => {
+                client.privmsg(&target, &text)?;
+            }
+            Action::Notice { target, text } => {
+                client.notice(&target, &text)?;
+            }
+            Action::Join { channel } => {
+                client.send(Command::Join {
+                    channels: vec![channel],
+                    keys: Vec::new(),
+                })?;
+            }
+            Action::Part { channel, reason } => {
+                client.send(Command::Part {
+                    channels: vec![channel],
+                    reason,
+                })?;
+            }
+            Action::Kick {
+                channel,
+                nick,
+                reason,
+            } => {
+                client.send(Command::Kick {
+                    channels: vec![channel],
+                    users: vec![nick],
+                    reason,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// What the functions scripts call share with the host
+#[derive(Debug, Default)]
+pub(super) struct State {
+    pub(super) nick: RefCell<String>,
+    pub(super) actions: RefCell<Vec<Action>>,
+}
+
+impl State {
+    fn push(&self, action: Action) {
+        self.actions.borrow_mut().push(action);
+    }
+}
+
+/// Makes `nick()`, `send(line)`, `privmsg(target, text)`, `notice(target, text)`,
+/// `join(channel)`, `part(channel[, reason])` and `kick(channel, nick[, reason])` available to
+/// scripts
+pub(super) fn register(engine: &mut Engine, state: &Rc<State>) {
+    let shared = state.clone();
+    engine.register_fn("nick", move || shared.nick.borrow().clone());
+
+    let shared = state.clone();
+    engine.register_fn("send", move |line: &str| {
+        shared.push(Action::Raw(line.to_string()))
+    });
+
+    let shared = state.clone();
+    engine.register_fn("privmsg", move |target: &str, text: &str| {
+        shared.push(Action::PrivMsg {
+            target: target.to_string(),
+            text: text.to_string(),
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("notice", move |target: &str, text: &str| {
+        shared.push(Action::Notice {
+            target: target.to_string(),
+            text: text.to_string(),
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("join", move |channel: &str| {
+        shared.push(Action::Join {
+            channel: channel.to_string(),
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("part", move |channel: &str| {
+        shared.push(Action::Part {
+            channel: channel.to_string(),
+            reason: None,
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("part", move |channel: &str, reason: &str| {
+        shared.push(Action::Part {
+            channel: channel.to_string(),
+            reason: Some(reason.to_string()),
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("kick", move |channel: &str, nick: &str| {
+        shared.push(Action::Kick {
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            reason: None,
+        })
+    });
+
+    let shared = state.clone();
+    engine.register_fn("kick", move |channel: &str, nick: &str, reason: &str| {
+        shared.push(Action::Kick {
+            channel: channel.to_string(),
+            nick: nick.to_string(),
+            reason: Some(reason.to_string()),
+        })
+    });
+}
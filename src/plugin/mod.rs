@@ -0,0 +1,367 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rhai::{Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+
+use crate::{
+    command::client::Command, connection::client::Client, message::Message, target::MessageTarget,
+};
+
+mod api;
+
+use self::api::State;
+
+pub mod error {
+    impl_error!(ScriptError {
+        script: String,
+        reason: String
+    });
+}
+
+/// Files in the plugin directory with other extensions are ignored
+pub const EXTENSION: &str = "rhai";
+pub const DEFAULT_TIME_LIMIT: Duration = Duration::from_millis(250);
+
+const MAX_STRING_SIZE: usize = 64 * 1024;
+const MAX_COLLECTION_SIZE: usize = 10_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// When and how large a file was the last time it was loaded
+type Stamp = (SystemTime, u64);
+
+struct Script {
+    name: String,
+    path: PathBuf,
+    ast: AST,
+    /// Variables the script declared outside its functions, kept between events
+    scope: Scope<'static>,
+}
+
+/// Runs the Rhai scripts in a directory on the events a `Client` reads
+///
+/// Scripts handle an event by defining a function for it:
+///
+/// - `on_message(nick, target, text)` and `on_notice(nick, target, text)`
+/// - `on_join(nick, channel)`, `on_part(nick, channel, reason)`,
+///   `on_kick(nick, channel, kicked, reason)`
+/// - `on_quit(nick, reason)`, `on_nick(nick, new_nick)`
+/// - `on_numeric(code, params)`
+///
+/// They act through `send`, `privmsg`, `notice`, `join`, `part` and `kick`, which take effect
+/// once the script returned. A script running longer than the time limit is stopped and its
+/// actions are dropped.
+pub struct Host {
+    directory: PathBuf,
+    time_limit: Duration,
+    engine: Engine,
+    state: Rc<State>,
+    deadline: Rc<Cell<Option<Instant>>>,
+    scripts: Vec<Script>,
+    stamps: HashMap<PathBuf, Stamp>,
+}
+
+impl Host {
+    /// Nothing is loaded before the first `reload`
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        let state = Rc::new(State::default());
+        let deadline = Rc::new(Cell::new(None));
+        let mut engine = Engine::new();
+
+        let expires = deadline.clone();
+        engine.on_progress(move |_| match expires.get() {
+            Some(deadline) if Instant::now() > deadline => Some(Dynamic::UNIT),
+            _ => None,
+        });
+
+        engine
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .disable_symbol("eval");
+
+        api::register(&mut engine, &state);
+
+        Host {
+            directory: directory.as_ref().to_path_buf(),
+            time_limit: DEFAULT_TIME_LIMIT,
+            engine,
+            state,
+            deadline,
+            scripts: Vec::new(),
+            stamps: HashMap::new(),
+        }
+    }
+
+    /// How long a script may run for one event, `DEFAULT_TIME_LIMIT` by default
+    pub fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = limit;
+
+        self
+    }
+}
+
+impl Host {
+    pub fn directory(&self) -> &Path {
+        self.directory.as_path()
+    }
+
+    /// Names of the loaded scripts, the file names without the extension
+    pub fn scripts(&self) -> Vec<&str> {
+        self.scripts
+            .iter()
+            .map(|script| script.name.as_str())
+            .collect()
+    }
+
+    /// Loads new and changed scripts and forgets deleted ones. A script that fails to compile
+    /// or to run keeps its previous version, the first such error is returned after all files
+    /// were looked at. Cheap enough to call before every event.
+    pub fn reload(&mut self) -> Result<(), Box<Error>> {
+        let mut found = Vec::new();
+        let mut result = Ok(());
+
+        for entry in fs::read_dir(&self.directory)? {
+            let path = entry?.path();
+
+            if path.extension().and_then(|extension| extension.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+
+            let metadata = fs::metadata(&path)?;
+
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let stamp = (metadata.modified()?, metadata.len());
+            found.push(path.clone());
+
+            if self.stamps.get(&path) == Some(&stamp) {
+                continue;
+            }
+
+            self.stamps.insert(path.clone(), stamp);
+
+            if let Err(e) = self.load(path) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+
+        self.scripts.retain(|script| found.contains(&script.path));
+        self.stamps.retain(|path, _| found.contains(path));
+
+        result
+    }
+
+    /// Runs the functions the scripts have for the events in `message`, then does what they
+    /// asked for. Scripts that fail don't keep the others from running, the first error is
+    /// returned at the end.
+    pub fn handle(
+        &mut self,
+        client: &Client,
+        message: &Message<Command>,
+    ) -> Result<(), Box<Error>> {
+        let events = events(client, message);
+
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        *self.state.nick.borrow_mut() = client.nick().to_string();
+
+        let mut actions = Vec::new();
+        let mut result = Ok(());
+
+        for script in self.scripts.iter_mut() {
+            for (name, args) in events.iter() {
+                let defined = script
+                    .ast
+                    .iter_functions()
+                    .any(|function| function.name == *name && function.params.len() == args.len());
+
+                if !defined {
+                    continue;
+                }
+
+                self.deadline.set(Some(Instant::now() + self.time_limit));
+
+                let called = self.engine.call_fn_with_options::<Dynamic>(
+                    CallFnOptions::new().eval_ast(false),
+                    &mut script.scope,
+                    &script.ast,
+                    name,
+                    args.clone(),
+                );
+
+                self.deadline.set(None);
+
+                let requested = self.state.actions.replace(Vec::new());
+
+                match called {
+                    Ok(_) => actions.extend(requested),
+                    Err(e) if result.is_ok() => {
+                        result = Err(error::ScriptError::new(script.name.clone(), reason(&e)))
+                    }
+                    Err(_) => (),
+                }
+            }
+        }
+
+        for action in actions {
+            action.run(client)?;
+        }
+
+        result
+    }
+
+    /// Compiles the file and runs what's outside its functions, replacing the version loaded
+    /// before if both worked
+    fn load(&mut self, path: PathBuf) -> Result<(), Box<Error>> {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let failed = |reason: String| error::ScriptError::new(name.clone(), reason);
+
+        let source = fs::read_to_string(&path)?;
+        let ast = self
+            .engine
+            .compile(&source)
+            .map_err(|e| failed(e.to_string()))?;
+
+        let mut scope = Scope::new();
+
+        self.deadline.set(Some(Instant::now() + self.time_limit));
+        let ran = self.engine.run_ast_with_scope(&mut scope, &ast);
+        self.deadline.set(None);
+
+        // actions outside of events aren't done
+        self.state.actions.borrow_mut().clear();
+        ran.map_err(|e| failed(reason(&e)))?;
+
+        let script = Script {
+            name: name.clone(),
+            path,
+            ast,
+            scope,
+        };
+
+        match self
+            .scripts
+            .iter()
+            .position(|other| other.path == script.path)
+        {
+            Some(pos) => self.scripts[pos] = script,
+            None => self.scripts.push(script),
+        }
+
+        Ok(())
+    }
+}
+
+/// The functions to call for `message` with their arguments, our own messages and notices
+/// aren't events
+fn events(client: &Client, message: &Message<Command>) -> Vec<(&'static str, Vec<Dynamic>)> {
+    let nick = message.origin().nick().unwrap_or_default();
+    let is_myself = client.server().casemapping().eq(nick, client.nick());
+
+    let text = |text: &str| Dynamic::from(text.to_string());
+    let reason = |reason: &Option<String>| text(reason.as_ref().map_or("", |r| r.as_str()));
+
+    match message.command() {
+        Command::PrivMsg {
+            targets,
+            text: body,
+        } if !is_myself => targets
+            .iter()
+            .map(|target| {
+                (
+                    "on_message",
+                    vec![text(nick), text(&target_name(target)), text(body)],
+                )
+            })
+            .collect(),
+
+        Command::Notice { target, text: body } if !is_myself => {
+            vec![(
+                "on_notice",
+                vec![text(nick), text(&target_name(target)), text(body)],
+            )]
+        }
+
+        Command::Join { channels, .. } => channels
+            .iter()
+            .map(|channel| ("on_join", vec![text(nick), text(channel)]))
+            .collect(),
+
+        Command::ExtendedJoin { channel, .. } => vec![("on_join", vec![text(nick), text(channel)])],
+
+        Command::Part {
+            channels,
+            reason: why,
+        } => channels
+            .iter()
+            .map(|channel| ("on_part", vec![text(nick), text(channel), reason(why)]))
+            .collect(),
+
+        // either one channel for all users or a channel for each user
+        Command::Kick {
+            channels,
+            users,
+            reason: why,
+        } => users
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, kicked)| {
+                let channel = channels.get(pos).or_else(|| channels.first())?;
+
+                Some((
+                    "on_kick",
+                    vec![text(nick), text(channel), text(kicked), reason(why)],
+                ))
+            })
+            .collect(),
+
+        Command::Quit { reason: why } => vec![("on_quit", vec![text(nick), reason(why)])],
+
+        Command::Nick { name } => vec![("on_nick", vec![text(nick), text(name)])],
+
+        Command::Numeric { code, params } => {
+            let params = params.iter().map(|param| text(param)).collect::<Array>();
+
+            vec![(
+                "on_numeric",
+                vec![Dynamic::from(i64::from(*code)), Dynamic::from(params)],
+            )]
+        }
+
+        _ => Vec::new(),
+    }
+}
+
+fn reason(error: &EvalAltResult) -> String {
+    match error {
+        EvalAltResult::ErrorTerminated(..) => "time limit exceeded".to_string(),
+        _ => error.to_string(),
+    }
+}
+
+fn target_name(target: &MessageTarget) -> String {
+    match target {
+        MessageTarget::Channel(ref channel) | MessageTarget::ChannelStatus { ref channel, .. } => {
+            channel.to_string()
+        }
+        _ => target.to_string(),
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{channel::RcChannel, origin::Origin, user::RcUser, utils::Defaults};
+use std::rc::Rc;
+
+use crate::{
+    casemapping::CaseMapping, channel::RcChannel, origin::Origin, user::RcUser, utils::Defaults,
+};
 
 #[derive(Debug)]
 pub enum Limit {
@@ -28,8 +32,14 @@ pub struct Server {
     // secure, insecure
     password: Option<String>,
     motd: Option<String>,
+    casemapping: CaseMapping,
+    info: String,
+    hop_count: u32,
+    token: u32,
+    uplink: Option<String>,
     users: Vec<RcUser>,
     channels: Vec<RcChannel>,
+    servers: Vec<Server>,
     // limits: Vec<Limits>
 }
 
@@ -47,6 +57,52 @@ impl Server {
         &self.origin
     }
 
+    pub fn set_origin(&mut self, origin: Origin) {
+        self.origin = origin;
+    }
+
+    pub fn name(&self) -> &str {
+        match self.origin {
+            Origin::Server { ref name } => name.as_str(),
+            _ => "",
+        }
+    }
+
+    pub fn info(&self) -> &str {
+        self.info.as_str()
+    }
+
+    pub fn set_info(&mut self, info: &str) {
+        self.info = info.to_string();
+    }
+
+    /// Distance to this server, `0` for the local one
+    pub fn hop_count(&self) -> u32 {
+        self.hop_count
+    }
+
+    pub fn set_hop_count(&mut self, hop_count: u32) {
+        self.hop_count = hop_count;
+    }
+
+    /// Token the local server knows this server by
+    pub fn token(&self) -> u32 {
+        self.token
+    }
+
+    pub fn set_token(&mut self, token: u32) {
+        self.token = token;
+    }
+
+    /// Server this one is linked to the network through, `None` for the local one
+    pub fn uplink(&self) -> Option<&str> {
+        self.uplink.as_ref().map(|uplink| uplink.as_str())
+    }
+
+    pub fn set_uplink(&mut self, uplink: Option<String>) {
+        self.uplink = uplink;
+    }
+
     pub fn users(&self) -> &Vec<RcUser> {
         &self.users
     }
@@ -70,6 +126,85 @@ impl Server {
     pub fn set_motd(&mut self, motd: Option<String>) {
         self.motd = motd;
     }
+
+    pub fn casemapping(&self) -> CaseMapping {
+        self.casemapping
+    }
+
+    pub fn set_casemapping(&mut self, casemapping: CaseMapping) {
+        self.casemapping = casemapping;
+    }
+}
+
+impl Server {
+    /// Looks the nick up with the server's case mapping
+    pub fn user(&self, nick: &str) -> Option<RcUser> {
+        self.users
+            .iter()
+            .find(|user| {
+                let user = user.borrow();
+
+                self.casemapping
+                    .eq(user.origin().nick().unwrap_or_default(), nick)
+            })
+            .cloned()
+    }
+
+    pub fn remove_user(&mut self, user: &RcUser) {
+        self.users.retain(|other| !Rc::ptr_eq(other, user));
+    }
+
+    /// Looks the channel up with the server's case mapping
+    pub fn channel(&self, name: &str) -> Option<RcChannel> {
+        self.channels
+            .iter()
+            .find(|channel| self.casemapping.eq(channel.borrow().name(), name))
+            .cloned()
+    }
+
+    pub fn remove_channel(&mut self, channel: &RcChannel) {
+        self.channels.retain(|other| !Rc::ptr_eq(other, channel));
+    }
+}
+
+impl Server {
+    /// Other servers of the network, each after its uplink
+    pub fn servers(&self) -> &Vec<Server> {
+        &self.servers
+    }
+
+    pub fn servers_mut(&mut self) -> &mut Vec<Server> {
+        &mut self.servers
+    }
+
+    /// Looks the server up, names are case insensitive
+    pub fn server(&self, name: &str) -> Option<&Server> {
+        self.servers
+            .iter()
+            .find(|server| server.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Removes the server and all servers linked through it, which are returned
+    pub fn remove_server(&mut self, name: &str) -> Vec<Server> {
+        let mut names = vec![name.to_ascii_lowercase()];
+        let mut removed = Vec::new();
+
+        // uplinks come first, so one pass finds the whole subtree
+        for server in self.servers.drain(..).collect::<Vec<Server>>() {
+            let behind = server
+                .uplink()
+                .map_or(false, |uplink| names.contains(&uplink.to_ascii_lowercase()));
+
+            if behind || names[0] == server.name().to_ascii_lowercase() {
+                names.push(server.name().to_ascii_lowercase());
+                removed.push(server);
+            } else {
+                self.servers.push(server);
+            }
+        }
+
+        removed
+    }
 }
 
 impl std::convert::TryFrom<&Origin> for Server {
@@ -79,10 +214,7 @@ impl std::convert::TryFrom<&Origin> for Server {
         if let Origin::Server { .. } = origin {
             Ok(Server {
                 origin: origin.clone(),
-                password: None,
-                motd: None,
-                users: Vec::new(),
-                channels: Vec::new(),
+                ..Default::default()
             })
         } else {
             Err(())
@@ -0,0 +1,176 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{formatting, limits, END_OF_MESSAGE, SEPARATOR};
+
+/// Bytes left for the text of `<prefix>COMMAND target :text\r\n`.
+/// `prefix_len` includes the leading `:` and the trailing space of the prefix.
+pub fn available(command: &str, target: &str, prefix_len: usize) -> usize {
+    let fixed = prefix_len + command.len() + target.len() + 2 * SEPARATOR.len() + 1;
+
+    limits::LINE.saturating_sub(fixed + END_OF_MESSAGE.len())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    text: String,
+    concat: bool,
+}
+
+impl Chunk {
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    /// Whether this chunk continues the previous one, which got split because it was too long
+    pub fn concat(&self) -> bool {
+        self.concat
+    }
+}
+
+/// Splits text into lines of at most `max` bytes. Embedded newlines start a new line, long lines
+/// are split at word boundaries (or grapheme boundaries if a word doesn't fit). Formatting active
+/// at a split is repeated at the start of the next chunk.
+pub fn split(text: &str, max: usize) -> Vec<Chunk> {
+    split_lines(text, max, false)
+}
+
+/// Like `split`, but chunks are kept verbatim, so joining the concatenated chunks restores the
+/// original line. Meant for `draft/multiline-concat`.
+pub fn split_concat(text: &str, max: usize) -> Vec<Chunk> {
+    split_lines(text, max, true)
+}
+
+fn split_lines(text: &str, max: usize, verbatim: bool) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+
+    for line in text.split('\n') {
+        let line = line.trim_end_matches('\r');
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let start = chunks.len();
+
+        split_line(line, max, verbatim, &mut chunks);
+
+        if let Some(first) = chunks.get_mut(start) {
+            first.concat = false;
+        }
+    }
+
+    chunks
+}
+
+fn split_line(line: &str, max: usize, verbatim: bool, chunks: &mut Vec<Chunk>) {
+    let mut current = String::new();
+    let mut prefix_len = 0;
+    let mut break_at = None;
+    let mut at_split = false;
+
+    for token in tokenize(line) {
+        if !current.is_empty() && current.len() + token.len() > max {
+            // split after the last space, if there is a word before it
+            let pos = match break_at {
+                _ if token == SEPARATOR => current.len(),
+                Some(pos) if pos > prefix_len => pos,
+                _ => current.len(),
+            };
+
+            let mut rest = current.split_off(pos);
+            let mut prefix = carry(&current, verbatim);
+
+            push(chunks, current, verbatim);
+
+            if !rest.is_empty() && prefix.len() + rest.len() + token.len() > max {
+                // the word in front of the token doesn't fit either
+                let text = prefix + rest.as_str();
+
+                prefix = carry(&text, verbatim);
+
+                if text.len() <= max {
+                    push(chunks, text, verbatim);
+                } else {
+                    push(chunks, rest, verbatim);
+                }
+
+                rest = String::new();
+            }
+
+            if prefix.len() + rest.len() + token.len() > max {
+                prefix.clear();
+            }
+
+            prefix_len = prefix.len();
+            current = prefix + rest.as_str();
+            break_at = None;
+            at_split = true;
+        }
+
+        if token.len() > max {
+            // a single grapheme longer than the limit, cut it at char boundaries
+            for c in token.chars() {
+                if !current.is_empty() && current.len() + c.len_utf8() > max {
+                    push(chunks, std::mem::replace(&mut current, String::new()), verbatim);
+                }
+
+                current.push(c);
+            }
+
+            continue;
+        }
+
+        if token == SEPARATOR && !verbatim && at_split && current.len() == prefix_len {
+            // no need to start a line with the space it got split at
+            continue;
+        }
+
+        current.push_str(token);
+        at_split = false;
+
+        if token == SEPARATOR {
+            break_at = Some(current.len());
+        }
+    }
+
+    push(chunks, current, verbatim);
+}
+
+/// Formatting codes to repeat in front of the text following `text`
+fn carry(text: &str, verbatim: bool) -> String {
+    if verbatim {
+        String::new()
+    } else {
+        formatting::style_after(text).codes()
+    }
+}
+
+fn push(chunks: &mut Vec<Chunk>, text: String, verbatim: bool) {
+    let text = if verbatim {
+        text
+    } else {
+        text.trim_end_matches(SEPARATOR).to_string()
+    };
+
+    if !formatting::strip(&text).is_empty() {
+        chunks.push(Chunk { text, concat: true });
+    }
+}
+
+/// Splits into graphemes, keeping formatting codes with their parameters in one piece
+fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < line.len() {
+        let len = match formatting::code_len(&line[pos..]) {
+            0 => line[pos..].graphemes(true).next().map(|g| g.len()).unwrap_or(1),
+            len => len,
+        };
+
+        tokens.push(&line[pos..pos + len]);
+        pos += len;
+    }
+
+    tokens
+}
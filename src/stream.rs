@@ -1,26 +1,45 @@
 use std::{
     convert::TryFrom,
     error::Error,
-    io::{prelude::*, ErrorKind::WouldBlock},
-    net::{Shutdown, TcpStream, ToSocketAddrs},
-    time::Duration,
-    cell::RefCell,
+    io::{prelude::*, ErrorKind::{NotFound, TimedOut, WouldBlock}},
+    net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+    cell::{Cell, RefCell},
+    collections::VecDeque,
 };
 
+use socket2::{Domain, Socket, Type};
+
 use native_tls::{
+    HandshakeError,
+    MidHandshakeTlsStream,
+    TlsAcceptor,
     TlsConnector,
     TlsStream,
 };
 
 use crate::{
     command::{client, server, Command},
+    encoding::Encoding,
     limits,
-    message::{Message, ToMessage},
+    message::{error::MessageTooLongError, Message, ToMessage},
     END_OF_MESSAGE,
     utils::Defaults,
 };
 
-#[derive(Debug)]
+pub mod error {
+    impl_error!(ConnectionClosedError {});
+    impl_error!(HandshakeTimeoutError {});
+    impl_error!(SendQueueExceededError { pending: usize });
+}
+
+/// Time a TLS handshake may take
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes which may wait for a slow peer before sending fails
+pub const SEND_QUEUE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Port {
     Secure(u16),
     Insecure(u16),
@@ -65,7 +84,6 @@ impl Into<u16> for Port {
     }
 }
 
-type MessageQueue<C> = Vec<Message<C>>;
 type SecureTcpStream = TlsStream<TcpStream>;
 
 #[derive(Debug)]
@@ -143,8 +161,13 @@ impl std::io::Write for InnerStream {
 #[derive(Debug)]
 pub struct Stream<C> {
     inner_stream: RefCell<InnerStream>,
-    message_queue: RefCell<MessageQueue<C>>,
-    buffer: RefCell<String>,
+    line_queue: RefCell<VecDeque<String>>,
+    buffer: RefCell<Vec<u8>>,
+    /// Sent, but not written to the socket yet
+    outgoing: RefCell<VecDeque<u8>>,
+    encoding: Cell<Encoding>,
+    closed: Cell<bool>,
+    command: std::marker::PhantomData<C>,
 }
 
 pub type ClientStream = Stream<client::Command>;
@@ -155,52 +178,100 @@ impl<C> Stream<C>
         C: Command,
 {
     pub fn connect(host: &str, port: Port, timeout: Option<std::time::Duration>) -> Result<Self, Box<Error>> {
-        let mut tcp_stream = if let Some(timeout) = timeout {
-            let mut last_err = None;
-            let mut stream = None;
-
-            for addr in (host, port.port()).to_socket_addrs()? {
-                match TcpStream::connect_timeout(&addr, timeout) {
-                    Ok(_stream) => stream = Some(_stream),
-                    Err(e) => last_err = Some(Box::new(e))
-                }
-            }
+        let tcp_stream = connect_tcp(host, port, timeout)?;
 
-            if stream.is_none() {
-                return Err(last_err.unwrap())
-            }
+        let stream = if port.secure() {
+            let tls_stream = tls_connector()?.connect(host, tcp_stream)?;
 
-            stream.unwrap()
+            InnerStream::Secure(tls_stream)
         } else {
-            TcpStream::connect((host, port.port()))?
+            InnerStream::Insecure(tcp_stream)
         };
 
-        let mut stream = if port.secure() {
-            let mut tls_stream = TlsConnector::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .min_protocol_version(Some(native_tls::Protocol::Tlsv12))
-                .build()?
-                .connect(host, tcp_stream)?;
+        Ok(Stream::new(stream))
+    }
 
-            InnerStream::Secure(tls_stream)
-        } else {
-            InnerStream::Insecure(tcp_stream)
+    /// Connects without blocking, `Dial::poll` tells once it's done. Only looking up the host
+    /// blocks.
+    pub fn dial(host: &str, port: Port, timeout: Option<std::time::Duration>) -> Dial {
+        let (addresses, error) = match (host, port.port()).to_socket_addrs() {
+            Ok(addresses) => (addresses.collect(), None),
+            Err(e) => (VecDeque::new(), Some(e)),
         };
 
+        Dial {
+            host: host.to_string(),
+            port,
+            timeout,
+            addresses,
+            attempt: None,
+            error,
+        }
+    }
+
+    /// Wraps a connection accepted by a listener. With an acceptor, the TLS handshake is started
+    /// without waiting for the client.
+    pub fn accept(tcp_stream: TcpStream, tls: Option<&TlsAcceptor>) -> Result<Accept<C>, Box<Error>> {
+        tcp_stream.set_nonblocking(true)?;
+
+        match tls {
+            Some(acceptor) => Handshake::finish(acceptor.accept(tcp_stream), Instant::now()),
+            None => Ok(Accept::Done(Stream::new(InnerStream::Insecure(tcp_stream)))),
+        }
+    }
+
+    fn new(mut stream: InnerStream) -> Self {
         let _ = stream.tcp_mut().set_nodelay(true);
         let _ = stream.tcp_mut().set_nonblocking(true);
 
-        Ok(Stream {
+        Stream {
             inner_stream: stream.into(),
-            message_queue: MessageQueue::new().into(),
-            buffer: format!("").into(),
-        })
+            line_queue: VecDeque::new().into(),
+            buffer: Vec::new().into(),
+            outgoing: VecDeque::new().into(),
+            encoding: Encoding::default().into(),
+            closed: false.into(),
+            command: std::marker::PhantomData,
+        }
+    }
+
+    /// The same connection speaking another protocol, like a server linking on a client port
+    pub fn convert<D>(self) -> Stream<D> {
+        Stream {
+            inner_stream: self.inner_stream,
+            line_queue: self.line_queue,
+            buffer: self.buffer,
+            outgoing: self.outgoing,
+            encoding: self.encoding,
+            closed: self.closed,
+            command: std::marker::PhantomData,
+        }
+    }
+
+    pub fn is_secure(&self) -> bool {
+        self.inner_stream.borrow().secure()
+    }
+
+    pub fn peer_addr(&self) -> Result<std::net::SocketAddr, Box<Error>> {
+        Ok(self.inner_stream.borrow().tcp().peer_addr()?)
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding.get()
+    }
+
+    /// Applies to lines read and sent from now on
+    pub fn set_encoding(&self, encoding: Encoding) {
+        self.encoding.set(encoding);
     }
 
     pub fn close(self) {
+        // whatever fits into the socket now, a peer not reading anymore won't get the rest
+        let _ = self.flush();
+
         if let Some(tls_stream) = self.inner_stream.borrow_mut().tls_mut() {
-            tls_stream.shutdown().expect("shutting down tls stream")
+            // the peer may be gone already
+            let _ = tls_stream.shutdown();
         }
 
         let _ = self.inner_stream.borrow_mut().tcp_mut().shutdown(Shutdown::Both);
@@ -215,10 +286,10 @@ impl<C> Stream<C>
             let mut read_buffer = [0u8; 16 * limits::MESSAGE];
 
             match self.inner_stream.borrow_mut().read(&mut read_buffer) {
-                Ok(size) => {
-                    let enc = String::from_utf8_lossy(&read_buffer[..size]).to_string();
-                    self.buffer.borrow_mut().push_str(&enc);
-                }
+                Ok(0) => self.closed.set(true),
+
+                // stays bytes until the line is complete, a read may end within a character
+                Ok(size) => self.buffer.borrow_mut().extend_from_slice(&read_buffer[..size]),
 
                 Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
                     return Ok(())
@@ -229,23 +300,26 @@ impl<C> Stream<C>
         }
 
         loop {
-            let line_end = if let Some(_line_end) = self.buffer.borrow().find(END_OF_MESSAGE) {
+            let line_end = if let Some(_line_end) = self
+                .buffer
+                .borrow()
+                .windows(END_OF_MESSAGE.len())
+                .position(|window| window == END_OF_MESSAGE.as_bytes())
+            {
                 _line_end
             } else {
                 break;
             };
 
-            let line = self.buffer.borrow_mut().drain(..line_end + 2).collect::<String>();
-
-            if let Ok(message) = Message::try_from(line.as_str()) {
-                self.message_queue.borrow_mut().push(message);
+            let data = self
+                .buffer
+                .borrow_mut()
+                .drain(..line_end + END_OF_MESSAGE.len())
+                .collect::<Vec<u8>>();
 
-                // TODO:    use this instead of former call after implementing all commands so it's
-                //          clear that the message received was malformated.
-                //
-                //                self.tcp_stream.read_exact(vec![0; line_end + 2].as_mut_slice())?;
-            } else {
-                break;
+            // one undecodable line doesn't break the connection, it's skipped like a malformed one
+            if let Ok(line) = self.encoding.get().decode(&data) {
+                self.line_queue.borrow_mut().push_back(line);
             }
         }
 
@@ -253,55 +327,259 @@ impl<C> Stream<C>
     }
 
     pub fn total(&self) -> usize {
-        self.message_queue.borrow().len()
+        self.line_queue.borrow().len()
     }
 
-    pub fn read(&self) -> Result<Option<Message<C>>, Box<Error>> {
-        if self.message_queue.borrow().is_empty() {
+    /// Next complete line including the end of message, without parsing it.
+    /// Fails once the peer closed the connection and all lines are read.
+    pub fn read_line(&self) -> Result<Option<String>, Box<Error>> {
+        // clients only write from here, if a line didn't fit into the socket at once
+        self.flush()?;
+
+        if self.line_queue.borrow().is_empty() {
             self.read_some()?;
         }
 
-        if self.message_queue.borrow().is_empty() {
-            Ok(None)
-        } else {
-            let message = self.message_queue.borrow_mut().remove(0);
-
-            let secure = if self.inner_stream.borrow().secure() {
-                "secure"
-            } else {
-                "insecure"
-            };
-
-            println!("IN ({})>  {:#?}", secure, message.command());
+        match self.line_queue.borrow_mut().pop_front() {
+            Some(line) => Ok(Some(line)),
+            None if self.closed.get() => Err(error::ConnectionClosedError::new()),
+            None => Ok(None),
+        }
+    }
 
-            Ok(Some(message))
+    /// Next message, lines which can't be parsed are skipped
+    pub fn read(&self) -> Result<Option<Message<C>>, Box<Error>> {
+        while let Some(line) = self.read_line()? {
+            // TODO:    report malformed messages instead of skipping them, once all commands are
+            //          implemented.
+            if let Ok(message) = Message::try_from(line.as_str()) {
+                return Ok(Some(message));
+            }
         }
+
+        Ok(None)
     }
 
     pub fn send<T>(&self, msg_or_cmd: T) -> Result<&Self, Box<Error>>
                    where
                        T: ToMessage<C> + std::fmt::Debug,
     {
-        let secure = if self.inner_stream.borrow().secure() {
-            "secure"
-        } else {
-            "insecure"
+        let line = msg_or_cmd.into_message().to_string();
+
+        // tags don't count towards the limit
+        let (tags, without_tags) = match line.find(crate::SEPARATOR) {
+            Some(pos) if line.starts_with(crate::message::TAGS_PREFIX) => line.split_at(pos + 1),
+            _ => ("", line.as_str()),
         };
 
-        println!("OUT ({})> {:#?}", secure, msg_or_cmd);
+        // tags are always UTF-8, the limit counts encoded bytes
+        let mut data = tags.as_bytes().to_vec();
+        let encoded = self.encoding.get().encode(without_tags)?;
+
+        if encoded.len() > limits::LINE {
+            return Err(MessageTooLongError::new());
+        }
 
-        self.inner_stream
-            .borrow_mut()
-            .write(msg_or_cmd.into_message().to_string().as_bytes())?;
+        data.extend_from_slice(&encoded);
+
+        self.outgoing.borrow_mut().extend(data);
+        self.flush()?;
 
         Ok(self)
     }
 
+    /// Bytes sent but not written to the socket yet
+    pub fn pending(&self) -> usize {
+        self.outgoing.borrow().len()
+    }
+
+    /// Writes as much of the lines sent as the socket takes without blocking. Fails if the peer
+    /// doesn't read and more than `SEND_QUEUE` bytes are waiting.
+    pub fn flush(&self) -> Result<(), Box<Error>> {
+        let mut outgoing = self.outgoing.borrow_mut();
+
+        while !outgoing.is_empty() {
+            let written = match self.inner_stream.borrow_mut().write(outgoing.as_slices().0) {
+                Ok(0) => return Err(error::ConnectionClosedError::new()),
+                Ok(written) => written,
+                Err(ref e) if e.kind() == WouldBlock => break,
+                Err(e) => return Err(e.into()),
+            };
+
+            outgoing.drain(..written);
+        }
+
+        if outgoing.len() > SEND_QUEUE {
+            return Err(error::SendQueueExceededError::new(outgoing.len()));
+        }
+
+        Ok(())
+    }
+
     pub fn iter(&self) -> Iter<C> {
         Iter { stream: self }
     }
 }
 
+/// An accepted or dialed connection, maybe still in its TLS handshake
+pub enum Accept<C> {
+    Done(Stream<C>),
+    Handshaking(Handshake),
+}
+
+/// TLS handshake waiting for the peer
+pub struct Handshake {
+    stream: MidHandshakeTlsStream<TcpStream>,
+    started: Instant,
+}
+
+impl Handshake {
+    /// Continues where the handshake stopped. Fails once it took longer than
+    /// `HANDSHAKE_TIMEOUT`.
+    pub fn resume<C>(self) -> Result<Accept<C>, Box<Error>>
+        where
+            C: Command,
+    {
+        if self.started.elapsed() >= HANDSHAKE_TIMEOUT {
+            return Err(error::HandshakeTimeoutError::new());
+        }
+
+        Handshake::finish(self.stream.handshake(), self.started)
+    }
+
+    fn finish<C>(
+        result: Result<SecureTcpStream, HandshakeError<TcpStream>>,
+        started: Instant,
+    ) -> Result<Accept<C>, Box<Error>>
+        where
+            C: Command,
+    {
+        match result {
+            Ok(tls_stream) => Ok(Accept::Done(Stream::new(InnerStream::Secure(tls_stream)))),
+            Err(HandshakeError::WouldBlock(stream)) => {
+                Ok(Accept::Handshaking(Handshake { stream, started }))
+            }
+            Err(HandshakeError::Failure(e)) => Err(e.into()),
+        }
+    }
+}
+
+/// An outgoing connection `Stream::dial` is setting up
+pub struct Dial {
+    host: String,
+    port: Port,
+    timeout: Option<Duration>,
+    /// Addresses of the host not tried yet
+    addresses: VecDeque<SocketAddr>,
+    /// The socket connecting to the current address, and since when
+    attempt: Option<(TcpStream, Instant)>,
+    /// Why the last address failed
+    error: Option<std::io::Error>,
+}
+
+impl Dial {
+    /// `None` while still connecting. Tries every address of the host in turn, each for at
+    /// most the timeout. A secure connection starts its handshake here.
+    pub fn poll<C>(&mut self) -> Result<Option<Accept<C>>, Box<Error>>
+        where
+            C: Command,
+    {
+        loop {
+            let (tcp_stream, started) = match self.attempt.take() {
+                Some(attempt) => attempt,
+                None => match self.addresses.pop_front() {
+                    Some(address) => match connect_nonblocking(&address) {
+                        Ok(tcp_stream) => (tcp_stream, Instant::now()),
+                        Err(e) => {
+                            self.error = Some(e);
+                            continue;
+                        }
+                    },
+                    None => {
+                        let error = self.error.take().unwrap_or_else(|| {
+                            std::io::Error::new(NotFound, "no address for the host")
+                        });
+
+                        return Err(error.into());
+                    }
+                },
+            };
+
+            if let Some(e) = tcp_stream.take_error()? {
+                self.error = Some(e);
+                continue;
+            }
+
+            // no peer until the connection is made
+            if tcp_stream.peer_addr().is_err() {
+                if self.timeout.is_some_and(|timeout| started.elapsed() >= timeout) {
+                    self.error = Some(std::io::Error::new(TimedOut, "connection timed out"));
+                    continue;
+                }
+
+                self.attempt = Some((tcp_stream, started));
+
+                return Ok(None);
+            }
+
+            return self.connected(tcp_stream).map(Some);
+        }
+    }
+
+    fn connected<C>(&self, tcp_stream: TcpStream) -> Result<Accept<C>, Box<Error>>
+        where
+            C: Command,
+    {
+        if !self.port.secure() {
+            return Ok(Accept::Done(Stream::new(InnerStream::Insecure(tcp_stream))));
+        }
+
+        Handshake::finish(tls_connector()?.connect(&self.host, tcp_stream), Instant::now())
+    }
+}
+
+/// Starts connecting to `address` on a non-blocking socket
+fn connect_nonblocking(address: &SocketAddr) -> std::io::Result<TcpStream> {
+    let socket = Socket::new(Domain::for_address(*address), Type::STREAM, None)?;
+    socket.set_nonblocking(true)?;
+
+    match socket.connect(&(*address).into()) {
+        Ok(()) => (),
+        // still connecting, `WouldBlock` on Windows
+        Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) || e.kind() == WouldBlock => (),
+        Err(e) => return Err(e),
+    }
+
+    Ok(socket.into())
+}
+
+/// Tries every address of `host` until one takes the connection
+fn connect_tcp(host: &str, port: Port, timeout: Option<Duration>) -> std::io::Result<TcpStream> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return TcpStream::connect((host, port.port())),
+    };
+
+    let mut last_err = None;
+
+    for addr in (host, port.port()).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err.unwrap_or_else(|| std::io::Error::new(NotFound, "no address for the host")))
+}
+
+fn tls_connector() -> Result<TlsConnector, Box<Error>> {
+    Ok(TlsConnector::builder()
+        .danger_accept_invalid_certs(true)
+        .danger_accept_invalid_hostnames(true)
+        .min_protocol_version(Some(native_tls::Protocol::Tlsv12))
+        .build()?)
+}
+
 pub struct Iter<'a, C> {
     stream: &'a Stream<C>
 }
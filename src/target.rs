@@ -0,0 +1,226 @@
+use std::error::Error;
+
+use crate::{
+    origin::{HOST_SEPARATOR, IDENT_SEPARATOR},
+    validate,
+};
+
+pub mod error {
+    impl_error!(IllegalTargetError {
+        target: String,
+        validation: Box<std::error::Error>
+    });
+}
+
+pub const SERVER_MASK_PREFIX: char = '$';
+pub const HOST_MASK_PREFIX: char = '#';
+pub const HOST_PERCENT: char = '%';
+/// Stands for the client in server notices sent before it registered
+pub const UNREGISTERED: &str = "*";
+
+/// Status prefixes (`STATUSMSG`) accepted in front of a channel
+pub const STATUS_PREFIXES: &str = "~&@%+";
+
+const WILDCARDS: &[char] = &['*', '?'];
+
+#[derive(Debug, Clone, PartialEq)]
+/// Target of `PRIVMSG` and `NOTICE` (`msgto` in RFC 2812)
+pub enum MessageTarget {
+    Channel(String),
+    /// `@#channel`, only reaches members with that status (or a higher one)
+    ChannelStatus { status: char, channel: String },
+    Nick(String),
+    /// `nick!user@host`
+    NickMask {
+        nick: String,
+        user: String,
+        host: String,
+    },
+    /// `user[%host]@server`
+    UserServer {
+        user: String,
+        host: Option<String>,
+        server: String,
+    },
+    /// `user%host`
+    UserHost { user: String, host: String },
+    /// `$*.example.com`, everybody on matching servers (operators only)
+    ServerMask(String),
+    /// `#*.example.com`, everybody with a matching host (operators only)
+    HostMask(String),
+    /// `*`, a client that hasn't registered yet (`NOTICE * :*** Looking up your hostname`)
+    Unregistered,
+}
+
+/// `targetmask` needs a `.` and no wildcards after the last one
+fn is_target_mask(mask: &str) -> bool {
+    match mask.rfind('.') {
+        Some(pos) => !mask[pos + 1..].contains(WILDCARDS) && pos + 1 < mask.len(),
+        None => false,
+    }
+}
+
+impl MessageTarget {
+    pub fn parse(data: &str) -> Result<Self, Box<Error>> {
+        let illegal = |e| error::IllegalTargetError::new(data.to_string(), e);
+
+        if data == UNREGISTERED {
+            return Ok(MessageTarget::Unregistered);
+        }
+
+        let first = match data.chars().next() {
+            Some(first) => first,
+            None => return Err(illegal(validate::nick_name(data).unwrap_err())),
+        };
+
+        if STATUS_PREFIXES.contains(first) && validate::channel_name(&data[1..]).is_ok() {
+            return Ok(MessageTarget::ChannelStatus {
+                status: first,
+                channel: data[1..].to_string(),
+            });
+        }
+
+        if first == SERVER_MASK_PREFIX && is_target_mask(&data[1..]) {
+            return Ok(MessageTarget::ServerMask(data[1..].to_string()));
+        }
+
+        // a channel name with wildcards and a domain is more likely a mask
+        if first == HOST_MASK_PREFIX && data.contains(WILDCARDS) && is_target_mask(&data[1..]) {
+            return Ok(MessageTarget::HostMask(data[1..].to_string()));
+        }
+
+        if validate::channel_name(data).is_ok() {
+            return Ok(MessageTarget::Channel(data.to_string()));
+        }
+
+        if let Some(at) = data.rfind(HOST_SEPARATOR) {
+            let (before, after) = (&data[..at], &data[at + 1..]);
+
+            if let Some(bang) = before.find(IDENT_SEPARATOR) {
+                let (nick, user) = (&before[..bang], &before[bang + 1..]);
+
+                validate::nick_name(nick).map_err(illegal)?;
+                validate::user_name(user).map_err(illegal)?;
+                validate::host_name(after).map_err(illegal)?;
+
+                return Ok(MessageTarget::NickMask {
+                    nick: nick.to_string(),
+                    user: user.to_string(),
+                    host: after.to_string(),
+                });
+            }
+
+            let (user, host) = match before.find(HOST_PERCENT) {
+                Some(percent) => (&before[..percent], Some(&before[percent + 1..])),
+                None => (before, None),
+            };
+
+            validate::user_name(user).map_err(illegal)?;
+            validate::host_name(after).map_err(illegal)?;
+
+            if let Some(host) = host {
+                validate::host_name(host).map_err(illegal)?;
+            }
+
+            return Ok(MessageTarget::UserServer {
+                user: user.to_string(),
+                host: host.map(|h| h.to_string()),
+                server: after.to_string(),
+            });
+        }
+
+        if let Some(percent) = data.find(HOST_PERCENT) {
+            let (user, host) = (&data[..percent], &data[percent + 1..]);
+
+            validate::user_name(user).map_err(illegal)?;
+            validate::host_name(host).map_err(illegal)?;
+
+            return Ok(MessageTarget::UserHost {
+                user: user.to_string(),
+                host: host.to_string(),
+            });
+        }
+
+        validate::nick_name(data).map_err(illegal)?;
+
+        Ok(MessageTarget::Nick(data.to_string()))
+    }
+}
+
+impl MessageTarget {
+    pub fn is_channel(&self) -> bool {
+        match self {
+            MessageTarget::Channel(_) | MessageTarget::ChannelStatus { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// The channel, without any status prefix
+    pub fn channel(&self) -> Option<&str> {
+        match self {
+            MessageTarget::Channel(ref channel)
+            | MessageTarget::ChannelStatus { ref channel, .. } => Some(channel.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn nick(&self) -> Option<&str> {
+        match self {
+            MessageTarget::Nick(ref nick) | MessageTarget::NickMask { ref nick, .. } => {
+                Some(nick.as_str())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn is_mask(&self) -> bool {
+        match self {
+            MessageTarget::ServerMask(_) | MessageTarget::HostMask(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl std::convert::TryFrom<&str> for MessageTarget {
+    type Error = Box<Error>;
+
+    fn try_from(data: &str) -> Result<Self, Self::Error> {
+        MessageTarget::parse(data)
+    }
+}
+
+/// Builds the target as sent
+impl ToString for MessageTarget {
+    fn to_string(&self) -> String {
+        match self {
+            MessageTarget::Channel(ref channel) => channel.to_string(),
+            MessageTarget::ChannelStatus {
+                ref status,
+                ref channel,
+            } => format!("{}{}", status, channel),
+            MessageTarget::Nick(ref nick) => nick.to_string(),
+            MessageTarget::NickMask {
+                ref nick,
+                ref user,
+                ref host,
+            } => format!("{}{}{}{}{}", nick, IDENT_SEPARATOR, user, HOST_SEPARATOR, host),
+            MessageTarget::UserServer {
+                ref user,
+                ref host,
+                ref server,
+            } => match host {
+                Some(ref host) => format!(
+                    "{}{}{}{}{}",
+                    user, HOST_PERCENT, host, HOST_SEPARATOR, server
+                ),
+                None => format!("{}{}{}", user, HOST_SEPARATOR, server),
+            },
+            MessageTarget::UserHost { ref user, ref host } => {
+                format!("{}{}{}", user, HOST_PERCENT, host)
+            }
+            MessageTarget::ServerMask(ref mask) => format!("{}{}", SERVER_MASK_PREFIX, mask),
+            MessageTarget::HostMask(ref mask) => format!("{}{}", HOST_MASK_PREFIX, mask),
+            MessageTarget::Unregistered => UNREGISTERED.to_string(),
+        }
+    }
+}
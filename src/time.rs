@@ -0,0 +1,119 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Milliseconds since the epoch, as `server-time` and chat history count
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Formats milliseconds since the epoch like `server-time` does: `2019-01-04T14:33:26.123Z`
+pub fn format_time(millis: u64) -> String {
+    format!("{}.{:03}Z", date_time(millis / 1000), millis % 1000)
+}
+
+/// Formats seconds since the epoch without a fraction: `2019-01-04T14:33:26Z`
+pub fn format_seconds(seconds: u64) -> String {
+    format!("{}Z", date_time(seconds))
+}
+
+fn date_time(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Parses a `server-time` timestamp, the fraction of a second is optional
+pub fn parse_time(data: &str) -> Option<u64> {
+    let data = data.strip_suffix('Z')?;
+    let (date, time) = (data.get(..10)?, data.get(10..)?);
+
+    let time = time.strip_prefix('T')?;
+    let (time, fraction) = match time.find('.') {
+        Some(pos) => (&time[..pos], Some(&time[pos + 1..])),
+        None => (time, None),
+    };
+
+    let number = |data: &str| -> Option<u64> {
+        if data.is_empty() || !data.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        data.parse().ok()
+    };
+
+    let date = date.split('-').collect::<Vec<&str>>();
+    let time = time.split(':').collect::<Vec<&str>>();
+
+    if date.len() != 3 || date[0].len() != 4 || time.len() != 3 {
+        return None;
+    }
+
+    let (year, month, day) = (number(date[0])?, number(date[1])?, number(date[2])?);
+    let (hour, minute, second) = (number(time[0])?, number(time[1])?, number(time[2])?);
+
+    if year < 1970 || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let millis = match fraction {
+        Some(fraction) if fraction.len() <= 3 => {
+            number(fraction)? * 10u64.pow(3 - fraction.len() as u32)
+        }
+        // finer than milliseconds gets cut
+        Some(fraction) => number(fraction.get(..3)?)?,
+        None => 0,
+    };
+
+    let days = days_from_civil(year as i64, month as i64, day as i64) as u64;
+
+    Some(((days * 24 + hour) * 60 + minute) * 60 * 1000 + second * 1000 + millis)
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar, see
+/// http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
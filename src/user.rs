@@ -1,7 +1,10 @@
+use std::rc::Rc;
+
 use crate::{
-    channel::WeakChannel,
+    channel::{RcChannel, WeakChannel},
     mode::{user, Mode},
     origin::Origin,
+    time,
 };
 
 #[derive(Debug)]
@@ -10,6 +13,9 @@ pub struct User {
     real_name: String,
     modes: Vec<Mode<user::Mode>>,
     channels: Vec<WeakChannel>,
+    server: Option<String>,
+    timestamp: u64,
+    account: Option<String>,
 }
 
 impl User {
@@ -19,6 +25,9 @@ impl User {
             real_name: real_name.to_string(),
             modes: vec![],
             channels: vec![],
+            server: None,
+            account: None,
+            timestamp: time::now(),
         }
     }
 }
@@ -41,9 +50,53 @@ impl User {
         self.real_name.push_str(real_name);
     }
 
+    /// Server the user is connected to, `None` for users of the local server
+    pub fn server(&self) -> Option<&str> {
+        self.server.as_ref().map(|server| server.as_str())
+    }
+
+    pub fn set_server(&mut self, server: Option<String>) {
+        self.server = server;
+    }
+
+    /// Time of the last nick change in seconds, the older nick wins a collision
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = timestamp;
+    }
+
+    /// Account the user is logged into
+    pub fn account(&self) -> Option<&str> {
+        self.account.as_ref().map(|account| account.as_str())
+    }
+
+    pub fn set_account(&mut self, account: Option<String>) {
+        self.account = account;
+    }
+
     pub fn modes(&self) -> &Vec<Mode<user::Mode>> {
         &self.modes
     }
+
+    pub fn has_mode(&self, mode: &user::Mode) -> bool {
+        self.modes.iter().any(|m| m.mode() == mode)
+    }
+
+    pub fn set_modes(&mut self, modes: Vec<Mode<user::Mode>>) {
+        self.modes = modes.into_iter().filter(|m| m.granted()).collect();
+    }
+
+    /// Adds granted and removes revoked modes
+    pub fn apply_mode(&mut self, mode: &Mode<user::Mode>) {
+        self.modes.retain(|m| m.mode() != mode.mode());
+
+        if mode.granted() {
+            self.modes.push(mode.clone());
+        }
+    }
 }
 
 impl User {
@@ -66,6 +119,19 @@ impl User {
     pub fn in_channel(&self, name: &str) -> bool {
         self.channel(name).is_some()
     }
+
+    pub fn add_channel(&mut self, channel: &RcChannel) {
+        self.remove_channel(channel);
+        self.channels.push(Rc::downgrade(channel));
+    }
+
+    /// Also drops channels which are gone
+    pub fn remove_channel(&mut self, channel: &RcChannel) {
+        self.channels.retain(|weak| match weak.upgrade() {
+            Some(ref other) => !Rc::ptr_eq(other, channel),
+            None => false,
+        });
+    }
 }
 
 pub type WeakUser = std::rc::Weak<std::cell::RefCell<User>>;
@@ -0,0 +1,252 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{
+    bot::{Arg, Args, Bot, Definition, Permission, Value},
+    command::client::Command,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn parse_arguments() {
+    let specs = vec![
+        Arg::nick("who"),
+        Arg::integer("times"),
+        Arg::word("how").optional(),
+        Arg::rest("text").optional(),
+    ];
+
+    let args = Args::parse(&specs, "alice 3 \"very loud\" hello  there ").unwrap();
+    assert_eq!(args.len(), 4);
+    assert_eq!(args.text("who"), Some("alice"));
+    assert_eq!(args.integer("times"), Some(3));
+    assert_eq!(args.number("times"), Some(3.0));
+    assert_eq!(args.text("how"), Some("very loud"));
+    assert_eq!(args.text("text"), Some("hello  there"));
+
+    let args = Args::parse(&specs, "alice -2").unwrap();
+    assert_eq!(args.get("times"), Some(&Value::Integer(-2)));
+    assert_eq!(args.get("how"), None);
+
+    assert!(Args::parse(&specs, "alice").is_err());
+    assert!(Args::parse(&specs, "#alice 3").is_err());
+    assert!(Args::parse(&specs, "alice three").is_err());
+    assert!(Args::parse(&specs, "alice 3 \"open").is_err());
+
+    let specs = vec![Arg::channel("channel"), Arg::number("ratio")];
+
+    let args = Args::parse(&specs, "#room 0.5").unwrap();
+    assert_eq!(args.number("ratio"), Some(0.5));
+    assert!(Args::parse(&specs, "#room 0.5 more").is_err());
+    assert!(Args::parse(&[], "").unwrap().is_empty());
+
+    assert_eq!(
+        Definition::new("roll", |_| Ok(()))
+            .arg(Arg::integer("sides"))
+            .arg(Arg::integer("count").optional())
+            .arg(Arg::rest("comment").optional())
+            .usage("!"),
+        "!roll <sides> [count] [comment...]"
+    );
+}
+
+/// Runs a server and a bot called `bot` on `#room`, returns the port of the server
+fn start() -> u16 {
+    let port = common::start_ircd("irc.test", |builder| builder);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut client = common::connect(port, "bot").unwrap();
+
+        let mut bot = Bot::new()
+            .prefixes(vec!["!", "."])
+            .command(
+                Definition::new("echo", |context| {
+                    context.reply(context.args().text("text").unwrap_or_default())
+                })
+                .alias("say")
+                .arg(Arg::rest("text"))
+                .help("Says the text again"),
+            )
+            .command(
+                Definition::new("add", |context| {
+                    let args = context.args();
+                    let sum = args.number("a").unwrap() + args.number("b").unwrap();
+
+                    context.reply(&format!("{}: {}", context.nick(), sum))
+                })
+                .arg(Arg::number("a"))
+                .arg(Arg::number("b")),
+            )
+            .command(
+                Definition::new("slow", |context| context.reply("slow done"))
+                    .cooldown(Duration::from_secs(3600)),
+            )
+            .command(
+                Definition::new("secret", |context| context.reply("secret done"))
+                    .permission(Permission::Operator)
+                    .permission(Permission::Mask("trusted!*@*".to_string())),
+            );
+
+        client
+            .send(Command::Join {
+                channels: vec!["#room".to_string()],
+                keys: Vec::new(),
+            })
+            .unwrap();
+
+        let mut ready = Some(sender);
+
+        loop {
+            match client.read().unwrap() {
+                Some(message) => {
+                    if let Command::Numeric { code: 366, .. } = message.command() {
+                        if let Some(sender) = ready.take() {
+                            sender.send(()).unwrap();
+                        }
+                    }
+
+                    bot.handle(&client, &message).unwrap();
+                }
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    });
+
+    receiver.recv().unwrap();
+
+    port
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    fn register(port: u16, nick: &str) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut client = TestClient {
+            reader: BufReader::new(stream),
+        };
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+        client.expect(" 422 ");
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Skips lines until one is from the bot
+    fn reply(&mut self) -> String {
+        self.expect(":bot!")
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", part),
+                Ok(_) if line.contains(part) => return line.trim_end().to_string(),
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, part),
+            }
+        }
+    }
+}
+
+#[test]
+fn run_commands() {
+    let port = start();
+
+    let mut alice = TestClient::register(port, "alice");
+    alice.send("JOIN #room");
+    alice.expect(" 366 ");
+
+    alice.send("PRIVMSG #room :!echo hello  world");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :hello  world"));
+
+    alice.send("PRIVMSG #room :BOT, say addressed");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :addressed"));
+
+    alice.send("PRIVMSG #room :.add 1 2.5");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :alice: 3.5"));
+
+    // private messages need no prefix, answers go back privately
+    alice.send("PRIVMSG bot :add 1 x");
+    assert!(alice
+        .reply()
+        .ends_with(" PRIVMSG alice :Usage: !add <a> <b>"));
+
+    alice.send("PRIVMSG #room :!help");
+    assert!(alice
+        .reply()
+        .ends_with(" :Commands: !echo, !add, !slow, !secret, !help"));
+
+    // more usages or help there and to alice have to wait
+    alice.send("PRIVMSG #room :!help say");
+    alice.send("PRIVMSG #room :!add 1 x");
+    alice.send("PRIVMSG bot :help");
+    alice.send("PRIVMSG #room :!echo after help");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :after help"));
+
+    // the second one is still cooling down
+    alice.send("PRIVMSG #room :!slow");
+    alice.send("PRIVMSG #room :!slow");
+    alice.send("PRIVMSG #room :!echo after");
+    assert!(alice.reply().ends_with(" :slow done"));
+    assert!(alice.reply().ends_with(" :after"));
+
+    // the cooldown is per channel, or per sender in private
+    alice.send("PRIVMSG bot :slow");
+    assert!(alice.reply().ends_with(" PRIVMSG alice :slow done"));
+
+    // the bot joined first, so only it is an operator
+    alice.send("PRIVMSG #room :!secret");
+    assert!(alice
+        .reply()
+        .ends_with(" NOTICE alice :You may not use !secret"));
+
+    // and gets told only once
+    alice.send("PRIVMSG #room :!secret");
+    alice.send("PRIVMSG #room :!echo told once");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :told once"));
+
+    let mut trusted = TestClient::register(port, "trusted");
+    trusted.send("JOIN #room");
+    trusted.expect(" 366 ");
+    trusted.send("PRIVMSG #room :!secret");
+    assert!(trusted.reply().ends_with(" PRIVMSG #room :secret done"));
+
+    trusted.send("PRIVMSG bot :help say");
+    assert!(trusted
+        .reply()
+        .ends_with(" PRIVMSG trusted :!echo <text...> - Says the text again"));
+
+    // unknown commands and plain talk are ignored
+    alice.send("PRIVMSG #room :!unknown");
+    alice.send("PRIVMSG #room :just talking");
+    alice.send("PRIVMSG #room :!echo last");
+    assert!(alice.reply().ends_with(" :secret done"));
+    assert!(alice.reply().ends_with(" :last"));
+}
@@ -0,0 +1,205 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::bouncer::Bouncer;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const PASSWORD: &str = "secret";
+
+/// Runs a server and a bouncer connected to it as `bnc`, returns both ports
+fn start() -> (u16, u16) {
+    let server_port = common::start_ircd("irc.test", |builder| builder.motd("welcome"));
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut bouncer = Bouncer::builder()
+            .listen("127.0.0.1:0")
+            .password(PASSWORD)
+            .buffer(2)
+            .upstream(move || common::client(server_port, "bnc"))
+            .build()
+            .unwrap();
+
+        sender.send(bouncer.addresses()[0].port()).unwrap();
+        bouncer.run().unwrap();
+    });
+
+    (server_port, receiver.recv().unwrap())
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    fn connect(port: u16) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        TestClient {
+            reader: BufReader::new(stream),
+        }
+    }
+
+    fn register(port: u16, nick: &str, password: Option<&str>) -> Self {
+        let mut client = TestClient::connect(port);
+
+        if let Some(password) = password {
+            client.send(&format!("PASS {}", password));
+        }
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Round trip to the server, everything sent before has been handled afterwards
+    fn sync(&mut self) {
+        self.send("PING :sync");
+        self.expect("sync");
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        loop {
+            match self.next() {
+                Some(line) if line.contains(part) => return line,
+                Some(_) => (),
+                None => panic!("connection closed waiting for {:?}", part),
+            }
+        }
+    }
+
+    /// The next line, `None` once the connection is closed
+    fn next(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(line.trim_end().to_string()),
+            Err(e) => panic!("{} reading", e),
+        }
+    }
+}
+
+#[test]
+fn attach_and_playback() {
+    let (server_port, port) = start();
+
+    let mut first = TestClient::register(port, "someone", Some(PASSWORD));
+    assert!(first.expect(" 001 ").contains(" 001 bnc :Welcome"));
+    first.expect(" 376 ");
+
+    first.send("JOIN #room");
+    assert_eq!(first.expect("JOIN"), ":bnc!bnc@127.0.0.1 JOIN #room");
+    first.expect(" 366 ");
+
+    let mut alice = TestClient::register(server_port, "alice", None);
+    alice.expect(" 001 ");
+    alice.send("JOIN #room");
+    alice.expect(" 366 ");
+    alice.send("TOPIC #room :all about rooms");
+    alice.send("PRIVMSG #room :hi");
+
+    first.expect("TOPIC #room");
+    assert_eq!(
+        first.expect("PRIVMSG"),
+        ":alice!alice@127.0.0.1 PRIVMSG #room :hi"
+    );
+
+    // the upstream connection stays when the last client leaves
+    first.send("QUIT :bye");
+    while first.next().is_some() {}
+
+    alice.send("PRIVMSG #room :one");
+    alice.send("PRIVMSG #room :two");
+    alice.send("PRIVMSG bnc :three");
+    alice.sync();
+
+    let mut second = TestClient::register(port, "bnc", Some(PASSWORD));
+    second.expect(" 001 ");
+    second.expect(" 376 ");
+    assert_eq!(second.expect("JOIN"), ":bnc!bnc@127.0.0.1 JOIN #room");
+    assert_eq!(
+        second.expect(" 332 "),
+        ":irc.test 332 bnc #room :all about rooms"
+    );
+    assert!(second.expect(" 333 ").contains(" #room alice "));
+
+    let names = second.expect(" 353 ");
+    assert!(names.contains("@bnc"));
+    assert!(names.contains("alice"));
+    second.expect(" 366 ");
+
+    // the buffer only holds the latest two
+    assert_eq!(
+        second.next().unwrap(),
+        ":alice!alice@127.0.0.1 PRIVMSG #room :two"
+    );
+    assert_eq!(
+        second.next().unwrap(),
+        ":alice!alice@127.0.0.1 PRIVMSG bnc :three"
+    );
+
+    // several clients at once, messages one of them sends show up in the others
+    let mut third = TestClient::connect(port);
+    third.send("CAP LS 302");
+    assert!(third.expect("CAP").contains("server-time"));
+    third.send("CAP REQ :server-time");
+    third.expect("ACK");
+    third.send(&format!("PASS {}", PASSWORD));
+    third.send("NICK bnc");
+    third.send("USER bnc 0 * :bnc");
+    third.send("CAP END");
+    third.expect(" 366 ");
+
+    third.send("PRIVMSG #room :from the third");
+    assert_eq!(
+        alice.expect("third"),
+        ":bnc!bnc@127.0.0.1 PRIVMSG #room :from the third"
+    );
+    assert_eq!(
+        second.expect("third"),
+        ":bnc!bnc@127.0.0.1 PRIVMSG #room :from the third"
+    );
+
+    alice.send("PRIVMSG #room :to both");
+    assert_eq!(
+        second.expect("both"),
+        ":alice!alice@127.0.0.1 PRIVMSG #room :to both"
+    );
+    assert!(third.expect("both").starts_with("@time="));
+
+    // pings are answered by the bouncer
+    second.send("PING :token");
+    assert_eq!(second.expect("PONG"), ":irc.test PONG irc.test token");
+}
+
+#[test]
+fn wrong_password() {
+    let (_, port) = start();
+
+    let mut client = TestClient::register(port, "someone", Some("guess"));
+    client.expect(" 464 ");
+    client.expect("ERROR");
+
+    assert!(client.next().is_none());
+}
@@ -0,0 +1,324 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use np1th_irc::{
+    bridge::{Bridge, Endpoint},
+    command::client::Command,
+    manager::{Event, Manager, Session, POLL_INTERVAL},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs a relay linking `#project` on both servers, built by `bridge`, and waits until it
+/// joined on both
+fn relay<F>(a: u16, b: u16, bridge: F)
+where
+    F: FnOnce(Bridge) -> Bridge + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut manager = Manager::new()
+            .session(Session::new("a", move || common::client(a, "relay")).channel("#project"))
+            .session(Session::new("b", move || common::client(b, "relay")).channel("#project"));
+
+        let mut bridge = bridge(Bridge::new().link(
+            Endpoint::new("a", "#project"),
+            Endpoint::new("b", "#project"),
+        ));
+
+        let mut joined = 0;
+
+        loop {
+            match manager.poll() {
+                Some(event) => {
+                    if let Event::Message { ref message, .. } = event {
+                        if let Command::Numeric { code: 366, .. } = message.command() {
+                            joined += 1;
+
+                            if joined == 2 {
+                                sender.send(()).unwrap();
+                            }
+                        }
+                    }
+
+                    bridge.handle(&mut manager, &event).unwrap();
+                }
+                None => thread::sleep(POLL_INTERVAL),
+            }
+
+            bridge.flush(&mut manager).unwrap();
+        }
+    });
+
+    receiver.recv_timeout(TIMEOUT).unwrap();
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    /// Registers and joins `#project`
+    fn join(port: u16, nick: &str) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut client = TestClient {
+            reader: BufReader::new(stream),
+        };
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+        client.expect(" 422 ");
+        client.send("JOIN #project");
+        client.expect(" 366 ");
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", part),
+                Ok(_) if line.contains(part) => return line.trim_end().to_string(),
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, part),
+            }
+        }
+    }
+
+    /// Skips lines until one contains any of `parts`
+    fn expect_any(&mut self, parts: &[&str]) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", parts),
+                Ok(_) if parts.iter().any(|part| line.contains(part)) => {
+                    return line.trim_end().to_string()
+                }
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, parts),
+            }
+        }
+    }
+
+    /// The next line from the relay
+    fn relayed(&mut self) -> String {
+        let line = self.expect(":relay!");
+
+        line[line.find(' ').unwrap() + 1..].to_string()
+    }
+}
+
+#[test]
+fn relay_channels() {
+    let a = common::start_ircd("irc.a.test", |builder| builder);
+    let b = common::start_ircd("irc.b.test", |builder| builder);
+
+    relay(a, b, |bridge| {
+        bridge
+            .ignore("otherbot!*@*")
+            .rate_limit(3, Duration::from_millis(500))
+    });
+
+    let mut bob = TestClient::join(b, "bob");
+    let mut alice = TestClient::join(a, "alice");
+    assert_eq!(bob.relayed(), "NOTICE #project :<alice@a> has joined");
+
+    alice.send("PRIVMSG #project :hello");
+    assert_eq!(bob.relayed(), "PRIVMSG #project :<alice@a> hello");
+
+    alice.send("PRIVMSG #project :\x01ACTION waves\x01");
+    assert_eq!(
+        bob.relayed(),
+        "PRIVMSG #project :\x01ACTION <alice@a> waves\x01"
+    );
+
+    // other relays are ignored, and what the relay says itself doesn't come back
+    let mut other = TestClient::join(b, "otherbot");
+    other.send("PRIVMSG #project :<carol@c> not again");
+    bob.send("NOTICE #project :psst");
+
+    // bob's join reaches alice too if the relay only saw it once she was on the channel
+    let mut line = alice.relayed();
+
+    if line == "NOTICE #project :<bob@b> has joined" {
+        line = alice.relayed();
+    }
+
+    assert_eq!(line, "NOTICE #project :<bob@b> psst");
+
+    let mut dave = TestClient::join(b, "dave");
+    dave.send("PART #project :bye");
+    assert_eq!(alice.relayed(), "NOTICE #project :<dave@b> has joined");
+    assert_eq!(alice.relayed(), "NOTICE #project :<dave@b> has left (bye)");
+
+    let mut erin = TestClient::join(b, "erin");
+    erin.send("QUIT :gone");
+    assert_eq!(alice.relayed(), "NOTICE #project :<erin@b> has joined");
+    assert!(alice
+        .relayed()
+        .starts_with("NOTICE #project :<erin@b> has quit ("));
+
+    // three lines per half a second, the rest waits its turn
+    let started = Instant::now();
+
+    for number in 0..7 {
+        alice.send(&format!("PRIVMSG #project :line {}", number));
+    }
+
+    for number in 0..7 {
+        assert_eq!(
+            bob.relayed(),
+            format!("PRIVMSG #project :<alice@a> line {}", number)
+        );
+    }
+
+    assert!(started.elapsed() >= Duration::from_millis(1000));
+}
+
+#[test]
+fn puppets() {
+    let a = common::start_ircd("irc.a.test", |builder| builder);
+    let b = common::start_ircd("irc.b.test", |builder| builder);
+
+    relay(a, b, move |bridge| {
+        bridge.puppets("b", move |nick| common::client(b, nick))
+    });
+
+    let mut alice = TestClient::join(a, "alice");
+    let mut bob = TestClient::join(b, "bob");
+
+    // bob is mirrored by the relay, alice gets her own connection
+    assert_eq!(alice.relayed(), "NOTICE #project :<bob@b> has joined");
+    assert!(bob.expect("JOIN").starts_with(":alice|a!"));
+
+    alice.send("PRIVMSG #project :hello");
+    assert!(bob
+        .expect("PRIVMSG")
+        .starts_with(":alice|a!alice|a@127.0.0.1 PRIVMSG #project :hello"));
+
+    // what the puppet says isn't mirrored back
+    bob.send("PRIVMSG #project :hi");
+    assert_eq!(alice.relayed(), "PRIVMSG #project :<bob@b> hi");
+
+    alice.send("PRIVMSG #project :\x01ACTION waves\x01");
+    assert!(bob
+        .expect("PRIVMSG")
+        .ends_with(" PRIVMSG #project :\x01ACTION waves\x01"));
+
+    // leaving the last channel ends the puppet
+    alice.send("PART #project :later");
+    assert!(bob.expect("PART").ends_with(" PART #project :later"));
+
+    let started = Instant::now();
+
+    loop {
+        bob.send("WHOIS alice|a");
+
+        // no such nick, or the end of its WHOIS while it's still around
+        if bob.expect_any(&[" 401 ", " 318 "]).contains(" 401 ") {
+            break;
+        }
+
+        assert!(started.elapsed() < TIMEOUT);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+#[test]
+fn puppet_nick_changes_and_kicks() {
+    let a = common::start_ircd("irc.a.test", |builder| builder);
+    let b = common::start_ircd("irc.b.test", |builder| builder);
+
+    // joining before the relay makes alice the operator on a
+    let mut alice = TestClient::join(a, "alice");
+
+    relay(a, b, move |bridge| {
+        bridge.puppets("b", move |nick| common::client(b, nick))
+    });
+
+    let mut bob = TestClient::join(b, "bob");
+    let mut carol = TestClient::join(a, "carol");
+
+    // the relay joined after alice
+    alice.expect(":relay!");
+    assert_eq!(alice.relayed(), "NOTICE #project :<bob@b> has joined");
+    assert!(bob.expect("JOIN").starts_with(":carol|a!"));
+
+    // the puppet follows the nick change and still speaks for her
+    carol.send("NICK carla");
+    assert!(bob.expect(" NICK ").ends_with(" NICK :carla|a"));
+
+    carol.send("PRIVMSG #project :renamed");
+    assert!(bob
+        .expect("PRIVMSG")
+        .starts_with(":carla|a!carol|a@127.0.0.1 PRIVMSG #project :renamed"));
+
+    // the relay tells about nick changes on the other side
+    bob.send("NICK robert");
+    assert_eq!(
+        alice.relayed(),
+        "NOTICE #project :<bob@b> is now known as <robert@b>"
+    );
+
+    // a kicked user's puppet leaves as well
+    alice.send("KICK #project carla :behave");
+    assert!(bob
+        .expect(" PART ")
+        .ends_with(" PART #project :Kicked by alice: behave"));
+}
+
+#[test]
+fn kicked_puppet() {
+    let a = common::start_ircd("irc.a.test", |builder| builder);
+    let b = common::start_ircd("irc.b.test", |builder| builder);
+
+    // joining before the relay makes bob the operator on b
+    let mut bob = TestClient::join(b, "bob");
+
+    relay(a, b, move |bridge| {
+        bridge.puppets("b", move |nick| common::client(b, nick))
+    });
+
+    let mut alice = TestClient::join(a, "alice");
+
+    // the relay joined after bob
+    bob.expect(":relay!");
+    assert!(bob.expect("JOIN").starts_with(":alice|a!"));
+
+    bob.send("KICK #project alice|a :no puppets");
+    bob.expect(" KICK ");
+
+    // the relay said so on a, the bridge saw the kick by then
+    bob.send("PRIVMSG #project :kicked");
+    assert_eq!(alice.relayed(), "PRIVMSG #project :<bob@b> kicked");
+
+    // the relay speaks for alice instead of the puppet joining again
+    alice.send("PRIVMSG #project :still here");
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        ":relay!relay@127.0.0.1 PRIVMSG #project :<alice@a> still here"
+    );
+}
@@ -0,0 +1,146 @@
+extern crate np1th_irc;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread,
+    time::{Duration, Instant},
+};
+
+use np1th_irc::{
+    connection::{client::Client, registration::error::RegistrationTimeoutError},
+    origin::Origin,
+    stream::Port,
+    user::User,
+};
+
+fn avon() -> User {
+    User::new(
+        Origin::User {
+            nick: "avon".to_string(),
+            user: Some("avon".to_string()),
+            host: None,
+        },
+        "Avon",
+    )
+}
+
+/// Registers one client with `welcome` as the text of RPL_WELCOME, returns what it sent after
+/// registering
+fn registered_with(welcome: &'static str) -> Vec<String> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        let mut reader = BufReader::new(stream);
+        let mut lines = Vec::new();
+        let mut registered = false;
+
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_string();
+
+            if line.starts_with("USER ") && !registered {
+                registered = true;
+
+                reader
+                    .get_mut()
+                    .write_all(
+                        format!(
+                            ":irc.test 001 avon :{}\r\n:irc.test 422 avon :MOTD File is missing\r\n",
+                            welcome
+                        )
+                        .as_bytes(),
+                    )
+                    .unwrap();
+            } else if line.starts_with("PRIVMSG ") {
+                return lines;
+            } else if registered {
+                lines.push(line);
+            }
+        }
+    });
+
+    let client = Client::builder()
+        .host("127.0.0.1")
+        .port(Port::Insecure(port))
+        .user(avon())
+        .insecure_only()
+        .build()
+        .unwrap();
+
+    client.privmsg("#done", "done").unwrap();
+
+    server.join().unwrap()
+}
+
+#[test]
+fn who_only_without_hostmask() {
+    assert_eq!(
+        registered_with("Welcome to the network avon!avon@example.org"),
+        Vec::<String>::new()
+    );
+
+    assert_eq!(
+        registered_with("Welcome to the network avon"),
+        vec!["WHO avon".to_string()]
+    );
+}
+
+#[test]
+fn registration_timeout_despite_lines() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    // never welcomes the client, but doesn't stop talking either
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+
+        while stream
+            .write_all(b":irc.test NOTICE avon :*** Still looking up your hostname\r\n")
+            .is_ok()
+        {}
+    });
+
+    let started = Instant::now();
+
+    let error = Client::builder()
+        .host("127.0.0.1")
+        .port(Port::Insecure(port))
+        .user(avon())
+        .insecure_only()
+        .registration_timeout(Duration::from_millis(200))
+        .build()
+        .err()
+        .unwrap();
+
+    assert!(error.is::<RegistrationTimeoutError>());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn refused_connection_fails() {
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        listener.local_addr().unwrap().port()
+    };
+
+    let started = Instant::now();
+    let result = Client::builder()
+        .host("127.0.0.1")
+        .port(Port::Insecure(port))
+        .user(avon())
+        .insecure_only()
+        .timeout(Duration::from_secs(5))
+        .build();
+
+    assert!(result.is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
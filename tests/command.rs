@@ -1,8 +1,19 @@
 extern crate np1th_irc;
 
-use np1th_irc::command::{client, Command};
+use np1th_irc::{
+    command::{client, server, Command},
+    history::{Range, Reference},
+    stream::{Port, ServerStream},
+};
 
-use std::{convert::TryInto, error::Error};
+use std::{
+    convert::TryInto,
+    error::Error,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    thread,
+    time::Duration,
+};
 
 fn test_command<C, P>(tests: Vec<&str>, p: P)
                       where
@@ -82,3 +93,298 @@ fn test_part_command() {
         assert!(res.is_ok())
     });
 }
+
+#[test]
+fn test_invite_and_list_commands() {
+    let valid_tests = vec![
+        "INVITE bob #test",
+        "LIST",
+        "LIST #test,&foo",
+        "LIST #test irc.example.org",
+    ];
+    let invalid_tests = vec!["INVITE bob", "INVITE bob test", "INVITE ~bob #test"];
+
+    test_command(valid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_ok())
+    });
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_operator_commands() {
+    let lines = vec![
+        "OPER admin hunter2",
+        "KILL bob :Spamming",
+        "STATS k",
+        "KLINE 10 *@example.org :Go away",
+        "KLINE bob@192.0.2.* :Go away",
+        "UNKLINE *@example.org",
+        "DLINE 192.0.2.0/24 :Open proxies",
+        "UNDLINE 192.0.2.0/24",
+    ];
+
+    for line in lines {
+        let raw = line.try_into().unwrap();
+        let command = <client::Command as Command>::try_from(raw).unwrap();
+
+        assert_eq!(command.to_string(), line);
+    }
+
+    let invalid_tests = vec!["OPER admin", "KILL bob", "KLINE 10", "UNKLINE"];
+
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_account_commands() {
+    let lines = vec![
+        "AUTHENTICATE PLAIN",
+        "AUTHENTICATE +",
+        "AUTHENTICATE AGJvYgBzZWNyZXQ=",
+        "ACCOUNT alice",
+        "ACCOUNT *",
+        "JOIN #test alice :Alice Real",
+        "JOIN #test * :Bob",
+    ];
+
+    for line in lines {
+        let raw = line.try_into().unwrap();
+        let command = <client::Command as Command>::try_from(raw).unwrap();
+
+        assert_eq!(command.to_string(), line);
+    }
+
+    match <client::Command as Command>::try_from("ACCOUNT *".try_into().unwrap()) {
+        Ok(client::Command::Account { account }) => assert_eq!(account, None),
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let invalid_tests = vec!["AUTHENTICATE", "ACCOUNT", "JOIN test alice :Alice"];
+
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_chathistory_commands() {
+    let lines = vec![
+        "CHATHISTORY LATEST #test * 50",
+        "CHATHISTORY LATEST bob msgid=abc-1 10",
+        "CHATHISTORY BEFORE #test timestamp=2019-01-04T14:33:26.123Z 20",
+        "CHATHISTORY AFTER #test msgid=abc-1 20",
+        "CHATHISTORY AROUND #test msgid=abc-1 5",
+        "CHATHISTORY BETWEEN #test msgid=abc-1 timestamp=2019-01-04T14:33:26.000Z 100",
+        "CHATHISTORY TARGETS timestamp=2019-01-04T00:00:00.000Z timestamp=2019-01-05T00:00:00.000Z 10",
+        "CHATHISTORY TARGETS #test 2019-01-04T14:33:26.123Z",
+        "FAIL CHATHISTORY INVALID_TARGET LATEST #test :Messages could not be retrieved",
+        "FAIL CHATHISTORY INVALID_PARAMS :Invalid parameters",
+    ];
+
+    for line in lines {
+        let raw = line.try_into().unwrap();
+        let command = <client::Command as Command>::try_from(raw).unwrap();
+
+        assert_eq!(command.to_string(), line);
+    }
+
+    let raw = "CHATHISTORY before #test msgid=a 3".try_into().unwrap();
+
+    match <client::Command as Command>::try_from(raw) {
+        Ok(client::Command::ChatHistory { target, range }) => {
+            assert_eq!(target, "#test");
+            assert_eq!(
+                range,
+                Range::Before {
+                    reference: Reference::MsgId("a".to_string()),
+                    limit: 3,
+                }
+            );
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let invalid_tests = vec![
+        "CHATHISTORY LATEST #test",
+        "CHATHISTORY LATEST #test * many",
+        "CHATHISTORY BEFORE #test * 10",
+        "CHATHISTORY AFTER #test id=abc 10",
+        "CHATHISTORY BETWEEN #test msgid=abc 10",
+        "CHATHISTORY TARGETS msgid=abc msgid=def 10",
+        "CHATHISTORY SOMETIME #test * 10",
+        "FAIL CHATHISTORY",
+    ];
+
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_registration_replies() {
+    let valid_tests = vec![
+        "001 avonarret :Welcome to the network avonarret!~avon@localhost",
+        "422 avonarret :MOTD File is missing",
+        "433 * avonarret :Nickname is already in use",
+        "437 * avonarret :Nick/channel is temporarily unavailable",
+        "464 * :Password incorrect",
+        "465 * :You are banned from this server",
+    ];
+    let invalid_tests = vec!["001 avonarret", "433 *"];
+
+    test_command(valid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_ok())
+    });
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_identity_replies() {
+    let valid_tests = vec![
+        "221 avonarret +iwx",
+        "352 avonarret * ~avon cloak.example.org irc.example.org avonarret H :0 Avon Arret",
+        "396 avonarret cloak.example.org :is now your displayed host",
+        "CHGHOST ~avon cloak.example.org",
+        "MODE avonarret :+i-w",
+        "WHO avonarret",
+    ];
+    let invalid_tests = vec!["MODE avonarret i", "352 avonarret * ~avon"];
+
+    test_command(valid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_ok())
+    });
+    test_command(invalid_tests, |res: Result<client::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_user_mode_round_trip() {
+    let raw = "MODE avonarret +iw-x".try_into().unwrap();
+    let command = <client::Command as Command>::try_from(raw).unwrap();
+
+    assert_eq!(command.to_string(), "MODE avonarret +iw-x");
+}
+
+#[test]
+fn test_server_commands() {
+    let valid_tests = vec![
+        "PASS secret 0210 IRC| P",
+        "SERVER irc.example.org 1 2 :Example server",
+        "NICK avonarret 2 ~avon host.example.org 3 +iw :Avon Arret",
+        "NICK avonarret 2 ~avon host.example.org 3 + 1569000000 :Avon Arret",
+        "NICK :avonarret",
+        "NICK avonarret :1569000000",
+        "SERVICE dict 4 *.fr 0 1 :French dictionary",
+        "SQUIT irc.example.org :Bye",
+        "JOIN #test\x07ov,&foo",
+        "NJOIN #test :@@avon,@+nick,+other,plain",
+        "MODE #test +ov avon nick",
+        "TOPIC #test :new topic",
+        "KICK #test avon :bye",
+        "PRIVMSG #test :hello",
+        "KILL avon :irc.example.org (collision)",
+        "ERROR :Closing Link",
+        "401 avon nobody :No such nick/channel",
+    ];
+    let invalid_tests = vec![
+        "SERVER irc.example.org one 2 :Example server",
+        "NICK avonarret 2 ~avon",
+        "NJOIN #test :@@",
+        "JOIN test",
+        "FROB",
+    ];
+
+    test_command(valid_tests, |res: Result<server::Command, Box<Error>>| {
+        assert!(res.is_ok())
+    });
+    test_command(invalid_tests, |res: Result<server::Command, Box<Error>>| {
+        assert!(res.is_err())
+    });
+}
+
+#[test]
+fn test_server_command_round_trip() {
+    let lines = vec![
+        "PASS secret 0210 IRC|",
+        "SERVER irc.example.org 1 2 :Example server",
+        "NICK avonarret 2 ~avon host.example.org 3 +iw :Avon Arret",
+        "NICK avonarret 2 ~avon host.example.org 3 +i 1569000000 :Avon Arret",
+        "NICK avonarret :1569000000",
+        "JOIN #test\x07ov,&foo",
+        "NJOIN #test :@@avon,@+nick,+other,plain",
+        "MODE #test +ov avon nick",
+        "INVITE bob #test",
+        "ACCOUNT alice",
+        "ACCOUNT *",
+        "SQUIT irc.example.org :Bye",
+    ];
+
+    for line in lines {
+        let raw = line.try_into().unwrap();
+        let command = <server::Command as Command>::try_from(raw).unwrap();
+
+        assert_eq!(command.to_string(), line);
+    }
+
+    let members = match <server::Command as Command>::try_from(
+        "NJOIN #test :@@avon,+other".try_into().unwrap(),
+    ) {
+        Ok(server::Command::NJoin { members, .. }) => members,
+        other => panic!("unexpected {:?}", other),
+    };
+
+    assert!(members[0].creator && members[0].operator && !members[0].voice);
+    assert!(!members[1].operator && members[1].voice);
+}
+
+#[test]
+fn test_server_stream() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let peer = thread::spawn(move || {
+        let (peer, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(peer);
+        let mut line = String::new();
+
+        reader.read_line(&mut line).unwrap();
+        reader
+            .get_mut()
+            .write_all(b":irc.example.org SERVER irc.example.org 1 1 :Example\r\n")
+            .unwrap();
+
+        line
+    });
+
+    let stream = ServerStream::connect("127.0.0.1", Port::Insecure(port), None).unwrap();
+    stream.send(server::Command::pass("secret")).unwrap();
+
+    assert_eq!(peer.join().unwrap(), "PASS secret 0210 IRC|\r\n");
+
+    for _ in 0..500 {
+        if let Some(message) = stream.read().unwrap() {
+            assert_eq!(
+                message.command(),
+                &server::Command::Server {
+                    name: "irc.example.org".to_string(),
+                    hop_count: 1,
+                    token: 1,
+                    info: "Example".to_string(),
+                }
+            );
+
+            return;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("no message received");
+}
@@ -0,0 +1,54 @@
+#![allow(dead_code)]
+
+use std::{error::Error, sync::mpsc, thread};
+
+use np1th_irc::{
+    connection::client::{Builder as ClientBuilder, Client},
+    ircd::{Builder, Ircd},
+    origin::Origin,
+    stream::Port,
+    user::User,
+};
+
+/// Runs a server called `name` on a free local port for the rest of the test process, returns
+/// the port. `configure` adds to the builder.
+pub fn start_ircd<F>(name: &'static str, configure: F) -> u16
+where
+    F: FnOnce(Builder) -> Builder + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut ircd = configure(Ircd::builder().name(name).listen("127.0.0.1:0"))
+            .build()
+            .unwrap();
+
+        sender.send(ircd.addresses()[0].port()).unwrap();
+        ircd.run().unwrap();
+    });
+
+    receiver.recv().unwrap()
+}
+
+/// Registers a client as `nick`, which is its user name as well
+pub fn connect(port: u16, nick: &str) -> Result<Client, Box<Error>> {
+    client(port, nick).build()
+}
+
+/// Sets up a client like `connect` without connecting yet
+pub fn client(port: u16, nick: &str) -> ClientBuilder {
+    let myself = User::new(
+        Origin::User {
+            nick: nick.to_string(),
+            user: Some(nick.to_string()),
+            host: None,
+        },
+        nick,
+    );
+
+    Client::builder()
+        .host("127.0.0.1")
+        .port(Port::Insecure(port))
+        .user(myself)
+        .insecure_only()
+}
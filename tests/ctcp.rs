@@ -0,0 +1,156 @@
+extern crate np1th_irc;
+
+use std::{
+    convert::TryFrom,
+    io::{BufRead, BufReader},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{
+    command::client,
+    ctcp::{self, Ctcp, Event, Responder},
+    message::Message,
+    stream::{ClientStream, Port},
+    target::MessageTarget,
+};
+
+#[test]
+fn parse_ctcp() {
+    assert_eq!(
+        Ctcp::parse("\x01ACTION waves at everyone\x01"),
+        Some(Ctcp::Action("waves at everyone".to_string()))
+    );
+    assert_eq!(Ctcp::parse("\x01VERSION\x01"), Some(Ctcp::Version(None)));
+    assert_eq!(
+        Ctcp::parse("\x01PING 123456"),
+        Some(Ctcp::Ping(Some("123456".to_string())))
+    );
+    assert_eq!(
+        Ctcp::parse("\x01FINGER\x01"),
+        Some(Ctcp::Other {
+            command: "FINGER".to_string(),
+            params: None,
+        })
+    );
+    assert_eq!(Ctcp::parse("no ctcp"), None);
+    assert_eq!(Ctcp::parse("\x01\x01"), None);
+}
+
+#[test]
+fn build_ctcp() {
+    let action = Ctcp::Action("jumps\naround".to_string());
+
+    assert_eq!(action.to_string(), "\x01ACTION jumps\x10naround\x01");
+    assert_eq!(Ctcp::parse(&action.to_string()), Some(action));
+    assert_eq!(Ctcp::Time(None).to_string(), "\x01TIME\x01");
+}
+
+#[test]
+fn low_level_quoting() {
+    let raw = "a\0b\rc\nd\x10e";
+
+    assert_eq!(ctcp::low_level_dequote(&ctcp::low_level_quote(raw)), raw);
+}
+
+#[test]
+fn event_from_message() {
+    let message = Message::<client::Command>::try_from(
+        ":avona1!~avon1@localhost PRIVMSG #channel :\x01ACTION waves\x01\r\n",
+    )
+        .unwrap();
+
+    match Event::from_message(&message) {
+        Some(Event::Query { target, ctcp, .. }) => {
+            assert_eq!(target, MessageTarget::Channel("#channel".to_string()));
+            assert_eq!(ctcp, Ctcp::Action("waves".to_string()));
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+/// Both ends of a connection, the responder replies through the client one
+struct Peer {
+    stream: ClientStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Peer {
+    fn new() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let stream = ClientStream::connect("127.0.0.1", Port::Insecure(port), None).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        Peer {
+            stream,
+            reader: BufReader::new(server),
+        }
+    }
+
+    /// Asks for the version once from every nick, returns the nicks which got a reply
+    fn query(&mut self, responder: &mut Responder, nicks: &[&str]) -> Vec<String> {
+        for nick in nicks {
+            let line = format!(":{}!user@host PRIVMSG avon :\x01VERSION\x01", nick);
+            let message = Message::<client::Command>::try_from(line.as_str()).unwrap();
+
+            responder.handle(&self.stream, &message).unwrap();
+        }
+
+        self.stream
+            .send(client::Command::PrivMsg {
+                targets: vec![MessageTarget::Channel("#done".to_string())],
+                text: "done".to_string(),
+            })
+            .unwrap();
+
+        let mut replied = Vec::new();
+
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+
+            match line.split(' ').collect::<Vec<&str>>().as_slice() {
+                ["NOTICE", nick, ..] => replied.push(nick.to_string()),
+                ["PRIVMSG", "#done", ..] => return replied,
+                _ => panic!("unexpected {:?}", line),
+            }
+        }
+    }
+}
+
+#[test]
+fn global_rate_limit() {
+    let mut peer = Peer::new();
+    let mut responder = Responder::new()
+        .rate_limit(2, Duration::from_millis(100))
+        .per_sender(Duration::from_millis(0));
+
+    assert_eq!(
+        peer.query(&mut responder, &["avon1", "avon2", "avon3"]),
+        vec!["avon1", "avon2"]
+    );
+
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(peer.query(&mut responder, &["avon3"]), vec!["avon3"]);
+}
+
+#[test]
+fn per_sender_rate_limit() {
+    let mut peer = Peer::new();
+    let mut responder = Responder::new()
+        .rate_limit(10, Duration::from_secs(10))
+        .per_sender(Duration::from_millis(100));
+
+    assert_eq!(
+        peer.query(&mut responder, &["avon1", "avon1", "avon2"]),
+        vec!["avon1", "avon2"]
+    );
+
+    thread::sleep(Duration::from_millis(120));
+    assert_eq!(
+        peer.query(&mut responder, &["avon1", "avon1"]),
+        vec!["avon1"]
+    );
+}
@@ -0,0 +1,210 @@
+extern crate np1th_irc;
+
+use std::{
+    io::Cursor,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{
+    ctcp::Ctcp,
+    dcc::{
+        chat::Chat,
+        transfer::{connect, Listener, Transfer},
+        Offer,
+    },
+};
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn parse_offers() {
+    assert_eq!(
+        Offer::parse("SEND file.txt 2130706433 5000 1024").unwrap(),
+        Offer::Send {
+            file_name: "file.txt".to_string(),
+            address: LOCALHOST,
+            port: 5000,
+            size: Some(1024),
+            token: None,
+        }
+    );
+    assert_eq!(
+        Offer::parse("SEND \"my file.txt\" ::1 0 1024 42").unwrap(),
+        Offer::Send {
+            file_name: "my file.txt".to_string(),
+            address: IpAddr::V6(Ipv6Addr::LOCALHOST),
+            port: 0,
+            size: Some(1024),
+            token: Some("42".to_string()),
+        }
+    );
+    assert_eq!(
+        Offer::parse("CHAT chat 2130706433 5001").unwrap(),
+        Offer::Chat {
+            address: LOCALHOST,
+            port: 5001,
+            token: None,
+        }
+    );
+    assert_eq!(
+        Offer::parse("RESUME file.txt 5000 512").unwrap(),
+        Offer::Resume {
+            file_name: "file.txt".to_string(),
+            port: 5000,
+            position: 512,
+            token: None,
+        }
+    );
+    assert_eq!(
+        Offer::parse("ACCEPT file.txt 0 512 42").unwrap(),
+        Offer::Accept {
+            file_name: "file.txt".to_string(),
+            port: 0,
+            position: 512,
+            token: Some("42".to_string()),
+        }
+    );
+
+    assert!(Offer::parse("SEND file.txt").is_err());
+    assert!(Offer::parse("SEND file.txt nowhere 5000").is_err());
+    assert!(Offer::parse("FOO bar 1 2").is_err());
+}
+
+#[test]
+fn offer_round_trip() {
+    let offers = vec![
+        "SEND \"my file.txt\" 2130706433 5000 1024",
+        "SEND file.txt ::1 0 1024 42",
+        "CHAT chat 2130706433 5001",
+        "RESUME file.txt 5000 512",
+        "ACCEPT file.txt 0 512 42",
+    ];
+
+    for offer in offers {
+        assert_eq!(Offer::parse(offer).unwrap().to_string(), offer);
+    }
+
+    let passive = Offer::parse("SEND file.txt 2130706433 0 1024 42").unwrap();
+    assert!(passive.is_passive());
+    assert_eq!(passive.token(), Some("42"));
+
+    let ctcp = Ctcp::parse("\x01DCC SEND file.txt 2130706433 5000\x01").unwrap();
+    let offer = Offer::from_ctcp(&ctcp).unwrap().unwrap();
+    assert!(!offer.is_passive());
+    assert_eq!(offer.to_ctcp(), ctcp);
+
+    assert!(Offer::from_ctcp(&Ctcp::Version(None)).is_none());
+}
+
+#[test]
+fn safe_file_name() {
+    let offer = |name: &str| Offer::Resume {
+        file_name: name.to_string(),
+        port: 0,
+        position: 0,
+        token: None,
+    };
+
+    assert_eq!(offer("../../etc/passwd").safe_file_name(), Some("passwd".to_string()));
+    assert_eq!(offer("C:\\evil.exe").safe_file_name(), Some("evil.exe".to_string()));
+    assert_eq!(offer(".bashrc").safe_file_name(), Some("bashrc".to_string()));
+    assert_eq!(offer("..").safe_file_name(), None);
+}
+
+fn transfer(data: Vec<u8>, position: u64, size: Option<u64>) -> (Vec<u8>, Result<u64, String>) {
+    let listener = Listener::bind(LOCALHOST).unwrap();
+    let port = listener.port().unwrap();
+    let sent = data[position as usize..].to_vec();
+
+    let sender = thread::spawn(move || {
+        let stream = listener.accept(TIMEOUT).unwrap();
+
+        Transfer::new(stream)
+            .position(position)
+            .size(Some(data.len() as u64))
+            .timeout(TIMEOUT)
+            .send(Cursor::new(sent), |_| ())
+            .map_err(|e| e.to_string())
+    });
+
+    let stream = connect(LOCALHOST, port, TIMEOUT).unwrap();
+    let mut received = Vec::new();
+    let mut last = None;
+
+    let result = Transfer::new(stream)
+        .position(position)
+        .size(size)
+        .timeout(TIMEOUT)
+        .receive(&mut received, |progress| last = Some(progress))
+        .map_err(|e| e.to_string());
+
+    if result.is_ok() {
+        assert!(sender.join().unwrap().is_ok());
+        assert_eq!(last.unwrap().transferred(), result.clone().unwrap());
+    }
+
+    (received, result)
+}
+
+#[test]
+fn send_file() {
+    let data = (0..100_000u32).map(|i| i as u8).collect::<Vec<u8>>();
+
+    let (received, result) = transfer(data.clone(), 0, Some(data.len() as u64));
+
+    assert_eq!(result, Ok(data.len() as u64));
+    assert_eq!(received, data);
+}
+
+#[test]
+fn resume_file() {
+    let data = (0..50_000u32).map(|i| i as u8).collect::<Vec<u8>>();
+
+    let (received, result) = transfer(data.clone(), 20_000, Some(data.len() as u64));
+
+    assert_eq!(result, Ok(data.len() as u64));
+    assert_eq!(received, &data[20_000..]);
+}
+
+#[test]
+fn size_checks() {
+    let data = vec![1u8; 1000];
+
+    let (_, result) = transfer(data.clone(), 0, Some(500));
+    assert!(result.unwrap_err().starts_with("SizeMismatchError"));
+
+    let listener = Listener::bind(LOCALHOST).unwrap();
+    let stream = connect(LOCALHOST, listener.port().unwrap(), TIMEOUT).unwrap();
+
+    let result = Transfer::new(stream)
+        .size(Some(1000))
+        .limit(Some(100))
+        .receive(Vec::new(), |_| ());
+    assert!(result.unwrap_err().to_string().starts_with("SizeLimitError"));
+}
+
+#[test]
+fn chat() {
+    let listener = Listener::bind(LOCALHOST).unwrap();
+    let port = listener.port().unwrap();
+
+    let peer = thread::spawn(move || {
+        let mut chat = Chat::new(listener.accept(TIMEOUT).unwrap()).unwrap();
+
+        let line = chat.read_line().unwrap().unwrap();
+        chat.send(&format!("echo: {}", line)).unwrap();
+    });
+
+    let mut chat = Chat::new(connect(LOCALHOST, port, TIMEOUT).unwrap()).unwrap();
+    chat.set_timeout(Some(TIMEOUT)).unwrap();
+
+    chat.send("hello").unwrap();
+    assert_eq!(chat.read_line().unwrap(), Some("echo: hello".to_string()));
+
+    peer.join().unwrap();
+    assert_eq!(chat.read_line().unwrap(), None);
+}
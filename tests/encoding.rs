@@ -0,0 +1,121 @@
+extern crate np1th_irc;
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{
+    command::client::Command,
+    encoding::Encoding,
+    stream::{ClientStream, Port},
+    target::MessageTarget,
+};
+
+#[test]
+fn decode() {
+    let latin1 = b"caf\xe9";
+
+    assert_eq!(Encoding::Utf8.decode("café".as_bytes()).unwrap(), "café");
+    assert!(Encoding::Utf8.decode(latin1).is_err());
+
+    assert_eq!(Encoding::Utf8Latin1.decode("café".as_bytes()).unwrap(), "café");
+    assert_eq!(Encoding::Utf8Latin1.decode(latin1).unwrap(), "café");
+
+    let cp1251 = Encoding::legacy("windows-1251").unwrap();
+    assert_eq!(cp1251.decode(b"\xef\xf0\xe8\xe2\xe5\xf2").unwrap(), "привет");
+
+    assert!(Encoding::legacy("no-such-encoding").is_err());
+    assert_eq!(Encoding::legacy("utf8").unwrap(), Encoding::Utf8);
+}
+
+#[test]
+fn encode() {
+    assert_eq!(Encoding::Utf8.encode("café").unwrap(), "café".as_bytes());
+    assert_eq!(Encoding::Utf8Latin1.encode("café").unwrap(), "café".as_bytes());
+
+    let cp1251 = Encoding::legacy("windows-1251").unwrap();
+    assert_eq!(cp1251.encode("привет").unwrap(), b"\xef\xf0\xe8\xe2\xe5\xf2");
+    assert!(cp1251.encode("日本").is_err());
+}
+
+fn read_until_message(stream: &ClientStream) -> String {
+    for _ in 0..500 {
+        if let Some(message) = stream.read().unwrap() {
+            match message.command() {
+                Command::PrivMsg { text, .. } => return text.to_string(),
+                other => panic!("unexpected command {:?}", other),
+            }
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("no message received");
+}
+
+#[test]
+fn stream_encoding() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let (mut peer, _) = listener.accept().unwrap();
+
+        // the multi-byte character gets split across two writes
+        peer.write_all(b":a!b@c PRIVMSG #d :\xc3").unwrap();
+        peer.flush().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        peer.write_all(b"\xa9t\xc3\xa9\r\n").unwrap();
+
+        peer.write_all(b":a!b@c PRIVMSG #d :caf\xe9\r\n").unwrap();
+
+        let mut received = vec![0; 64];
+        let size = peer.read(&mut received).unwrap();
+        received.truncate(size);
+
+        received
+    });
+
+    let stream = ClientStream::connect("127.0.0.1", Port::Insecure(port), None).unwrap();
+
+    assert_eq!(read_until_message(&stream), "été");
+    assert_eq!(read_until_message(&stream), "café");
+
+    stream.set_encoding(Encoding::legacy("latin1").unwrap());
+    stream
+        .send(Command::PrivMsg {
+            targets: vec![MessageTarget::Channel("#d".to_string())],
+            text: "café".to_string(),
+        })
+        .unwrap();
+
+    assert_eq!(server.join().unwrap(), b"PRIVMSG #d :caf\xe9\r\n");
+}
+
+#[test]
+fn skip_undecodable_lines() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let server = thread::spawn(move || {
+        let (mut peer, _) = listener.accept().unwrap();
+
+        peer.write_all(b":a!b@c PRIVMSG #d :caf\xe9\r\n").unwrap();
+        peer.write_all(b":a!b@c PRIVMSG #d :still here\r\n").unwrap();
+
+        // keeps the connection open until the client read both
+        let mut received = vec![0; 64];
+        let _ = peer.read(&mut received);
+    });
+
+    let stream = ClientStream::connect("127.0.0.1", Port::Insecure(port), None).unwrap();
+    stream.set_encoding(Encoding::Utf8);
+
+    assert_eq!(read_until_message(&stream), "still here");
+
+    stream.close();
+    server.join().unwrap();
+}
@@ -0,0 +1,61 @@
+extern crate np1th_irc;
+
+use np1th_irc::formatting::{self, Builder, Color};
+
+#[test]
+fn parse_spans() {
+    let spans = formatting::parse("plain \x02bold\x02 \x0304,12red\x03 \x1Ditalic\x0F end");
+
+    let texts = spans.iter().map(|span| span.text()).collect::<Vec<&str>>();
+
+    assert_eq!(texts, vec!["plain ", "bold", " ", "red", " ", "italic", " end"]);
+    assert!(spans[1].style().bold);
+    assert_eq!(spans[3].style().foreground, Some(Color::RED));
+    assert_eq!(spans[3].style().background, Some(Color::LIGHT_BLUE));
+    assert_eq!(spans[4].style().foreground, None);
+    assert!(spans[5].style().italic);
+    assert!(spans[6].style().is_plain());
+}
+
+#[test]
+fn parse_colors() {
+    let spans = formatting::parse("\x04FF8800,000000hex\x03,5comma\x0399default");
+
+    assert_eq!(spans[0].style().foreground, Some(Color::Rgb(0xff, 0x88, 0x00)));
+    assert_eq!(spans[1].text(), ",5comma");
+    assert_eq!(spans[1].style().foreground, None);
+    assert_eq!(spans[2].style().foreground, None);
+}
+
+#[test]
+fn strip() {
+    assert_eq!(
+        formatting::strip("\x02\x1F\x0313,01Hello\x0F \x1E\x11\x16world\x03"),
+        "Hello world"
+    );
+    assert_eq!(formatting::strip("100\x03 percent"), "100 percent");
+}
+
+#[test]
+fn build_and_render() {
+    let text = Builder::new()
+        .text("a ")
+        .bold()
+        .color(Color::RED, None)
+        .text("<b>")
+        .build();
+
+    assert_eq!(text, "a \x02\x0304<b>\x0F");
+    assert_eq!(formatting::strip(&text), "a <b>");
+
+    let spans = formatting::parse(&text);
+
+    assert_eq!(
+        formatting::to_html(&spans),
+        "a <span style=\"font-weight:bold;color:#ff0000\">&lt;b&gt;</span>"
+    );
+    assert_eq!(
+        formatting::to_ansi(&spans),
+        "a \x1b[1;38;2;255;0;0m<b>\x1b[0m"
+    );
+}
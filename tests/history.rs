@@ -0,0 +1,34 @@
+extern crate np1th_irc;
+
+use np1th_irc::history::{Range, Reference};
+
+#[test]
+fn parse_references_and_ranges() {
+    assert_eq!(
+        Reference::parse("timestamp=2019-01-04T14:33:26.123Z"),
+        Some(Reference::Timestamp(1_546_612_406_123))
+    );
+    assert_eq!(
+        Reference::parse("msgid=abc"),
+        Some(Reference::MsgId("abc".to_string()))
+    );
+    assert_eq!(Reference::parse("msgid="), None);
+    assert_eq!(Reference::parse("abc"), None);
+
+    let range = Range::parse("between", &["msgid=a", "msgid=b", "10"]).unwrap();
+
+    assert_eq!(range.subcommand(), "BETWEEN");
+    assert_eq!(range.params(), vec!["msgid=a", "msgid=b", "10"]);
+    assert_eq!(range.limit(), 10);
+
+    assert_eq!(
+        Range::parse("LATEST", &["*", "5"]),
+        Some(Range::Latest {
+            after: None,
+            limit: 5,
+        })
+    );
+    assert_eq!(Range::parse("LATEST", &["*"]), None);
+    assert_eq!(Range::parse("BEFORE", &["*", "5"]), None);
+    assert_eq!(Range::parse("BETWEEN", &["msgid=a", "5"]), None);
+}
@@ -0,0 +1,1244 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process,
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{
+    command::client::Command,
+    connection::client::Client,
+    history::{self, Range, Reference},
+    ircd::{
+        ban::Kind,
+        oper::{self, Class, Oper, Privilege},
+        sasl, Ircd,
+    },
+    message::Message,
+    origin::Origin,
+    stream::Port,
+    time,
+    user::User,
+};
+use sha2::{Digest, Sha256};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const LINK_PASSWORD: &str = "secret";
+
+/// Runs a server on a free port for the rest of the test process
+fn start(motd: Option<&str>) -> u16 {
+    let motd = motd.map(|motd| motd.to_string());
+
+    common::start_ircd("irc.test", move |builder| match motd {
+        Some(motd) => builder.motd(&motd),
+        None => builder,
+    })
+}
+
+/// Runs a server which accepts links from all `*.test` servers used here and links to `uplink`
+fn start_linked(name: &str, uplink: Option<(u16, &str)>) -> u16 {
+    let (sender, receiver) = mpsc::channel();
+    let name = name.to_string();
+    let uplink = uplink.map(|(port, name)| (port, name.to_string()));
+
+    thread::spawn(move || {
+        let mut builder = Ircd::builder()
+            .name(&name)
+            .info(&format!("{} server", name))
+            .listen("127.0.0.1:0");
+
+        for peer in &["irc.a.test", "irc.b.test", "irc.c.test", "irc.fake.test"] {
+            builder = builder.link(peer, LINK_PASSWORD);
+        }
+
+        let mut ircd = builder.build().unwrap();
+
+        if let Some((port, peer)) = uplink {
+            ircd.connect("127.0.0.1", Port::Insecure(port), &peer)
+                .unwrap();
+        }
+
+        sender.send(ircd.addresses()[0].port()).unwrap();
+        ircd.run().unwrap();
+    });
+
+    receiver.recv().unwrap()
+}
+
+/// Runs a server with the operators `admin`, who may kill and ban, and `helper`, who may only
+/// kill. Both use the password `hunter2`.
+fn start_opers(ban_file: PathBuf) -> u16 {
+    common::start_ircd("irc.test", move |builder| {
+        let hash = oper::hash_password("hunter2");
+
+        builder
+            .class(
+                Class::new("admins")
+                    .privilege(Privilege::Kill)
+                    .privilege(Privilege::Ban),
+            )
+            .class(Class::new("helpers").privilege(Privilege::Kill))
+            .oper(Oper::new("admin", &hash, "admins").host("*@127.0.0.1"))
+            .oper(Oper::new("helper", &hash, "helpers").host("*@127.0.0.*"))
+            .oper(Oper::new("remote", &hash, "admins").host("*@192.0.2.0/24"))
+            .ban_file(ban_file)
+    })
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    fn connect(port: u16) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        TestClient {
+            reader: BufReader::new(stream),
+        }
+    }
+
+    fn register(port: u16, nick: &str) -> Self {
+        let mut client = TestClient::connect(port);
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+        client.expect(" 001 ");
+        // end of the MOTD or no MOTD
+        client.expect_any(&[" 376 ", " 422 "]);
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        self.expect_any(&[part])
+    }
+
+    /// Asks for `LINKS` until the server knows `server`
+    fn wait_for_server(&mut self, server: &str) {
+        loop {
+            let mut known = false;
+            let name = format!(" {} ", server);
+
+            self.send("LINKS");
+
+            loop {
+                let line = self.expect_any(&[" 364 ", " 365 "]);
+
+                if line.contains(" 365 ") {
+                    break;
+                }
+
+                known |= line.contains(&name);
+            }
+
+            if known {
+                return;
+            }
+
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    fn expect_any(&mut self, parts: &[&str]) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", parts),
+                Ok(_) if parts.iter().any(|part| line.contains(part)) => {
+                    return line.trim_end().to_string()
+                }
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, parts),
+            }
+        }
+    }
+}
+
+#[test]
+fn registration() {
+    let port = start(Some("first line\nsecond line"));
+
+    let mut client = TestClient::connect(port);
+    client.send("JOIN #early");
+    assert_eq!(
+        client.expect(" 451 "),
+        ":irc.test 451 * :You have not registered"
+    );
+
+    client.send("NICK alice");
+    client.send("USER alice 0 * :Alice Liddell");
+    assert_eq!(
+        client.expect(" 001 "),
+        ":irc.test 001 alice :Welcome to the Internet Relay Network alice!alice@127.0.0.1"
+    );
+    assert!(client.expect(" 005 ").contains("CASEMAPPING=rfc1459"));
+    assert_eq!(client.expect(" 372 "), ":irc.test 372 alice :- first line");
+    client.expect(" 376 ");
+
+    let mut other = TestClient::connect(port);
+    other.send("NICK ALICE");
+    assert!(other
+        .expect(" 433 ")
+        .contains("ALICE :Nickname is already in use"));
+    other.send("NICK 9lives");
+    assert!(other.expect(" 432 ").contains("Erroneous nickname"));
+
+    client.send("FROB");
+    assert_eq!(
+        client.expect(" 421 "),
+        ":irc.test 421 alice FROB :Unknown command"
+    );
+    client.send("KICK");
+    assert!(client
+        .expect(" 461 ")
+        .contains("KICK :Not enough parameters"));
+    client.send("USER again 0 * :Again");
+    client.expect(" 462 ");
+
+    client.send("PING :token");
+    assert_eq!(client.expect("PONG"), ":irc.test PONG irc.test token");
+
+    client.send("QUIT :bye");
+    assert!(client.expect("ERROR").contains("Quit: bye"));
+}
+
+#[test]
+fn channels() {
+    let port = start(None);
+
+    let mut alice = TestClient::register(port, "alice");
+    let mut bob = TestClient::register(port, "bob");
+
+    alice.send("JOIN #test");
+    assert_eq!(alice.expect("JOIN"), ":alice!alice@127.0.0.1 JOIN #test");
+    assert!(alice.expect(" 353 ").ends_with("= #test @alice"));
+    alice.expect(" 366 ");
+
+    bob.send("JOIN #Test");
+    assert_eq!(alice.expect("JOIN"), ":bob!bob@127.0.0.1 JOIN #test");
+    assert!(bob.expect(" 353 ").contains("@alice"));
+
+    alice.send("PRIVMSG #test :hello there");
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        ":alice!alice@127.0.0.1 PRIVMSG #test :hello there"
+    );
+
+    bob.send("TOPIC #test :the topic");
+    assert_eq!(
+        alice.expect("TOPIC"),
+        ":bob!bob@127.0.0.1 TOPIC #test :the topic"
+    );
+    alice.send("TOPIC #test");
+    assert_eq!(
+        alice.expect(" 332 "),
+        ":irc.test 332 alice #test :the topic"
+    );
+
+    bob.send("MODE #test +m");
+    assert!(bob.expect(" 482 ").contains("You're not channel operator"));
+
+    alice.send("MODE #test +nt-x+v bob");
+    assert!(alice.expect(" 472 ").contains("x :is unknown mode char"));
+    assert_eq!(
+        bob.expect("MODE"),
+        ":alice!alice@127.0.0.1 MODE #test +ntv bob"
+    );
+    alice.send("MODE #test");
+    assert_eq!(alice.expect(" 324 "), ":irc.test 324 alice #test +nt");
+
+    alice.send("MODE #test +b nobody");
+    bob.expect("+b nobody!*@*");
+    alice.send("MODE #test +b");
+    assert_eq!(
+        alice.expect(" 367 "),
+        ":irc.test 367 alice #test nobody!*@*"
+    );
+    alice.expect(" 368 ");
+
+    alice.send("NAMES #test");
+    assert!(alice.expect(" 353 ").ends_with(":@alice +bob"));
+
+    alice.send("KICK #test bob :behave");
+    assert_eq!(
+        bob.expect("KICK"),
+        ":alice!alice@127.0.0.1 KICK #test bob :behave"
+    );
+
+    bob.send("PART #test");
+    assert!(bob
+        .expect(" 442 ")
+        .contains("#test :You're not on that channel"));
+
+    alice.send("PART #test :done");
+    alice.expect("PART #test :done");
+    alice.send("TOPIC #test");
+    alice.expect(" 403 ");
+}
+
+#[test]
+fn users() {
+    let port = start(None);
+
+    let mut alice = TestClient::register(port, "alice");
+    let mut bob = TestClient::register(port, "bob");
+
+    alice.send("PRIVMSG bob :hi bob");
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        ":alice!alice@127.0.0.1 PRIVMSG bob :hi bob"
+    );
+    alice.send("PRIVMSG carol :anyone?");
+    assert!(alice
+        .expect(" 401 ")
+        .contains("carol :No such nick/channel"));
+
+    bob.send("WHOIS alice");
+    assert_eq!(
+        bob.expect(" 311 "),
+        ":irc.test 311 bob alice alice 127.0.0.1 * :alice real"
+    );
+    bob.expect(" 318 ");
+
+    alice.send("JOIN #room");
+    alice.expect(" 366 ");
+    bob.send("JOIN #room");
+    alice.expect("bob!bob@127.0.0.1 JOIN #room");
+
+    alice.send("WHO #room");
+    assert!(alice
+        .expect(" 352 ")
+        .contains("#room alice 127.0.0.1 irc.test alice H@ :0 alice real"));
+    alice.expect(" 315 ");
+
+    alice.send("MODE alice +i");
+    assert_eq!(alice.expect("MODE"), ":alice!alice@127.0.0.1 MODE alice +i");
+    alice.send("MODE bob +i");
+    alice.expect(" 502 ");
+
+    bob.send("NICK robert");
+    assert_eq!(alice.expect("NICK"), ":bob!bob@127.0.0.1 NICK :robert");
+
+    bob.send("QUIT :gone");
+    assert_eq!(
+        alice.expect("QUIT"),
+        ":robert!bob@127.0.0.1 QUIT :Quit: gone"
+    );
+
+    alice.send("NAMES #room");
+    assert!(alice.expect(" 353 ").ends_with("#room @alice"));
+}
+
+#[test]
+fn channel_modes() {
+    let port = start(None);
+
+    let mut alice = TestClient::register(port, "alice");
+    let mut bob = TestClient::register(port, "bob");
+    let mut carol = TestClient::register(port, "carol");
+
+    alice.send("JOIN #modes");
+    alice.expect(" 366 ");
+
+    alice.send("MODE #modes +b bob!*@*");
+    alice.expect("+b bob!*@*");
+    bob.send("JOIN #modes");
+    assert_eq!(
+        bob.expect(" 474 "),
+        ":irc.test 474 bob #modes :Cannot join channel (+b)"
+    );
+    bob.send("PRIVMSG #modes :hi");
+    bob.expect(" 404 ");
+
+    alice.send("MODE #modes +e *!bob@*");
+    alice.expect("+e *!bob@*");
+    bob.send("JOIN #modes");
+    alice.expect(":bob!bob@127.0.0.1 JOIN #modes");
+
+    alice.send("MODE #modes +imt");
+    alice.expect("MODE #modes +imt");
+    bob.send("PRIVMSG #modes :muted");
+    assert_eq!(
+        bob.expect(" 404 "),
+        ":irc.test 404 bob #modes :Cannot send to channel"
+    );
+    bob.send("TOPIC #modes :mine");
+    bob.expect(" 482 ");
+
+    carol.send("JOIN #modes");
+    carol.expect(" 473 ");
+    bob.send("INVITE carol #modes");
+    bob.expect(" 482 ");
+    alice.send("INVITE bob #modes");
+    assert!(alice.expect(" 443 ").contains("bob #modes :is already on channel"));
+    alice.send("INVITE carol #modes");
+    assert_eq!(alice.expect(" 341 "), ":irc.test 341 alice #modes carol");
+    assert_eq!(
+        carol.expect("INVITE"),
+        ":alice!alice@127.0.0.1 INVITE carol #modes"
+    );
+    carol.send("JOIN #modes");
+    alice.expect(":carol!carol@127.0.0.1 JOIN #modes");
+    carol.send("PART #modes");
+    alice.expect("PART #modes");
+
+    // the invitation was used up, but the exception lets carol in
+    carol.send("JOIN #modes");
+    carol.expect(" 473 ");
+    alice.send("MODE #modes -i+I carol!*@*");
+    alice.expect("-i+I carol!*@*");
+
+    alice.send("MODE #modes +k");
+    alice.expect(" 461 ");
+    alice.send("MODE #modes +kl key 2");
+    alice.expect("+kl key 2");
+    carol.send("JOIN #modes wrong");
+    assert_eq!(
+        carol.expect(" 475 "),
+        ":irc.test 475 carol #modes :Cannot join channel (+k)"
+    );
+    carol.send("JOIN #modes key");
+    carol.expect(" 471 ");
+
+    alice.send("MODE #modes +v bob");
+    alice.expect("+v bob");
+    bob.send("PRIVMSG #modes :voiced");
+    alice.expect("PRIVMSG #modes :voiced");
+
+    carol.send("PRIVMSG #modes :outside");
+    carol.expect(" 404 ");
+
+    alice.send("MODE #modes +s");
+    alice.expect("+s");
+    carol.send("LIST");
+    assert_eq!(
+        carol.expect_any(&[" 322 ", " 323 "]),
+        ":irc.test 323 carol :End of LIST"
+    );
+    alice.send("LIST #modes");
+    assert!(alice.expect(" 322 ").contains("#modes 2 :"));
+}
+
+#[test]
+fn operators() {
+    let hash = oper::hash_password("hunter2");
+    assert!(oper::verify_password("hunter2", &hash));
+    assert!(!oper::verify_password("hunter3", &hash));
+    assert_ne!(hash, oper::hash_password("hunter2"));
+    assert_eq!(
+        sasl::Credentials::parse(&hash).unwrap().iterations(),
+        sasl::ITERATIONS
+    );
+
+    let ban_file = env::temp_dir().join(format!("np1th-irc-bans-{}", process::id()));
+    let port = start_opers(ban_file.clone());
+
+    let mut alice = TestClient::register(port, "alice");
+    let mut bob = TestClient::register(port, "bob");
+    let mut carol = TestClient::register(port, "carol");
+
+    bob.send("KILL alice :bye");
+    assert_eq!(
+        bob.expect(" 481 "),
+        ":irc.test 481 bob :Permission Denied- You're not an IRC operator"
+    );
+    bob.send("STATS k");
+    bob.expect(" 481 ");
+    bob.send("OPER nobody hunter2");
+    bob.expect(" 491 ");
+    bob.send("OPER remote hunter2");
+    bob.expect(" 491 ");
+    bob.send("OPER helper hunter3");
+    bob.expect(" 464 ");
+
+    bob.send("OPER helper hunter2");
+    assert_eq!(
+        bob.expect(" 381 "),
+        ":irc.test 381 bob :You are now an IRC operator"
+    );
+    assert_eq!(bob.expect("MODE"), ":bob!bob@127.0.0.1 MODE bob +o");
+    bob.send("KLINE *@example.org :spam");
+    assert_eq!(
+        bob.expect(" 723 "),
+        ":irc.test 723 bob ban :Insufficient oper privileges."
+    );
+    alice.send("WHOIS bob");
+    alice.expect(" 313 ");
+
+    bob.send("STATS o");
+    assert_eq!(
+        bob.expect(" 243 "),
+        ":irc.test 243 bob O *@127.0.0.1 * admin admins"
+    );
+    bob.expect(" 219 ");
+
+    bob.send("KILL alice :enough");
+    assert_eq!(
+        alice.expect("KILL"),
+        ":bob!bob@127.0.0.1 KILL alice :enough"
+    );
+    assert!(alice.expect("ERROR").contains("Killed (bob (enough))"));
+
+    carol.send("OPER admin hunter2");
+    carol.expect(" 381 ");
+    carol.send("KLINE 10 dave :go away");
+    assert!(carol
+        .expect("NOTICE")
+        .ends_with(":Added temporary 10 min. K-Line [*@dave]"));
+    carol.send("KLINE dave@127.0.0.* :go away");
+    carol.expect("Added K-Line [dave@127.0.0.*]");
+    carol.send("KLINE dave@127.0.0.* :again");
+    carol.expect("already K-Lined by [go away]");
+    carol.send("DLINE 10 192.0.2.0/24 :lan");
+    carol.expect("Added temporary 10 min. D-Line [192.0.2.0/24]");
+    carol.send("DLINE example.org :nope");
+    carol.expect("Invalid D-Line");
+
+    let mut dave = TestClient::connect(port);
+    dave.send("NICK dave");
+    dave.send("USER dave 0 * :Dave");
+    assert_eq!(
+        dave.expect(" 465 "),
+        ":irc.test 465 dave :You are banned from this server- go away"
+    );
+    assert!(dave.expect("ERROR").contains("K-Lined"));
+
+    carol.send("STATS K");
+    assert_eq!(
+        carol.expect(" 216 "),
+        ":irc.test 216 carol k dave * * :go away"
+    );
+    assert_eq!(
+        carol.expect(" 216 "),
+        ":irc.test 216 carol K 127.0.0.* * dave :go away"
+    );
+    carol.expect(" 219 ");
+    carol.send("STATS d");
+    assert_eq!(
+        carol.expect(" 225 "),
+        ":irc.test 225 carol d 192.0.2.0/24 lan"
+    );
+
+    carol.send("UNKLINE dave");
+    carol.expect("K-Line for [*@dave] is removed");
+    carol.send("UNKLINE dave");
+    carol.expect("No K-Line for [*@dave] found");
+
+    // the bans outlive the server
+    let bans = Ircd::builder()
+        .listen("127.0.0.1:0")
+        .ban_file(&ban_file)
+        .build()
+        .unwrap()
+        .bans()
+        .clone();
+
+    assert_eq!(bans.len(), 2);
+    assert_eq!(bans[0].kind(), Kind::KLine);
+    assert_eq!(bans[0].mask(), "dave@127.0.0.*");
+    assert_eq!(bans[0].expires(), None);
+    assert_eq!(bans[1].kind(), Kind::DLine);
+    assert_eq!(bans[1].setter(), "carol");
+    assert!(bans[1].expires().is_some());
+
+    // everybody connects from here, so carol is the first to go
+    carol.send("DLINE 127.0.0.1 :closed");
+    carol.expect(" 465 ");
+    assert!(carol.expect("ERROR").contains("D-Lined"));
+
+    let mut erin = TestClient::connect(port);
+    assert!(erin.expect(" 465 ").ends_with(":You are banned from this server- closed"));
+
+    let _ = fs::remove_file(&ban_file);
+}
+
+const CONFIG: &str = r#"
+[server]
+name = "irc.test"
+motd = "motd.txt"
+
+[limits]
+nick_length = 6
+topic_length = 10
+channels = 1
+
+[[listen]]
+address = "127.0.0.1:0"
+
+[[class]]
+name = "local"
+hosts = ["127.0.0.*"]
+max_clients = 2
+
+[[oper_class]]
+name = "admins"
+privileges = ["rehash"]
+
+[[oper]]
+name = "admin"
+password = "HASH"
+hosts = ["*@127.0.0.1"]
+class = "admins"
+"#;
+
+#[test]
+fn configuration() {
+    let directory = env::temp_dir().join(format!("np1th-irc-config-{}", process::id()));
+    let path = directory.join("ircd.toml");
+    let config = CONFIG.replace("HASH", &oper::hash_password("hunter2"));
+
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("motd.txt"), "first motd").unwrap();
+
+    let broken = vec![
+        ("name = \"irc.test\"", "nmae = \"irc.test\"", "server.nmae"),
+        ("127.0.0.1:0", "127.0.0.1", "listen[0].address"),
+        ("nick_length = 6", "nick_length = 60", "limits.nick_length"),
+        ("class = \"admins\"", "class = \"nobody\"", "oper[0].class"),
+        ("[\"rehash\"]", "[\"rehash\", \"die\"]", "oper_class[0].privileges"),
+    ];
+
+    for (from, to, key) in broken {
+        fs::write(&path, config.replace(from, to)).unwrap();
+
+        match Ircd::from_config(&path) {
+            Ok(_) => panic!("{} isn't checked", key),
+            Err(e) => assert!(e.to_string().contains(&format!("key: {};", key)), "{}", e),
+        }
+    }
+
+    fs::write(&path, &config).unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+    let config_path = path.clone();
+
+    thread::spawn(move || {
+        let mut ircd = Ircd::from_config(&config_path).unwrap();
+
+        sender.send(ircd.addresses()[0].port()).unwrap();
+        ircd.run().unwrap();
+    });
+
+    let port = receiver.recv().unwrap();
+
+    let mut alice = TestClient::connect(port);
+    alice.send("NICK alice");
+    alice.send("USER alice 0 * :Alice");
+    let isupport = alice.expect(" 005 ");
+    assert!(isupport.contains("NICKLEN=6 CHANNELLEN=50 TOPICLEN=10"));
+    assert!(isupport.contains("CHANLIMIT=#&+!:1"));
+    assert_eq!(alice.expect(" 372 "), ":irc.test 372 alice :- first motd");
+
+    let mut bob = TestClient::register(port, "bob");
+    let mut third = TestClient::connect(port);
+    assert!(third
+        .expect("ERROR")
+        .contains("Too many connections in this class"));
+
+    alice.send("NICK alice22");
+    alice.expect(" 432 ");
+    alice.send("JOIN #one,#two");
+    alice.expect(" 366 ");
+    assert!(alice
+        .expect(" 405 ")
+        .ends_with("#two :You have joined too many channels"));
+    alice.send("TOPIC #one :a rather long topic");
+    assert!(alice.expect("TOPIC").ends_with("TOPIC #one :a rather l"));
+
+    bob.send("REHASH");
+    bob.expect(" 481 ");
+
+    alice.send("OPER admin hunter2");
+    alice.expect(" 381 ");
+
+    fs::write(directory.join("motd.txt"), "second motd").unwrap();
+    fs::write(&path, config.replace("nick_length = 6", "nick_length = 9")).unwrap();
+
+    alice.send("REHASH");
+    assert!(alice.expect(" 382 ").ends_with("/ircd.toml Rehashing"));
+    alice.send("MOTD");
+    assert_eq!(alice.expect(" 372 "), ":irc.test 372 alice :- second motd");
+    alice.send("NICK alice22");
+    alice.expect("NICK :alice22");
+
+    // a broken file changes nothing
+    fs::write(&path, config.replace("motd.txt", "missing.txt")).unwrap();
+    alice.send("REHASH");
+    assert!(alice.expect("NOTICE").contains("Rehash failed"));
+    bob.send("MOTD");
+    bob.expect("second motd");
+
+    let _ = fs::remove_dir_all(&directory);
+}
+
+/// Runs a server with services keeping the accounts in `account_file`
+fn start_services(account_file: PathBuf) -> u16 {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut ircd = Ircd::builder()
+            .name("irc.test")
+            .listen("127.0.0.1:0")
+            .account_file(account_file)
+            .build()
+            .unwrap();
+
+        sender.send(ircd.addresses()[0].port()).unwrap();
+        ircd.run().unwrap();
+    });
+
+    receiver.recv().unwrap()
+}
+
+/// Decoded data of the next `AUTHENTICATE` line
+fn challenge(client: &mut TestClient) -> String {
+    let line = client.expect("AUTHENTICATE ");
+    let data = sasl::decode_base64(line.rsplit(' ').next().unwrap()).unwrap();
+
+    String::from_utf8(data).unwrap()
+}
+
+#[test]
+fn services() {
+    assert_eq!(sasl::encode_base64(b"\0bob\0secret"), "AGJvYgBzZWNyZXQ=");
+    assert_eq!(sasl::decode_base64("c2VjcmV0").unwrap(), b"secret");
+    assert!(sasl::decode_base64("c2V=jcmV0").is_none());
+
+    // RFC 7677
+    let salt = sasl::decode_base64("W22ZaJ0SNY7soEsUEjb6gQ==").unwrap();
+    let salted_password = sasl::pbkdf2(b"pencil", &salt, 4096);
+    let auth_message = "n=user,r=rOprNGfwEbeRWgbNEkqO,\
+                        r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,\
+                        i=4096,c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0";
+    let server_key = sasl::hmac(&salted_password, b"Server Key");
+    assert_eq!(
+        sasl::encode_base64(&sasl::hmac(&server_key, auth_message.as_bytes())),
+        "6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4="
+    );
+
+    let account_file = env::temp_dir().join(format!("np1th-irc-accounts-{}", process::id()));
+    let port = start_services(account_file.clone());
+
+    let mut alice = TestClient::register(port, "alice");
+    alice.send("PRIVMSG NickServ :REGISTER hunter2");
+    assert_eq!(
+        alice.expect(" 900 "),
+        ":irc.test 900 alice alice!alice@127.0.0.1 alice :You are now logged in as alice"
+    );
+    alice.send("PRIVMSG NickServ :REGISTER hunter2");
+    assert_eq!(
+        alice.expect("NOTICE"),
+        ":NickServ!NickServ@irc.test NOTICE alice :You are already logged in as alice"
+    );
+    alice.send("NICK nickserv");
+    alice.expect(" 433 ");
+
+    let mut bob = TestClient::register(port, "bob");
+    bob.send("PRIVMSG NickServ :REGISTER secret");
+    bob.expect(" 900 ");
+    bob.send("QUIT");
+    bob.expect("ERROR");
+
+    alice.send("JOIN #room");
+    alice.expect(" 366 ");
+    alice.send("PRIVMSG ChanServ :REGISTER #room");
+    assert!(alice
+        .expect("NOTICE")
+        .ends_with(":#room is now registered to alice"));
+    alice.send("PRIVMSG ChanServ :ACCESS #room ADD carol op");
+    assert!(alice.expect("NOTICE").ends_with(":carol isn't registered"));
+    alice.send("PRIVMSG ChanServ :ACCESS #room ADD bob voice");
+    assert!(alice
+        .expect("NOTICE")
+        .ends_with(":bob now gets voice in #room"));
+
+    // registration waits for the end of the negotiation
+    let mut bob = TestClient::connect(port);
+    bob.send("CAP LS 302");
+    assert!(bob.expect("CAP").ends_with(
+        ":account-notify account-tag batch draft/chathistory extended-join message-tags \
+         server-time sasl=PLAIN,SCRAM-SHA-256"
+    ));
+    bob.send("CAP REQ :sasl extended-join account-tag account-notify");
+    bob.expect("ACK");
+    bob.send("NICK bob");
+    bob.send("USER bob 0 * :Bob Real");
+    bob.send("AUTHENTICATE EXTERNAL");
+    assert!(bob.expect(" 908 ").contains("PLAIN,SCRAM-SHA-256"));
+    bob.expect(" 904 ");
+    bob.send("AUTHENTICATE PLAIN");
+    bob.expect("AUTHENTICATE +");
+    bob.send(&format!(
+        "AUTHENTICATE {}",
+        sasl::encode_base64(b"\0bob\0wrong")
+    ));
+    bob.expect(" 904 ");
+    bob.send("AUTHENTICATE PLAIN");
+    bob.expect("AUTHENTICATE +");
+    bob.send(&format!(
+        "AUTHENTICATE {}",
+        sasl::encode_base64(b"\0bob\0secret")
+    ));
+    assert!(bob
+        .expect(" 900 ")
+        .ends_with("bob :You are now logged in as bob"));
+    bob.expect(" 903 ");
+    bob.send("CAP END");
+    bob.expect(" 001 ");
+
+    bob.send("JOIN #room");
+    assert_eq!(
+        bob.expect("JOIN"),
+        "@account=bob :bob!bob@127.0.0.1 JOIN #room bob :Bob Real"
+    );
+    assert_eq!(bob.expect("MODE"), ":irc.test MODE #room +v bob");
+    alice.expect("MODE #room +v bob");
+
+    alice.send("PRIVMSG #room :hello");
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        "@account=alice :alice!alice@127.0.0.1 PRIVMSG #room :hello"
+    );
+    alice.send("PRIVMSG NickServ :LOGOUT");
+    alice.expect(" 901 ");
+    assert_eq!(bob.expect("ACCOUNT"), ":alice!alice@127.0.0.1 ACCOUNT *");
+    alice.send("PRIVMSG NickServ :IDENTIFY alice hunter3");
+    assert!(alice.expect("NOTICE").ends_with(":Invalid password"));
+    alice.send("PRIVMSG NickServ :IDENTIFY hunter2");
+    alice.expect(" 900 ");
+    assert_eq!(
+        bob.expect("ACCOUNT"),
+        "@account=alice :alice!alice@127.0.0.1 ACCOUNT alice"
+    );
+
+    bob.send("WHOIS alice");
+    assert_eq!(
+        bob.expect(" 330 "),
+        ":irc.test 330 bob alice alice :is logged in as"
+    );
+
+    // SCRAM-SHA-256 and taking the nick back from the old connection
+    let mut other = TestClient::connect(port);
+    other.send("CAP REQ :sasl");
+    other.expect("ACK");
+    other.send("NICK bob_");
+    other.send("USER bob 0 * :Bob Real");
+    other.send("AUTHENTICATE SCRAM-SHA-256");
+    other.expect("AUTHENTICATE +");
+
+    let client_first = "n=bob,r=fyko+d2lbbFgONRv9qkxdawL";
+    other.send(&format!(
+        "AUTHENTICATE {}",
+        sasl::encode_base64(format!("n,,{}", client_first).as_bytes())
+    ));
+
+    let server_first = challenge(&mut other);
+    let fields = server_first.split(',').collect::<Vec<&str>>();
+    assert!(fields[0].starts_with("r=fyko+d2lbbFgONRv9qkxdawL"));
+
+    let salt = sasl::decode_base64(&fields[1][2..]).unwrap();
+    let salted_password = sasl::pbkdf2(b"secret", &salt, fields[2][2..].parse().unwrap());
+    let client_key = sasl::hmac(&salted_password, b"Client Key");
+    let without_proof = format!("c=biws,{}", fields[0]);
+    let auth_message = format!("{},{},{}", client_first, server_first, without_proof);
+    let signature = sasl::hmac(&Sha256::digest(&client_key), auth_message.as_bytes());
+    let proof = client_key
+        .iter()
+        .zip(signature.iter())
+        .map(|(a, b)| a ^ b)
+        .collect::<Vec<u8>>();
+
+    other.send(&format!(
+        "AUTHENTICATE {}",
+        sasl::encode_base64(
+            format!("{},p={}", without_proof, sasl::encode_base64(&proof)).as_bytes()
+        )
+    ));
+
+    let server_key = sasl::hmac(&salted_password, b"Server Key");
+    let verifier = sasl::hmac(&server_key, auth_message.as_bytes());
+    assert_eq!(
+        challenge(&mut other),
+        format!("v={}", sasl::encode_base64(&verifier))
+    );
+    other.send("AUTHENTICATE +");
+    other.expect(" 903 ");
+    other.send("CAP END");
+    other.expect(" 001 ");
+
+    other.send("PRIVMSG NickServ :GHOST bob");
+    assert!(bob
+        .expect("ERROR")
+        .contains("Killed (irc.test (GHOST command used by bob_))"));
+    assert!(other.expect("NOTICE").ends_with(":bob has been ghosted"));
+    other.send("NICK bob");
+    other.expect("NICK :bob");
+
+    // the accounts survive a restart
+    let data = fs::read_to_string(&account_file).unwrap();
+    assert!(data.contains("A alice "));
+    assert!(data.contains("C #room "));
+    assert!(data.contains("X #room bob voice"));
+
+    let port = start_services(account_file.clone());
+    let mut alice = TestClient::register(port, "alice");
+    alice.send("PRIVMSG NickServ :IDENTIFY hunter2");
+    alice.expect(" 900 ");
+
+    let _ = fs::remove_file(&account_file);
+}
+
+/// Waits for the next message `read` returns
+fn next_message(client: &mut Client) -> Message<Command> {
+    for _ in 0..500 {
+        if let Some(message) = client.read().unwrap() {
+            return message;
+        }
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    panic!("no message");
+}
+
+fn texts(messages: &[Message<Command>]) -> Vec<String> {
+    messages
+        .iter()
+        .map(|message| match message.command() {
+            Command::PrivMsg { text, .. } => text.to_string(),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn chat_history() {
+    let port = start(None);
+
+    let mut alice = TestClient::register(port, "alice");
+    alice.send("JOIN #history");
+    alice.expect(" 366 ");
+
+    let myself = User::new(
+        Origin::User {
+            nick: "bob".to_string(),
+            user: Some("bob".to_string()),
+            host: None,
+        },
+        "Bob",
+    );
+
+    let mut bob = Client::builder()
+        .host("127.0.0.1")
+        .port(Port::Insecure(port))
+        .user(myself)
+        .capabilities(vec![
+            "batch",
+            history::CAPABILITY,
+            "message-tags",
+            "server-time",
+        ])
+        .build()
+        .unwrap();
+
+    assert!(bob.capabilities().is_enabled(history::CAPABILITY));
+
+    bob.send(Command::Join {
+        channels: vec!["#history".to_string()],
+        keys: Vec::new(),
+    })
+    .unwrap();
+    alice.expect("JOIN");
+
+    for text in &["one", "two", "three", "four", "five"] {
+        alice.send(&format!("PRIVMSG #history :{}", text));
+    }
+
+    alice.send("PRIVMSG bob :psst");
+
+    // clients without the capabilities get no tags
+    alice.send("PRIVMSG alice :self");
+    assert_eq!(
+        alice.expect("self"),
+        ":alice!alice@127.0.0.1 PRIVMSG alice :self"
+    );
+
+    let latest = bob
+        .history(
+            "#history",
+            Range::Latest {
+                after: None,
+                limit: 3,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(texts(&latest), vec!["three", "four", "five"]);
+    assert!(latest.iter().all(|message| message.tag("time").is_some()));
+
+    let id = |message: &Message<Command>| {
+        Reference::MsgId(message.tag("msgid").unwrap().unwrap().to_string())
+    };
+
+    let before = bob
+        .history(
+            "#HISTORY",
+            Range::Before {
+                reference: id(&latest[0]),
+                limit: 10,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(texts(&before), vec!["one", "two"]);
+
+    let between = bob
+        .history(
+            "#history",
+            Range::Between {
+                start: id(&latest[2]),
+                end: id(&before[0]),
+                limit: 2,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(texts(&between), vec!["three", "four"]);
+
+    let around = bob
+        .history(
+            "#history",
+            Range::Around {
+                reference: id(&latest[0]),
+                limit: 3,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(texts(&around), vec!["two", "three", "four"]);
+
+    let private = bob
+        .history(
+            "alice",
+            Range::After {
+                reference: Reference::Timestamp(0),
+                limit: 10,
+            },
+        )
+        .unwrap();
+
+    assert_eq!(texts(&private), vec!["psst"]);
+    assert!(bob
+        .history(
+            "#elsewhere",
+            Range::Latest {
+                after: None,
+                limit: 10,
+            },
+        )
+        .is_err());
+
+    let targets = bob.history_targets(0, time::now_millis() + 1000, 10).unwrap();
+
+    assert_eq!(
+        targets
+            .iter()
+            .map(|(target, _)| target.as_str())
+            .collect::<Vec<&str>>(),
+        vec!["#history", "alice"]
+    );
+
+    // the live messages read while waiting for the history are still there, with the same ids
+    let mut live = Vec::new();
+
+    while live.len() < 6 {
+        let message = next_message(&mut bob);
+
+        if let Command::PrivMsg { .. } = message.command() {
+            live.push(message);
+        }
+    }
+
+    assert_eq!(
+        texts(&live),
+        vec!["one", "two", "three", "four", "five", "psst"]
+    );
+    assert_eq!(live[2].tag("msgid"), latest[0].tag("msgid"));
+}
+
+#[test]
+fn linking() {
+    let a = start_linked("irc.a.test", None);
+    let mut alice = TestClient::register(a, "alice");
+
+    alice.send("JOIN #net");
+    alice.expect(" 366 ");
+
+    let b = start_linked("irc.b.test", Some((a, "irc.a.test")));
+    let mut bob = TestClient::register(b, "bob");
+    bob.wait_for_server("irc.a.test");
+
+    bob.send("JOIN #net");
+    assert_eq!(alice.expect("JOIN"), ":bob!bob@127.0.0.1 JOIN #net");
+    assert!(bob.expect(" 353 ").ends_with("#net :@alice bob"));
+
+    alice.send("PRIVMSG #net :hello network");
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        ":alice!alice@127.0.0.1 PRIVMSG #net :hello network"
+    );
+
+    bob.send("WHOIS alice");
+    assert_eq!(
+        bob.expect(" 312 "),
+        ":irc.b.test 312 bob alice irc.a.test :irc.a.test server"
+    );
+
+    let c = start_linked("irc.c.test", Some((b, "irc.b.test")));
+    let mut carol = TestClient::register(c, "carol");
+    carol.wait_for_server("irc.a.test");
+
+    carol.send("LINKS");
+    assert!(carol
+        .expect(" 364 carol irc.a.test")
+        .ends_with("irc.a.test irc.b.test :2 irc.a.test server"));
+
+    // two hops away
+    carol.send("PRIVMSG alice :hi from c");
+    assert_eq!(
+        alice.expect("PRIVMSG"),
+        ":carol!carol@127.0.0.1 PRIVMSG alice :hi from c"
+    );
+
+    carol.send("JOIN #net");
+    alice.expect(":carol!carol@127.0.0.1 JOIN #net");
+    bob.expect(":carol!carol@127.0.0.1 JOIN #net");
+
+    alice.send("MODE #net +v carol");
+    assert_eq!(
+        carol.expect("MODE"),
+        ":alice!alice@127.0.0.1 MODE #net +v carol"
+    );
+
+    carol.send("NICK caroline");
+    assert_eq!(alice.expect("NICK"), ":carol!carol@127.0.0.1 NICK :caroline");
+
+    alice.send("NICK caroline");
+    alice.expect(" 433 ");
+
+    carol.send("QUIT :later");
+    assert_eq!(
+        bob.expect("QUIT"),
+        ":caroline!carol@127.0.0.1 QUIT :Quit: later"
+    );
+}
+
+/// A server speaking the protocol through a plain socket
+struct TestServer {
+    client: TestClient,
+}
+
+impl TestServer {
+    fn link(port: u16) -> Self {
+        let mut client = TestClient::connect(port);
+
+        client.send(&format!("PASS {} 0210 IRC|", LINK_PASSWORD));
+        client.send("SERVER irc.fake.test 1 1 :Fake server");
+        client.expect("SERVER irc.a.test 1 1");
+
+        TestServer { client }
+    }
+}
+
+#[test]
+fn netsplit_and_collisions() {
+    let a = start_linked("irc.a.test", None);
+
+    let mut alice = TestClient::register(a, "alice");
+    let mut bob = TestClient::register(a, "bob");
+
+    alice.send("JOIN #net");
+    alice.expect(" 366 ");
+    bob.send("JOIN #net");
+    bob.expect(" 366 ");
+
+    let mut fake = TestServer::link(a);
+    assert!(fake
+        .client
+        .expect("NICK alice 1 alice 127.0.0.1 1 +")
+        .ends_with(":alice real"));
+    assert_eq!(
+        fake.client.expect("NJOIN"),
+        ":irc.a.test NJOIN #net :@alice,bob"
+    );
+
+    fake.client
+        .send("NICK mallory 1 mal evil.test 1 + 100 :Mallory");
+    fake.client.send(":mallory JOIN #net");
+    assert_eq!(bob.expect("JOIN"), ":mallory!mal@evil.test JOIN #net");
+
+    // a server behind the fake one
+    fake.client.send(":irc.fake.test SERVER irc.leaf.test 2 2 :Leaf");
+    fake.client.send("NICK trudy 2 trudy leaf.test 2 + 100 :Trudy");
+    fake.client.send(":trudy JOIN #net\x07v");
+    bob.expect(":trudy!trudy@leaf.test JOIN #net");
+    assert_eq!(bob.expect("MODE"), ":irc.leaf.test MODE #net +v trudy");
+
+    fake.client.send("SQUIT irc.leaf.test :Leaf gone");
+    assert_eq!(
+        bob.expect("QUIT"),
+        ":trudy!trudy@leaf.test QUIT :irc.fake.test irc.leaf.test"
+    );
+
+    // the newer nick loses and never shows up
+    fake.client
+        .send("NICK alice 1 alice2 fake.test 1 + 99999999999 :Newer");
+    alice.send("PRIVMSG bob :still here");
+    bob.expect(":alice!alice@127.0.0.1 PRIVMSG bob :still here");
+
+    // the older one wins
+    fake.client.send("NICK alice 1 alice1 fake.test 1 + 1 :Older");
+    assert_eq!(
+        alice.expect("KILL"),
+        ":irc.a.test KILL alice :Nick collision"
+    );
+    assert_eq!(
+        bob.expect("QUIT"),
+        ":alice!alice@127.0.0.1 QUIT :Killed (irc.a.test (Nick collision))"
+    );
+
+    bob.send("WHOIS alice");
+    assert!(bob
+        .expect(" 311 ")
+        .ends_with("alice alice1 fake.test * Older"));
+
+    drop(fake);
+    assert_eq!(
+        bob.expect("QUIT"),
+        ":mallory!mal@evil.test QUIT :irc.a.test irc.fake.test"
+    );
+
+    bob.send("LINKS");
+    assert!(bob.expect(" 364 ").contains("irc.a.test irc.a.test"));
+    bob.expect(" 365 ");
+}
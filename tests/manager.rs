@@ -0,0 +1,248 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use np1th_irc::{
+    command::client::Command,
+    ircd::oper::{self, Class, Oper, Privilege},
+    manager::{Event, Manager, Session},
+    target::MessageTarget,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs a server where `admin` with the password `hunter2` may kill
+fn start(name: &'static str) -> u16 {
+    common::start_ircd(name, |builder| {
+        builder
+            .class(Class::new("admins").privilege(Privilege::Kill))
+            .oper(Oper::new("admin", &oper::hash_password("hunter2"), "admins").host("*@127.0.0.1"))
+    })
+}
+
+/// Polls until an event matches, returns the events skipped on the way as well
+fn until<F>(manager: &mut Manager, matches: F) -> (Event, Vec<Event>)
+where
+    F: Fn(&Event) -> bool,
+{
+    let started = Instant::now();
+    let mut skipped = Vec::new();
+
+    while started.elapsed() < TIMEOUT {
+        match manager.poll() {
+            Some(ref event) if matches(event) => return (event.clone(), skipped),
+            Some(event) => skipped.push(event),
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    }
+
+    panic!("no matching event, got {:?}", skipped);
+}
+
+fn is_numeric(event: &Event, network: &str, numeric: u16) -> bool {
+    match event {
+        Event::Message {
+            network: from,
+            message,
+        } if from == network => match message.command() {
+            Command::Numeric { code, .. } => *code == numeric,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    fn register(port: u16, nick: &str) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut client = TestClient {
+            reader: BufReader::new(stream),
+        };
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+        client.expect(" 422 ");
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", part),
+                Ok(_) if line.contains(part) => return line.trim_end().to_string(),
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, part),
+            }
+        }
+    }
+}
+
+#[test]
+fn several_networks() {
+    let a = start("irc.a.test");
+    let b = start("irc.b.test");
+
+    // nothing listens there anymore
+    let closed = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let mut manager = Manager::new()
+        .session(
+            Session::new("a", move || common::client(a, "relay"))
+                .channel("#room")
+                .reconnect_delay(Duration::from_millis(100)),
+        )
+        .session(Session::new("b", move || common::client(b, "relay")).channel("#other"))
+        .session(Session::new("c", move || common::client(closed, "relay")).reconnect(false));
+
+    assert_eq!(manager.networks(), vec!["a", "b", "c"]);
+
+    // both joined, in either order
+    let joined = |event: &Event| is_numeric(event, "a", 366) || is_numeric(event, "b", 366);
+    let (first, mut skipped) = until(&mut manager, joined);
+    let (second, more) = until(&mut manager, joined);
+    skipped.extend(more);
+
+    assert_ne!(first.network(), second.network());
+
+    let mut states = skipped
+        .iter()
+        .filter_map(|event| match event {
+            Event::Connected { network } => Some(format!("connected {}", network)),
+            Event::ConnectFailed { network, .. } => Some(format!("failed {}", network)),
+            Event::Disconnected { network, .. } => Some(format!("disconnected {}", network)),
+            Event::Message { .. } => None,
+        })
+        .collect::<Vec<String>>();
+
+    // connecting goes on in the background, so c may fail at any point
+    if !states.contains(&"failed c".to_string()) {
+        until(&mut manager, |event| match event {
+            Event::ConnectFailed { network, .. } => network == "c",
+            _ => false,
+        });
+        states.push("failed c".to_string());
+    }
+
+    states.sort();
+    assert_eq!(states, vec!["connected a", "connected b", "failed c"]);
+
+    assert!(manager.get("c").unwrap().client().is_none());
+    assert!(manager.privmsg("c", "#room", "hi").is_err());
+    assert!(manager.privmsg("d", "#room", "hi").is_err());
+
+    // events are tagged with the network, messages go to the network named
+    let mut alice = TestClient::register(a, "alice");
+    let mut bob = TestClient::register(b, "bob");
+    alice.send("JOIN #room");
+    bob.send("JOIN #other");
+    alice.expect(" 366 ");
+    bob.expect(" 366 ");
+
+    alice.send("PRIVMSG #room :hello from a");
+
+    let (event, _) = until(&mut manager, |event| match event {
+        Event::Message { message, .. } => match message.command() {
+            Command::PrivMsg { .. } => true,
+            _ => false,
+        },
+        _ => false,
+    });
+
+    assert_eq!(event.network(), "a");
+
+    if let Event::Message { message, .. } = event {
+        assert_eq!(message.origin().nick(), Some("alice"));
+        assert_eq!(
+            message.command(),
+            &Command::PrivMsg {
+                targets: vec![MessageTarget::Channel("#room".to_string())],
+                text: "hello from a".to_string(),
+            }
+        );
+    }
+
+    manager.privmsg("b", "#other", "hello from a").unwrap();
+    assert_eq!(
+        bob.expect("PRIVMSG"),
+        ":relay!relay@127.0.0.1 PRIVMSG #other :hello from a"
+    );
+
+    // a lost connection comes back and joins the channels again
+    alice.send("OPER admin hunter2");
+    alice.expect(" 381 ");
+    alice.send("KILL relay :come back");
+
+    until(&mut manager, |event| match event {
+        Event::Disconnected { network, .. } => network == "a",
+        _ => false,
+    });
+    assert!(manager.client("a").is_none());
+
+    let (_, skipped) = until(&mut manager, |event| is_numeric(event, "a", 366));
+
+    match skipped.first() {
+        Some(Event::Connected { network }) => assert_eq!(network, "a"),
+        _ => panic!("unexpected events {:?}", skipped),
+    }
+
+    assert!(alice.expect("JOIN").starts_with(":relay!"));
+    assert!(manager.client("b").is_some());
+
+    manager.remove("b").unwrap();
+    bob.expect("QUIT");
+    assert_eq!(manager.networks(), vec!["a", "c"]);
+
+    manager.disconnect();
+}
+
+#[test]
+fn connecting_without_blocking() {
+    let a = start("irc.a.test");
+
+    // takes the connection, but never answers
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = silent.local_addr().unwrap().port();
+
+    let mut manager = Manager::new()
+        .session(Session::new("silent", move || {
+            common::client(port, "relay")
+        }))
+        .session(Session::new("a", move || common::client(a, "relay")));
+
+    // a doesn't wait for the registration timeout of the other one
+    until(&mut manager, |event| match event {
+        Event::Connected { network } => network == "a",
+        _ => false,
+    });
+
+    assert!(manager.get("silent").unwrap().is_connecting());
+    assert!(manager.client("silent").is_none());
+}
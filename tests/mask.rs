@@ -0,0 +1,121 @@
+extern crate np1th_irc;
+
+use std::convert::TryFrom;
+
+use np1th_irc::{
+    casemapping::CaseMapping,
+    origin::{mask, BanStyle, ExtBan, Mask, Origin},
+};
+
+fn origin(data: &str) -> Origin {
+    Origin::try_from(data).unwrap()
+}
+
+#[test]
+fn glob_matching() {
+    let rfc1459 = CaseMapping::Rfc1459;
+
+    assert!(mask::matches("*", "", rfc1459));
+    assert!(mask::matches("*.example.com", "host.EXAMPLE.com", rfc1459));
+    assert!(mask::matches("?bot*", "xbot123", rfc1459));
+    assert!(!mask::matches("?bot*", "bot123", rfc1459));
+    assert!(mask::matches("a*b*c", "aXXbYYbZZc", rfc1459));
+    assert!(!mask::matches("a*b*c", "aXXbYYbZZ", rfc1459));
+    assert!(mask::matches("what\\?", "what?", rfc1459));
+    assert!(!mask::matches("what\\?", "whats", rfc1459));
+    assert!(mask::matches("\\*star", "*star", rfc1459));
+
+    assert!(mask::matches("[foo]", "{FOO}", rfc1459));
+    assert!(mask::matches("a~", "A^", rfc1459));
+    assert!(!mask::matches("a~", "a^", CaseMapping::StrictRfc1459));
+    assert!(!mask::matches("[foo]", "{foo}", CaseMapping::Ascii));
+}
+
+#[test]
+fn mask_matching() {
+    let user = origin("Nick!~ident@host.example.com");
+
+    assert!(Mask::new("*!*@*.example.com").matches(&user));
+    assert!(Mask::new("nick").matches(&user));
+    assert!(Mask::new("*ident@host.example.com").matches(&user));
+    assert!(Mask::new("n?ck!*").matches(&user));
+    assert!(!Mask::new("*!*@*.example.org").matches(&user));
+    assert!(!Mask::new("other!*@*").matches(&user));
+
+    assert_eq!(Mask::new("nick").as_str(), "nick!*@*");
+    assert_eq!(Mask::new("user@host").as_str(), "*!user@host");
+    assert_eq!(Mask::new("*.example.com").as_str(), "*!*@*.example.com");
+
+    assert!(Mask::new("*!*@*.example.com").matches_str("nick!user@a.example.com"));
+    assert!(!Mask::new("*!*@*.example.com").matches(&origin("irc.example.com")));
+}
+
+#[test]
+fn cidr_matching() {
+    let mask = Mask::new("*!*@192.0.2.0/24");
+
+    assert!(mask.matches_str("nick!user@192.0.2.17"));
+    assert!(!mask.matches_str("nick!user@192.0.3.17"));
+    assert!(!mask.matches_str("nick!user@host.example.com"));
+
+    let mask = Mask::new("*!*@2001:db8::/32");
+
+    assert!(mask.matches_str("nick!user@2001:db8:1::5"));
+    assert!(!mask.matches_str("nick!user@2001:db9::5"));
+    assert!(!mask.matches_str("nick!user@192.0.2.17"));
+}
+
+#[test]
+fn ban_masks() {
+    let user = origin("nick!~ident@a.b.example.com");
+
+    let ban = |style| Mask::ban(&user, style).unwrap().to_string();
+
+    assert_eq!(ban(BanStyle::Nick), "nick!*@*");
+    assert_eq!(ban(BanStyle::Host), "*!*@a.b.example.com");
+    assert_eq!(ban(BanStyle::UserHost), "*!*ident@a.b.example.com");
+    assert_eq!(ban(BanStyle::Domain), "*!*@*.b.example.com");
+    assert_eq!(ban(BanStyle::UserDomain), "*!*ident@*.b.example.com");
+    assert_eq!(ban(BanStyle::Full), "nick!*ident@a.b.example.com");
+
+    for style in vec![BanStyle::Host, BanStyle::Domain, BanStyle::UserDomain] {
+        assert!(Mask::ban(&user, style).unwrap().matches(&user));
+    }
+
+    let ip = origin("nick!ident@192.0.2.17");
+    assert_eq!(
+        Mask::ban(&ip, BanStyle::Domain).unwrap().as_str(),
+        "*!*@192.0.2.*"
+    );
+
+    assert!(Mask::ban(&origin("irc.example.com"), BanStyle::Host).is_none());
+}
+
+#[test]
+fn extended_bans() {
+    let user = origin("nick!ident@host.example.com");
+    let rfc1459 = CaseMapping::Rfc1459;
+
+    let account = ExtBan::parse("$a:Someone").unwrap();
+    assert!(account.is_account());
+    assert_eq!(account.matches(&user, Some("someone"), rfc1459), Some(true));
+    assert_eq!(account.matches(&user, Some("other"), rfc1459), Some(false));
+    assert_eq!(account.matches(&user, None, rfc1459), Some(false));
+
+    let unregistered = ExtBan::parse("$~a").unwrap();
+    assert!(unregistered.is_negated());
+    assert_eq!(unregistered.matches(&user, None, rfc1459), Some(true));
+    assert_eq!(unregistered.to_string(), "$~a");
+
+    let quiet = ExtBan::parse("~q:*!*@*.example.com").unwrap();
+    assert!(quiet.is_quiet());
+    assert_eq!(quiet.matches(&user, None, rfc1459), Some(true));
+    assert_eq!(quiet.to_string(), "~q:*!*@*.example.com");
+
+    let channel = ExtBan::parse("$j:#channel").unwrap();
+    assert_eq!(channel.kind(), "j");
+    assert_eq!(channel.matches(&user, None, rfc1459), None);
+
+    assert!(ExtBan::parse("*!*@host").is_none());
+    assert!(ExtBan::parse("~ident@host").is_none());
+}
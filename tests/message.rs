@@ -1,6 +1,10 @@
 extern crate np1th_irc;
 
-use np1th_irc::{command::client, message::Message};
+use np1th_irc::{
+    command::client,
+    message::{Message, MessageRef},
+    target::MessageTarget,
+};
 
 use std::convert::TryFrom;
 
@@ -11,3 +15,68 @@ fn test_nick_client_message() {
 
     assert!(message_res.is_ok());
 }
+
+#[test]
+fn test_tagged_message() {
+    let message = Message::<client::Command>::try_from(
+        "@time=2019-01-01T00:00:00.000Z;msgid=a\\sb\\:c;+draft/reply :avona1!~avon1@localhost NICK :whatever\r\n",
+    )
+        .unwrap();
+
+    assert_eq!(message.tag("msgid"), Some(Some("a b;c")));
+    assert_eq!(message.tag("+draft/reply"), Some(None));
+    assert_eq!(message.tag("account"), None);
+    assert!(message.to_string().starts_with("@time=2019-01-01T00:00:00.000Z;msgid=a\\sb\\:c;+draft/reply :"));
+}
+
+#[test]
+fn test_borrowed_message() {
+    let message = MessageRef::parse(
+        "@msgid=a\\sb;+draft/reply :avona1!~avon1@localhost PRIVMSG #channel :hello there\r\n",
+    )
+        .unwrap();
+
+    assert_eq!(message.prefix(), Some("avona1!~avon1@localhost"));
+    assert_eq!(message.nick(), Some("avona1"));
+    assert_eq!(message.command(), "PRIVMSG");
+    assert_eq!(
+        message.params().collect::<Vec<&str>>(),
+        vec!["#channel", "hello there"]
+    );
+    assert_eq!(message.param(2), None);
+    assert_eq!(message.tag("msgid").unwrap().unwrap(), "a b");
+    assert_eq!(message.tag("+draft/reply"), Some(None));
+    assert_eq!(message.tags().count(), 2);
+
+    let owned = Message::<client::Command>::try_from(message).unwrap();
+    assert_eq!(owned.tag("msgid"), Some(Some("a b")));
+    assert_eq!(owned.origin().nick(), Some("avona1"));
+    assert_eq!(
+        owned.to_string(),
+        Message::<client::Command>::try_from(message.line())
+            .unwrap()
+            .to_string()
+    );
+
+    let ping = MessageRef::parse("PING :irc.example.com").unwrap();
+    assert_eq!(ping.prefix(), None);
+    assert_eq!(ping.param(0), Some("irc.example.com"));
+
+    assert!(MessageRef::parse(":prefix.only").is_err());
+    assert!(MessageRef::parse("").is_err());
+}
+
+#[test]
+fn test_notice_before_registration() {
+    let message =
+        Message::<client::Command>::try_from(":irc.example NOTICE * :hi\r\n").unwrap();
+
+    match message.command() {
+        client::Command::Notice { target, text } => {
+            assert_eq!(*target, MessageTarget::Unregistered);
+            assert_eq!(text, "hi");
+        }
+        command => panic!("not a notice: {:?}", command),
+    }
+    assert_eq!(message.to_string(), ":irc.example NOTICE * :hi\r\n");
+}
@@ -0,0 +1,32 @@
+extern crate np1th_irc;
+
+use np1th_irc::connection::nick::{Alternatives, Fallback};
+
+#[test]
+fn alternatives_before_fallback() {
+    let mut nicks = Alternatives::new("avonarret")
+        .alternatives(vec!["avon".to_string()])
+        .fallback(Some(Fallback::Underscore));
+
+    assert_eq!(nicks.next(), Some("avon".to_string()));
+    assert_eq!(nicks.next(), Some("avonarre_".to_string()));
+    assert_eq!(nicks.next(), Some("avonarr__".to_string()));
+    assert_eq!(nicks.next(), Some("avonar___".to_string()));
+    assert_eq!(nicks.next(), None);
+}
+
+#[test]
+fn digit_fallback() {
+    let mut nicks = Alternatives::new("avon").fallback(Some(Fallback::Digits));
+
+    let generated = std::iter::from_fn(|| nicks.next()).collect::<Vec<String>>();
+
+    assert_eq!(generated.len(), 9);
+    assert_eq!(generated[0], "avon1");
+    assert_eq!(generated[8], "avon9");
+}
+
+#[test]
+fn no_alternatives() {
+    assert_eq!(Alternatives::new("avon").next(), None);
+}
@@ -1,6 +1,6 @@
 extern crate np1th_irc;
 
-use np1th_irc::parsing;
+use np1th_irc::{parsing, target::MessageTarget};
 
 #[test]
 pub fn msg_target_text() {
@@ -12,3 +12,65 @@ pub fn msg_target_text() {
         assert!(res.is_ok());
     }
 }
+
+#[test]
+pub fn msg_to_grammar() {
+    let parse = |data| parsing::msg_to(data).unwrap();
+
+    assert_eq!(parse("#channel"), MessageTarget::Channel("#channel".to_string()));
+    assert_eq!(
+        parse("@#channel"),
+        MessageTarget::ChannelStatus {
+            status: '@',
+            channel: "#channel".to_string(),
+        }
+    );
+    assert_eq!(parse("+channel"), MessageTarget::Channel("+channel".to_string()));
+    assert_eq!(parse("nickname"), MessageTarget::Nick("nickname".to_string()));
+    assert_eq!(
+        parse("nick!user@host"),
+        MessageTarget::NickMask {
+            nick: "nick".to_string(),
+            user: "user".to_string(),
+            host: "host".to_string(),
+        }
+    );
+    assert_eq!(
+        parse("user%host@server"),
+        MessageTarget::UserServer {
+            user: "user".to_string(),
+            host: Some("host".to_string()),
+            server: "server".to_string(),
+        }
+    );
+    assert_eq!(
+        parse("user@server"),
+        MessageTarget::UserServer {
+            user: "user".to_string(),
+            host: None,
+            server: "server".to_string(),
+        }
+    );
+    assert_eq!(
+        parse("user%host"),
+        MessageTarget::UserHost {
+            user: "user".to_string(),
+            host: "host".to_string(),
+        }
+    );
+    assert_eq!(parse("$*.fi"), MessageTarget::ServerMask("*.fi".to_string()));
+    assert_eq!(parse("#*.edu"), MessageTarget::HostMask("*.edu".to_string()));
+
+    let targets = vec![
+        "#channel", "@#channel", "nickname", "nick!user@host", "user%host@server", "$*.fi",
+    ];
+
+    for target in targets {
+        assert_eq!(parse(target).to_string(), target);
+    }
+
+    assert!(parsing::msg_to("").is_err());
+    assert!(parsing::msg_to("9nick").is_err());
+    assert!(parsing::msg_to("$*").is_err());
+    assert!(parsing::msg_target("#channel,9nick").is_err());
+}
@@ -0,0 +1,200 @@
+extern crate np1th_irc;
+
+mod common;
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use np1th_irc::{command::client::Command, plugin::Host};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+const GREET: &str = r#"
+let count = 0;
+
+fn on_message(nick, target, text) {
+    if text == "!count" {
+        count += 1;
+        privmsg(target, `${nick}: ${count}`);
+    } else if text == "!kick me" {
+        kick(target, nick, "asked for it");
+    } else if target == nick() {
+        notice(nick, "private " + text);
+    }
+}
+
+fn on_join(nick, channel) {
+    if nick != nick() {
+        privmsg(channel, "hello " + nick);
+    }
+}
+"#;
+
+const SLOW: &str = r#"
+fn on_message(nick, target, text) {
+    if text == "!slow" {
+        privmsg(target, "never sent");
+        loop {}
+    }
+}
+"#;
+
+/// Runs a server and a client called `bot` on `#room` with the scripts in `directory`. Errors
+/// from the host come out of the receiver.
+fn start(directory: PathBuf) -> (u16, Receiver<String>) {
+    let port = common::start_ircd("irc.test", |builder| builder);
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut client = common::connect(port, "bot").unwrap();
+
+        let mut host = Host::new(directory).time_limit(Duration::from_millis(100));
+
+        client
+            .send(Command::Join {
+                channels: vec!["#room".to_string()],
+                keys: Vec::new(),
+            })
+            .unwrap();
+
+        let mut joined = false;
+
+        loop {
+            if let Err(e) = host.reload() {
+                sender.send(e.to_string()).unwrap();
+            }
+
+            match client.read().unwrap() {
+                Some(message) => {
+                    if let Err(e) = host.handle(&client, &message) {
+                        sender.send(e.to_string()).unwrap();
+                    }
+
+                    if let Command::Numeric { code: 366, .. } = message.command() {
+                        if !joined {
+                            joined = true;
+                            sender.send("joined".to_string()).unwrap();
+                        }
+                    }
+                }
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    });
+
+    assert_eq!(receiver.recv_timeout(TIMEOUT).unwrap(), "joined");
+
+    (port, receiver)
+}
+
+struct TestClient {
+    reader: BufReader<TcpStream>,
+}
+
+impl TestClient {
+    fn register(port: u16, nick: &str) -> Self {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let mut client = TestClient {
+            reader: BufReader::new(stream),
+        };
+
+        client.send(&format!("NICK {}", nick));
+        client.send(&format!("USER {} 0 * :{} real", nick, nick));
+        client.expect(" 422 ");
+
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        self.reader
+            .get_mut()
+            .write_all(format!("{}\r\n", line).as_bytes())
+            .unwrap();
+    }
+
+    /// Skips lines until one is from the bot
+    fn reply(&mut self) -> String {
+        self.expect(":bot!")
+    }
+
+    /// Skips lines until one contains `part`
+    fn expect(&mut self, part: &str) -> String {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => panic!("connection closed waiting for {:?}", part),
+                Ok(_) if line.contains(part) => return line.trim_end().to_string(),
+                Ok(_) => (),
+                Err(e) => panic!("{} waiting for {:?}", e, part),
+            }
+        }
+    }
+}
+
+#[test]
+fn run_scripts() {
+    let directory = std::env::temp_dir().join(format!("np1th-irc-plugins-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    fs::write(directory.join("greet.rhai"), GREET).unwrap();
+    fs::write(directory.join("slow.rhai"), SLOW).unwrap();
+    fs::write(directory.join("notes.txt"), "not a script").unwrap();
+
+    let (port, errors) = start(directory.clone());
+
+    let mut alice = TestClient::register(port, "alice");
+    alice.send("JOIN #room");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :hello alice"));
+
+    // variables outside the functions are kept between events
+    alice.send("PRIVMSG #room :!count");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :alice: 1"));
+    alice.send("PRIVMSG #room :!count");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :alice: 2"));
+
+    alice.send("PRIVMSG bot :psst");
+    assert!(alice.reply().ends_with(" NOTICE alice :private psst"));
+
+    // stopped by the time limit, without sending anything
+    alice.send("PRIVMSG #room :!slow");
+    let error = errors.recv_timeout(TIMEOUT).unwrap();
+    assert!(error.contains("slow"), "{}", error);
+    assert!(error.contains("time limit exceeded"), "{}", error);
+
+    alice.send("PRIVMSG #room :!count");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :alice: 3"));
+
+    // broken scripts aren't loaded, changed ones are and start over
+    fs::write(directory.join("broken.rhai"), "fn on_join(nick, channel) {").unwrap();
+    let error = errors.recv_timeout(TIMEOUT).unwrap();
+    assert!(error.contains("broken"), "{}", error);
+
+    fs::write(
+        directory.join("greet.rhai"),
+        GREET.replace("count += 1;", "count += 10;"),
+    )
+    .unwrap();
+    fs::remove_file(directory.join("slow.rhai")).unwrap();
+    thread::sleep(Duration::from_millis(200));
+
+    alice.send("PRIVMSG #room :!slow");
+    alice.send("PRIVMSG #room :!count");
+    assert!(alice.reply().ends_with(" PRIVMSG #room :alice: 10"));
+
+    alice.send("PRIVMSG #room :!kick me");
+    assert!(alice.reply().ends_with(" KICK #room alice :asked for it"));
+
+    assert!(errors.try_recv().is_err());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,87 @@
+extern crate np1th_irc;
+
+use np1th_irc::{connection::policy::ConnectPolicy, stream::Port};
+
+#[test]
+fn secure_only_removes_insecure_ports() {
+    let candidates = ConnectPolicy::new()
+        .host("irc.example.org")
+        .port(Port::Insecure(6667))
+        .port(Port::Secure(6697))
+        .secure_only()
+        .candidates()
+        .unwrap();
+
+    assert_eq!(candidates.len(), 1);
+    assert!(candidates.iter().all(|candidate| candidate.secure()));
+}
+
+#[test]
+fn insecure_only_removes_secure_ports() {
+    let candidates = ConnectPolicy::new()
+        .host("irc.example.org")
+        .port(Port::Secure(6697))
+        .port(Port::Insecure(6667))
+        .port(Port::Secure(7000))
+        .insecure_only()
+        .candidates()
+        .unwrap();
+
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].port(), Port::Insecure(6667));
+}
+
+#[test]
+fn default_ports_and_priority() {
+    let candidates = ConnectPolicy::new()
+        .hosts(vec!["a.example.org", "b.example.org"])
+        .prioritize_secure()
+        .candidates()
+        .unwrap();
+
+    assert_eq!(candidates.len(), 6);
+    assert!(candidates[..4].iter().all(|candidate| candidate.secure()));
+    assert_eq!(candidates[0].host(), "a.example.org");
+    assert_eq!(candidates[2].host(), "b.example.org");
+}
+
+#[test]
+fn no_candidates() {
+    assert!(ConnectPolicy::new().candidates().is_err());
+
+    let res = ConnectPolicy::new()
+        .host("irc.example.org")
+        .port(Port::Insecure(6667))
+        .secure_only()
+        .candidates();
+
+    assert!(res.is_err());
+}
+
+#[test]
+fn hosts_and_ports_append() {
+    let candidates = ConnectPolicy::new()
+        .host("a.example.org")
+        .hosts(vec!["b.example.org"])
+        .port(Port::Insecure(6667))
+        .ports(vec![Port::Secure(6697)])
+        .candidates()
+        .unwrap();
+
+    let hosts: Vec<&str> = candidates
+        .iter()
+        .map(|candidate| candidate.host())
+        .collect();
+
+    assert_eq!(
+        hosts,
+        vec![
+            "a.example.org",
+            "a.example.org",
+            "b.example.org",
+            "b.example.org"
+        ]
+    );
+    assert_eq!(candidates[0].port(), Port::Insecure(6667));
+    assert_eq!(candidates[1].port(), Port::Secure(6697));
+}
@@ -0,0 +1,61 @@
+extern crate np1th_irc;
+
+use np1th_irc::split;
+
+fn texts(chunks: Vec<split::Chunk>) -> Vec<String> {
+    chunks.iter().map(|chunk| chunk.text().to_string()).collect()
+}
+
+#[test]
+fn available_payload() {
+    // `:nick!user@host ` is 17 bytes
+    assert_eq!(split::available("PRIVMSG", "#channel", 17), 512 - 17 - 7 - 8 - 3 - 2);
+}
+
+#[test]
+fn split_on_words_and_newlines() {
+    let chunks = split::split("hello world foo\nbar\r\n\nbaz", 11);
+
+    assert_eq!(texts(chunks.clone()), vec!["hello world", "foo", "bar", "baz"]);
+    assert!(!chunks[0].concat());
+    assert!(chunks[1].concat());
+    assert!(!chunks[2].concat());
+}
+
+#[test]
+fn split_long_words_on_graphemes() {
+    let text = "ä".repeat(5) + "\u{1F1E9}\u{1F1EA}";
+
+    for chunk in split::split(&text, 7) {
+        assert!(chunk.text().len() <= 7);
+    }
+
+    assert_eq!(texts(split::split(&text, 7)).concat(), text);
+    assert_eq!(texts(split::split("e\u{301}e\u{301}", 4)), vec!["e\u{301}", "e\u{301}"]);
+}
+
+#[test]
+fn split_keeps_formatting() {
+    let chunks = texts(split::split("\x02\x0304,12bold red text", 12));
+
+    assert_eq!(chunks[0], "\x02\x0304,12bold");
+    assert_eq!(chunks[1], "\x02\x0304,12red");
+    assert_eq!(chunks[2], "\x02\x0304,12text");
+}
+
+#[test]
+fn split_concat_is_verbatim() {
+    let text = "one two three four five";
+    let chunks = split::split_concat(text, 8);
+
+    assert!(chunks.iter().all(|chunk| chunk.text().len() <= 8));
+    assert_eq!(texts(chunks).concat(), text);
+}
+
+#[test]
+fn split_reads_codes_like_formatting() {
+    // hex colors need six digits, so these are text after a color reset
+    let chunks = texts(split::split("\x04ABC def", 5));
+
+    assert_eq!(chunks, vec!["\x04ABC", "def"]);
+}
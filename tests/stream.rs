@@ -0,0 +1,69 @@
+extern crate np1th_irc;
+
+use std::{
+    io::{BufRead, BufReader},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use np1th_irc::{
+    command::client::Command,
+    stream::{error::SendQueueExceededError, ClientStream, Port},
+    target::MessageTarget,
+};
+
+#[test]
+fn slow_peer() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (sender, receiver) = mpsc::channel::<usize>();
+
+    let peer = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+
+        // doesn't read before the client's send queue is full
+        let expected = receiver.recv().unwrap();
+        let mut lines = BufReader::new(stream).lines();
+
+        for number in 0..expected {
+            let line = lines.next().unwrap().unwrap();
+            assert_eq!(line, format!("PRIVMSG #d :{} {}", number, "x".repeat(400)));
+        }
+    });
+
+    let stream = ClientStream::connect("127.0.0.1", Port::Insecure(port), None).unwrap();
+    let mut sent = 0;
+
+    let error = loop {
+        let line = Command::PrivMsg {
+            targets: vec![MessageTarget::Channel("#d".to_string())],
+            text: format!("{} {}", sent, "x".repeat(400)),
+        };
+
+        // lines the socket doesn't take wait, and fail once too many are waiting
+        let result = stream.send(line);
+        sent += 1;
+
+        if let Err(e) = result {
+            break e;
+        }
+    };
+
+    assert!(error.is::<SendQueueExceededError>());
+    assert!(stream.pending() > 0);
+
+    sender.send(sent).unwrap();
+
+    let started = Instant::now();
+
+    while stream.pending() > 0 {
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let _ = stream.flush();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    peer.join().unwrap();
+}
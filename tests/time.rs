@@ -0,0 +1,50 @@
+extern crate np1th_irc;
+
+use np1th_irc::time;
+
+#[test]
+fn format_and_parse_times() {
+    assert_eq!(time::format_time(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(
+        time::format_time(1_546_612_406_123),
+        "2019-01-04T14:33:26.123Z"
+    );
+    assert_eq!(
+        time::format_time(951_782_400_000),
+        "2000-02-29T00:00:00.000Z"
+    );
+    assert_eq!(time::format_seconds(1_546_612_406), "2019-01-04T14:33:26Z");
+
+    assert_eq!(
+        time::parse_time("2019-01-04T14:33:26.123Z"),
+        Some(1_546_612_406_123)
+    );
+    assert_eq!(
+        time::parse_time("2019-01-04T14:33:26Z"),
+        Some(1_546_612_406_000)
+    );
+    assert_eq!(
+        time::parse_time("2019-01-04T14:33:26.5Z"),
+        Some(1_546_612_406_500)
+    );
+    assert_eq!(
+        time::parse_time("2019-01-04T14:33:26.123456Z"),
+        Some(1_546_612_406_123)
+    );
+    assert_eq!(
+        time::parse_time("2000-02-29T00:00:00.000Z"),
+        Some(951_782_400_000)
+    );
+
+    for time in &[
+        "2019-01-04T14:33:26.123",
+        "2019-01-04 14:33:26.123Z",
+        "2019-13-04T14:33:26.123Z",
+        "2019-01-04T24:00:00.000Z",
+        "2019-01-04T14:33Z",
+        "19-01-04T14:33:26.123Z",
+        "2019-01-04T14:33:+6.123Z",
+    ] {
+        assert_eq!(time::parse_time(time), None, "{}", time);
+    }
+}
@@ -0,0 +1,112 @@
+extern crate np1th_irc;
+
+use std::convert::TryFrom;
+
+use np1th_irc::{
+    casemapping::CaseMapping, command::client::Command, connection::tracking, message::Message,
+    server::Server,
+};
+
+fn feed(server: &mut Server, nick: &str, lines: &[&str]) {
+    for line in lines {
+        let message = Message::<Command>::try_from(*line).unwrap();
+
+        tracking::track(server, nick, &message);
+    }
+}
+
+fn members(server: &Server, channel: &str) -> Vec<String> {
+    let channel = server.channel(channel).unwrap();
+    let channel = channel.borrow();
+
+    let mut members = channel
+        .members()
+        .iter()
+        .map(|member| {
+            let user = member.user().upgrade().unwrap();
+            let nick = user.borrow().origin().nick().unwrap().to_string();
+
+            format!(
+                "{}{}",
+                member.prefix().map(String::from).unwrap_or_default(),
+                nick
+            )
+        })
+        .collect::<Vec<String>>();
+
+    members.sort();
+
+    members
+}
+
+#[test]
+fn track_channels() {
+    let mut server = Server::default();
+
+    feed(
+        &mut server,
+        "me",
+        &[
+            ":irc.test 005 me CASEMAPPING=ascii :are supported by this server",
+            ":bob!bob@host JOIN #elsewhere",
+            ":me!me@host JOIN #room",
+            ":irc.test 332 me #room :the topic",
+            ":irc.test 333 me #room bob 1546612406",
+            ":irc.test 353 me = #room :me @bob +carol!carol@host",
+            ":irc.test 366 me #room :End of /NAMES list.",
+        ],
+    );
+
+    assert_eq!(server.casemapping(), CaseMapping::Ascii);
+    assert!(server.channel("#elsewhere").is_none());
+    assert_eq!(members(&server, "#room"), vec!["+carol", "@bob", "me"]);
+
+    {
+        let channel = server.channel("#ROOM").unwrap();
+        let channel = channel.borrow();
+
+        assert_eq!(channel.topic(), Some("the topic"));
+        assert_eq!(channel.topic_setter(), Some("bob"));
+        assert_eq!(channel.topic_time(), 1_546_612_406);
+    }
+
+    feed(
+        &mut server,
+        "me",
+        &[
+            ":bob!bob@host MODE #room -o+v bob bob",
+            ":bob!bob@host MODE #room +k key",
+            ":carol!carol@host NICK dave",
+            ":dave!carol@host TOPIC #room :new topic",
+            ":eve!eve@host JOIN #room",
+            ":eve!eve@host PART #room",
+        ],
+    );
+
+    assert_eq!(members(&server, "#room"), vec!["+bob", "+dave", "me"]);
+    assert_eq!(server.users().len(), 3);
+
+    {
+        let channel = server.channel("#room").unwrap();
+        let channel = channel.borrow();
+
+        assert_eq!(channel.key(), Some("key"));
+        assert_eq!(channel.topic(), Some("new topic"));
+        assert_eq!(channel.topic_setter(), Some("dave"));
+    }
+
+    feed(
+        &mut server,
+        "me",
+        &[
+            ":dave!carol@host QUIT :gone",
+            ":me!me@host JOIN #other",
+            ":bob!bob@host JOIN #other",
+            ":bob!bob@host KICK #room me :out",
+        ],
+    );
+
+    assert!(server.channel("#room").is_none());
+    assert_eq!(members(&server, "#other"), vec!["bob", "me"]);
+    assert!(server.user("dave").is_none());
+}